@@ -0,0 +1,278 @@
+//! How the solve loop decides whether to carry on from a candidate that's no better than the
+//! solution it came from, and what it can say about that while the search runs.
+
+use std::{collections::VecDeque, str::FromStr};
+
+use rand::Rng;
+use serde::Serialize;
+
+use crate::random::SeededRng;
+
+/// How many of the latest decisions the acceptance ratio is over.
+const WINDOW_LEN: usize = 1000;
+
+/// Which acceptance criterion the search uses, see [`Acceptance::criterion`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Acceptance {
+    /// keep a worse candidate with this fixed probability
+    Random(f64),
+    /// simulated annealing: keep a candidate `delta` worse with probability `exp(-delta / T)`,
+    /// where `T` starts at `initial_temperature` and is multiplied by `cooling` every iteration
+    Annealing {
+        initial_temperature: f64,
+        cooling: f64,
+    },
+}
+
+impl Default for Acceptance {
+    fn default() -> Self {
+        Acceptance::Random(0.1)
+    }
+}
+
+impl FromStr for Acceptance {
+    type Err = String;
+
+    /// `random:<probability>` or `anneal:<initial temperature>,<cooling>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "invalid acceptance {s:?}, expected random:<probability> or \
+                 anneal:<initial temperature>,<cooling>"
+            )
+        };
+        let number = |v: &str| v.trim().parse::<f64>().map_err(|_| err());
+        match s.split_once(':').ok_or_else(err)? {
+            ("random", p) => {
+                let p = number(p)?;
+                if !(0.0..=1.0).contains(&p) {
+                    return Err(format!("acceptance probability {p} isn't between 0 and 1"));
+                }
+                Ok(Acceptance::Random(p))
+            }
+            ("anneal", rest) => {
+                let (temperature, cooling) = rest.split_once(',').ok_or_else(err)?;
+                let (initial_temperature, cooling) = (number(temperature)?, number(cooling)?);
+                if initial_temperature <= 0.0 || initial_temperature.is_infinite() {
+                    return Err(format!(
+                        "initial temperature {initial_temperature} isn't a positive number"
+                    ));
+                }
+                if cooling <= 0.0 || cooling > 1.0 {
+                    return Err(format!("cooling {cooling} isn't in (0, 1]"));
+                }
+                Ok(Acceptance::Annealing {
+                    initial_temperature,
+                    cooling,
+                })
+            }
+            _ => Err(err()),
+        }
+    }
+}
+
+impl Acceptance {
+    /// A fresh criterion for one solve.
+    pub fn criterion(self) -> Box<dyn AcceptanceCriterion> {
+        match self {
+            Acceptance::Random(p) => Box::new(RandomAcceptance(p)),
+            Acceptance::Annealing {
+                initial_temperature,
+                cooling,
+            } => Box::new(Annealing {
+                temperature: initial_temperature,
+                cooling,
+            }),
+        }
+    }
+}
+
+/// What a criterion can tell about its state, for watching it while tuning. Empty (and serialized
+/// as `{}`) for criteria without any.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AcceptanceSnapshot {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+}
+
+pub trait AcceptanceCriterion {
+    /// Whether to carry on from a candidate `delta` (at least 0) worse than the solution it came
+    /// from, rather than going back. Only asked on iterations that didn't improve.
+    fn accept(&mut self, delta: f64, rng: &mut SeededRng) -> bool;
+
+    /// Called at the end of every iteration.
+    fn tick(&mut self) {}
+
+    fn snapshot(&self) -> AcceptanceSnapshot {
+        AcceptanceSnapshot::default()
+    }
+}
+
+struct RandomAcceptance(f64);
+
+impl AcceptanceCriterion for RandomAcceptance {
+    fn accept(&mut self, _delta: f64, rng: &mut SeededRng) -> bool {
+        !rng.random_bool(1.0 - self.0)
+    }
+}
+
+struct Annealing {
+    temperature: f64,
+    cooling: f64,
+}
+
+impl AcceptanceCriterion for Annealing {
+    fn accept(&mut self, delta: f64, rng: &mut SeededRng) -> bool {
+        rng.random_bool((-delta / self.temperature).exp())
+    }
+
+    fn tick(&mut self) {
+        self.temperature *= self.cooling;
+    }
+
+    fn snapshot(&self) -> AcceptanceSnapshot {
+        AcceptanceSnapshot {
+            temperature: Some(self.temperature),
+        }
+    }
+}
+
+/// The share of the latest [`WINDOW_LEN`] decisions that were to accept.
+pub struct AcceptanceWindow {
+    decisions: VecDeque<bool>,
+    accepted: usize,
+}
+
+impl AcceptanceWindow {
+    pub fn new() -> Self {
+        AcceptanceWindow {
+            decisions: VecDeque::with_capacity(WINDOW_LEN),
+            accepted: 0,
+        }
+    }
+
+    pub fn record(&mut self, accepted: bool) {
+        if self.decisions.len() == WINDOW_LEN {
+            self.accepted -= usize::from(self.decisions.pop_front().unwrap());
+        }
+        self.decisions.push_back(accepted);
+        self.accepted += usize::from(accepted);
+    }
+
+    /// `None` before the first decision.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn ratio(&self) -> Option<f64> {
+        (!self.decisions.is_empty()).then(|| self.accepted as f64 / self.decisions.len() as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::*;
+    use crate::{
+        generate, random,
+        solver::{self, SolveParams, TermCond},
+        solvers::ALNSSolver,
+    };
+
+    #[test]
+    fn geometric_cooling_only_ever_cools() {
+        let mut criterion = "anneal:100,0.9".parse::<Acceptance>().unwrap().criterion();
+        let mut last = f64::INFINITY;
+        for k in 0..50 {
+            let temperature = criterion.snapshot().temperature.unwrap();
+            assert!(temperature < last, "tick {k}");
+            assert!((temperature - 100.0 * 0.9f64.powi(k)).abs() < 1e-9);
+            last = temperature;
+            criterion.tick();
+        }
+    }
+
+    #[test]
+    fn the_default_criterion_has_nothing_to_report() {
+        let mut criterion = Acceptance::default().criterion();
+        criterion.tick();
+        assert_eq!(criterion.snapshot(), AcceptanceSnapshot::default());
+        assert_eq!(serde_json::to_string(&criterion.snapshot()).unwrap(), "{}");
+
+        let annealing = "anneal:2.5,1".parse::<Acceptance>().unwrap().criterion();
+        assert_eq!(
+            serde_json::to_string(&annealing.snapshot()).unwrap(),
+            r#"{"Temperature":2.5}"#
+        );
+    }
+
+    #[test]
+    fn acceptance_parses_and_bad_ones_are_refused() {
+        assert_eq!("random:0.3".parse(), Ok(Acceptance::Random(0.3)));
+        assert_eq!(
+            "anneal:10,0.99".parse(),
+            Ok(Acceptance::Annealing {
+                initial_temperature: 10.0,
+                cooling: 0.99
+            })
+        );
+        for bad in [
+            "random",
+            "random:1.5",
+            "anneal:10",
+            "anneal:0,0.9",
+            "anneal:10,1.1",
+            "anneal:10,0",
+            "greedy:1",
+        ] {
+            assert!(bad.parse::<Acceptance>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn the_ratio_is_over_the_latest_decisions() {
+        let mut window = AcceptanceWindow::new();
+        assert_eq!(window.ratio(), None);
+        for _ in 0..WINDOW_LEN {
+            window.record(true);
+        }
+        assert_eq!(window.ratio(), Some(1.0));
+        for _ in 0..WINDOW_LEN / 4 {
+            window.record(false);
+        }
+        assert_eq!(window.ratio(), Some(0.75));
+    }
+
+    #[test]
+    fn improvements_record_the_temperature_they_were_found_at() {
+        let instance = Arc::new(generate::random_instance(40, 50, 2004));
+        for acceptance in [Acceptance::default(), "anneal:50,0.995".parse().unwrap()] {
+            let params = SolveParams {
+                terminate: TermCond::MaxIters(1000),
+                acceptance,
+                ..crate::default_params(Duration::ZERO)
+            };
+            random::seed(2004);
+            let initial = (params.constructor)(&instance);
+            let (_, stats) =
+                solver::solve_from_with_stats::<ALNSSolver>(&instance, &params, initial);
+            let temperatures = &stats.improvement_temperatures;
+            if acceptance == Acceptance::default() {
+                assert!(temperatures.is_empty());
+                continue;
+            }
+            assert!(!temperatures.is_empty());
+            assert_eq!(temperatures.len(), stats.improvements.len());
+            for (&(iter, temperature), &(improved_at, _)) in
+                temperatures.iter().zip(&stats.improvements)
+            {
+                assert_eq!(iter, improved_at);
+                // the temperature at the end of the iteration before, so cooled `iter` times
+                let expected = 50.0 * 0.995f64.powi(i32::try_from(iter).unwrap());
+                assert!(
+                    (temperature - expected).abs() < 1e-6 * expected,
+                    "iteration {iter}"
+                );
+            }
+        }
+    }
+}
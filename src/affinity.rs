@@ -0,0 +1,17 @@
+/// Pin the calling thread to core `core` (modulo the number of cores), so a sub-solve's solution
+/// buffers stay in that core's cache. Does nothing unless built with the `pin-threads` feature on Linux.
+#[cfg(all(feature = "pin-threads", target_os = "linux"))]
+pub fn pin_to_core(core: usize) {
+    let core = core % num_cpus::get();
+    // SAFETY: `set` is a plain bitmask owned by this stack frame, and 0 means the calling thread
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) != 0 {
+            eprintln!("couldn't pin thread to core {core}");
+        }
+    }
+}
+
+#[cfg(not(all(feature = "pin-threads", target_os = "linux")))]
+pub fn pin_to_core(_core: usize) {}
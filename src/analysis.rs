@@ -0,0 +1,153 @@
+use std::mem::size_of;
+
+use crate::{
+    common::{DistanceMatrix, Route, Stop},
+    decompose,
+    error::VrpError,
+    insertion_cache::InsertionCache,
+    vrp_instance::VRPInstance,
+};
+
+/// Solutions a single search keeps alive at once: the pool's current/best/jump-best/new/old copies,
+/// the solver's own current solution, plus a spare for jumps (elites are counted separately).
+const WORKING_SOLUTIONS: usize = 7;
+
+/// A rough upper bound on how much memory a run will need.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// the distance matrix and the main search, needed however many threads there are
+    pub shared_bytes: usize,
+    /// each sub-solve thread's own sub-instance and working solutions
+    pub per_thread_bytes: usize,
+}
+
+impl MemoryEstimate {
+    pub fn for_instance(instance: &VRPInstance, elite_count: usize) -> Self {
+        let n = instance.num_customers;
+        let precision = instance.distance_matrix.precision();
+        let matrix_bytes = |n: usize| {
+            let triangle = DistanceMatrix::stores_triangle(n, instance.is_symmetric);
+            DistanceMatrix::bytes_for(n, precision, triangle)
+        };
+        let solution_bytes = |vehicles: usize, route_len: usize| {
+            vehicles * (size_of::<Route>() + route_len * size_of::<Stop>())
+        };
+
+        let shared_bytes = instance.distance_matrix.bytes()
+            + (WORKING_SOLUTIONS + elite_count)
+                * solution_bytes(instance.num_vehicles, instance.max_route_len)
+            + InsertionCache::bytes_for(n, instance.num_vehicles);
+
+        // only decomposed runs solve anything on other threads
+        let per_thread_bytes = if decompose::should_decompose(instance) {
+            let clusters = (instance.num_vehicles / decompose::VEHICLES_PER_CLUSTER).max(2);
+            let cluster_size = n.div_ceil(clusters) + 1;
+            let cluster_vehicles = instance.num_vehicles.div_ceil(clusters);
+            matrix_bytes(cluster_size)
+                + (WORKING_SOLUTIONS + elite_count)
+                    * solution_bytes(cluster_vehicles, instance.max_route_len.min(cluster_size))
+                + InsertionCache::bytes_for(cluster_size, cluster_vehicles)
+        } else {
+            0
+        };
+
+        MemoryEstimate {
+            shared_bytes,
+            per_thread_bytes,
+        }
+    }
+
+    pub fn total_bytes(&self, threads: usize) -> usize {
+        self.shared_bytes + threads * self.per_thread_bytes
+    }
+
+    /// The most threads (up to `wanted`) that fit in `budget_mb`, or an error if not even one does.
+    pub fn fit_threads(&self, wanted: usize, budget_mb: usize) -> Result<usize, VrpError> {
+        let budget = budget_mb * 1024 * 1024;
+        let needed_mb = self.total_bytes(1).div_ceil(1024 * 1024);
+        if self.total_bytes(1) > budget {
+            return Err(VrpError::OverMemoryBudget {
+                needed_mb,
+                budget_mb,
+            });
+        }
+
+        let spare = budget - self.shared_bytes;
+        let fit = spare
+            .checked_div(self.per_thread_bytes)
+            .unwrap_or(usize::MAX);
+        Ok(wanted.min(fit).max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate;
+
+    #[test]
+    fn small_instances_need_nothing_per_thread() {
+        let instance = generate::random_instance(100, 50, 1972);
+        let estimate = MemoryEstimate::for_instance(&instance, 4);
+        assert_eq!(estimate.per_thread_bytes, 0);
+        // 101 full rows of f64s, and a pointer to each
+        assert_eq!(
+            instance.distance_matrix.bytes(),
+            101 * 101 * 8 + 101 * size_of::<Box<[f64]>>()
+        );
+        assert!(estimate.shared_bytes > instance.distance_matrix.bytes());
+        assert_eq!(estimate.total_bytes(16), estimate.shared_bytes);
+    }
+
+    #[test]
+    fn large_instances_keep_a_triangle_and_need_sub_solves() {
+        let instance = generate::random_instance(5000, 1000, 1972);
+        let estimate = MemoryEstimate::for_instance(&instance, 4);
+        // the 5001 * 5002 / 2 distances of one triangle
+        assert_eq!(instance.distance_matrix.bytes(), 100_060_008);
+        assert!(estimate.shared_bytes > 100_060_008);
+        assert!(estimate.per_thread_bytes > 0);
+        assert!(estimate.per_thread_bytes < estimate.shared_bytes);
+    }
+
+    #[test]
+    fn each_elite_costs_a_full_solution() {
+        let instance = generate::random_instance(100, 50, 1972);
+        // every vehicle's route, with room for its longest possible run of stops
+        let solution_bytes = instance.num_vehicles
+            * (size_of::<Route>() + instance.max_route_len * size_of::<Stop>());
+
+        let without_elites = MemoryEstimate::for_instance(&instance, 0);
+        let with_elites = MemoryEstimate::for_instance(&instance, 4);
+        assert_eq!(
+            with_elites.shared_bytes - without_elites.shared_bytes,
+            4 * solution_bytes
+        );
+        assert!(without_elites.shared_bytes >= WORKING_SOLUTIONS * solution_bytes);
+    }
+
+    #[test]
+    fn threads_are_cut_to_fit_the_budget() {
+        const MB: usize = 1024 * 1024;
+        let estimate = MemoryEstimate {
+            shared_bytes: 10 * MB,
+            per_thread_bytes: 2 * MB,
+        };
+        assert_eq!(estimate.fit_threads(8, 100), Ok(8));
+        assert_eq!(estimate.fit_threads(8, 15), Ok(2));
+        assert_eq!(estimate.fit_threads(8, 12), Ok(1));
+        assert_eq!(
+            estimate.fit_threads(8, 11),
+            Err(VrpError::OverMemoryBudget {
+                needed_mb: 12,
+                budget_mb: 11
+            })
+        );
+
+        let shared_only = MemoryEstimate {
+            shared_bytes: 10 * MB,
+            per_thread_bytes: 0,
+        };
+        assert_eq!(shared_only.fit_threads(8, 10), Ok(8));
+    }
+}
@@ -0,0 +1,249 @@
+//! Running a solve from async code without blocking the executor, and without tying the crate to
+//! any particular runtime: the caller hands over how to run a blocking closure (e.g. tokio's
+//! `spawn_blocking`, or just `std::thread::spawn`).
+//!
+//! ```ignore
+//! let (solving, mut progress) = async_solve::solve_async::<MultiLNSSolver>(
+//!     instance,
+//!     params,
+//!     |work| drop(tokio::task::spawn_blocking(work)),
+//! );
+//! tokio::spawn(async move {
+//!     while let Some(event) = progress.next().await {
+//!         tracing::info!(best = event.best_cost, "solving");
+//!     }
+//! });
+//! // dropping `solving` (e.g. because the request was aborted) cancels the search
+//! let (best, stats) = solving.await;
+//! ```
+#![allow(dead_code)]
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    common::VRPSolution,
+    solver::{
+        self, CancelToken, IterativeSolver, ProgressEvent, SolveParams, SolveResult,
+        stats::SolveStats,
+    },
+    vrp_instance::VRPInstance,
+};
+
+/// A value (or values) handed from the solver thread to whoever is awaiting them.
+struct Shared<T> {
+    items: VecDeque<T>,
+    /// the sending side has gone away, so no more items are coming
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl<T> Shared<T> {
+    fn new() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Shared {
+            items: VecDeque::new(),
+            closed: false,
+            waker: None,
+        }))
+    }
+
+    fn push(shared: &Mutex<Self>, item: T) {
+        let mut shared = shared.lock().unwrap();
+        shared.items.push_back(item);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn close(shared: &Mutex<Self>) {
+        let mut shared = shared.lock().unwrap();
+        shared.closed = true;
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// The next item, `Ready(None)` once there are none left and none coming.
+    fn poll_next(shared: &Mutex<Self>, cx: &Context<'_>) -> Poll<Option<T>> {
+        let mut shared = shared.lock().unwrap();
+        match shared.items.pop_front() {
+            Some(item) => Poll::Ready(Some(item)),
+            None if shared.closed => Poll::Ready(None),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Resolves to the best solution and the solve's stats. Dropping it before then cancels the solve.
+pub struct SolveFuture {
+    result: Arc<Mutex<Shared<SolveResult>>>,
+    cancel: CancelToken,
+    done: bool,
+}
+
+impl Future for SolveFuture {
+    type Output = (VRPSolution, SolveStats);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Shared::poll_next(&self.result, cx) {
+            Poll::Ready(Some(result)) => {
+                self.done = true;
+                Poll::Ready(result)
+            }
+            Poll::Ready(None) => panic!("the solver thread exited without a result"),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for SolveFuture {
+    fn drop(&mut self) {
+        if !self.done {
+            self.cancel.cancel();
+        }
+    }
+}
+
+/// The solve's [`ProgressEvent`]s, about one a second, until it finishes.
+pub struct ProgressReceiver {
+    events: Arc<Mutex<Shared<ProgressEvent>>>,
+}
+
+impl ProgressReceiver {
+    /// The next progress event, or `None` once the solve is over.
+    pub fn next(&mut self) -> impl Future<Output = Option<ProgressEvent>> + '_ {
+        std::future::poll_fn(|cx| Shared::poll_next(&self.events, cx))
+    }
+}
+
+/// Closes both channels when the solver thread finishes, even if it panics, so nothing awaits forever.
+struct CloseOnDrop {
+    result: Arc<Mutex<Shared<SolveResult>>>,
+    events: Arc<Mutex<Shared<ProgressEvent>>>,
+}
+
+impl Drop for CloseOnDrop {
+    fn drop(&mut self) {
+        Shared::close(&self.result);
+        Shared::close(&self.events);
+    }
+}
+
+/// Start solving `instance` on whatever `spawn_blocking` runs its closure on, returning a future
+/// for the result and a receiver for the progress events. Any `params.progress` callback is still
+/// called too, and any `params.cancel` token is replaced by the future's own.
+pub fn solve_async<S: IterativeSolver>(
+    instance: Arc<VRPInstance>,
+    params: SolveParams,
+    spawn_blocking: impl FnOnce(Box<dyn FnOnce() + Send>),
+) -> (SolveFuture, ProgressReceiver) {
+    let result = Shared::new();
+    let events = Shared::new();
+    let cancel = CancelToken::default();
+
+    let forward_to = events.clone();
+    let callback = params.progress.clone();
+    let params = SolveParams {
+        progress: Some(Arc::new(move |event: &ProgressEvent| {
+            if let Some(callback) = &callback {
+                callback(event);
+            }
+            Shared::push(&forward_to, *event);
+        })),
+        cancel: Some(cancel.clone()),
+        ..params
+    };
+
+    let closer = CloseOnDrop {
+        result: result.clone(),
+        events: events.clone(),
+    };
+    spawn_blocking(Box::new(move || {
+        let initial_solution = params.starting_solution(&instance);
+        let res = solver::solve_from_with_stats::<S>(&instance, &params, initial_solution);
+        Shared::push(&closer.result, res);
+    }));
+
+    (
+        SolveFuture {
+            result,
+            cancel,
+            done: false,
+        },
+        ProgressReceiver { events },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc,
+        task::Waker,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::{generate, solver::TermCond, solvers::MultiLNSSolver};
+
+    /// Start solving a small instance on a plain thread, returning its handle too.
+    fn start(terminate: TermCond) -> (SolveFuture, ProgressReceiver, thread::JoinHandle<()>) {
+        let instance = Arc::new(generate::random_instance(200, 100, 1991));
+        let params = SolveParams {
+            terminate,
+            ..crate::default_params(Duration::ZERO)
+        };
+        let (handle_tx, handle_rx) = mpsc::channel();
+        let (solving, progress) = solve_async::<MultiLNSSolver>(instance, params, |work| {
+            handle_tx.send(thread::spawn(work)).unwrap();
+        });
+        (solving, progress, handle_rx.recv().unwrap())
+    }
+
+    /// Poll `future` until it's ready, without a runtime.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn dropping_the_future_cancels_the_solve() {
+        let (solving, _progress, handle) = start(TermCond::TimeElapsed(Duration::from_secs(600)));
+        thread::sleep(Duration::from_millis(200));
+        assert!(!handle.is_finished());
+
+        let dropped = Instant::now();
+        drop(solving);
+        handle.join().unwrap();
+        assert!(
+            dropped.elapsed() < Duration::from_secs(5),
+            "the solver thread took {:?} to stop",
+            dropped.elapsed()
+        );
+    }
+
+    #[test]
+    fn a_finished_solve_resolves_and_closes_the_progress_channel() {
+        let (solving, mut progress, handle) = start(TermCond::MaxIters(200));
+        let (best, stats) = block_on(solving);
+        handle.join().unwrap();
+        assert_eq!(stats.total_iterations, 200);
+        assert!(best.cost() > 0.0);
+        // any events sent are still there, then the channel reports it's done
+        while block_on(progress.next()).is_some() {}
+    }
+}
@@ -0,0 +1,277 @@
+use crate::vrp_instance::VRPInstance;
+
+/// A cheap lower bound on the cost of any feasible solution, the larger of [`degree_bound`] and
+/// [`fleet_bound`]. The latter assumes distances satisfy the triangle inequality, which holds for
+/// distances computed with any [`Metric`](crate::vrp_instance::Metric).
+pub fn lower_bound(instance: &VRPInstance) -> f64 {
+    degree_bound(instance).max(fleet_bound(instance))
+}
+
+/// How far `cost` is above `lower_bound`, as a percentage of the cost.
+pub fn gap_percent(cost: f64, lower_bound: f64) -> f64 {
+    if cost <= 0.0 {
+        return 0.0;
+    }
+    100.0 * (cost - lower_bound) / cost
+}
+
+/// How far `cost` is above the best known solution's cost, as a percentage of the latter.
+pub fn bks_gap_percent(cost: f64, bks: f64) -> f64 {
+    100.0 * (cost - bks) / bks
+}
+
+/// The cost within `gap_percent` of `bks`, at which the search can stop.
+pub fn bks_target(bks: f64, gap_percent: f64) -> f64 {
+    bks * (1.0 + gap_percent / 100.0)
+}
+
+/// The best known solution cost for the instance called `file_name`: either `spec` itself, or
+/// looked up in the CSV file `spec` names, whose lines are `<instance file name>,<cost>` (with or
+/// without the `.vrp`). `None` if the file has no line for the instance.
+pub fn parse_bks(spec: &str, file_name: &str) -> Result<Option<f64>, String> {
+    if let Ok(cost) = spec.parse::<f64>() {
+        return Ok(Some(cost));
+    }
+    let csv = std::fs::read_to_string(spec).map_err(|e| format!("can't read {spec}: {e}"))?;
+    let stem = file_name.strip_suffix(".vrp").unwrap_or(file_name);
+    csv.lines()
+        .filter_map(|line| line.split_once(','))
+        .find(|(instance, _)| {
+            let instance = instance.trim();
+            instance == file_name || instance == stem
+        })
+        .map(|(_, cost)| {
+            cost.trim()
+                .parse()
+                .map_err(|_| format!("bad cost for {file_name} in {spec}"))
+        })
+        .transpose()
+}
+
+/// Every customer is left by one edge and entered by another, and every edge touches at most two
+/// customers, so half the sum over customers of their two shortest possible incident edges is a
+/// lower bound. Either edge can be a depot edge (route start or end), which is how a customer on
+/// its own gets both of them. With an asymmetric matrix, the edges into customers are all
+/// different, and so are the edges out of them, so either sum of shortest edges is a bound.
+pub fn degree_bound(instance: &VRPInstance) -> f64 {
+    let dm = &instance.distance_matrix;
+    let end = usize::from(instance.end_node);
+
+    if !instance.is_symmetric {
+        let (mut total_in, mut total_out) = (0.0, 0.0);
+        for c in 1..instance.num_customers {
+            let others = (1..instance.num_customers).filter(|&o| o != c);
+            total_in += others
+                .clone()
+                .map(|o| dm.dist(o, c))
+                .fold(dm.dist(0, c), f64::min);
+            total_out += others
+                .map(|o| dm.dist(c, o))
+                .fold(dm.dist(c, end), f64::min);
+        }
+        return f64::max(total_in, total_out);
+    }
+
+    (1..instance.num_customers)
+        .map(|c| {
+            let mut shortest = [dm.dist(c, 0), dm.dist(c, end)];
+            shortest.sort_by(f64::total_cmp);
+            for other in (1..instance.num_customers).filter(|&o| o != c) {
+                let d = dm.dist(c, other);
+                if d < shortest[1] {
+                    shortest = if d < shortest[0] {
+                        [d, shortest[0]]
+                    } else {
+                        [shortest[0], d]
+                    };
+                }
+            }
+            f64::midpoint(shortest[0], shortest[1])
+        })
+        .sum()
+}
+
+/// At least `ceil(total demand / capacity)` routes are needed, and (by the triangle inequality)
+/// each costs at least the trip from the depot to any one of its customers and on to the route's
+/// end. Routes don't share customers, so the cheapest such trips to that many customers add up to
+/// a lower bound.
+pub fn fleet_bound(instance: &VRPInstance) -> f64 {
+    let dm = &instance.distance_matrix;
+    let end = usize::from(instance.end_node);
+
+    let total_demand = instance.demand_of_customer.iter().sum::<usize>();
+    let min_routes = total_demand.div_ceil(instance.vehicle_capacity.max(1));
+
+    let mut trips = (1..instance.num_customers)
+        .map(|c| dm.dist(0, c) + dm.dist(c, end))
+        .collect::<Vec<_>>();
+    trips.sort_by(f64::total_cmp);
+    trips.iter().take(min_routes).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{
+        generate, random,
+        solver::{self, SolveParams, TermCond},
+        solvers::ALNSSolver,
+        vrp_instance::ReadOptions,
+    };
+
+    /// The optimal cost, by cutting every order of the customers into consecutive routes in the
+    /// cheapest way that fits the fleet. Only for a handful of customers.
+    fn optimum(instance: &VRPInstance) -> f64 {
+        fn permutations(
+            rest: &mut Vec<usize>,
+            order: &mut Vec<usize>,
+            visit: &mut impl FnMut(&[usize]),
+        ) {
+            if rest.is_empty() {
+                visit(order);
+            }
+            for i in 0..rest.len() {
+                let c = rest.remove(i);
+                order.push(c);
+                permutations(rest, order, visit);
+                order.pop();
+                rest.insert(i, c);
+            }
+        }
+
+        let dm = &instance.distance_matrix;
+        let end = usize::from(instance.end_node);
+        let n = instance.num_customers - 1;
+        let mut best = f64::INFINITY;
+        permutations(&mut (1..=n).collect(), &mut Vec::new(), &mut |order| {
+            // cheapest[k][i]: the cheapest way to serve the first i customers with k routes
+            let mut cheapest = vec![vec![f64::INFINITY; n + 1]; instance.num_vehicles + 1];
+            cheapest[0][0] = 0.0;
+            for k in 1..=instance.num_vehicles {
+                for i in 1..=n {
+                    for start in 0..i {
+                        let route = &order[start..i];
+                        let load = route
+                            .iter()
+                            .map(|&c| instance.demand_of_customer[c])
+                            .sum::<usize>();
+                        if load > instance.vehicle_capacity {
+                            continue;
+                        }
+                        let legs = route.windows(2).map(|w| dm.dist(w[0], w[1])).sum::<f64>();
+                        let cost =
+                            dm.dist(0, route[0]) + legs + dm.dist(route[route.len() - 1], end);
+                        cheapest[k][i] = cheapest[k][i].min(cheapest[k - 1][start] + cost);
+                    }
+                }
+                best = best.min(cheapest[k][n]);
+            }
+        });
+        best
+    }
+
+    #[test]
+    fn bks_come_from_the_flag_or_the_instances_line_in_a_csv() {
+        assert_eq!(parse_bks("817.5", "101_8_1.vrp"), Ok(Some(817.5)));
+
+        let dir = std::env::temp_dir().join(format!("vrp-bks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("bks.csv");
+        std::fs::write(&csv, "101_8_1.vrp,817\n 121_7_1 , 1034.5\n135_7_1,lots\n").unwrap();
+        let csv = csv.to_str().unwrap();
+        assert_eq!(parse_bks(csv, "101_8_1.vrp"), Ok(Some(817.0)));
+        // with or without the extension, and spaces around either field
+        assert_eq!(parse_bks(csv, "121_7_1.vrp"), Ok(Some(1034.5)));
+        assert_eq!(parse_bks(csv, "200_16_2.vrp"), Ok(None));
+        assert!(
+            parse_bks(csv, "135_7_1.vrp")
+                .unwrap_err()
+                .contains("bad cost")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            parse_bks(csv, "101_8_1.vrp")
+                .unwrap_err()
+                .contains("can't read")
+        );
+    }
+
+    #[test]
+    fn bks_gaps_are_a_share_of_the_bks() {
+        assert_eq!(bks_gap_percent(110.0, 100.0), 10.0);
+        assert_eq!(bks_gap_percent(95.0, 100.0), -5.0);
+        assert_eq!(bks_target(200.0, 5.0), 210.0);
+        assert!((bks_gap_percent(bks_target(800.0, 2.5), 800.0) - 2.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn the_bound_is_never_above_the_optimum() {
+        for seed in 0..10 {
+            let instance = generate::random_instance(6, 8, seed);
+            let (bound, best) = (lower_bound(&instance), optimum(&instance));
+            assert!(bound <= best + 1e-9, "seed {seed}: {bound} > {best}");
+            assert!(bound > 0.0);
+            // and the search can't beat the optimum, so it isn't too high either
+            random::seed(seed);
+            let params = SolveParams {
+                terminate: TermCond::MaxIters(300),
+                ..crate::default_params(Duration::ZERO)
+            };
+            let found = solver::solve::<ALNSSolver>(&Arc::new(instance), &params).cost();
+            assert!(found >= best - 1e-9, "seed {seed}: found {found} < {best}");
+
+            let instance = generate::random_instance(6, 8, seed);
+            let ending_elsewhere = instance.with_end_location(90.0, 10.0).unwrap();
+            let (bound, best) = (lower_bound(&ending_elsewhere), optimum(&ending_elsewhere));
+            assert!(
+                bound <= best + 1e-9,
+                "seed {seed} with an end: {bound} > {best}"
+            );
+        }
+    }
+
+    #[test]
+    fn the_degree_bound_holds_one_way_too() {
+        let mut rng = StdRng::seed_from_u64(1980);
+        for _ in 0..10 {
+            // any distances at all, not even obeying the triangle inequality
+            let distances = (0..7)
+                .map(|a| {
+                    (0..7)
+                        .map(|b| {
+                            if a == b {
+                                0.0
+                            } else {
+                                rng.random_range(1.0..100.0)
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+            let instance = VRPInstance::from_matrix(3, 4, vec![0, 1, 2, 1, 2, 1, 2], distances);
+            assert!(!instance.is_symmetric);
+            assert!(degree_bound(&instance) <= optimum(&instance) + 1e-9);
+        }
+    }
+
+    #[test]
+    fn the_bound_is_below_what_the_solver_finds_on_the_bundled_instances() {
+        for name in ["16_5_1", "21_4_1", "30_4_1", "45_4_1", "51_5_1"] {
+            let path = format!("{}/input/{name}.vrp", env!("CARGO_MANIFEST_DIR"));
+            let instance = Arc::new(VRPInstance::new(&path, ReadOptions::default()).unwrap());
+            random::seed(1980);
+            let params = SolveParams {
+                terminate: TermCond::MaxIters(500),
+                ..crate::default_params(Duration::ZERO)
+            };
+            let sol = solver::solve::<ALNSSolver>(&instance, &params);
+            let bound = lower_bound(&instance);
+            assert!(bound <= sol.cost(), "{name}: {bound} > {}", sol.cost());
+            assert!(gap_percent(sol.cost(), bound) < 100.0);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Results from earlier runs, so rerunning over a folder of instances only solves the ones that
+//! changed. An entry is only used if it came from the same solver version and the same flags, for
+//! an instance file with the same contents, and its solution still checks out against the instance.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{common::VRPSolution, output, vrp_instance::VRPInstance};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// 64-bit FNV-1a, which (unlike `DefaultHasher`) is the same on every build, so cache entries
+/// stay usable across recompiles.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// What a cached result has to match to be reused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheKey {
+    /// of the instance file's contents
+    pub instance_hash: u64,
+    /// of everything else that affects the result, i.e. the flags and solve parameters
+    pub config_hash: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Entry {
+    version: String,
+    instance_hash: u64,
+    config_hash: u64,
+    routes: Vec<Vec<u32>>,
+    /// the JSON report the run printed
+    report: Value,
+}
+
+pub struct ResultsCache {
+    dir: PathBuf,
+}
+
+impl ResultsCache {
+    pub fn new(dir: &Path) -> Self {
+        ResultsCache {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn path(&self, key: CacheKey, extension: &str) -> PathBuf {
+        self.dir.join(format!(
+            "{:016x}-{:016x}.{extension}",
+            key.instance_hash, key.config_hash
+        ))
+    }
+
+    /// The cached solution and report for `key`, if there's a usable one. Entries that are
+    /// unreadable, from another version, or whose solution no longer validates are misses.
+    pub fn lookup(
+        &self,
+        key: CacheKey,
+        instance: &Arc<VRPInstance>,
+    ) -> Option<(VRPSolution, Value)> {
+        let path = self.path(key, "json");
+        let text = fs::read_to_string(&path).ok()?;
+        match Self::check_entry(&text, key, instance) {
+            Ok(hit) => Some(hit),
+            Err(e) => {
+                eprintln!("ignoring cache entry {}: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    fn check_entry(
+        text: &str,
+        key: CacheKey,
+        instance: &Arc<VRPInstance>,
+    ) -> Result<(VRPSolution, Value), String> {
+        let entry: Entry = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        if entry.version != VERSION {
+            return Err(format!("written by version {}", entry.version));
+        }
+        if entry.instance_hash != key.instance_hash || entry.config_hash != key.config_hash {
+            return Err("recorded hashes don't match its name".to_string());
+        }
+        let sol =
+            VRPSolution::from_routes_vec(instance, &entry.routes).map_err(|e| e.to_string())?;
+        sol.validate(instance)?;
+        Ok((sol, entry.report))
+    }
+
+    /// Remember `sol` and its report under `key`, along with its `.sol` file.
+    pub fn store(&self, key: CacheKey, sol: &VRPSolution, report: &Value) -> Result<(), String> {
+        let entry = Entry {
+            version: VERSION.to_string(),
+            instance_hash: key.instance_hash,
+            config_hash: key.config_hash,
+            routes: sol.to_routes_vec(true),
+            report: report.clone(),
+        };
+        for (path, contents) in [
+            (self.path(key, "sol"), sol.to_file_string()),
+            (
+                self.path(key, "json"),
+                serde_json::to_string(&entry).unwrap(),
+            ),
+        ] {
+            output::write_atomic(&path, &contents)
+                .map_err(|e| format!("can't write {}: {e}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{construct, generate, random};
+
+    const KEY: CacheKey = CacheKey {
+        instance_hash: 1,
+        config_hash: 2,
+    };
+
+    /// A cache in a fresh, empty directory, with a solution and report stored under [`KEY`].
+    fn stored(name: &str) -> (ResultsCache, Arc<VRPInstance>, VRPSolution, Value) {
+        let dir = std::env::temp_dir().join(format!("vrp-cache-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = ResultsCache::new(&dir);
+        let instance = Arc::new(generate::random_instance(20, 50, 1996));
+        random::seed(1996);
+        let sol = construct::clarke_wright_and_then_sweep(&instance);
+        let report = json!({ "Result": sol.cost() });
+        cache.store(KEY, &sol, &report).unwrap();
+        (cache, instance, sol, report)
+    }
+
+    #[test]
+    fn stored_results_are_found_again() {
+        let (cache, instance, sol, report) = stored("hit");
+        let (cached, cached_report) = cache.lookup(KEY, &instance).unwrap();
+        assert_eq!(cached.to_routes_vec(true), sol.to_routes_vec(true));
+        assert_eq!(cached_report, report);
+        assert_eq!(
+            fs::read_to_string(cache.path(KEY, "sol")).unwrap(),
+            sol.to_file_string()
+        );
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn other_flags_or_instances_miss() {
+        let (cache, instance, ..) = stored("miss");
+        for key in [
+            CacheKey {
+                config_hash: 3,
+                ..KEY
+            },
+            CacheKey {
+                instance_hash: 3,
+                ..KEY
+            },
+        ] {
+            assert!(cache.lookup(key, &instance).is_none());
+        }
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn corrupted_entries_miss() {
+        let (cache, instance, ..) = stored("corrupt");
+        let path = cache.path(KEY, "json");
+        let good: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let with = |field: &str, value: Value| {
+            let mut entry = good.clone();
+            entry[field] = value;
+            entry.to_string()
+        };
+        for bad in [
+            String::new(),
+            "{\"Version\":".to_string(),
+            good.to_string().replace("Routes", "Rootes"),
+            with("Version", json!("0.0.0-old")),
+            with("ConfigHash", json!(3)),
+            // customers missing, or in the wrong vehicle count
+            with("Routes", json!([[1, 2]])),
+            with("Routes", json!([[1, 1]])),
+        ] {
+            fs::write(&path, &bad).unwrap();
+            assert!(cache.lookup(KEY, &instance).is_none(), "{bad}");
+        }
+
+        // a solution for another instance of the same size doesn't validate against this one
+        fs::write(&path, good.to_string()).unwrap();
+        let other = Arc::new(generate::random_instance(20, 5, 1996));
+        assert!(cache.lookup(KEY, &other).is_none());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+use std::{sync::Arc, time::Instant};
+
+use serde::Serialize;
+
+use crate::{
+    common::VRPSolution,
+    solver::{self, IterativeSolver, SolveParams, TermCond},
+    vrp_instance::VRPInstance,
+};
+
+/// The destroy sizes a calibration tries, each for an equal share of its budget.
+pub const CALIBRATION_SIZES: [usize; 5] = [2, 3, 5, 8, 12];
+
+/// How one destroy size did during calibration.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CalibrationRow {
+    pub destroy_size: usize,
+    /// how much cheaper than the starting solution the trial got
+    pub improvement: f64,
+    pub millis: f64,
+}
+
+impl CalibrationRow {
+    pub fn improvement_per_ms(&self) -> f64 {
+        if self.millis > 0.0 {
+            self.improvement / self.millis
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Run a short search with each of [`CALIBRATION_SIZES`] (that fits the instance) from a copy of
+/// `initial_solution`, splitting `params.calibration` between them.
+pub fn calibrate<S: IterativeSolver>(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    initial_solution: &VRPSolution,
+) -> Vec<CalibrationRow> {
+    let Some(budget) = params.calibration.filter(|d| !d.is_zero()) else {
+        return Vec::new();
+    };
+    let sizes = CALIBRATION_SIZES
+        .iter()
+        .copied()
+        .filter(|&size| size < instance.num_customers)
+        .collect::<Vec<_>>();
+    if sizes.is_empty() {
+        return Vec::new();
+    }
+    let num_sizes = u32::try_from(sizes.len()).unwrap();
+
+    let start_cost = params.objective.evaluate(initial_solution);
+    sizes
+        .into_iter()
+        .map(|destroy_size| {
+            let trial_params = SolveParams {
+                terminate: TermCond::TimeElapsed(budget / num_sizes),
+                destroy_size: Some(destroy_size),
+                calibration: None,
+                progress: None,
+                on_improvement: None,
+                target_cost: None,
+                ..params.clone()
+            };
+            let started = Instant::now();
+            let best = solver::solve_from::<S>(instance, &trial_params, initial_solution.clone());
+            CalibrationRow {
+                destroy_size,
+                improvement: start_cost - params.objective.evaluate(&best),
+                millis: started.elapsed().as_secs_f64() * 1000.0,
+            }
+        })
+        .collect()
+}
+
+/// The destroy size that improved fastest, if any of them improved at all.
+pub fn best_destroy_size(table: &[CalibrationRow]) -> Option<usize> {
+    table
+        .iter()
+        .filter(|row| row.improvement > 0.0)
+        .max_by(|a, b| a.improvement_per_ms().total_cmp(&b.improvement_per_ms()))
+        .map(|row| row.destroy_size)
+}
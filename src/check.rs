@@ -0,0 +1,535 @@
+//! `--check`: verify a results log, as `runAll.sh` writes it (a JSON line per run), against the
+//! instances the runs were on.
+
+use std::{fmt, fs, path::Path};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    common::parse_depot_routes,
+    error::{CheckError, SolutionViolation},
+    ids::CustomerId,
+    verify,
+    vrp_instance::{ReadOptions, VRPInstance},
+};
+
+/// How far a run's reported result can be from the recomputed cost. Logs round results to two
+/// decimals.
+const COST_TOLERANCE: f64 = 0.1;
+
+/// How one line of the log held up.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CheckReport {
+    pub instance: String,
+    /// `None` for a run that failed without a solution (logged with `--` as its result)
+    pub recomputed_cost: Option<f64>,
+    /// everything wrong with the run's solution, empty if it's right
+    pub violations: Vec<Violation>,
+}
+
+/// Something wrong with a logged solution.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all_fields = "PascalCase")]
+pub enum Violation {
+    /// The run failed without logging a solution.
+    NoSolution,
+    /// The logged result is more than [`COST_TOLERANCE`] off the solution's actual cost.
+    CostMismatch { reported: f64, recomputed: f64 },
+    /// Something [`verify_routes`](verify::verify_routes) found wrong with the solution itself.
+    #[serde(untagged)]
+    Invalid(SolutionViolation),
+}
+
+impl CheckReport {
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// every customer visited exactly once, and nothing else visited
+    pub fn coverage_ok(&self) -> bool {
+        !self.violations.iter().any(|v| {
+            matches!(
+                v,
+                Violation::NoSolution
+                    | Violation::Invalid(
+                        SolutionViolation::UnknownCustomer { .. }
+                            | SolutionViolation::MissingCustomer(_)
+                            | SolutionViolation::DuplicateCustomer(_)
+                    )
+            )
+        })
+    }
+
+    /// no route carries more than a vehicle can
+    pub fn capacity_ok(&self) -> bool {
+        !self.violations.iter().any(|v| {
+            matches!(
+                v,
+                Violation::NoSolution | Violation::Invalid(SolutionViolation::OverCapacity { .. })
+            )
+        })
+    }
+
+    /// the reported result is within [`COST_TOLERANCE`] of `recomputed_cost`
+    pub fn cost_ok(&self) -> bool {
+        !self
+            .violations
+            .iter()
+            .any(|v| matches!(v, Violation::NoSolution | Violation::CostMismatch { .. }))
+    }
+}
+
+/// One line of a results log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRun {
+    /// counting from 1
+    pub line: usize,
+    /// the instance's file name
+    pub instance: String,
+    /// `None` for a run that failed (logged with `--`), as for the rest
+    pub time: Option<f64>,
+    pub result: Option<f64>,
+    /// what the `Solution` field holds, see [`parse_depot_routes`]
+    pub solution: Option<String>,
+    /// the gap to the instance's lower bound, which older logs don't have
+    pub gap_percent: Option<f64>,
+    /// the instance file's depot rows, as `--depot` gave them: the first is read as the depot, and
+    /// any more are the extra depots of a multi-depot run (see [`crate::multi_depot`])
+    pub depots: Vec<usize>,
+}
+
+/// Every run in the log at `path`, skipping blank lines.
+pub fn read_log(path: &Path) -> Result<Vec<LogRun>, CheckError> {
+    let log = fs::read_to_string(path).map_err(|e| CheckError::Unreadable {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    })?;
+    let mut runs = Vec::new();
+    for (i, line) in log.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let bad_line = |reason: &str| CheckError::BadLine {
+            path: path.display().to_string(),
+            line: i + 1,
+            reason: reason.to_string(),
+        };
+        let run: Value = serde_json::from_str(line).map_err(|e| bad_line(&e.to_string()))?;
+        let instance = run["Instance"]
+            .as_str()
+            .ok_or_else(|| bad_line("no Instance"))?;
+        runs.push(LogRun {
+            line: i + 1,
+            instance: instance.to_string(),
+            time: run["Time"].as_f64(),
+            result: run["Result"].as_f64(),
+            solution: run["Solution"].as_str().map(str::to_string),
+            gap_percent: run["GapPercent"].as_f64(),
+            depots: depot_rows(&run).ok_or_else(|| bad_line("bad DepotRows"))?,
+        });
+    }
+    Ok(runs)
+}
+
+/// The depot rows a run's line gives: every one of a multi-depot run's `DepotRows`, the `Depot`
+/// of a run with the depot in another row, or else row 0.
+fn depot_rows(run: &Value) -> Option<Vec<usize>> {
+    let row = |row: &Value| row.as_u64().and_then(|row| usize::try_from(row).ok());
+    match (&run["DepotRows"], &run["Depot"]) {
+        (Value::Array(rows), _) if !rows.is_empty() => rows.iter().map(row).collect(),
+        (Value::Null, Value::Null) => Some(vec![0]),
+        (Value::Null, depot) => row(depot).map(|depot| vec![depot]),
+        _ => None,
+    }
+}
+
+/// Check every line of the log at `logs_path`, loading each line's instance from `instance_dir`.
+/// A bad solution only fails its own report; the log or an instance not being readable fails the
+/// whole check.
+pub fn check(logs_path: &Path, instance_dir: &Path) -> Result<Vec<CheckReport>, CheckError> {
+    let mut reports = Vec::new();
+    for run in read_log(logs_path)? {
+        let (Some(result), Some(solution)) = (run.result, &run.solution) else {
+            reports.push(CheckReport {
+                instance: run.instance,
+                recomputed_cost: None,
+                violations: vec![Violation::NoSolution],
+            });
+            continue;
+        };
+        let depot_rows = run
+            .depots
+            .iter()
+            .map(|&row| u32::try_from(row).unwrap())
+            .collect::<Vec<_>>();
+        let rows = parse_depot_routes(solution, &depot_rows).map_err(|e| CheckError::BadLine {
+            path: logs_path.display().to_string(),
+            line: run.line,
+            reason: e.to_string(),
+        })?;
+        let options = ReadOptions {
+            depot: run.depots[0],
+            ..ReadOptions::default()
+        };
+        let instance =
+            VRPInstance::new(instance_dir.join(&run.instance), options).map_err(|error| {
+                CheckError::Instance {
+                    name: run.instance.clone(),
+                    error,
+                }
+            })?;
+        let depots = depot_rows
+            .iter()
+            .map(|&row| CustomerId(instance.customer_of_row(row)))
+            .collect::<Vec<_>>();
+        let routes = rows
+            .into_iter()
+            .map(|(depot, route)| {
+                let route = route.iter().map(|&row| instance.customer_of_row(row));
+                (CustomerId(instance.customer_of_row(depot)), route.collect())
+            })
+            .collect::<Vec<_>>();
+        reports.push(check_routes(
+            &run.instance,
+            &instance,
+            &depots,
+            &routes,
+            result,
+        ));
+    }
+    Ok(reports)
+}
+
+fn check_routes(
+    name: &str,
+    instance: &VRPInstance,
+    depots: &[CustomerId],
+    routes: &[(CustomerId, Vec<u32>)],
+    result: f64,
+) -> CheckReport {
+    let report = verify::verify_routes(instance, depots, routes);
+    let cost = report.cost;
+    let mut violations = report
+        .violations
+        .into_iter()
+        .map(Violation::Invalid)
+        .collect::<Vec<_>>();
+    if (cost - result).abs() > COST_TOLERANCE {
+        violations.push(Violation::CostMismatch {
+            reported: result,
+            recomputed: cost,
+        });
+    }
+
+    CheckReport {
+        instance: name.to_string(),
+        recomputed_cost: Some(cost),
+        violations,
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ok = |passed: bool| if passed { "ok" } else { "FAIL" };
+        write!(
+            f,
+            "{:<24} coverage {:<4} capacity {:<4} cost {:<4} ",
+            self.instance,
+            ok(self.coverage_ok()),
+            ok(self.capacity_ok()),
+            ok(self.cost_ok())
+        )?;
+        match self.recomputed_cost {
+            Some(cost) => write!(f, "(recomputed {cost:.2})")?,
+            None => write!(f, "(no solution)")?,
+        }
+        for violation in self
+            .violations
+            .iter()
+            .filter(|v| **v != Violation::NoSolution)
+        {
+            write!(f, "\n    {violation}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::NoSolution => write!(f, "the run didn't log a solution"),
+            Violation::CostMismatch {
+                reported,
+                recomputed,
+            } => write!(f, "reported a cost of {reported}, but it's {recomputed}"),
+            Violation::Invalid(violation) => write!(f, "{violation}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::CustomerId;
+
+    /// Check a log of runs on a three-customer instance, each given as its result and solution.
+    /// The solution `0 0 1 2 0 0 3 0` costs 3 + 5 + 4 for the first route and 4 + 4 for the
+    /// second, 20 in all.
+    fn check_runs(test: &str, runs: &[(f64, &str)]) -> Vec<CheckReport> {
+        let dir = std::env::temp_dir().join(format!("vrp-check-{}-{test}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("tiny.vrp"),
+            "4 2 10\n0 0 0\n3 0 3\n4 4 0\n5 0 -4\n",
+        )
+        .unwrap();
+        let log = runs
+            .iter()
+            .map(|(result, solution)| {
+                serde_json::json!({"Instance": "tiny.vrp", "Result": result, "Solution": solution})
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        fs::write(dir.join("results.log"), log.join("\n")).unwrap();
+        let reports = check(&dir.join("results.log"), &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        reports
+    }
+
+    #[test]
+    fn every_line_gets_a_report_even_after_a_bad_one() {
+        let dir = std::env::temp_dir().join(format!("vrp-check-{}-lines", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("tiny.vrp"),
+            "4 2 10\n0 0 0\n3 0 3\n4 4 0\n5 0 -4\n",
+        )
+        .unwrap();
+        // a failed run, a wrong total and a right one, with a blank line in between
+        let log = [
+            r#"{"Instance": "tiny.vrp", "Time": "--", "Result": "--", "Solution": "--"}"#,
+            r#"{"Instance": "tiny.vrp", "Result": 30.0, "Solution": "0 0 1 2 0 0 3 0"}"#,
+            "",
+            r#"{"Instance": "tiny.vrp", "Result": 20.0, "Solution": "0 0 1 2 0 0 3 0"}"#,
+        ];
+        fs::write(dir.join("results.log"), log.join("\n")).unwrap();
+        let reports = check(&dir.join("results.log"), &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0].violations, [Violation::NoSolution]);
+        assert_eq!(reports[0].recomputed_cost, None);
+        assert!(!reports[0].coverage_ok() && !reports[0].capacity_ok());
+        assert!(!reports[1].passed());
+        assert!(reports[2].passed());
+        assert_eq!(
+            reports[0].to_string(),
+            "tiny.vrp                 coverage FAIL capacity FAIL cost FAIL (no solution)"
+        );
+        assert_eq!(
+            reports[1].to_string(),
+            "tiny.vrp                 coverage ok   capacity ok   cost FAIL (recomputed 20.00)\n    \
+             reported a cost of 30, but it's 20"
+        );
+    }
+
+    #[test]
+    fn unreadable_logs_bad_lines_and_missing_instances_fail_the_check() {
+        let dir = std::env::temp_dir().join(format!("vrp-check-{}-errors", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("results.log");
+        assert!(matches!(
+            check(&log, &dir),
+            Err(CheckError::Unreadable { .. })
+        ));
+
+        fs::write(&log, "{\"Instance\": \"tiny.vrp\"}\nnot json\n").unwrap();
+        assert!(matches!(
+            check(&log, &dir),
+            Err(CheckError::BadLine { line: 2, .. })
+        ));
+        fs::write(&log, r#"{"Result": 1.0, "Solution": "0 1 0"}"#).unwrap();
+        assert!(matches!(
+            check(&log, &dir),
+            Err(CheckError::BadLine { line: 1, .. })
+        ));
+        fs::write(
+            &log,
+            r#"{"Instance": "tiny.vrp", "Result": 1.0, "Solution": "0 one 0"}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            check(&log, &dir),
+            Err(CheckError::BadLine { line: 1, .. })
+        ));
+        fs::write(
+            &log,
+            r#"{"Instance": "gone.vrp", "Result": 1.0, "Solution": "0 0 1 0"}"#,
+        )
+        .unwrap();
+        let error = check(&log, &dir).err().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            error,
+            CheckError::Instance {
+                name: "gone.vrp".to_string(),
+                error: crate::error::InstanceError::FileNotFound,
+            }
+        );
+    }
+
+    #[test]
+    fn the_right_total_passes() {
+        let reports = check_runs(
+            "right",
+            &[(20.0, "0 0 1 2 0 0 3 0"), (20.05, "0 0 1 2 0 0 3 0")],
+        );
+        assert!(reports.iter().all(CheckReport::passed), "{reports:?}");
+        assert_eq!(reports[0].recomputed_cost, Some(20.0));
+    }
+
+    #[test]
+    fn a_total_off_either_way_is_a_cost_mismatch() {
+        // 15 is under the total but over each route's cost, which comparing one route at a time
+        // against it wouldn't catch
+        for reported in [15.0, 19.8, 20.2, 25.0] {
+            let reports = check_runs("mismatch", &[(reported, "0 0 1 2 0 0 3 0")]);
+            assert_eq!(
+                reports[0].violations,
+                [Violation::CostMismatch {
+                    reported,
+                    recomputed: 20.0
+                }]
+            );
+            assert!(!reports[0].cost_ok() && reports[0].coverage_ok());
+        }
+    }
+
+    #[test]
+    fn a_customer_visited_twice_is_reported() {
+        // the second route goes 0 -> 3 -> 1 -> 0, 4 + 7 + 3
+        let reports = check_runs("duplicate", &[(26.0, "0 0 1 2 0 0 3 1 0")]);
+        assert_eq!(
+            reports[0].violations,
+            [Violation::Invalid(SolutionViolation::DuplicateCustomer(
+                CustomerId(1)
+            ))]
+        );
+        assert!(reports[0].cost_ok() && !reports[0].coverage_ok());
+    }
+
+    #[test]
+    fn each_kind_of_corruption_is_its_own_violation() {
+        let invalid = |v| vec![Violation::Invalid(v)];
+        for (name, result, solution, expected) in [
+            (
+                "missing",
+                12.0,
+                "0 0 1 2 0",
+                invalid(SolutionViolation::MissingCustomer(CustomerId(3))),
+            ),
+            (
+                "over",
+                12.0 + 32_f64.sqrt(),
+                "0 0 1 2 3 0",
+                invalid(SolutionViolation::OverCapacity {
+                    route: 0,
+                    load: 12,
+                    cap: 10,
+                }),
+            ),
+            (
+                "unknown",
+                20.0,
+                "0 0 1 2 0 0 3 7 0",
+                invalid(SolutionViolation::UnknownCustomer {
+                    route: 1,
+                    customer: 7,
+                }),
+            ),
+            (
+                "routes",
+                22.0,
+                "0 0 1 0 0 2 0 0 3 0",
+                invalid(SolutionViolation::TooManyRoutes {
+                    routes: 3,
+                    vehicles: 2,
+                }),
+            ),
+        ] {
+            let reports = check_runs(name, &[(result, solution)]);
+            assert_eq!(reports[0].violations, expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn a_solution_wrong_every_way_reports_it_all() {
+        // 1 twice, 2 missing, and 3 + 5 + 3 = 11 in one route
+        let reports = check_runs("everything", &[(1.0, "0 0 1 3 1 0")]);
+        let violations = &reports[0].violations;
+        for violation in [
+            Violation::Invalid(SolutionViolation::DuplicateCustomer(CustomerId(1))),
+            Violation::Invalid(SolutionViolation::MissingCustomer(CustomerId(2))),
+            Violation::Invalid(SolutionViolation::OverCapacity {
+                route: 0,
+                load: 11,
+                cap: 10,
+            }),
+        ] {
+            assert!(violations.contains(&violation), "{violations:?}");
+        }
+        assert!(matches!(
+            violations.last(),
+            Some(Violation::CostMismatch { reported: 1.0, .. })
+        ));
+        let report = &reports[0];
+        assert!(!report.coverage_ok() && !report.capacity_ok() && !report.cost_ok());
+        // one line for the flags and one for each violation
+        assert_eq!(report.to_string().lines().count(), 1 + violations.len());
+    }
+
+    #[test]
+    fn multi_depot_runs_are_checked_against_each_routes_depot() {
+        let dir = std::env::temp_dir().join(format!("vrp-check-{}-depots", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // a depot at each end of a line, with two customers next to each; row 3 is the instance's
+        // own depot, and row 0 the extra one
+        fs::write(
+            dir.join("line.vrp"),
+            "6 2 10\n0 0 0\n1 1 0\n2 2 0\n0 100 0\n3 99 0\n4 98 0\n",
+        )
+        .unwrap();
+        let log = [
+            r#"{"Instance": "line.vrp", "Result": 8.0, "Solution": "0 3 4 5 3 0 1 2 0", "DepotRows": [3, 0]}"#,
+            // the same customers, each from the other depot
+            r#"{"Instance": "line.vrp", "Result": 8.0, "Solution": "0 3 1 2 3 0 4 5 0", "DepotRows": [3, 0]}"#,
+        ];
+        fs::write(dir.join("results.log"), log.join("\n")).unwrap();
+        let reports = check(&dir.join("results.log"), &dir).unwrap();
+        // a route from a row that isn't a depot
+        fs::write(
+            dir.join("results.log"),
+            r#"{"Instance": "line.vrp", "Result": 8.0, "Solution": "0 1 2 1 0 4 5 0", "DepotRows": [3, 0]}"#,
+        )
+        .unwrap();
+        let not_from_a_depot = check(&dir.join("results.log"), &dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(reports[0].passed(), "{}", reports[0]);
+        assert_eq!(reports[0].recomputed_cost, Some(8.0));
+        assert!(reports[1].coverage_ok() && reports[1].capacity_ok());
+        assert_eq!(
+            reports[1].violations,
+            [Violation::CostMismatch {
+                reported: 8.0,
+                recomputed: 2.0 * (98.0 + 1.0 + 99.0)
+            }]
+        );
+        assert!(matches!(
+            not_from_a_depot,
+            Err(CheckError::BadLine { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,517 @@
+use std::{str::FromStr, time::Duration};
+
+use crate::{
+    acceptance::Acceptance,
+    common::{DistancePrecision, SolFormat},
+    generate::Layout,
+    objective::Objective,
+    phases::{SolverChoice, parse_phases},
+    vrp_instance::{DistanceRounding, Metric},
+};
+
+/// Seed used by `--deterministic` when no `--seed` is given.
+pub const DEFAULT_SEED: u64 = 2951;
+
+/// Command line options for the solver binary.
+#[derive(Debug, Default, Clone)]
+pub struct CliArgs {
+    pub instance_path: String,
+    /// single worker, fixed seed, and an iteration budget instead of a time budget
+    pub deterministic: bool,
+    pub seed: Option<u64>,
+    /// stop after this many iterations (takes precedence over any time budget)
+    pub iters: Option<usize>,
+    pub time_limit: Option<Duration>,
+    /// solve this many times with different seeds, each with the full budget
+    pub repeats: Option<usize>,
+    /// where to write the .sol file (defaults to `./<instance file name>.sol`)
+    pub out: Option<String>,
+    /// how many sub-solves to run at once (defaults to the number of physical cores)
+    pub threads: Option<usize>,
+    /// where routes finish, if not back at the depot
+    pub end_location: Option<(f64, f64)>,
+    /// report this many of the customers that are most expensive to serve
+    pub problem_customers: Option<usize>,
+    /// cut down the number of threads to (roughly) stay under this much memory
+    pub memory_budget_mb: Option<usize>,
+    /// run these solvers one after another, each for its fraction of the budget
+    pub phases: Option<Vec<(f64, SolverChoice)>>,
+    /// start each printed route from its end nearer the depot
+    pub orient_output: bool,
+    /// log the search's progress to stderr every second
+    pub progress: bool,
+    /// where to write a JSON line for every new best solution, `-` for stderr
+    pub improvement_log: Option<String>,
+    pub objective: Objective,
+    /// when to carry on from a solution that's no better than the one before
+    pub acceptance: Acceptance,
+    /// (window, minimum distinct solutions) for restarting when the search goes in circles
+    pub diversity: Option<(usize, usize)>,
+    /// how many iterations repair avoids recreating just-broken edges for
+    pub edge_tabu: Option<usize>,
+    /// edges (pairs of customer numbers, or the end location's index) no route may use
+    pub forbidden_edges: Vec<(u32, u32)>,
+    /// how long to spend choosing a destroy size before the real search
+    pub calibration: Option<Duration>,
+    /// how to round distances, e.g. to compare with published costs
+    pub rounding: DistanceRounding,
+    /// how to measure distances between coordinates
+    pub metric: Metric,
+    /// f32 for instances whose distance matrix barely fits in memory
+    pub distance_precision: DistancePrecision,
+    /// keep the distance matrix in a `.dmat` file next to the instance, to skip working it out
+    pub distance_cache: bool,
+    /// above this many nodes, work distances out as they're needed instead of keeping a matrix
+    pub matrix_limit: Option<usize>,
+    /// which rows of the instance file are depots, the first being the instance's own (and the
+    /// rest, if any, splitting the instance between them, see [`crate::multi_depot`])
+    pub depots: Vec<usize>,
+    /// reject instance files with a nonzero depot demand instead of warning and zeroing it
+    pub strict_input: bool,
+    /// JSON file with the ALNS solver's settings
+    pub alns_config: Option<String>,
+    /// print the settings the run would use and where each came from, instead of solving
+    pub explain: bool,
+    /// print a summary of the instance (see [`crate::stats`]) instead of solving
+    pub stats: bool,
+    /// search in snapshotted rounds for this long, checking each round's result
+    pub soak: Option<Duration>,
+    /// where soak snapshots go (defaults to `./<instance file name>.soak`)
+    pub soak_dir: Option<String>,
+    /// rerun the soak round this snapshot was taken before
+    pub soak_replay: Option<String>,
+    /// solve every instance file in this folder in turn, instead of `instance_path`
+    pub batch: Option<String>,
+    /// `.sol` file of a solution to start searching from, instead of constructing one
+    pub warm_start: Option<String>,
+    /// patch up an infeasible `warm_start` instead of ignoring it
+    pub repair_warm_start: bool,
+    /// write each route's load and distance into the `.sol` file as comment lines
+    pub sol_details: bool,
+    /// how to write the solution file
+    pub sol_format: SolFormat,
+    /// where to write the solution as GeoJSON, for drawing it on a map
+    pub geojson: Option<String>,
+    /// where to write a drawing of the solution as SVG
+    pub svg: Option<String>,
+    /// where to write the solution as CSV, a row per stop
+    pub csv: Option<String>,
+    /// give each route in the CSV a last row for the leg back to the depot
+    pub csv_return_leg: bool,
+    /// results log to check against the instances in `instance_path` (a folder, with this),
+    /// instead of solving
+    pub check: Option<String>,
+    /// two solutions (`.sol` files, or what the `Solution` field holds) to compare on the
+    /// instance, instead of solving
+    pub diff: Option<(String, String)>,
+    /// an old and a new results log to compare instance by instance, instead of solving
+    pub compare: Option<(String, String)>,
+    /// with `compare`, fail if the results got worse by more than this many percent on average
+    pub regression_threshold: f64,
+    /// search a copy of the instance scaled into the unit box, reporting costs in the original units
+    pub normalize: bool,
+    /// reuse (and save) results in this folder, see [`crate::cache`]
+    pub cache: Option<String>,
+    /// the best known solution's cost, or a CSV of them per instance
+    pub bks: Option<String>,
+    /// stop once within this many percent of `bks`
+    pub bks_gap: f64,
+    /// (customers, vehicles, capacity, layout) of an instance to write to the instance path
+    /// instead of solving
+    pub generate: Option<(usize, usize, usize, Layout)>,
+    /// write a shareable copy of the instance here instead of solving it
+    pub anonymize: Option<String>,
+    /// what the instance's distances are measured in, for the report
+    pub distance_unit: Option<String>,
+    /// what one unit of distance costs, for the report
+    pub cost_per_unit: Option<f64>,
+}
+
+impl CliArgs {
+    /// Parse the arguments after the program name.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut res = CliArgs::default();
+        let mut instance_path = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--deterministic" => res.deterministic = true,
+                "--orient-output" => res.orient_output = true,
+                "--progress" => res.progress = true,
+                "--improvement-log" => res.improvement_log = Some(value(arg, args.next())?),
+                "--strict-input" => res.strict_input = true,
+                "--explain" => res.explain = true,
+                "--stats" => res.stats = true,
+                "--normalize" => res.normalize = true,
+                "--csv-return-leg" => res.csv_return_leg = true,
+                "--sol-details" => res.sol_details = true,
+                "--repair-warm-start" => res.repair_warm_start = true,
+                "--seed" => res.seed = Some(value(arg, args.next())?),
+                "--iters" => res.iters = Some(value(arg, args.next())?),
+                "--repeats" => res.repeats = Some(value(arg, args.next())?),
+                "--alns-config" => res.alns_config = Some(value(arg, args.next())?),
+                "--out" => res.out = Some(value(arg, args.next())?),
+                "--threads" => res.threads = Some(value(arg, args.next())?),
+                "--time" => {
+                    res.time_limit = Some(duration(arg, args.next(), 1.0)?);
+                }
+                "--problem-customers" => res.problem_customers = Some(value(arg, args.next())?),
+                "--memory-budget" => res.memory_budget_mb = Some(value(arg, args.next())?),
+                "--phases" => {
+                    let value = args.next().ok_or("--phases needs a value")?;
+                    res.phases = Some(parse_phases(value)?);
+                }
+                "--objective" => res.objective = value(arg, args.next())?,
+                "--acceptance" => {
+                    let value = args.next().ok_or("--acceptance needs a value")?;
+                    res.acceptance = value.parse()?;
+                }
+                "--format" => {
+                    let value = args.next().ok_or("--format needs a value")?;
+                    res.sol_format = value.parse()?;
+                }
+                "--rounding" => {
+                    let value = args.next().ok_or("--rounding needs a value")?;
+                    res.rounding = value.parse()?;
+                }
+                "--depot" => {
+                    let value = args.next().ok_or("--depot needs a value")?;
+                    res.depots = rows(value)?;
+                }
+                "--f32-distances" => res.distance_precision = DistancePrecision::F32,
+                "--distance-cache" => res.distance_cache = true,
+                "--matrix-limit" => res.matrix_limit = Some(value(arg, args.next())?),
+                "--metric" => {
+                    let value = args.next().ok_or("--metric needs a value")?;
+                    res.metric = value.parse()?;
+                }
+                "--edge-tabu" => res.edge_tabu = Some(value(arg, args.next())?),
+                "--diversity" => {
+                    let value = args.next().ok_or("--diversity needs a value")?;
+                    res.diversity = Some(window_and_k(value)?);
+                }
+                "--end-location" => {
+                    let value = args.next().ok_or("--end-location needs a value")?;
+                    res.end_location = Some(coords(value)?);
+                }
+                "--soak" => res.soak = Some(duration(arg, args.next(), 60.0)?),
+                "--bks" => res.bks = Some(value(arg, args.next())?),
+                "--bks-gap" => res.bks_gap = value(arg, args.next())?,
+                "--distance-unit" => res.distance_unit = Some(value(arg, args.next())?),
+                "--cost-per-unit" => res.cost_per_unit = Some(value(arg, args.next())?),
+                "--generate" => {
+                    let value = args.next().ok_or("--generate needs a value")?;
+                    res.generate = Some(generate_spec(value)?);
+                }
+                "--anonymize" => res.anonymize = Some(value(arg, args.next())?),
+                "--cache" => res.cache = Some(value(arg, args.next())?),
+                "--soak-dir" => res.soak_dir = Some(value(arg, args.next())?),
+                "--soak-replay" => res.soak_replay = Some(value(arg, args.next())?),
+                "--batch" => res.batch = Some(value(arg, args.next())?),
+                "--warm-start" => res.warm_start = Some(value(arg, args.next())?),
+                "--check" => res.check = Some(value(arg, args.next())?),
+                "--diff" => {
+                    let value = args.next().ok_or("--diff needs a value")?;
+                    let (a, b) = value.split_once(',').ok_or_else(|| {
+                        format!("invalid value {value:?} for --diff, expected A,B")
+                    })?;
+                    res.diff = Some((a.to_string(), b.to_string()));
+                }
+                "--compare" => {
+                    let value = args.next().ok_or("--compare needs a value")?;
+                    let (old, new) = value.split_once(',').ok_or_else(|| {
+                        format!("invalid value {value:?} for --compare, expected OLD,NEW")
+                    })?;
+                    res.compare = Some((old.to_string(), new.to_string()));
+                }
+                "--regression-threshold" => {
+                    res.regression_threshold = value(arg, args.next())?;
+                }
+                "--geojson" => res.geojson = Some(value(arg, args.next())?),
+                "--svg" => res.svg = Some(value(arg, args.next())?),
+                "--csv" => res.csv = Some(value(arg, args.next())?),
+                "--calibrate" => res.calibration = Some(duration(arg, args.next(), 1.0)?),
+                "--forbid" => {
+                    let value = args.next().ok_or("--forbid needs a value")?;
+                    res.forbidden_edges = edges(value)?;
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown flag {flag}")),
+                path => {
+                    if instance_path.replace(path.to_string()).is_some() {
+                        return Err(format!("unexpected extra argument {path}"));
+                    }
+                }
+            }
+        }
+
+        if res.compare.is_some() {
+            if res.batch.is_some() {
+                return Err("--compare can't be combined with --batch".to_string());
+            }
+            if let Some(path) = instance_path {
+                return Err(format!("unexpected instance file {path} with --compare"));
+            }
+            return Ok(res);
+        }
+        match (&res.batch, instance_path) {
+            (None, path) => res.instance_path = path.ok_or("no instance file given")?,
+            (Some(_), Some(path)) => {
+                return Err(format!("unexpected instance file {path} with --batch"));
+            }
+            (Some(_), None) => {}
+        }
+        if res.repeats == Some(0) {
+            return Err("--repeats needs to be at least 1".to_string());
+        }
+        if res.depots.len() > 1
+            && (res.repeats.is_some()
+                || res.soak.is_some()
+                || res.soak_replay.is_some()
+                || res.cache.is_some())
+        {
+            return Err(
+                "more than one --depot can't be combined with --repeats, --soak, --soak-replay or \
+                 --cache"
+                    .to_string(),
+            );
+        }
+        if res.normalize
+            && (res.depots.len() > 1 || res.soak.is_some() || res.soak_replay.is_some())
+        {
+            return Err(
+                "--normalize can't be combined with more than one --depot, --soak or --soak-replay"
+                    .to_string(),
+            );
+        }
+        for (flag, path) in [
+            ("--geojson", &res.geojson),
+            ("--svg", &res.svg),
+            ("--csv", &res.csv),
+        ] {
+            if path.is_some() && (res.depots.len() > 1 || res.batch.is_some()) {
+                return Err(format!(
+                    "{flag} can't be combined with more than one --depot or --batch"
+                ));
+            }
+        }
+        if res.check.is_some() && res.batch.is_some() {
+            return Err("--check can't be combined with --batch".to_string());
+        }
+        if (res.sol_details || res.sol_format != SolFormat::Sol) && res.depots.len() > 1 {
+            return Err(
+                "--sol-details and --format can't be combined with more than one --depot"
+                    .to_string(),
+            );
+        }
+        if res.sol_details && res.sol_format != SolFormat::Sol {
+            return Err("--sol-details only goes with --format sol".to_string());
+        }
+        if res.improvement_log.is_some() && (res.depots.len() > 1 || res.batch.is_some()) {
+            return Err(
+                "--improvement-log can't be combined with more than one --depot or --batch"
+                    .to_string(),
+            );
+        }
+        if res.diff.is_some() && (res.depots.len() > 1 || res.batch.is_some()) {
+            return Err(
+                "--diff can't be combined with more than one --depot or --batch".to_string(),
+            );
+        }
+        if res.repair_warm_start && res.warm_start.is_none() {
+            return Err("--repair-warm-start needs --warm-start".to_string());
+        }
+        if res.warm_start.is_some() && (res.depots.len() > 1 || res.batch.is_some()) {
+            return Err(
+                "--warm-start can't be combined with more than one --depot or --batch".to_string(),
+            );
+        }
+        if res.batch.is_some()
+            && (res.out.is_some()
+                || res.generate.is_some()
+                || res.anonymize.is_some()
+                || res.soak.is_some()
+                || res.soak_replay.is_some())
+        {
+            return Err(
+                "--batch can't be combined with --out, --generate, --anonymize, --soak or \
+                 --soak-replay"
+                    .to_string(),
+            );
+        }
+        Ok(res)
+    }
+
+    /// Everything that can change what a run finds, leaving out where the input and output go.
+    pub fn result_affecting(&self) -> String {
+        let relevant = CliArgs {
+            instance_path: String::new(),
+            out: None,
+            progress: false,
+            improvement_log: None,
+            explain: false,
+            stats: false,
+            cache: None,
+            soak_dir: None,
+            geojson: None,
+            svg: None,
+            csv: None,
+            csv_return_leg: false,
+            sol_details: false,
+            sol_format: SolFormat::Sol,
+            ..self.clone()
+        };
+        format!("{relevant:?}")
+    }
+
+    /// The seed to run with, if the run should be reproducible.
+    pub fn effective_seed(&self) -> Option<u64> {
+        self.seed.or(self.deterministic.then_some(DEFAULT_SEED))
+    }
+}
+
+fn value<T: FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or(format!("{flag} needs a value"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {value:?} for {flag}"))
+}
+
+/// A flag's value as a duration, given in units of `unit_secs` seconds. It has to be a
+/// non-negative number small enough for a [`Duration`].
+fn duration(flag: &str, value: Option<&String>, unit_secs: f64) -> Result<Duration, String> {
+    let amount: f64 = self::value(flag, value)?;
+    Duration::try_from_secs_f64(amount * unit_secs)
+        .map_err(|_| format!("invalid value {amount} for {flag}, expected a non-negative number"))
+}
+
+fn coords(value: &str) -> Result<(f64, f64), String> {
+    let err = || format!("invalid value {value:?} for --end-location, expected X,Y");
+    let (x, y) = value.split_once(',').ok_or_else(err)?;
+    Ok((
+        x.trim().parse().map_err(|_| err())?,
+        y.trim().parse().map_err(|_| err())?,
+    ))
+}
+
+fn rows(value: &str) -> Result<Vec<usize>, String> {
+    value
+        .split(',')
+        .map(|row| {
+            row.trim()
+                .parse()
+                .map_err(|_| format!("invalid value {value:?} for --depot, expected ROW,ROW,..."))
+        })
+        .collect()
+}
+
+fn window_and_k(value: &str) -> Result<(usize, usize), String> {
+    let err = || format!("invalid value {value:?} for --diversity, expected WINDOW:K");
+    let (window, k) = value.split_once(':').ok_or_else(err)?;
+    Ok((
+        window.trim().parse().map_err(|_| err())?,
+        k.trim().parse().map_err(|_| err())?,
+    ))
+}
+
+fn generate_spec(value: &str) -> Result<(usize, usize, usize, Layout), String> {
+    let err = || {
+        format!(
+            "invalid value {value:?} for --generate, expected CUSTOMERS,VEHICLES,CAPACITY,LAYOUT"
+        )
+    };
+    let [customers, vehicles, capacity, layout] = value
+        .split(',')
+        .collect::<Vec<_>>()
+        .try_into()
+        .map_err(|_| err())?;
+    let [customers, vehicles, capacity] =
+        [customers, vehicles, capacity].map(|n| n.trim().parse::<usize>());
+    let (customers, vehicles, capacity) = (
+        customers.map_err(|_| err())?,
+        vehicles.map_err(|_| err())?,
+        capacity.map_err(|_| err())?,
+    );
+    if vehicles == 0 || capacity < customers.div_ceil(vehicles) {
+        return Err(format!(
+            "{vehicles} vehicles of capacity {capacity} can't serve {customers} customers"
+        ));
+    }
+    Ok((customers, vehicles, capacity, layout.trim().parse()?))
+}
+
+fn edges(value: &str) -> Result<Vec<(u32, u32)>, String> {
+    let err = || format!("invalid value {value:?} for --forbid, expected A-B,C-D,...");
+    value
+        .split(',')
+        .map(|edge| {
+            let (a, b) = edge.split_once('-').ok_or_else(err)?;
+            Ok((
+                a.trim().parse().map_err(|_| err())?,
+                b.trim().parse().map_err(|_| err())?,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliArgs, String> {
+        let args = args.iter().map(ToString::to_string).collect::<Vec<_>>();
+        CliArgs::parse(&args)
+    }
+
+    #[test]
+    fn threads_are_a_count() {
+        assert_eq!(parse(&["a.vrp"]).unwrap().threads, None);
+        assert_eq!(
+            parse(&["a.vrp", "--threads", "4"]).unwrap().threads,
+            Some(4)
+        );
+        for bad in ["-1", "two", "1.5"] {
+            let err = parse(&["a.vrp", "--threads", bad]).unwrap_err();
+            assert!(err.contains("--threads"), "{bad}: {err}");
+        }
+        assert!(parse(&["a.vrp", "--threads"]).is_err());
+    }
+
+    #[test]
+    fn time_is_in_seconds() {
+        let cli = parse(&["a.vrp", "--time", "1.5"]).unwrap();
+        assert_eq!(cli.time_limit, Some(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn time_must_be_a_usable_duration() {
+        for bad in ["-1", "NaN", "inf", "1e300"] {
+            let err = parse(&["a.vrp", "--time", bad]).unwrap_err();
+            assert!(err.contains("--time"), "{bad}: {err}");
+        }
+    }
+
+    #[test]
+    fn soak_is_in_minutes() {
+        let cli = parse(&["a.vrp", "--soak", "0.5"]).unwrap();
+        assert_eq!(cli.soak, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn soak_must_be_a_usable_duration() {
+        for bad in ["-1", "NaN", "inf", "1e300"] {
+            let err = parse(&["a.vrp", "--soak", bad]).unwrap_err();
+            assert!(err.contains("--soak"), "{bad}: {err}");
+        }
+    }
+
+    #[test]
+    fn calibrate_must_be_a_usable_duration() {
+        let cli = parse(&["a.vrp", "--calibrate", "2"]).unwrap();
+        assert_eq!(cli.calibration, Some(Duration::from_secs(2)));
+        for bad in ["-1", "NaN", "inf", "1e300"] {
+            let err = parse(&["a.vrp", "--calibrate", bad]).unwrap_err();
+            assert!(err.contains("--calibrate"), "{bad}: {err}");
+        }
+    }
+}
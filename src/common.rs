@@ -8,53 +8,562 @@ impl Eq for Stop {}
 
 use std::{
     cmp::{max, min},
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     fmt::Write,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, Read},
+    str::FromStr,
     sync::Arc,
 };
 
-use crate::vrp_instance::VRPInstance;
+#[cfg(debug_assertions)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::{SolutionParseError, SolutionViolation, VrpError};
+use crate::ids::{CustomerId, RouteIdx};
+use crate::verify;
+use crate::vrp_instance::{DistanceRounding, Metric, VRPInstance};
 
 #[macro_export]
 macro_rules! dbg_println {
     ($($arg:tt)*) => (if false { println!($($arg)*); });
 }
 
-pub struct DistanceMatrix(&'static mut [&'static mut [f64]]);
+/// The distance of an edge no route may use. Inserting across one is infeasible, but a destroy can
+/// still leave one behind for a moment, so it's a (very) large finite penalty rather than infinity
+/// to keep the cost arithmetic sane until repair or the acceptance test gets rid of it.
+pub const FORBIDDEN: f64 = 1e9;
+
+/// How a `.sol` file with comment lines starts, followed by [`SOL_VERSION`].
+const SOL_HEADER: &str = "# vrp-sol";
+
+/// The newest `.sol` format [`VRPSolution::from_file_string`] can read. Files without a header
+/// are version 1, which has no comment lines.
+const SOL_VERSION: u32 = 2;
+
+/// How the solution file is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SolFormat {
+    /// see [`VRPSolution::to_file_string`]
+    #[default]
+    Sol,
+    /// see [`VRPSolution::to_cvrplib_string`]
+    Cvrplib,
+}
+
+impl FromStr for SolFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sol" => Ok(SolFormat::Sol),
+            "cvrplib" => Ok(SolFormat::Cvrplib),
+            _ => Err(format!("unknown format {s:?}, expected sol or cvrplib")),
+        }
+    }
+}
+
+/// How a [`DistanceMatrix`] stores its distances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum DistancePrecision {
+    #[default]
+    F64,
+    /// half the memory, for instances whose matrix barely fits, with every distance off by at most
+    /// about one part in 17 million
+    F32,
+}
+
+impl DistancePrecision {
+    /// How much memory each distance takes.
+    pub fn bytes(self) -> usize {
+        match self {
+            DistancePrecision::F64 => size_of::<f64>(),
+            DistancePrecision::F32 => size_of::<f32>(),
+        }
+    }
+}
+
+/// Symmetric matrices of at least this many nodes keep only one triangle, half the memory of full
+/// rows (which would take 200 MB of `f64`s here). Below it they keep full rows anyway, since
+/// looking a distance up in a triangle is 5-10% slower.
+pub const TRIANGLE_MIN_NODES: usize = 5_000;
+
+/// The distance between every pair of nodes, stored as a triangle when they're the same both ways
+/// (as they always are between coordinates) and there are enough of them for the memory to matter,
+/// or for instances too big to store them at all, worked out from the coordinates whenever they're
+/// needed.
+pub struct DistanceMatrix {
+    source: DistanceSource,
+    /// `source`'s cells when they're full `f64` rows, the default, and null otherwise. `dist` reads
+    /// these straight off: matching on `source` in every call kept LLVM from hoisting the match out
+    /// of the search's loops, and made a 1,000 customer search about 25% slower.
+    full: *const f64,
+    len: usize,
+}
+
+// SAFETY: `full` only ever points into `source`, which is `Send` and `Sync` itself, and is reset
+//         whenever `source` changes
+unsafe impl Send for DistanceMatrix {}
+unsafe impl Sync for DistanceMatrix {}
+
+enum DistanceSource {
+    F64(Storage<f64>),
+    F32(Storage<f32>),
+    Computed(Box<Computed>),
+}
+
+/// Coordinates to work out distances from on demand.
+struct Computed {
+    points: Box<[(f64, f64)]>,
+    metric: Metric,
+    rounding: DistanceRounding,
+    /// `(lo, hi)` pairs that are [`FORBIDDEN`], few enough to just look through
+    forbidden: Vec<(usize, usize)>,
+}
+
+impl Computed {
+    /// Plain Euclidean distances, by far the most common, are worked out inline; anything else
+    /// goes through [`Computed::get_slow`] so `DistanceMatrix::dist` stays small enough to inline.
+    #[inline]
+    fn get(&self, a: usize, b: usize) -> f64 {
+        if self.forbidden.is_empty()
+            && self.metric == Metric::Euclidean
+            && self.rounding == DistanceRounding::Exact
+        {
+            // SAFETY: `DistanceMatrix::dist` checks `a` and `b` are in range in debug builds
+            let ((x1, y1), (x2, y2)) =
+                unsafe { (*self.points.get_unchecked(a), *self.points.get_unchecked(b)) };
+            return ((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt();
+        }
+        self.get_slow(a, b)
+    }
+
+    #[inline(never)]
+    fn get_slow(&self, a: usize, b: usize) -> f64 {
+        if self.forbidden.contains(&(a.min(b), a.max(b))) {
+            return FORBIDDEN;
+        }
+        self.between(a, b)
+    }
+
+    /// The distance between `a` and `b`, forbidden or not.
+    #[inline]
+    fn between(&self, a: usize, b: usize) -> f64 {
+        // SAFETY: `DistanceMatrix::dist` checks `a` and `b` are in range in debug builds
+        let (p, q) = unsafe { (*self.points.get_unchecked(a), *self.points.get_unchecked(b)) };
+        self.rounding.apply(self.metric.distance(p, q))
+    }
+}
+
+enum Storage<T> {
+    /// every row, one after another in one allocation, so `(a, b)` is at `a * len + b`
+    Full { len: usize, cells: Box<[T]> },
+    /// `(a, b)` for `a >= b` only, the rows one after another in one allocation. It's the lower
+    /// triangle so row `a` starts at `a * (a + 1) / 2`, without needing the number of nodes.
+    Triangle { len: usize, cells: Box<[T]> },
+}
+
+/// A distance as it's stored, and as it's written to and read from a file.
+trait Cell: Copy {
+    const BYTES: usize;
+    fn write_le(self, out: &mut Vec<u8>);
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+impl Cell for f64 {
+    const BYTES: usize = size_of::<f64>();
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend(self.to_le_bytes());
+    }
+    fn read_le(bytes: &[u8]) -> Self {
+        f64::from_le_bytes(bytes.try_into().unwrap())
+    }
+}
+
+impl Cell for f32 {
+    const BYTES: usize = size_of::<f32>();
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend(self.to_le_bytes());
+    }
+    fn read_le(bytes: &[u8]) -> Self {
+        f32::from_le_bytes(bytes.try_into().unwrap())
+    }
+}
+
+/// Where `(a, b)` is in a [`Storage::Triangle`].
+#[inline]
+fn triangle_index(a: usize, b: usize) -> usize {
+    let (hi, lo) = if a >= b { (a, b) } else { (b, a) };
+    hi * (hi + 1) / 2 + lo
+}
+
+impl<T: Cell> Storage<T> {
+    fn from_fn(len: usize, triangle: bool, distance: impl Fn(usize, usize) -> T) -> Self {
+        if triangle {
+            Storage::Triangle {
+                len,
+                cells: (0..len)
+                    .flat_map(|a| (0..=a).map(move |b| (a, b)))
+                    .map(|(a, b)| distance(a, b))
+                    .collect(),
+            }
+        } else {
+            Storage::Full {
+                len,
+                cells: (0..len)
+                    .flat_map(|a| (0..len).map(move |b| (a, b)))
+                    .map(|(a, b)| distance(a, b))
+                    .collect(),
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Storage::Full { len, .. } | Storage::Triangle { len, .. } => *len,
+        }
+    }
+
+    #[inline]
+    fn get(&self, a: usize, b: usize) -> T {
+        // SAFETY: `DistanceMatrix::dist` checks `a` and `b` are in range in debug builds, and
+        //         every cell of a triangle from `a, b < len` is inside it
+        match self {
+            Storage::Full { len, cells } => unsafe { *cells.get_unchecked(a * len + b) },
+            Storage::Triangle { cells, .. } => unsafe {
+                *cells.get_unchecked(triangle_index(a, b))
+            },
+        }
+    }
+
+    /// Set the distance between `a` and `b` in both directions.
+    fn set(&mut self, a: usize, b: usize, value: T) {
+        match self {
+            Storage::Full { len, cells } => {
+                (cells[a * *len + b], cells[b * *len + a]) = (value, value)
+            }
+            Storage::Triangle { cells, .. } => cells[triangle_index(a, b)] = value,
+        }
+    }
+
+    /// The same distances as another type.
+    fn map<U>(self, f: impl Fn(T) -> U) -> Storage<U> {
+        match self {
+            Storage::Full { len, cells } => Storage::Full {
+                len,
+                cells: cells.iter().map(|&d| f(d)).collect(),
+            },
+            Storage::Triangle { len, cells } => Storage::Triangle {
+                len,
+                cells: cells.iter().map(|&d| f(d)).collect(),
+            },
+        }
+    }
+
+    fn write_to(&self, out: &mut impl io::Write) -> io::Result<()> {
+        let mut buf = Vec::new();
+        let mut write = |chunk: &[T]| {
+            buf.clear();
+            for &d in chunk {
+                d.write_le(&mut buf);
+            }
+            out.write_all(&buf)
+        };
+        match self {
+            Storage::Full { len, cells } => {
+                (0..*len).try_for_each(|a| write(&cells[a * len..][..*len]))
+            }
+            Storage::Triangle { len, cells } => {
+                (0..*len).try_for_each(|a| write(&cells[triangle_index(a, 0)..][..=a]))
+            }
+        }
+    }
+
+    fn read_from(input: &mut impl Read, len: usize, triangle: bool) -> io::Result<Self> {
+        let mut buf = Vec::new();
+        let mut read = |cells: usize, into: &mut Vec<T>| {
+            buf.resize(cells * T::BYTES, 0);
+            input.read_exact(&mut buf)?;
+            into.extend(buf.chunks_exact(T::BYTES).map(T::read_le));
+            io::Result::Ok(())
+        };
+        if triangle {
+            let mut cells = Vec::with_capacity(triangle_index(len, 0));
+            for a in 0..len {
+                read(a + 1, &mut cells)?;
+            }
+            Ok(Storage::Triangle {
+                len,
+                cells: cells.into_boxed_slice(),
+            })
+        } else {
+            let mut cells = Vec::with_capacity(len * len);
+            for _ in 0..len {
+                read(len, &mut cells)?;
+            }
+            Ok(Storage::Full {
+                len,
+                cells: cells.into_boxed_slice(),
+            })
+        }
+    }
+}
 
 impl DistanceMatrix {
+    /// Distances given explicitly, e.g. travel times over a road network. They're stored as a
+    /// triangle if they turn out to be the same both ways, and there are enough of them.
     pub fn new(vec: Vec<Vec<f64>>) -> Self {
-        let v = vec
-            .into_iter()
-            .map(std::vec::Vec::leak)
-            .collect::<Vec<_>>()
-            .leak();
+        let symmetric = (0..vec.len()).all(|a| (0..a).all(|b| vec[a][b] == vec[b][a]));
+        DistanceMatrix::of(DistanceSource::F64(Storage::from_fn(
+            vec.len(),
+            Self::stores_triangle(vec.len(), symmetric),
+            |a, b| vec[a][b],
+        )))
+    }
+
+    fn of(source: DistanceSource) -> Self {
+        let len = match &source {
+            DistanceSource::F64(storage) => storage.len(),
+            DistanceSource::F32(storage) => storage.len(),
+            DistanceSource::Computed(computed) => computed.points.len(),
+        };
+        let mut matrix = DistanceMatrix {
+            source,
+            full: std::ptr::null(),
+            len,
+        };
+        matrix.point_at_full_rows();
+        matrix
+    }
+
+    /// Set `full` for whatever `source` is now. Has to be called after every change to it.
+    fn point_at_full_rows(&mut self) {
+        self.full = match &self.source {
+            DistanceSource::F64(Storage::Full { cells, .. }) => cells.as_ptr(),
+            _ => std::ptr::null(),
+        };
+    }
+
+    /// Whether a matrix of `len` nodes is kept as a triangle, see [`TRIANGLE_MIN_NODES`].
+    pub fn stores_triangle(len: usize, symmetric: bool) -> bool {
+        symmetric && len >= TRIANGLE_MIN_NODES
+    }
+
+    /// `distance(from, to)` between every pair of `len` nodes, which has to be the same both ways,
+    /// stored at `precision` as they're worked out, so an `f32` matrix never needs the memory of an
+    /// `f64` one.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn from_fn(
+        len: usize,
+        precision: DistancePrecision,
+        distance: impl Fn(usize, usize) -> f64,
+    ) -> Self {
+        let triangle = Self::stores_triangle(len, true);
+        DistanceMatrix::of(match precision {
+            DistancePrecision::F64 => {
+                DistanceSource::F64(Storage::from_fn(len, triangle, distance))
+            }
+            DistancePrecision::F32 => {
+                DistanceSource::F32(Storage::from_fn(len, triangle, |a, b| {
+                    distance(a, b) as f32
+                }))
+            }
+        })
+    }
+
+    /// Distances between `points` worked out as they're asked for instead of stored, so they take
+    /// next to no memory but cost a (rounded) `metric` distance each time.
+    pub fn computed(points: Vec<(f64, f64)>, metric: Metric, rounding: DistanceRounding) -> Self {
+        DistanceMatrix::of(DistanceSource::Computed(Box::new(Computed {
+            points: points.into_boxed_slice(),
+            metric,
+            rounding,
+            forbidden: Vec::new(),
+        })))
+    }
+
+    /// Roughly how much memory a matrix of `len` nodes takes, stored as a triangle or not.
+    pub fn bytes_for(len: usize, precision: DistancePrecision, triangle: bool) -> usize {
+        if triangle {
+            len * (len + 1) / 2 * precision.bytes()
+        } else {
+            len * len * precision.bytes() + len * size_of::<Box<[f64]>>()
+        }
+    }
+
+    /// How much memory the distances take.
+    pub fn bytes(&self) -> usize {
+        match &self.source {
+            DistanceSource::Computed(computed) => {
+                computed.points.len() * size_of::<(f64, f64)>()
+                    + computed.forbidden.len() * size_of::<(usize, usize)>()
+            }
+            _ => Self::bytes_for(self.len(), self.precision(), self.is_triangle()),
+        }
+    }
+
+    /// Computed distances are always `f64`s.
+    pub fn precision(&self) -> DistancePrecision {
+        match self.source {
+            DistanceSource::F64(_) | DistanceSource::Computed(_) => DistancePrecision::F64,
+            DistanceSource::F32(_) => DistancePrecision::F32,
+        }
+    }
+
+    /// Whether distances are worked out as they're needed rather than stored.
+    pub fn is_computed(&self) -> bool {
+        matches!(self.source, DistanceSource::Computed(_))
+    }
+
+    /// Whether it's stored as a triangle, because every distance is the same both ways.
+    pub fn is_triangle(&self) -> bool {
+        matches!(
+            self.source,
+            DistanceSource::F64(Storage::Triangle { .. })
+                | DistanceSource::F32(Storage::Triangle { .. })
+        )
+    }
+
+    /// Store the distances at `precision` from now on, unless they aren't stored at all.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn set_precision(&mut self, precision: DistancePrecision) {
+        if precision == self.precision() || self.is_computed() {
+            return;
+        }
+        self.source = match std::mem::replace(
+            &mut self.source,
+            DistanceSource::F64(Storage::Full {
+                len: 0,
+                cells: Box::default(),
+            }),
+        ) {
+            DistanceSource::F64(storage) => DistanceSource::F32(storage.map(|d| d as f32)),
+            DistanceSource::F32(storage) => DistanceSource::F64(storage.map(f64::from)),
+            DistanceSource::Computed(_) => unreachable!("computed distances aren't stored"),
+        };
+        self.point_at_full_rows();
+    }
 
-        DistanceMatrix(v)
+    /// How many nodes there are distances between.
+    pub fn len(&self) -> usize {
+        self.len
     }
 
+    #[inline]
     pub fn dist<T: Into<usize>>(&self, a: T, b: T) -> f64 {
         let (a, b): (usize, usize) = (a.into(), b.into());
 
-        debug_assert!(a < self.0.len());
-        debug_assert!(b < self.0[a].len());
+        debug_assert!(a < self.len);
+        debug_assert!(b < self.len);
+
+        if !self.full.is_null() {
+            // SAFETY: `full` points at `len * len` cells, and `a` and `b` are under `len` (checked
+            //         in debug builds)
+            return unsafe { *self.full.add(a * self.len + b) };
+        }
+        self.dist_from_source(a, b)
+    }
+
+    /// [`DistanceMatrix::dist`] for anything but full `f64` rows.
+    #[inline]
+    fn dist_from_source(&self, a: usize, b: usize) -> f64 {
+        match &self.source {
+            DistanceSource::F64(storage) => storage.get(a, b),
+            DistanceSource::F32(storage) => f64::from(storage.get(a, b)),
+            DistanceSource::Computed(computed) => computed.get(a, b),
+        }
+    }
+
+    pub fn is_forbidden<T: Into<usize>>(&self, a: T, b: T) -> bool {
+        self.dist(a, b) >= FORBIDDEN
+    }
+
+    /// Stop routes from going directly between `a` and `b`, in either direction.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn forbid(&mut self, a: usize, b: usize) {
+        match &mut self.source {
+            DistanceSource::F64(storage) => storage.set(a, b, FORBIDDEN),
+            // exactly representable, so still exactly FORBIDDEN when read back
+            DistanceSource::F32(storage) => storage.set(a, b, FORBIDDEN as f32),
+            DistanceSource::Computed(computed) => computed.forbidden.push((a.min(b), a.max(b))),
+        }
+        self.point_at_full_rows();
+    }
+
+    /// Write the distances out as little-endian floats at the matrix's precision: every row, or
+    /// for a triangle, row `a` up to and including `(a, a)`. Computed distances have nothing to
+    /// write, and can't be.
+    pub fn write_to(&self, out: &mut impl io::Write) -> io::Result<()> {
+        match &self.source {
+            DistanceSource::F64(storage) => storage.write_to(out),
+            DistanceSource::F32(storage) => storage.write_to(out),
+            DistanceSource::Computed(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "computed distances aren't stored, so they can't be saved",
+            )),
+        }
+    }
 
-        // SAFETY: we gotta trust ourselves here that we did the bounds checking
-        //         properly outside this function. if we believe, and use the power of friendship,
-        //         i think nothings impossible.
-        let a = unsafe { self.0.get_unchecked(a).get_unchecked(b) };
+    /// A `len`x`len` matrix at `precision`, stored as a triangle or not, as
+    /// [`DistanceMatrix::write_to`] wrote it. It's read a row at a time, so this takes hardly any
+    /// memory beyond the matrix itself.
+    pub fn read_from(
+        input: &mut impl Read,
+        len: usize,
+        precision: DistancePrecision,
+        triangle: bool,
+    ) -> io::Result<Self> {
+        Ok(DistanceMatrix::of(match precision {
+            DistancePrecision::F64 => {
+                DistanceSource::F64(Storage::read_from(input, len, triangle)?)
+            }
+            DistancePrecision::F32 => {
+                DistanceSource::F32(Storage::read_from(input, len, triangle)?)
+            }
+        }))
+    }
 
-        *a
+    /// Check every distance is a non-negative number, and either below [`FORBIDDEN`] or exactly it.
+    /// NaN, infinities and huge distances would otherwise silently break every cost comparison.
+    ///
+    /// Computed distances are too many to check one by one, so instead every node has to be under
+    /// half of [`FORBIDDEN`] from the first, which keeps every other distance under it too.
+    pub fn check(&self) -> Result<(), VrpError> {
+        if let DistanceSource::Computed(computed) = &self.source {
+            return match (0..self.len())
+                .map(|to| (to, computed.between(0, to)))
+                .find(|&(_, value)| !(0.0..FORBIDDEN / 2.0).contains(&value))
+            {
+                Some((to, value)) => Err(VrpError::InvalidDistance { from: 0, to, value }),
+                None => Ok(()),
+            };
+        }
+        for from in 0..self.len() {
+            for to in 0..self.len() {
+                let value = self.dist(from, to);
+                if !(0.0..FORBIDDEN).contains(&value) && value != FORBIDDEN {
+                    return Err(VrpError::InvalidDistance { from, to, value });
+                }
+            }
+        }
+        Ok(())
     }
 }
 
 #[allow(clippy::derived_hash_with_manual_eq)]
 #[derive(Clone, Copy, Hash)]
 pub struct Stop {
-    cust_no: u16,
-    capacity: usize,
+    cust_no: CustomerId,
+    // a u32 keeps a stop to 8 bytes
+    capacity: u32,
 }
 
+// solutions are cloned every iteration, so a bigger stop slows the whole search down
+const _: () = assert!(size_of::<Stop>() == 8);
+
 impl std::fmt::Debug for Stop {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!("{}({:?})", self.cust_no, self.capacity))
@@ -62,59 +571,138 @@ impl std::fmt::Debug for Stop {
 }
 
 impl Stop {
-    pub fn new(cust_no: u16, capacity: usize) -> Self {
-        Stop { cust_no, capacity }
+    pub fn new(cust_no: impl Into<CustomerId>, capacity: usize) -> Self {
+        Stop {
+            cust_no: cust_no.into(),
+            capacity: u32::try_from(capacity).expect("demands fit in a u32"),
+        }
     }
 
-    pub fn cust_no(&self) -> u16 {
+    pub fn cust_no(&self) -> CustomerId {
         self.cust_no
     }
     pub fn capacity(&self) -> usize {
-        self.capacity
+        self.capacity as usize
     }
 }
 
 pub struct VRPSolution {
+    /// The routes in use plus (while there's a vehicle left for it) one empty spare, so opening
+    /// a new route doesn't mean scanning every unused vehicle. See [`VRPSolution::compact`].
     pub routes: Vec<Route>,
+    /// routes dropped from `routes`, kept so their stop buffers can be reused the next time a
+    /// route is opened instead of allocating new ones
+    retired: Vec<Route>,
+}
+
+/// A solution without its instance, for checkpointing it or handing it to another process: each
+/// non-empty route's customers in visiting order. [`VRPSolution`] serializes as this, and
+/// [`VRPSolution::from_serialized`] reads it back against an instance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SerializedSolution {
+    pub routes: Vec<Vec<u32>>,
+}
+
+// the routes' instance can't come along, so only their customers are written
+impl Serialize for VRPSolution {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_serialized().serialize(serializer)
+    }
+}
+
+// how many times solutions have been `clone`d (allocating) vs `clone_from`'d (reusing buffers).
+// only tracked in debug builds, to keep the atomics out of the release hot path.
+#[cfg(debug_assertions)]
+static SOLUTION_CLONES: AtomicUsize = AtomicUsize::new(0);
+#[cfg(debug_assertions)]
+static SOLUTION_CLONE_FROMS: AtomicUsize = AtomicUsize::new(0);
+
+/// (`clone` calls, `clone_from` calls) on solutions so far, always zero in release builds.
+pub fn solution_clone_counts() -> (usize, usize) {
+    #[cfg(debug_assertions)]
+    {
+        (
+            SOLUTION_CLONES.load(Ordering::Relaxed),
+            SOLUTION_CLONE_FROMS.load(Ordering::Relaxed),
+        )
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        (0, 0)
+    }
 }
 
 impl Clone for VRPSolution {
     fn clone(&self) -> Self {
+        #[cfg(debug_assertions)]
+        SOLUTION_CLONES.fetch_add(1, Ordering::Relaxed);
+
         VRPSolution {
             routes: self.routes.clone(),
+            retired: Vec::new(),
         }
     }
 
     fn clone_from(&mut self, source: &Self) {
-        assert!(self.routes.len() == source.routes.len());
+        #[cfg(debug_assertions)]
+        SOLUTION_CLONE_FROMS.fetch_add(1, Ordering::Relaxed);
+
+        // the two can have different numbers of routes, so set aside the ones we have too many of
+        // and make up any we're missing from those set aside before, only cloning past that
+        let keep = self.routes.len().min(source.routes.len());
+        self.retired.extend(self.routes.drain(keep..));
+        while self.routes.len() < source.routes.len() {
+            let route = match self.retired.pop() {
+                Some(route) => route,
+                None => source.routes[self.routes.len()].clone(),
+            };
+            self.routes.push(route);
+        }
 
-        for (my_route, source_route) in self.routes.iter_mut().zip(source.routes.iter()) {
+        for (my_route, source_route) in self.routes.iter_mut().zip(&source.routes) {
             let Route {
                 instance,
                 id,
                 stops,
                 cost,
                 used_cap,
+                service_time,
+                generation,
             } = my_route;
-            assert!(stops.capacity() == source_route.stops.capacity());
-
             *instance = source_route.instance.clone();
             *id = source_route.id;
             *cost = source_route.cost;
             *used_cap = source_route.used_cap;
+            *service_time = source_route.service_time;
+            *generation = source_route.generation;
 
             // copy over stops to use exisiting allocation
-            // SAFETY: both vectors have the same capacity, which much be less than the source vec's length.
-            //         that means we can safely copy that many elements into the destination.
-            unsafe {
-                std::ptr::copy(
-                    source_route.stops.as_ptr(),
-                    stops.as_mut_ptr(),
-                    source_route.stops.len(),
-                );
-                stops.set_len(source_route.stops.len());
+            stops.clear();
+            stops.extend_from_slice(&source_route.stops);
+        }
+    }
+}
+
+/// The flat encoding the output JSON's `Solution` uses: a `0`, then `<depot> <customers> <depot>`
+/// for every vehicle, everything given by its row in the instance file (see
+/// [`VRPInstance::file_row`]).
+impl std::fmt::Display for VRPSolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let row = |c: u32| self.routes.first().map_or(c, |r| r.instance.file_row(c));
+        // unused vehicles are still written out, as empty routes
+        f.write_str("0 ")?;
+        for (i, route) in self.to_routes_vec(true).iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", row(0))?;
+            for &cust_no in route {
+                write!(f, " {}", row(cust_no))?;
             }
+            write!(f, " {}", row(0))?;
         }
+        Ok(())
     }
 }
 
@@ -128,89 +716,638 @@ impl std::fmt::Debug for VRPSolution {
 }
 
 impl VRPSolution {
+    /// A solution with nothing but a spare route (or no routes at all if there are no vehicles).
     pub fn new(vrp_instance: &Arc<VRPInstance>) -> Self {
         VRPSolution {
-            routes: (0..vrp_instance.num_vehicles)
-                .map(|i| Route::new(vrp_instance.clone(), i))
+            routes: (0..vrp_instance.num_vehicles.min(1))
+                .map(|i| Route::new(vrp_instance.clone(), RouteIdx(i)))
                 .collect(),
+            retired: Vec::new(),
         }
     }
 
-    #[allow(dead_code)]
-    pub fn is_valid_solution(&self, vrp_instance: &Arc<VRPInstance>) -> bool {
-        // all routes should be under capacity
-        self.routes.iter().for_each(|r| {
-            assert!(
-                r.used_capacity() <= vrp_instance.vehicle_capacity,
-                "route {r:?} is over cap {:?}",
-                vrp_instance.vehicle_capacity
-            );
-        });
+    /// An empty route for vehicle `id`, in the buffer of a retired route if there is one.
+    fn empty_route(&mut self, instance: &Arc<VRPInstance>, id: RouteIdx) -> Route {
+        match self.retired.pop() {
+            Some(mut route) => {
+                route.reset(instance, id);
+                route
+            }
+            None => Route::new(instance.clone(), id),
+        }
+    }
 
-        // all customers should be visited
-        for c in 1..vrp_instance.num_customers {
-            let is_visited = self
-                .routes
-                .iter()
-                .any(|r| r.contains_stop(u16::try_from(c).unwrap()));
-            assert!(
-                is_visited,
-                "customer {c} isn't visited in solution {self:?}"
-            );
+    /// How many vehicles the solution's instance has, i.e. how many routes it may use.
+    fn num_vehicles(&self) -> usize {
+        self.routes.first().map_or(0, |r| r.instance.num_vehicles)
+    }
+
+    /// Insert `stop` into route `r` at `index`, and if that used up the spare route, open
+    /// another one (if there's a vehicle left for it).
+    pub fn add_stop(&mut self, r: usize, stop: Stop, index: usize) {
+        let opened = self.routes[r].stops.is_empty();
+        self.routes[r].add_stop_to_index(stop, index);
+        if opened
+            && self.routes.len() < self.num_vehicles()
+            && self.routes.iter().all(|route| !route.stops.is_empty())
+        {
+            let instance = self.routes[r].instance.clone();
+            let spare = self.empty_route(&instance, RouteIdx(self.routes.len()));
+            self.routes.push(spare);
         }
+    }
 
-        true
+    /// Drop the routes left empty by removing stops, apart from one spare at the end (if there's
+    /// a vehicle for it), and renumber the rest so each route's id is its index again.
+    pub fn compact(&mut self) {
+        let empty = self.routes.iter().filter(|r| r.stops.is_empty()).count();
+        let spare_at_end = self.routes.last().is_some_and(|r| r.stops.is_empty());
+        if empty == usize::from(spare_at_end)
+            && (spare_at_end || self.routes.len() >= self.num_vehicles())
+        {
+            return;
+        }
+
+        // move the routes in use to the front (in order), so an emptied one can be the spare
+        let mut in_use = 0;
+        for i in 0..self.routes.len() {
+            if !self.routes[i].stops.is_empty() {
+                self.routes.swap(in_use, i);
+                in_use += 1;
+            }
+        }
+        let num_vehicles = self.num_vehicles();
+        if in_use == self.routes.len() && in_use < num_vehicles {
+            let instance = self.routes[0].instance.clone();
+            let spare = self.empty_route(&instance, RouteIdx(in_use));
+            self.routes.push(spare);
+        }
+        let keep = (in_use + 1).min(num_vehicles).min(self.routes.len());
+        self.retired.extend(self.routes.drain(keep..));
+        for (i, route) in self.routes.iter_mut().enumerate() {
+            route.set_id(RouteIdx(i));
+        }
+    }
+
+    /// The first problem [`verify`](crate::verify::verify) finds with the solution, if any. Meant
+    /// for solutions we didn't build ourselves.
+    pub fn validate(&self, vrp_instance: &VRPInstance) -> Result<(), String> {
+        let report = verify::verify(self, vrp_instance, verify::DEFAULT_TOLERANCE);
+        match report.violations.first() {
+            Some(violation) => Err(violation.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    /// Every problem [`VRPSolution::validate`] looks for, against the instance the routes were
+    /// built for, rather than just the first.
+    pub fn check(&self) -> Result<(), Vec<SolutionViolation>> {
+        let Some(route) = self.routes.first() else {
+            return Ok(());
+        };
+        let report = verify::verify(self, &route.instance, verify::DEFAULT_TOLERANCE);
+        if report.is_valid() {
+            Ok(())
+        } else {
+            Err(report.violations)
+        }
     }
 
     pub fn cost(&self) -> f64 {
         self.routes.iter().map(Route::cost).sum()
     }
 
-    #[allow(clippy::inherent_to_string)]
-    pub fn to_string(&self) -> String {
-        let route_strings: Vec<String> = self
+    /// The cost of the longest route.
+    pub fn max_route_cost(&self) -> f64 {
+        self.routes.iter().map(Route::cost).fold(0.0, f64::max)
+    }
+
+    /// Each route's customers in visiting order, optionally with an empty route for every unused
+    /// vehicle.
+    pub fn to_routes_vec(&self, include_empty: bool) -> Vec<Vec<u32>> {
+        let mut routes = self
             .routes
             .iter()
-            .map(|route| {
-                let mut result = String::from("0");
+            .filter(|r| !r.stops.is_empty())
+            .map(|r| r.stops.iter().map(|s| s.cust_no.0).collect())
+            .collect::<Vec<_>>();
+        if include_empty {
+            routes.resize(self.num_vehicles(), Vec::new());
+        }
+        routes
+    }
+
+    /// Build a solution from each route's customers in visiting order, with any vehicles not
+    /// given a (non-empty) route left unused. Customers can be left out (e.g. for
+    /// [`repair_imported`](crate::repair::repair_imported) to fill in), but each one may only be visited once and every route has to be within capacity.
+    pub fn from_routes_vec(
+        vrp_instance: &Arc<VRPInstance>,
+        routes: &[Vec<u32>],
+    ) -> Result<Self, VrpError> {
+        if routes.len() > vrp_instance.num_vehicles {
+            return Err(VrpError::TooManyRoutes {
+                routes: routes.len(),
+                vehicles: vrp_instance.num_vehicles,
+            });
+        }
 
-                for stop in route.stops() {
-                    write!(result, " {}", stop.cust_no()).unwrap();
+        let mut seen = vec![false; vrp_instance.num_customers];
+        for (r, route) in routes.iter().enumerate() {
+            let mut load = 0;
+            for &cust_no in route {
+                let c = cust_no as usize;
+                if c == 0 || c >= vrp_instance.num_customers {
+                    return Err(VrpError::UnknownCustomer(c));
                 }
+                if std::mem::replace(&mut seen[c], true) {
+                    return Err(VrpError::DuplicateCustomer(cust_no.into()));
+                }
+                load += vrp_instance.demand_of_customer[c];
+            }
+            if load > vrp_instance.vehicle_capacity {
+                return Err(VrpError::OverCapacity {
+                    route: r,
+                    load,
+                    cap: vrp_instance.vehicle_capacity,
+                });
+            }
+        }
 
-                result.push_str(" 0");
-                result
+        Ok(VRPSolution::from_routes_unchecked(vrp_instance, routes))
+    }
+
+    /// [`VRPSolution::from_routes_vec`] without its checks, so the routes can be over capacity or
+    /// share customers (for [`repair_imported`](crate::repair::repair_imported) to fix). There
+    /// can't be more routes than vehicles, or unknown customers, and a route can't visit the same
+    /// customer twice.
+    pub fn from_routes_unchecked(vrp_instance: &Arc<VRPInstance>, routes: &[Vec<u32>]) -> Self {
+        let mut sol = VRPSolution::new(vrp_instance);
+        for cust_nos in routes.iter().filter(|cust_nos| !cust_nos.is_empty()) {
+            let r = sol.routes.len() - 1;
+            for (i, &cust_no) in cust_nos.iter().enumerate() {
+                let stop = Stop::new(cust_no, vrp_instance.demand_of_customer[cust_no as usize]);
+                sol.add_stop(r, stop, i);
+            }
+        }
+        sol
+    }
+
+    pub fn to_serialized(&self) -> SerializedSolution {
+        SerializedSolution {
+            routes: self.to_routes_vec(false),
+        }
+    }
+
+    /// Rebuild a solution to `instance` from what it serialized as. Costs and loads are worked
+    /// out again from `instance`, so only the customers have to be right (see
+    /// [`VRPSolution::from_routes_vec`]).
+    pub fn from_serialized(
+        data: &SerializedSolution,
+        instance: &Arc<VRPInstance>,
+    ) -> Result<Self, VrpError> {
+        VRPSolution::from_routes_vec(instance, &data.routes)
+    }
+
+    /// Read back what a [`VRPSolution`] displays as: a `0`, then each vehicle's route as
+    /// `0 <customers> 0`. The costs are worked out again from `instance` rather than trusted.
+    pub fn from_string(s: &str, instance: &Arc<VRPInstance>) -> Result<Self, SolutionParseError> {
+        let rows = parse_routes(s, instance.file_row(0))?;
+        Ok(VRPSolution::from_routes_vec(
+            instance,
+            &instance.customers_of_rows(&rows),
+        )?)
+    }
+
+    /// Read back a `.sol` file written from [`VRPSolution::to_file_string`]: the cost and a `0`
+    /// on the first line, then a `<depot> <customers> <depot>` line per vehicle. As with
+    /// [`VRPSolution::from_string`], the cost in the file is only checked to be a number. Files
+    /// from [`VRPSolution::to_detailed_file_string`] can be read too, their `#` lines skipped.
+    pub fn from_file_string(
+        s: &str,
+        instance: &Arc<VRPInstance>,
+    ) -> Result<Self, SolutionParseError> {
+        let rows = parse_file_routes(s, instance.file_row(0))?;
+        Ok(VRPSolution::from_routes_vec(
+            instance,
+            &instance.customers_of_rows(&rows),
+        )?)
+    }
+
+    /// Flip each route whose cost doesn't depend on direction so that it starts with whichever
+    /// of its two ends is nearer the depot. Only changes how the solution reads, never its cost.
+    pub fn orient_for_output(&mut self, vrp_instance: &VRPInstance) {
+        let dm = &vrp_instance.distance_matrix;
+        for route in &mut self.routes {
+            if route.stops.len() < 2 || !route.is_reversible() {
+                continue;
+            }
+            if dm.dist(0, route.last()) < dm.dist(0, route.first()) {
+                route.reverse();
+            }
+        }
+    }
+
+    /// How much cheaper the solution would be without each customer (its route just skipping it),
+    /// i.e. what each customer's detour costs. Ordered by route, then position in the route.
+    pub fn marginal_costs(&self) -> Vec<(CustomerId, f64)> {
+        self.routes
+            .iter()
+            .flat_map(|r| {
+                (0..r.stops.len()).map(move |i| {
+                    (
+                        r.stops[i].cust_no,
+                        r.cost() - r.speculative_remove_stop(i).0,
+                    )
+                })
             })
-            .collect();
+            .collect()
+    }
 
-        let mut combined = String::from("0 ");
-        combined.push_str(&route_strings.join(" "));
-        combined
+    /// The `k` most expensive legs in the whole solution as `(route, index, cost)`, most expensive
+    /// first, with `index` as in [`Route::legs`].
+    pub fn longest_legs(&self, k: usize) -> Vec<(usize, usize, f64)> {
+        let mut legs = self
+            .routes
+            .iter()
+            .enumerate()
+            .flat_map(|(r, route)| route.legs().map(move |(i, cost)| (r, i, cost)))
+            .collect::<Vec<_>>();
+        legs.sort_by(|a, b| b.2.total_cmp(&a.2));
+        legs.truncate(k);
+        legs
     }
 
+    /// The `.sol` file: the cost and a `0` on the first line, then a `<depot> <customers> <depot>`
+    /// line per vehicle, in the instance file's rows as for the `Solution` string.
     pub fn to_file_string(&self) -> String {
-        let mut res = format!("{} 0\n", self.cost());
-        let route_strings: Vec<String> = self
+        self.file_string(false)
+    }
+
+    /// [`VRPSolution::to_file_string`] with a `# vrp-sol 2` header line, and a
+    /// `# route <id> load=<load> dist=<distance>` comment line before each route.
+    pub fn to_detailed_file_string(&self) -> String {
+        self.file_string(true)
+    }
+
+    fn file_string(&self, details: bool) -> String {
+        let mut res = String::new();
+        if details {
+            writeln!(res, "{SOL_HEADER} {SOL_VERSION}").unwrap();
+        }
+        writeln!(res, "{:.2} 0", self.cost()).unwrap();
+        let row = |c: u32| self.routes.first().map_or(c, |r| r.instance.file_row(c));
+        // the routes in use come first, in the same order, then an empty one per unused vehicle
+        let mut used = self.routes.iter().filter(|r| !r.stops.is_empty());
+        for (i, route) in self.to_routes_vec(true).iter().enumerate() {
+            if details {
+                let (load, dist) = used.next().map_or((0, 0.0), |r| (r.used_cap, r.cost));
+                writeln!(res, "# route {i} load={load} dist={dist:.2}").unwrap();
+            }
+            write!(res, "{}", row(0)).unwrap();
+            for &cust_no in route {
+                write!(res, " {}", row(cust_no)).unwrap();
+            }
+            writeln!(res, " {}", row(0)).unwrap();
+        }
+        res
+    }
+
+    /// The solution as CVRPLIB and the 12th DIMACS challenge's verifiers read it: a
+    /// `Route #<n>: <customers>` line for each non-empty route, numbered from 1, then
+    /// `Cost <cost>`, which is a whole number when the instance's distances are rounded to whole
+    /// numbers.
+    pub fn to_cvrplib_string(&self) -> String {
+        let mut res = String::new();
+        for (i, route) in self.to_routes_vec(false).iter().enumerate() {
+            write!(res, "Route #{}:", i + 1).unwrap();
+            for cust_no in route {
+                write!(res, " {cust_no}").unwrap();
+            }
+            res.push('\n');
+        }
+        let whole = self
             .routes
-            .iter()
-            .map(|route| {
-                let mut result = String::from("0");
+            .first()
+            .is_some_and(|r| r.instance.distance_rounding != DistanceRounding::Exact);
+        if whole {
+            writeln!(res, "Cost {:.0}", self.cost()).unwrap();
+        } else {
+            writeln!(res, "Cost {}", self.cost()).unwrap();
+        }
+        res
+    }
 
-                for stop in route.stops() {
-                    write!(result, " {}", stop.cust_no()).unwrap();
-                }
+    /// The non-empty routes' customers in visiting order, as the caller's ids if the instance has
+    /// them (see [`VRPInstance::from_json`]) and otherwise as customer numbers.
+    pub fn to_json(&self, instance: &VRPInstance) -> serde_json::Value {
+        let routes = self.to_routes_vec(false);
+        match &instance.customer_ids {
+            Some(ids) => routes
+                .iter()
+                .map(|route| {
+                    route
+                        .iter()
+                        .map(|&cust_no| ids[cust_no as usize].as_str())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+                .into(),
+            None => routes.into(),
+        }
+    }
 
-                result.push_str(" 0\n");
-                result
+    /// A GeoJSON `FeatureCollection` for drawing the solution on a map (e.g. in kepler.gl): a
+    /// `LineString` for each non-empty route, from the depot through its stops and back (or to the
+    /// end location), and a `Point` for the depot and each customer. Needs coordinates, which are
+    /// used as they are, so for a map they should be longitude and latitude.
+    pub fn to_geojson(&self, instance: &VRPInstance) -> String {
+        let point = |c: usize| {
+            json!([
+                instance.x_coord_of_customer[c],
+                instance.y_coord_of_customer[c]
+            ])
+        };
+        let end = match instance.end_location {
+            Some((x, y)) => json!([x, y]),
+            None => point(0),
+        };
+        let routes = self.routes.iter().filter(|r| !r.stops.is_empty()).map(|r| {
+            let coordinates = std::iter::once(point(0))
+                .chain(r.stops.iter().map(|s| point(s.cust_no.index())))
+                .chain(std::iter::once(end.clone()))
+                .collect::<Vec<_>>();
+            json!({
+                "type": "Feature",
+                "geometry": {"type": "LineString", "coordinates": coordinates},
+                "properties": {"Route": r.id, "Load": r.used_cap, "Cost": r.cost},
             })
-            .collect();
-        res.push_str(&route_strings.join(""));
-        res
+        });
+        let nodes = (0..instance.num_customers).map(|c| {
+            json!({
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": point(c)},
+                "properties": {
+                    "Customer": c,
+                    "Depot": c == 0,
+                    "Demand": instance.demand_of_customer[c],
+                },
+            })
+        });
+        json!({
+            "type": "FeatureCollection",
+            "features": routes.chain(nodes).collect::<Vec<_>>(),
+        })
+        .to_string()
+    }
+
+    /// An SVG drawing of the solution, `width` by `height` pixels, for eyeballing whether its
+    /// routes cross: each non-empty route is a line in its own colour (cycling through
+    /// [`ROUTE_COLOURS`]), the depot a square and each customer a circle as big as its demand
+    /// allows. The coordinates are scaled to fit with their aspect ratio kept and `y` pointing up.
+    /// Needs coordinates.
+    pub fn to_svg(&self, instance: &VRPInstance, width: u32, height: u32) -> String {
+        const MARGIN: f64 = 10.0;
+        const MIN_RADIUS: f64 = 2.0;
+        const MAX_RADIUS: f64 = 6.0;
+
+        let mut bounds = instance.bounding_box();
+        if let Some((x, y)) = instance.end_location {
+            bounds.min_x = bounds.min_x.min(x);
+            bounds.min_y = bounds.min_y.min(y);
+            bounds.max_x = bounds.max_x.max(x);
+            bounds.max_y = bounds.max_y.max(y);
+        }
+        // one scale for both axes, so the drawing isn't stretched; a single point (or a line of
+        // them) is drawn in the middle rather than divided by zero
+        let (w, h) = (f64::from(width), f64::from(height));
+        let span_x = bounds.max_x - bounds.min_x;
+        let span_y = bounds.max_y - bounds.min_y;
+        let scale = ((w - 2.0 * MARGIN) / span_x).min((h - 2.0 * MARGIN) / span_y);
+        let scale = if scale.is_finite() { scale } else { 1.0 };
+        let (offset_x, offset_y) = ((w - span_x * scale) / 2.0, (h - span_y * scale) / 2.0);
+        let to_px = |x: f64, y: f64| {
+            (
+                offset_x + (x - bounds.min_x) * scale,
+                h - offset_y - (y - bounds.min_y) * scale,
+            )
+        };
+        let at = |c: usize| {
+            to_px(
+                instance.x_coord_of_customer[c],
+                instance.y_coord_of_customer[c],
+            )
+        };
+        let end = instance.end_location.map_or(at(0), |(x, y)| to_px(x, y));
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+             viewBox=\"0 0 {width} {height}\">\n<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n"
+        );
+        for (i, route) in self
+            .routes
+            .iter()
+            .filter(|r| !r.stops.is_empty())
+            .enumerate()
+        {
+            let points = std::iter::once(at(0))
+                .chain(route.stops.iter().map(|s| at(s.cust_no.index())))
+                .chain(std::iter::once(end))
+                .map(|(x, y)| format!("{x:.1},{y:.1}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let _ = writeln!(
+                svg,
+                "<polyline points=\"{points}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>",
+                ROUTE_COLOURS[i % ROUTE_COLOURS.len()]
+            );
+        }
+        let max_demand = instance.demand_of_customer[1..instance.num_customers]
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        for c in 1..instance.num_customers {
+            let (x, y) = at(c);
+            let r = MIN_RADIUS
+                + (MAX_RADIUS - MIN_RADIUS) * instance.demand_of_customer[c] as f64
+                    / max_demand as f64;
+            let _ = writeln!(
+                svg,
+                "<circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"{r:.1}\" fill=\"#555\"/>"
+            );
+        }
+        let (x, y) = at(0);
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{s:.1}\" height=\"{s:.1}\" fill=\"black\"/>",
+            x - MAX_RADIUS,
+            y - MAX_RADIUS,
+            s = 2.0 * MAX_RADIUS
+        );
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The solution as CSV, a row per stop of each non-empty route with the distance of the leg to
+    /// it and the route's running distance and load, so a spreadsheet can audit it without working
+    /// anything out. Customers go by the instance's own ids when it has them, and `x` and `y` are
+    /// left empty when it has no coordinates. With `return_leg`, each route also gets a last row
+    /// for going back to the depot (or on to the end location), so its final
+    /// `cumulative_distance` is its cost.
+    pub fn to_csv(&self, instance: &VRPInstance, return_leg: bool) -> String {
+        let dm = &instance.distance_matrix;
+        let customer = |c: usize| match &instance.customer_ids {
+            Some(ids) => csv_field(&ids[c]),
+            None => c.to_string(),
+        };
+        let coords = |c: usize| {
+            if instance.has_coordinates {
+                format!(
+                    "{},{}",
+                    instance.x_coord_of_customer[c], instance.y_coord_of_customer[c]
+                )
+            } else {
+                String::from(",")
+            }
+        };
+        // an end location only comes with coordinates
+        let end_coords = match instance.end_location {
+            Some((x, y)) => format!("{x},{y}"),
+            None => coords(0),
+        };
+
+        let mut csv = String::from(
+            "route_id,sequence,customer_id,demand,x,y,leg_distance,cumulative_distance,cumulative_load\n",
+        );
+        for route in self.routes.iter().filter(|r| !r.stops.is_empty()) {
+            let (mut at, mut distance, mut load) = (CustomerId::DEPOT.index(), 0.0, 0);
+            for (sequence, stop) in route.stops.iter().enumerate() {
+                let c = stop.cust_no.index();
+                let (leg, demand) = (dm.dist(at, c), instance.demand_of_customer[c]);
+                distance += leg;
+                load += demand;
+                let _ = writeln!(
+                    csv,
+                    "{},{},{},{demand},{},{leg},{distance},{load}",
+                    route.id,
+                    sequence + 1,
+                    customer(c),
+                    coords(c)
+                );
+                at = c;
+            }
+            if return_leg {
+                let leg = dm.dist(at, instance.end_node.index());
+                let _ = writeln!(
+                    csv,
+                    "{},{},{},0,{end_coords},{leg},{},{load}",
+                    route.id,
+                    route.stops.len() + 1,
+                    customer(0),
+                    distance + leg
+                );
+            }
+        }
+        csv
+    }
+}
+
+/// `s` as a CSV field, quoted if it has to be.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// The colours [`VRPSolution::to_svg`] draws routes in, in turn.
+pub const ROUTE_COLOURS: [&str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    "#bcbd22", "#17becf",
+];
+
+/// Each vehicle's customers from what a [`VRPSolution`] displays as, routes starting and ending at
+/// row `depot`, without checking them against an instance. They're left as rows of the instance
+/// file, see [`VRPInstance::customers_of_rows`].
+pub fn parse_routes(s: &str, depot: u32) -> Result<Vec<Vec<u32>>, SolutionParseError> {
+    let routes = parse_depot_routes(s, &[depot])?;
+    Ok(routes.into_iter().map(|(_, route)| route).collect())
+}
+
+/// [`parse_routes`] for a multi-depot run's `Solution`, where each route starts and ends at
+/// whichever of the rows `depots` it's from, given with each route's customers.
+pub fn parse_depot_routes(
+    s: &str,
+    depots: &[u32],
+) -> Result<Vec<(u32, Vec<u32>)>, SolutionParseError> {
+    let stops = parse_customers(s.split_whitespace())?;
+    // the leading 0 on its own, then every route's depot at either end
+    let Some((&0, stops)) = stops.split_first() else {
+        return Err(SolutionParseError::NotFromDepot { route: 0 });
+    };
+    let mut routes = Vec::new();
+    let mut rest = stops;
+    while let Some((&start, after_start)) = rest.split_first() {
+        let route = routes.len();
+        if !depots.contains(&start) {
+            return Err(SolutionParseError::NotFromDepot { route });
+        }
+        let end = after_start
+            .iter()
+            .position(|&c| c == start)
+            .ok_or(SolutionParseError::NotFromDepot { route })?;
+        routes.push((start, after_start[..end].to_vec()));
+        rest = &after_start[end + 1..];
     }
+    Ok(routes)
+}
+
+/// Each vehicle's customers from a `.sol` file, as read by [`VRPSolution::from_file_string`],
+/// without checking them against an instance. As with [`parse_routes`], they're rows of the
+/// instance file.
+pub fn parse_file_routes(s: &str, depot: u32) -> Result<Vec<Vec<u32>>, SolutionParseError> {
+    let mut lines = s.lines().filter(|line| !line.trim().is_empty()).peekable();
+    if let Some(version) = lines.peek().and_then(|line| line.strip_prefix(SOL_HEADER)) {
+        let version = version.trim();
+        if !version.parse::<u32>().is_ok_and(|v| v <= SOL_VERSION) {
+            return Err(SolutionParseError::UnknownVersion(version.to_string()));
+        }
+    }
+    let mut lines = lines.filter(|line| !line.trim_start().starts_with('#'));
+    if let Some(cost) = lines.next().and_then(|line| line.split_whitespace().next()) {
+        cost.parse::<f64>()
+            .map_err(|_| SolutionParseError::NotANumber(cost.to_string()))?;
+    }
+    lines
+        .enumerate()
+        .map(
+            |(route, line)| match &parse_customers(line.split_whitespace())?[..] {
+                [start, customers @ .., end]
+                    if *start == depot && *end == depot && !customers.contains(&depot) =>
+                {
+                    Ok(customers.to_vec())
+                }
+                _ => Err(SolutionParseError::NotFromDepot { route }),
+            },
+        )
+        .collect::<Result<Vec<_>, _>>()
+}
+
+/// Whitespace-separated customer numbers, as a [`VRPSolution`] displays them.
+fn parse_customers<'a>(
+    tokens: impl Iterator<Item = &'a str>,
+) -> Result<Vec<u32>, SolutionParseError> {
+    tokens
+        .map(|t| {
+            t.parse()
+                .map_err(|_| SolutionParseError::NotANumber(t.to_string()))
+        })
+        .collect()
 }
 
 impl VRPSolution {
-    #[allow(dead_code)]
     pub fn distance(a: &Self, b: &Self, instance: &Arc<VRPInstance>) -> f64 {
         let mut dist = 0;
         let map_a = a.make_vector(instance);
@@ -220,7 +1357,7 @@ impl VRPSolution {
 
         for i in 1..instance.num_customers {
             for j in (i + 1)..instance.num_customers {
-                let key = &(u16::try_from(i).unwrap(), u16::try_from(j).unwrap());
+                let key = &(u32::try_from(i).unwrap(), u32::try_from(j).unwrap());
                 dist += map_a.get(key).unwrap() ^ map_b.get(key).unwrap();
             }
         }
@@ -230,19 +1367,16 @@ impl VRPSolution {
         }
     }
 
-    #[allow(dead_code)]
-    fn make_vector(&self, instance: &Arc<VRPInstance>) -> HashMap<(u16, u16), usize> {
+    fn make_vector(&self, instance: &Arc<VRPInstance>) -> HashMap<(u32, u32), usize> {
         let mut map = HashMap::new();
 
         for r in &self.routes {
             for el in &r.stops {
-                for other_cust in (el.cust_no + 1)..u16::try_from(instance.num_customers).unwrap() {
-                    let entry = (el.cust_no, other_cust);
-                    assert_eq!(
-                        entry,
-                        (min(el.cust_no, other_cust), max(el.cust_no, other_cust))
-                    );
-                    let val = usize::from(r.contains_stop(other_cust));
+                let cust_no = el.cust_no.0;
+                for other_cust in (cust_no + 1)..u32::try_from(instance.num_customers).unwrap() {
+                    let entry = (cust_no, other_cust);
+                    assert_eq!(entry, (min(cust_no, other_cust), max(cust_no, other_cust)));
+                    let val = usize::from(r.contains_stop(CustomerId(other_cust)));
                     let insert_res = map.insert(entry, val);
                     assert!(insert_res.is_none());
                 }
@@ -251,73 +1385,245 @@ impl VRPSolution {
 
         map
     }
+
+    /// A hash of the plan: which customers are visited in which order, ignoring which vehicle
+    /// drives each route and, when a route can be driven backwards at the same cost (a symmetric
+    /// instance with no end location), which way round it goes. Cheap enough to call on every
+    /// accepted solution, since it neither allocates nor sorts.
+    pub fn canonical_hash(&self) -> u64 {
+        let reversible = self.is_reversible();
+        // each route is hashed on its own and the results summed, which doesn't care about order
+        self.routes
+            .iter()
+            .filter(|r| !r.stops.is_empty())
+            .map(|r| {
+                let mut hasher = DefaultHasher::new();
+                for c in canonical_order(&r.stops, reversible) {
+                    c.hash(&mut hasher);
+                }
+                hasher.finish()
+            })
+            .fold(0, u64::wrapping_add)
+    }
+
+    /// Whether `other` is the same plan as `self`, by the same rules as
+    /// [`canonical_hash`](Self::canonical_hash).
+    pub fn is_same_plan(&self, other: &Self) -> bool {
+        self.canonical_hash() == other.canonical_hash()
+            && self.canonical_routes() == other.canonical_routes()
+    }
+
+    /// The non-empty routes' customers, each the canonical way round, in sorted order.
+    fn canonical_routes(&self) -> Vec<Vec<CustomerId>> {
+        let reversible = self.is_reversible();
+        let mut routes = self
+            .routes
+            .iter()
+            .filter(|r| !r.stops.is_empty())
+            .map(|r| canonical_order(&r.stops, reversible).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        routes.sort_unstable();
+        routes
+    }
+
+    fn is_reversible(&self) -> bool {
+        self.routes
+            .first()
+            .is_some_and(|r| r.instance.is_symmetric && r.instance.end_node.is_depot())
+    }
+}
+
+/// The customers of `stops`, backwards if `reversible` and the last is numbered lower than the
+/// first, so that a route and its reverse come out the same.
+fn canonical_order(stops: &[Stop], reversible: bool) -> impl Iterator<Item = CustomerId> + '_ {
+    let n = stops.len();
+    let backwards = reversible && n > 1 && stops[n - 1].cust_no < stops[0].cust_no;
+    (0..n).map(move |i| stops[if backwards { n - 1 - i } else { i }].cust_no)
+}
+
+thread_local! {
+    // the top bits say which thread handed out a generation, so they're unique across threads
+    // without an atomic on every route change
+    static NEXT_GENERATION: std::cell::Cell<u64> = {
+        static THREADS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        let thread = THREADS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        std::cell::Cell::new((thread << 40) + 1)
+    };
+}
+
+/// A number no other route state has had, see [`Route::generation`].
+fn next_generation() -> u64 {
+    NEXT_GENERATION.with(|next| {
+        let generation = next.get();
+        next.set(generation + 1);
+        generation
+    })
 }
 
 #[repr(C)]
 pub struct Route {
     used_cap: usize,
     pub instance: std::sync::Arc<VRPInstance>,
-    id: usize,
+    id: RouteIdx,
     stops: Vec<Stop>,
     cost: f64,
+    /// the total service time of the stops, kept up to date like `used_cap`
+    service_time: f64,
+    generation: u64,
 }
 
 impl Clone for Route {
     fn clone(&self) -> Self {
         let mut new_stops = Vec::with_capacity(self.stops.capacity());
-        new_stops.extend(self.stops.clone());
+        new_stops.extend_from_slice(&self.stops);
         Self {
             instance: self.instance.clone(),
             id: self.id,
             stops: new_stops,
             cost: self.cost,
             used_cap: self.used_cap,
+            service_time: self.service_time,
+            generation: self.generation,
         }
     }
 }
 
+/// `r<id>[<stops>--c<load>]`, for reading in logs.
+impl std::fmt::Display for Route {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let middle = self
+            .stops
+            .iter()
+            .map(|i| format!("{i:?}"))
+            .collect::<Vec<String>>();
+        let middle = middle.join(" -> ");
+        write!(f, "r{}[{middle}--c{}]", self.id, self.used_cap)
+    }
+}
+
 impl std::fmt::Debug for Route {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!(
-            "{}, cap: {}",
-            self.to_string(),
-            self.used_capacity()
-        ))
+        f.write_fmt(format_args!("{self}, cap: {}", self.used_capacity()))
     }
 }
 
 impl Route {
-    pub fn retain_stops(&mut self, f: impl Fn(&Stop) -> bool) {
+    /// Remove every stop `f` picks out, returning them in the order they were visited.
+    pub fn drain_stops(&mut self, mut f: impl FnMut(&Stop) -> bool) -> Vec<Stop> {
         self.assert_sanity();
 
-        self.stops.retain(f);
+        let picked = self.stops.iter().map(&mut f).collect::<Vec<_>>();
+        let num_removed = picked.iter().filter(|&&p| p).count();
+        if num_removed == 0 {
+            return Vec::new();
+        }
 
-        self.cost = self.recalculate_cost();
-        self.used_cap = self.recalculate_capacity();
+        // with only a few stops going, just patch up the legs around each run of them instead of
+        // walking the whole route (and take the drift that comes with it)
+        let cost_delta = (num_removed * 2 < self.stops.len()).then(|| {
+            let removed_at = (0..self.stops.len())
+                .filter(|&i| picked[i])
+                .collect::<Vec<_>>();
+            removed_at
+                .chunk_by(|a, b| a + 1 == *b)
+                .map(|run| {
+                    let (first, last) = (run[0], run[run.len() - 1]);
+                    let (before, after) = (self.gap(first).0, self.gap(last + 1).1);
+                    self.instance.distance_matrix.dist(before, after)
+                        - (first..=last + 1)
+                            .map(|i| self.cost_at_index(i))
+                            .sum::<f64>()
+                })
+                .sum::<f64>()
+        });
+
+        let mut picked = picked.into_iter();
+        let removed = self
+            .stops
+            .extract_if(.., |_| picked.next().unwrap())
+            .collect::<Vec<_>>();
+        self.generation = next_generation();
+
+        match cost_delta {
+            Some(delta) => {
+                self.cost += delta;
+                self.used_cap -= removed.iter().map(|s| s.capacity()).sum::<usize>();
+                self.service_time -= removed.iter().map(|s| self.service_time_of(s)).sum::<f64>();
+            }
+            None => {
+                self.cost = self.recalculate_cost();
+                self.used_cap = self.recalculate_capacity();
+                self.service_time = self.recalculate_service_time();
+            }
+        }
 
         self.assert_sanity();
+        removed
     }
 
-    #[allow(clippy::inherent_to_string)]
-    pub fn to_string(&self) -> String {
-        let middle = self
-            .stops
-            .iter()
-            .map(|i| format!("{i:?}"))
-            .collect::<Vec<String>>();
-        let middle = middle.join(" -> ");
-        format!("r{}[{middle}--c{}]", self.id, self.used_cap)
-    }
-    pub fn new(instance: Arc<VRPInstance>, id: usize) -> Self {
+    pub fn new(instance: Arc<VRPInstance>, id: RouteIdx) -> Self {
         Route {
             stops: Vec::with_capacity(instance.max_route_len),
             instance,
             cost: 0f64,
             used_cap: 0,
+            service_time: 0f64,
             id,
+            generation: next_generation(),
         }
     }
 
+    /// The vehicle this route belongs to, which is always its index in `VRPSolution::routes`.
+    pub fn id(&self) -> RouteIdx {
+        self.id
+    }
+
+    pub fn set_id(&mut self, id: RouteIdx) {
+        self.id = id;
+    }
+
+    /// Empty the route out for vehicle `id` of `instance`, keeping its stop buffer.
+    fn reset(&mut self, instance: &Arc<VRPInstance>, id: RouteIdx) {
+        self.instance.clone_from(instance);
+        self.id = id;
+        self.stops.clear();
+        self.cost = 0.0;
+        self.used_cap = 0;
+        self.service_time = 0.0;
+        self.generation = next_generation();
+    }
+
+    /// Changes whenever the stops do, and is copied along with them, so two routes with the same
+    /// generation are guaranteed to have the same stops (the reverse doesn't hold).
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether driving this route backwards costs the same, which needs it to end back at the
+    /// depot and every leg to be the same length in both directions.
+    pub fn is_reversible(&self) -> bool {
+        let dm = &self.instance.distance_matrix;
+        let end = self.instance.end_node;
+        end.is_depot()
+            && std::iter::once(CustomerId::DEPOT)
+                .chain(self.stops.iter().map(|s| s.cust_no))
+                .zip(
+                    self.stops
+                        .iter()
+                        .map(|s| s.cust_no)
+                        .chain(std::iter::once(end)),
+                )
+                .all(|(a, b)| dm.dist(a, b) == dm.dist(b, a))
+    }
+
+    /// Visit the stops in the opposite order.
+    pub fn reverse(&mut self) {
+        self.stops.reverse();
+        self.generation = next_generation();
+        self.cost = self.recalculate_cost();
+        self.assert_sanity();
+    }
+
     pub fn stops(&self) -> &Vec<Stop> {
         &self.stops
     }
@@ -340,11 +1646,76 @@ impl Route {
         self.used_cap
     }
 
-    pub fn contains_stop(&self, cust_no: u16) -> bool {
+    /// [`Route::cost`] and [`Route::used_capacity`] as they're kept, without the sanity checks
+    /// that would panic on a stale value in a debug build before [`verify`] could report it.
+    pub fn cached_cost_and_load(&self) -> (f64, usize) {
+        (self.cost, self.used_cap)
+    }
+
+    /// How long the route takes: its travel (the same as its cost) plus the service time at each
+    /// stop.
+    pub fn duration(&self) -> f64 {
+        self.cost + self.service_time
+    }
+
+    /// What [`Route::duration`] would be after a speculative change that leaves the route costing
+    /// `new_cost`, having added `added` and taken out the stop at `removed_index`, e.g.
+    /// `route.duration_after(route.speculative_add_stop(&stop, i).0, Some(&stop), None)`.
+    #[allow(dead_code)]
+    pub fn duration_after(
+        &self,
+        new_cost: f64,
+        added: Option<&Stop>,
+        removed_index: Option<usize>,
+    ) -> f64 {
+        let added = added.map_or(0.0, |s| self.service_time_of(s));
+        let removed = removed_index.map_or(0.0, |i| self.service_time_of(&self.stops[i]));
+        new_cost + self.service_time + added - removed
+    }
+
+    fn service_time_of(&self, stop: &Stop) -> f64 {
+        self.instance.service_time_of_customer[stop.cust_no.index()]
+    }
+
+    /// The route's legs as `(index, cost)`, where leg `index` is the one arriving at `stops[index]`
+    /// (or at the route's end for `index == len`), as in [`Route::cost_at_index`]. Empty routes
+    /// have no legs.
+    pub fn legs(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let num_legs = if self.stops.is_empty() {
+            0
+        } else {
+            self.stops.len() + 1
+        };
+        (0..num_legs).map(|i| (i, self.cost_at_index(i)))
+    }
+
+    /// The index and cost of the route's most expensive leg, `(0, 0.0)` for an empty route.
+    #[allow(dead_code)]
+    pub fn longest_leg(&self) -> (usize, f64) {
+        self.legs()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, 0.0))
+    }
+
+    /// The two nodes a stop inserted at `index` would go between (the depot or route end at the edges).
+    pub fn gap(&self, index: usize) -> (CustomerId, CustomerId) {
+        let before = if index == 0 {
+            CustomerId::DEPOT
+        } else {
+            self.stops[index - 1].cust_no
+        };
+        let after = self
+            .stops
+            .get(index)
+            .map_or(self.instance.end_node, |s| s.cust_no);
+        (before, after)
+    }
+
+    pub fn contains_stop(&self, cust_no: CustomerId) -> bool {
         self.stops.iter().any(|a| a.cust_no == cust_no)
     }
 
-    pub fn index_of_stop(&self, cust_no: u16) -> Option<usize> {
+    pub fn index_of_stop(&self, cust_no: CustomerId) -> Option<usize> {
         self.stops.iter().position(|a| a.cust_no == cust_no)
     }
 
@@ -352,9 +1723,11 @@ impl Route {
         self.assert_sanity();
         assert!(index <= self.stops.len()); // should be less than stops.len()
 
-        let cap = stop.capacity;
+        let cap = stop.capacity();
         let (new_cost, _) = self.speculative_add_stop(&stop, index);
+        self.service_time += self.service_time_of(&stop);
         self.stops.insert(index, stop);
+        self.generation = next_generation();
         self.used_cap += cap;
         self.cost = new_cost;
 
@@ -367,7 +1740,9 @@ impl Route {
 
         let (new_cost, _) = self.speculative_remove_stop(index);
         let stop = self.stops.remove(index);
-        self.used_cap -= stop.capacity;
+        self.generation = next_generation();
+        self.used_cap -= stop.capacity();
+        self.service_time -= self.service_time_of(&stop);
         self.cost = new_cost;
 
         self.assert_sanity();
@@ -375,22 +1750,22 @@ impl Route {
         stop
     }
 
-    #[allow(dead_code)]
+    /// The route's cost (and whether it stays within capacity) if the stop at `index` was swapped for `stop`.
     pub fn speculative_replace_stop(&self, stop: &Stop, index: usize) -> (f64, bool) {
         self.assert_sanity();
-        assert!(index <= self.stops.len());
+        assert!(index < self.stops.len());
 
         let mut new_cost = self.cost;
         let before = if index != 0 {
             self.stops[index - 1].cust_no
         } else {
-            0
+            CustomerId::DEPOT
         };
 
-        let after = if index == self.stops.len() {
-            0
+        let after = if index == (self.stops.len() - 1) {
+            self.instance.end_node
         } else {
-            self.stops[index].cust_no
+            self.stops[index + 1].cust_no
         };
 
         new_cost -= self
@@ -406,25 +1781,89 @@ impl Route {
 
         (
             new_cost,
-            self.used_cap - self.stops[index].capacity + stop.capacity
-                <= self.instance.vehicle_capacity,
+            self.used_cap - self.stops[index].capacity() + stop.capacity()
+                <= self.instance.vehicle_capacity
+                && !self
+                    .instance
+                    .distance_matrix
+                    .is_forbidden(before, stop.cust_no)
+                && !self
+                    .instance
+                    .distance_matrix
+                    .is_forbidden(stop.cust_no, after),
         )
     }
 
+    /// Put `stop` where the stop at `index` is, returning the stop it displaced.
+    pub fn replace_stop_at_index(&mut self, stop: Stop, index: usize) -> Stop {
+        self.assert_sanity();
+        assert!(index < self.stops.len());
+
+        let (new_cost, _) = self.speculative_replace_stop(&stop, index);
+        self.used_cap = self.used_cap - self.stops[index].capacity() + stop.capacity();
+        self.service_time += self.service_time_of(&stop) - self.service_time_of(&self.stops[index]);
+        self.cost = new_cost;
+        let old = std::mem::replace(&mut self.stops[index], stop);
+        self.generation = next_generation();
+
+        self.assert_sanity();
+
+        old
+    }
+
+    /// A lower bound on the cost increase of inserting `stop` anywhere in this route.
+    ///
+    /// Any insertion between `before` and `after` costs `d(before, stop) + d(stop, after) - d(before, after)`,
+    /// and both of the first two legs are at least the distance from `stop` to its nearest node in the route
+    /// (depot and end location included), so the increase is at least `2 * nearest - longest_edge`. With
+    /// an asymmetric matrix the way in and the way out are bounded separately instead.
+    pub fn insertion_lower_bound(&self, stop: &Stop) -> f64 {
+        let dm = &self.instance.distance_matrix;
+        let end = self.instance.end_node;
+
+        if !self.instance.is_symmetric {
+            let mut nearest_in = dm.dist(CustomerId::DEPOT, stop.cust_no);
+            let mut nearest_out = dm.dist(stop.cust_no, end);
+            let mut longest_edge = 0f64;
+            let mut prev = CustomerId::DEPOT;
+            for s in &self.stops {
+                nearest_in = nearest_in.min(dm.dist(s.cust_no, stop.cust_no));
+                nearest_out = nearest_out.min(dm.dist(stop.cust_no, s.cust_no));
+                longest_edge = longest_edge.max(dm.dist(prev, s.cust_no));
+                prev = s.cust_no;
+            }
+            longest_edge = longest_edge.max(dm.dist(prev, end));
+            return nearest_in + nearest_out - longest_edge;
+        }
+
+        let mut nearest = dm
+            .dist(stop.cust_no, CustomerId::DEPOT)
+            .min(dm.dist(stop.cust_no, end));
+        let mut longest_edge = 0f64;
+        let mut prev = CustomerId::DEPOT;
+        for s in &self.stops {
+            nearest = nearest.min(dm.dist(stop.cust_no, s.cust_no));
+            longest_edge = longest_edge.max(dm.dist(prev, s.cust_no));
+            prev = s.cust_no;
+        }
+        longest_edge = longest_edge.max(dm.dist(prev, end));
+
+        2.0 * nearest - longest_edge
+    }
+
+    /// The cheapest place to insert `stop`, including after the last stop, and what the route would
+    /// cost (and whether it's feasible) with it there.
     pub fn speculative_add_best(&self, stop: &Stop) -> ((f64, bool), usize) {
         self.assert_sanity();
 
         let best_index = if self.stops.is_empty() {
             0
         } else {
-            (0..self.stops.len())
-                .max_by_key(|i| {
-                    #[allow(clippy::cast_possible_truncation)]
-                    {
-                        -(self.speculative_add_stop(stop, *i).0 as isize)
-                    }
-                })
+            (0..=self.stops.len())
+                .map(|i| (i, self.speculative_add_stop(stop, i).0))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
                 .unwrap()
+                .0
         };
 
         let best_val = self.speculative_add_stop(stop, best_index);
@@ -438,10 +1877,22 @@ impl Route {
         debug_assert!(index <= self.stops.len());
 
         let vehicle_capacity = self.instance.vehicle_capacity;
-        let stop_capacity = stop.capacity;
+        let stop_capacity = stop.capacity();
 
         let current_used_cap = self.used_cap;
 
+        if self.stops.is_empty() {
+            // an empty route costs nothing, so it's just the trip out and back
+            let dm = &self.instance.distance_matrix;
+            let end = self.instance.end_node;
+            return (
+                dm.dist(CustomerId::DEPOT, stop.cust_no) + dm.dist(stop.cust_no, end),
+                stop_capacity <= vehicle_capacity
+                    && !dm.is_forbidden(CustomerId::DEPOT, stop.cust_no)
+                    && !dm.is_forbidden(stop.cust_no, end),
+            );
+        }
+
         let mut new_cost = self.cost;
 
         let before = if index != 0 {
@@ -449,11 +1900,11 @@ impl Route {
             //         (should probably reuse code eventually...)
             unsafe { self.stops.get_unchecked(index - 1).cust_no }
         } else {
-            0
+            CustomerId::DEPOT
         };
 
         let after = if index == self.stops.len() {
-            0
+            self.instance.end_node
         } else {
             // SAFETY: see above ^^
             unsafe { self.stops.get_unchecked(index).cust_no }
@@ -465,25 +1916,49 @@ impl Route {
 
         let final_cost = new_cost;
 
-        let within_capacity = stop_capacity + current_used_cap <= vehicle_capacity;
-        (final_cost, within_capacity)
+        let feasible = stop_capacity + current_used_cap <= vehicle_capacity
+            && !self
+                .instance
+                .distance_matrix
+                .is_forbidden(before, stop.cust_no)
+            && !self
+                .instance
+                .distance_matrix
+                .is_forbidden(stop.cust_no, after);
+        (final_cost, feasible)
     }
 
     pub fn speculative_remove_stop(&self, index: usize) -> (f64, bool) {
         self.assert_sanity();
         assert!(index < self.stops.len());
         let stop = &self.stops[index];
+        let within_capacity =
+            self.used_cap - self.stops[index].capacity() <= self.instance.vehicle_capacity;
+
+        // work these out directly, rather than subtracting (and drifting) down to them
+        match self.stops.len() {
+            1 => return (0.0, within_capacity),
+            2 => {
+                let other = self.stops[1 - index].cust_no;
+                let dm = &self.instance.distance_matrix;
+                return (
+                    dm.dist(CustomerId::DEPOT, other) + dm.dist(other, self.instance.end_node),
+                    within_capacity,
+                );
+            }
+            _ => {}
+        }
 
         let mut new_cost = self.cost;
 
         let before = if index != 0 {
             self.stops[index - 1].cust_no
         } else {
-            0
+            CustomerId::DEPOT
         };
 
         let after = if index == (self.stops.len() - 1) {
-            0
+            self.instance.end_node
         } else {
             self.stops[index + 1].cust_no
         };
@@ -492,10 +1967,7 @@ impl Route {
         new_cost -= self.instance.distance_matrix.dist(stop.cust_no, after);
         new_cost += self.instance.distance_matrix.dist(before, after);
 
-        (
-            new_cost,
-            self.used_cap - self.stops[index].capacity <= self.instance.vehicle_capacity,
-        )
+        (new_cost, within_capacity)
     }
 
     #[allow(dead_code)]
@@ -509,11 +1981,11 @@ impl Route {
         let before = if index != 0 {
             self.stops[index - 1].cust_no
         } else {
-            0
+            CustomerId::DEPOT
         };
 
         let after = if index == (self.stops.len() - 1) {
-            0
+            self.instance.end_node
         } else {
             self.stops[index + 1].cust_no
         };
@@ -527,9 +1999,8 @@ impl Route {
     }
 
     // -1      0         1
-    // 0 -> stop[0] -> stop[1] -...-> stop[len - 1] -> 0
+    // 0 -> stop[0] -> stop[1] -...-> stop[len - 1] -> end
     /// The cost of going from the previous index to `index`. (if `index` == `len`, cost of going home after...)
-    #[allow(dead_code)]
     pub fn cost_at_index(&self, index: usize) -> f64 {
         debug_assert!(index <= self.stops.len());
 
@@ -539,11 +2010,11 @@ impl Route {
             //         which we would have noticed by now if we did!!
             unsafe { self.stops.get_unchecked(index - 1).cust_no }
         } else {
-            0
+            CustomerId::DEPOT
         };
 
         let end = if index == self.stops.len() {
-            0
+            self.instance.end_node
         } else {
             // SAFETY: we have to trust here that index isn't > self.stops.len(), but we have debug
             //         asserts for that so i'm confident the logic elsewhere accounts for that...
@@ -558,6 +2029,7 @@ impl Route {
     #[cfg(debug_assertions)]
     pub fn assert_sanity(&self) {
         self.check_route_cost();
+        self.check_service_time();
         self.check_capacity();
         self.check_no_duplicate_stops();
     }
@@ -584,33 +2056,1411 @@ impl Route {
         }
 
         if !self.stops.is_empty() {
-            cost += self.instance.distance_matrix.dist(0, self.stops[0].cust_no);
             cost += self
                 .instance
                 .distance_matrix
-                .dist(self.stops[self.stops.len() - 1].cust_no, 0);
+                .dist(CustomerId::DEPOT, self.stops[0].cust_no);
+            cost += self.instance.distance_matrix.dist(
+                self.stops[self.stops.len() - 1].cust_no,
+                self.instance.end_node,
+            );
         }
         cost
     }
 
+    #[allow(dead_code)]
+    fn check_service_time(&self) {
+        assert!((self.recalculate_service_time() - self.service_time).abs() < 0.5f64);
+    }
+
+    fn recalculate_service_time(&self) -> f64 {
+        self.stops.iter().map(|s| self.service_time_of(s)).sum()
+    }
+
     #[allow(dead_code)]
     fn check_capacity(&self) {
         assert!(self.recalculate_capacity() == self.used_cap);
     }
 
     fn recalculate_capacity(&self) -> usize {
-        self.stops.iter().map(|s| s.capacity).sum()
+        self.stops.iter().map(|s| s.capacity()).sum()
     }
 
     #[allow(dead_code)]
     fn check_no_duplicate_stops(&self) {
-        let mut existing = HashSet::new();
+        // pairwise rather than with a set, so the checks don't allocate in the middle of a search
+        // that's meant not to
+        for (i, el) in self.stops.iter().enumerate() {
+            assert!(self.stops[i + 1..].iter().all(|s| s.cust_no != el.cust_no));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+    use super::*;
+
+    /// `n` customers of demand 1 scattered over a 100x100 square, with room for all of them in one
+    /// route.
+    fn scattered(n: usize, rng: &mut StdRng) -> VRPInstance {
+        let xs = (0..=n).map(|_| rng.random_range(0.0..100.0)).collect();
+        let ys = (0..=n).map(|_| rng.random_range(0.0..100.0)).collect();
+        VRPInstance::from_parts_unchecked(2, n, vec![1; n + 1], xs, ys)
+    }
+
+    /// Like [`scattered`], but every leg costs somewhere between the same and twice as much in one
+    /// direction as in the other.
+    fn one_way(n: usize, rng: &mut StdRng) -> VRPInstance {
+        let symmetric = scattered(n, rng);
+        let dm = &symmetric.distance_matrix;
+        let distances = (0..=n)
+            .map(|a| {
+                (0..=n)
+                    .map(|b| {
+                        let d = dm.dist(CustomerId(a as u32), CustomerId(b as u32));
+                        if a < b {
+                            d * rng.random_range(1.0..2.0)
+                        } else {
+                            d
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        VRPInstance::from_matrix(2, n, vec![1; n + 1], distances)
+    }
+
+    #[test]
+    fn small_matrices_keep_full_rows() {
+        let mut rng = StdRng::seed_from_u64(2024);
+        let instance = scattered(50, &mut rng);
+        assert!(!instance.distance_matrix.is_triangle());
+        assert!(DistanceMatrix::stores_triangle(TRIANGLE_MIN_NODES, true));
+        assert!(!DistanceMatrix::stores_triangle(TRIANGLE_MIN_NODES, false));
+    }
+
+    #[test]
+    fn a_triangle_holds_the_same_distances_as_full_rows() {
+        let distance = |a: usize, b: usize| (a.max(b) * 100 + a.min(b)) as f64;
+        let full = Storage::from_fn(30, false, distance);
+        let triangle = Storage::from_fn(30, true, distance);
+        for a in 0..30 {
+            for b in 0..30 {
+                assert_eq!(full.get(a, b), distance(a, b));
+                assert_eq!(triangle.get(a, b), distance(a, b));
+            }
+        }
+    }
+
+    /// What a lookup costs in full rows and in a triangle, three to a candidate spot as in
+    /// [`Route::speculative_add_stop`], at a size that keeps full rows and one that doesn't. Run
+    /// with `cargo test --release -- --ignored --nocapture triangle_lookup_cost`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn triangle_lookup_cost() {
+        let mut rng = StdRng::seed_from_u64(2024);
+        for n in [1_000, TRIANGLE_MIN_NODES] {
+            let points = (0..n)
+                .map(|_| (rng.random_range(0.0..100.0), rng.random_range(0.0..100.0)))
+                .collect::<Vec<(f64, f64)>>();
+            let distance = |a: usize, b: usize| Metric::Euclidean.distance(points[a], points[b]);
+            let spots = (0..3_000_000)
+                .map(|_| {
+                    let [prev, new, next] = [0; 3].map(|_| rng.random_range(0..n));
+                    (prev, new, next)
+                })
+                .collect::<Vec<_>>();
+            for triangle in [false, true] {
+                let matrix = DistanceMatrix::of(DistanceSource::F64(Storage::from_fn(
+                    n, triangle, distance,
+                )));
+                let start = std::time::Instant::now();
+                let mut total = 0.0;
+                for _ in 0..10 {
+                    for &(prev, new, next) in &spots {
+                        total += matrix.dist(prev, new) + matrix.dist(new, next)
+                            - matrix.dist(prev, next);
+                    }
+                }
+                let elapsed = start.elapsed();
+                println!(
+                    "{n} nodes, {}: {:.2}ns a candidate spot ({total:.0})",
+                    if triangle { "triangle" } else { "full rows" },
+                    elapsed.as_nanos() as f64 / (10 * spots.len()) as f64,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_kind_of_matrix_gives_the_same_distances() {
+        let mut rng = StdRng::seed_from_u64(2030);
+        let points = (0..40)
+            .map(|_| (rng.random_range(0.0..100.0), rng.random_range(0.0..100.0)))
+            .collect::<Vec<(f64, f64)>>();
+        let distance = |a: usize, b: usize| Metric::Euclidean.distance(points[a], points[b]);
+        let full = DistanceMatrix::from_fn(points.len(), DistancePrecision::F64, distance);
+        let triangle = DistanceMatrix::of(DistanceSource::F64(Storage::from_fn(
+            points.len(),
+            true,
+            distance,
+        )));
+        let computed =
+            DistanceMatrix::computed(points.clone(), Metric::Euclidean, DistanceRounding::Exact);
+        let mut f32_rows = DistanceMatrix::from_fn(points.len(), DistancePrecision::F64, distance);
+        f32_rows.set_precision(DistancePrecision::F32);
+        assert!(!full.full.is_null() && !full.is_triangle());
+        assert!(triangle.full.is_null() && f32_rows.full.is_null() && computed.full.is_null());
+
+        let mut matrices = [full, triangle, computed, f32_rows];
+        for matrix in &mut matrices {
+            matrix.forbid(3, 7);
+        }
+        for (kind, matrix) in matrices.iter().enumerate() {
+            assert_eq!(matrix.len(), points.len());
+            for a in 0..points.len() {
+                for b in 0..points.len() {
+                    let expected = if (a.min(b), a.max(b)) == (3, 7) {
+                        FORBIDDEN
+                    } else {
+                        distance(a, b)
+                    };
+                    let tolerance = if kind == 3 { 1e-4 } else { 0.0 };
+                    assert!(
+                        (matrix.dist(a, b) - expected).abs() <= tolerance,
+                        "kind {kind}: ({a}, {b}) is {} instead of {expected}",
+                        matrix.dist(a, b)
+                    );
+                }
+            }
+        }
+
+        // and back to full rows, which `dist` reads directly again
+        let mut back = std::mem::replace(&mut matrices[3], DistanceMatrix::new(Vec::new()));
+        back.set_precision(DistancePrecision::F64);
+        assert!(!back.full.is_null());
+        assert!((back.dist(5_usize, 9) - distance(5, 9)).abs() <= 1e-4);
+    }
+
+    #[test]
+    fn insertion_lower_bound_never_beats_a_feasible_insertion() {
+        let mut rng = StdRng::seed_from_u64(1957);
+        let n = 30;
+        for case in 0..40 {
+            let mut instance = match case % 4 {
+                0 => scattered(n, &mut rng),
+                1 => one_way(n, &mut rng),
+                2 => scattered(n, &mut rng)
+                    .with_end_location(90.0, 10.0)
+                    .unwrap(),
+                _ => scattered(n, &mut rng),
+            };
+            if case % 4 == 3 {
+                for _ in 0..20 {
+                    let (a, b) = (rng.random_range(0..=n), rng.random_range(0..=n));
+                    instance.forbid_edge(a as u32, b as u32).unwrap();
+                }
+            }
+            let instance = Arc::new(instance);
+
+            let mut customers = CustomerId::customers(instance.num_customers).collect::<Vec<_>>();
+            customers.shuffle(&mut rng);
+            let (in_route, outside) = customers.split_at(rng.random_range(0..10));
+            let mut route = Route::new(instance.clone(), RouteIdx(0));
+            for &c in in_route {
+                route.add_stop_to_index(Stop::new(c, 1), route.stops().len());
+            }
+
+            for &c in outside {
+                let stop = Stop::new(c, 1);
+                let bound = route.insertion_lower_bound(&stop);
+                for i in 0..=route.stops().len() {
+                    let (new_cost, feasible) = route.speculative_add_stop(&stop, i);
+                    let increase = new_cost - route.cost();
+                    assert!(
+                        !feasible || bound <= increase + 1e-9,
+                        "case {case}: inserting {c} at {i} of {route} costs {increase}, \
+                         but the bound is {bound}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// A solution on [`scattered`] customers with room for `vehicles` routes, some of them left
+    /// unused.
+    fn random_solution(n: usize, vehicles: usize, rng: &mut StdRng) -> VRPSolution {
+        let mut instance = scattered(n, rng);
+        instance.num_vehicles = vehicles;
+        let instance = Arc::new(instance);
+        let mut routes = vec![Vec::new(); vehicles];
+        for c in 1..=n as u32 {
+            routes[rng.random_range(0..vehicles)].push(c);
+        }
+        routes.shuffle(rng);
+        VRPSolution::from_routes_vec(&instance, &routes).unwrap()
+    }
+
+    /// `VRPSolution::to_string` from before it became `Display`, which the output JSON's
+    /// `Solution` has to stay identical to.
+    fn old_to_string(sol: &VRPSolution) -> String {
+        let route_strings: Vec<String> = sol
+            .to_routes_vec(true)
+            .iter()
+            .map(|route| {
+                let mut result = String::from("0");
+                for cust_no in route {
+                    write!(result, " {cust_no}").unwrap();
+                }
+                result.push_str(" 0");
+                result
+            })
+            .collect();
+        let mut combined = String::from("0 ");
+        combined.push_str(&route_strings.join(" "));
+        combined
+    }
+
+    #[test]
+    fn a_solution_displays_as_it_always_has_and_reads_back() {
+        let mut rng = StdRng::seed_from_u64(2049);
+        for case in 0..100 {
+            let (n, vehicles) = (rng.random_range(1..30), rng.random_range(1..8));
+            let sol = random_solution(n, vehicles, &mut rng);
+            let instance = sol.routes[0].instance.clone();
+            let displayed = sol.to_string();
+            assert_eq!(displayed, old_to_string(&sol), "case {case}");
+            assert_eq!(format!("{sol}"), displayed, "case {case}");
+
+            let read = VRPSolution::from_string(&displayed, &instance).unwrap();
+            assert_eq!(read.to_string(), displayed, "case {case}");
+            assert_eq!(read.cost(), sol.cost(), "case {case}");
+            let read = VRPSolution::from_file_string(&sol.to_file_string(), &instance).unwrap();
+            assert_eq!(read.to_file_string(), sol.to_file_string(), "case {case}");
+        }
+    }
+
+    /// Three customers round the depot at (0, 0), with room for three vehicles.
+    fn three_customers() -> Arc<VRPInstance> {
+        Arc::new(VRPInstance::from_parts_unchecked(
+            3,
+            10,
+            vec![0, 3, 4, 5],
+            vec![0.0, 0.0, 4.0, 0.0],
+            vec![0.0, 3.0, 0.0, -4.0],
+        ))
+    }
+
+    #[test]
+    fn routes_end_at_the_end_location() {
+        // every route finishes at (4, 3) rather than the depot at (0, 0)
+        let instance = Arc::new(
+            VRPInstance::from_parts_unchecked(
+                3,
+                10,
+                vec![0, 3, 4, 5],
+                vec![0.0, 0.0, 4.0, 0.0],
+                vec![0.0, 3.0, 0.0, -4.0],
+            )
+            .with_end_location(4.0, 3.0)
+            .unwrap(),
+        );
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1, 2], vec![3]]).unwrap();
+        // 0 -> (0, 3) is 3, on to (4, 0) is 5, and up to the end is 3
+        assert!((sol.routes[0].cost() - 11.0).abs() < 1e-9);
+        // out to (0, -4) is 4, and across to the end is sqrt(4^2 + 7^2)
+        assert!((sol.routes[1].cost() - (4.0 + 65f64.sqrt())).abs() < 1e-9);
+
+        let route = &sol.routes[0];
+        let third = Stop::new(3, 5);
+        // adding (0, -4) last swaps the 3 up to the end for sqrt(4^2 + 4^2) and sqrt(4^2 + 7^2)
+        let (cost, _) = route.speculative_add_stop(&third, 2);
+        assert!((cost - (8.0 + 32f64.sqrt() + 65f64.sqrt())).abs() < 1e-9);
+        // dropping (4, 0) leaves 3 out and 4 across to the end
+        let (cost, _) = route.speculative_remove_stop(1);
+        assert!((cost - 7.0).abs() < 1e-9);
+        // an empty route still costs nothing, and its first stop goes out and on to the end
+        let (cost, _) = Route::new(instance.clone(), RouteIdx(2)).speculative_add_stop(&third, 0);
+        assert!((cost - (4.0 + 65f64.sqrt())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn marginal_costs_are_each_customers_detour() {
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            2,
+            10,
+            vec![0, 1, 1, 1, 1],
+            vec![0.0, 0.0, 0.0, 4.0, 4.0],
+            vec![0.0, 3.0, 6.0, 3.0, 0.0],
+        ));
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1, 2], vec![3, 4]]).unwrap();
+
+        let expected = [
+            // (0, 3) is on the way out to (0, 6), so costs nothing
+            (1, 0.0),
+            // going on from (0, 3) to (0, 6) and back is 3 + 6, rather than 3 straight back
+            (2, 6.0),
+            // 5 out to (4, 3) and 3 down, rather than 4 straight out to (4, 0)
+            (3, 4.0),
+            // 3 down to (4, 0) and 4 back, rather than 5 straight back from (4, 3)
+            (4, 2.0),
+        ];
+        let marginal_costs = sol.marginal_costs();
+        assert_eq!(marginal_costs.len(), expected.len());
+        for ((cust_no, cost), (expected_cust_no, expected_cost)) in
+            marginal_costs.into_iter().zip(expected)
+        {
+            assert_eq!(cust_no, CustomerId(expected_cust_no));
+            assert!(
+                (cost - expected_cost).abs() < 1e-9,
+                "{cust_no:?} costs {cost}"
+            );
+        }
+    }
+
+    #[test]
+    fn the_longest_legs_are_found_in_and_across_routes() {
+        // 0 -> 1 -> 2 -> 3 -> 0 costs 1, 50, 2 and 3, and 0 -> 4 -> 0 costs 5 out and 4 back
+        let mut distances = vec![vec![60.0; 5]; 5];
+        for (a, b, d) in [
+            (0, 1, 1.0),
+            (1, 2, 50.0),
+            (2, 3, 2.0),
+            (3, 0, 3.0),
+            (0, 4, 5.0),
+        ] {
+            distances[a][b] = d;
+            distances[b][a] = d;
+        }
+        distances[4][0] = 4.0;
+        for (i, row) in distances.iter_mut().enumerate() {
+            row[i] = 0.0;
+        }
+        let instance = Arc::new(VRPInstance::from_matrix(
+            3,
+            10,
+            vec![0, 1, 1, 1, 1],
+            distances,
+        ));
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1, 2, 3], vec![4]]).unwrap();
+
+        let legs = sol.routes[0]
+            .legs()
+            .map(|(_, cost)| cost)
+            .collect::<Vec<_>>();
+        assert_eq!(legs, [1.0, 50.0, 2.0, 3.0]);
+        // the leg arriving at 2, the route's second stop
+        assert_eq!(sol.routes[0].longest_leg(), (1, 50.0));
+        assert_eq!(sol.routes[1].longest_leg(), (0, 5.0));
+        assert_eq!(sol.routes[2].longest_leg(), (0, 0.0));
+
+        assert_eq!(
+            sol.longest_legs(3),
+            [(0, 1, 50.0), (1, 0, 5.0), (1, 1, 4.0)]
+        );
+        assert_eq!(sol.longest_legs(10).len(), 6);
+    }
+
+    #[test]
+    fn bad_distances_are_refused_but_forbidden_ones_are_not() {
+        let matrix = |bad: f64| {
+            let mut distances = vec![vec![1.0; 3]; 3];
+            distances[1][2] = bad;
+            DistanceMatrix::new(distances)
+        };
+        for bad in [f64::NAN, f64::INFINITY, -1.0, FORBIDDEN * 2.0] {
+            match matrix(bad).check() {
+                Err(VrpError::InvalidDistance {
+                    from: 1,
+                    to: 2,
+                    value,
+                }) => {
+                    assert!(value.is_nan() || value == bad);
+                }
+                res => panic!("{bad}: {res:?}"),
+            }
+        }
+        assert_eq!(matrix(FORBIDDEN).check(), Ok(()));
+        assert!(matrix(FORBIDDEN).is_forbidden(1_usize, 2_usize));
+    }
+
+    #[test]
+    fn solutions_never_use_forbidden_edges() {
+        let mut instance = crate::generate::random_instance(30, 50, 1985);
+        // every customer's way to its nearest neighbour, and the first few's to the depot
+        for c in 1..=30 {
+            let nearest = instance.neighbors[c][0];
+            instance.forbid_edge(c as u32, nearest).unwrap();
+        }
+        for c in 1..=5 {
+            instance.forbid_edge(0, c).unwrap();
+        }
+        let instance = Arc::new(instance);
+
+        crate::random::seed(1985);
+        let params = crate::solver::SolveParams {
+            terminate: crate::solver::TermCond::MaxIters(1000),
+            ..crate::default_params(std::time::Duration::ZERO)
+        };
+        let sol = crate::solver::solve::<crate::solvers::ALNSSolver>(&instance, &params);
+        sol.validate(&instance).unwrap();
+        let dm = &instance.distance_matrix;
+        for route in &sol.routes {
+            let nodes = std::iter::once(0)
+                .chain(route.stops().iter().map(|s| s.cust_no().index()))
+                .chain(std::iter::once(0))
+                .collect::<Vec<_>>();
+            for leg in nodes.windows(2) {
+                assert!(!dm.is_forbidden(leg[0], leg[1]), "{leg:?}");
+            }
+        }
+        assert!(sol.cost() < FORBIDDEN);
+    }
+
+    #[test]
+    fn solutions_keep_their_exact_output() {
+        let instance = three_customers();
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1, 2], vec![3]]).unwrap();
+        assert_eq!(sol.to_string(), "0 0 1 2 0 0 3 0 0 0");
+        assert_eq!(sol.to_file_string(), "20.00 0\n0 1 2 0\n0 3 0\n0 0\n");
+        assert_eq!(sol.routes[0].to_string(), "r0[1(3) -> 2(4)--c7]");
+    }
+
+    #[test]
+    fn read_solutions_get_demands_and_costs_from_the_instance() {
+        let instance = three_customers();
+        let sol = VRPSolution::from_string("0 0 3 0 0 2 1 0 0 0", &instance).unwrap();
+        assert_eq!(sol.to_routes_vec(true), [vec![3], vec![2, 1], vec![]]);
+        assert_eq!(sol.routes[1].used_capacity(), 7);
+        assert_eq!(sol.cost(), 8.0 + 12.0);
+        assert!(sol.check().is_ok());
+
+        // the cost in a .sol file is only checked to be a number
+        let sol = VRPSolution::from_file_string("1.5 0\n0 1 2 0\n0 3 0\n", &instance).unwrap();
+        assert_eq!(sol.cost(), 12.0 + 8.0);
+    }
+
+    #[test]
+    fn check_finds_a_missing_customer() {
+        let instance = three_customers();
+        let sol = VRPSolution::from_routes_unchecked(&instance, &[vec![1], vec![3]]);
+        assert_eq!(
+            sol.check(),
+            Err(vec![SolutionViolation::MissingCustomer(CustomerId(2))])
+        );
+    }
+
+    #[test]
+    fn check_finds_a_stale_cost_and_load() {
+        let instance = three_customers();
+        let mut sol = VRPSolution::from_routes_vec(&instance, &[vec![1, 2], vec![3]]).unwrap();
+        assert_eq!(sol.check(), Ok(()));
+
+        sol.routes[1].cost += 5.0;
+        sol.routes[0].used_cap = 6;
+        assert_eq!(
+            sol.check(),
+            Err(vec![
+                SolutionViolation::StaleLoad {
+                    route: 0,
+                    cached: 6,
+                    recomputed: 7
+                },
+                SolutionViolation::StaleCost {
+                    route: 1,
+                    cached: 13.0,
+                    recomputed: 8.0
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn route_lists_round_trip() {
+        let instance = three_customers();
+        let routes = [vec![2, 1], vec![3]];
+        let sol = VRPSolution::from_routes_vec(&instance, &routes).unwrap();
+        sol.validate(&instance).unwrap();
+        assert_eq!(sol.num_vehicles(), 3);
+        assert_eq!(sol.to_routes_vec(false), routes);
+        assert_eq!(sol.to_routes_vec(true), [vec![2, 1], vec![3], vec![]]);
+        // 3 out, 5 across and 4 back, then 4 out and back
+        assert!((sol.cost() - 20.0).abs() < 1e-9);
+        for (route, load) in sol.routes.iter().zip([7, 5, 0]) {
+            assert_eq!(route.used_capacity(), load);
+        }
+
+        // empty routes in the middle only leave vehicles unused
+        let padded = VRPSolution::from_routes_vec(&instance, &[vec![], vec![3], vec![2, 1]]);
+        assert_eq!(padded.unwrap().to_routes_vec(false), [vec![3], vec![2, 1]]);
+    }
+
+    #[test]
+    fn bad_route_lists_are_refused() {
+        let instance = three_customers();
+        let from = |routes: &[Vec<u32>]| VRPSolution::from_routes_vec(&instance, routes).err();
+        assert_eq!(from(&[vec![1, 4]]), Some(VrpError::UnknownCustomer(4)));
+        assert_eq!(from(&[vec![0, 1]]), Some(VrpError::UnknownCustomer(0)));
+        assert_eq!(
+            from(&[vec![1, 2], vec![1]]),
+            Some(VrpError::DuplicateCustomer(CustomerId(1)))
+        );
+        assert_eq!(
+            from(&[vec![1, 2, 3]]),
+            Some(VrpError::OverCapacity {
+                route: 0,
+                load: 12,
+                cap: 10
+            })
+        );
+        assert_eq!(
+            from(&[vec![1], vec![2], vec![3], vec![]]),
+            Some(VrpError::TooManyRoutes {
+                routes: 4,
+                vehicles: 3
+            })
+        );
+    }
+
+    #[test]
+    fn solutions_that_cant_be_read_back_are_refused() {
+        let instance = three_customers();
+        let read = |s| VRPSolution::from_string(s, &instance).err();
+        assert_eq!(
+            read("0 0 1 4 0"),
+            Some(SolutionParseError::Invalid(VrpError::UnknownCustomer(4)))
+        );
+        assert_eq!(
+            read("0 0 1 2 0 0 1 0"),
+            Some(SolutionParseError::Invalid(VrpError::DuplicateCustomer(
+                CustomerId(1)
+            )))
+        );
+        assert_eq!(
+            read("0 0 1 0 0 2 0 0 3 0 0 0"),
+            Some(SolutionParseError::Invalid(VrpError::TooManyRoutes {
+                routes: 4,
+                vehicles: 3
+            }))
+        );
+        assert_eq!(
+            read("0 0 1 two 0"),
+            Some(SolutionParseError::NotANumber("two".to_string()))
+        );
+        assert_eq!(
+            read("0 0 1 2"),
+            Some(SolutionParseError::NotFromDepot { route: 0 })
+        );
+
+        let read_file = |s| VRPSolution::from_file_string(s, &instance).err();
+        assert_eq!(
+            read_file("cheap 0\n0 1 0\n"),
+            Some(SolutionParseError::NotANumber("cheap".to_string()))
+        );
+        assert_eq!(
+            read_file("9 0\n0 1 0\n2 3 0\n"),
+            Some(SolutionParseError::NotFromDepot { route: 1 })
+        );
+        assert_eq!(
+            read_file("# vrp-sol 3\n9 0\n0 1 0\n"),
+            Some(SolutionParseError::UnknownVersion("3".to_string()))
+        );
+    }
+
+    #[test]
+    fn a_one_way_route_costs_more_backwards() {
+        // 0 -> 1 -> 2 -> 3 -> 0 costs 1 a leg, and any other trip 10
+        let distances = (0..4)
+            .map(|a: usize| {
+                (0..4)
+                    .map(|b: usize| match (b + 4 - a) % 4 {
+                        0 => 0.0,
+                        1 => 1.0,
+                        _ => 10.0,
+                    })
+                    .collect()
+            })
+            .collect();
+        let instance = Arc::new(VRPInstance::from_matrix(1, 3, vec![0, 1, 1, 1], distances));
+        assert!(!instance.is_symmetric);
+
+        let forward = VRPSolution::from_routes_vec(&instance, &[vec![1, 2, 3]]).unwrap();
+        let backward = VRPSolution::from_routes_vec(&instance, &[vec![3, 2, 1]]).unwrap();
+        assert_eq!(forward.cost(), 4.0);
+        assert_eq!(backward.cost(), 40.0);
+        assert!(!forward.routes[0].is_reversible());
+    }
+
+    #[test]
+    fn oriented_routes_start_nearer_the_depot_at_the_same_cost() {
+        let mut rng = StdRng::seed_from_u64(1974);
+        let mut flipped_any = false;
+        for _ in 0..20 {
+            let instance = Arc::new(scattered(12, &mut rng));
+            let mut customers = (1..=12).collect::<Vec<u32>>();
+            customers.shuffle(&mut rng);
+            let (a, b) = customers.split_at(rng.random_range(1..12));
+            let mut sol =
+                VRPSolution::from_routes_vec(&instance, &[a.to_vec(), b.to_vec()]).unwrap();
+            let before = sol.clone();
+
+            sol.orient_for_output(&instance);
+            sol.validate(&instance).unwrap();
+            let dm = &instance.distance_matrix;
+            for (route, old) in sol.routes.iter().zip(&before.routes) {
+                assert!((route.cost() - old.cost()).abs() < 1e-9);
+                assert!(dm.dist(0_usize, route.first()) <= dm.dist(0_usize, route.last()));
+                flipped_any |= route.first() != old.first();
+            }
+        }
+        assert!(flipped_any, "nothing was flipped, so nothing was tested");
+    }
+
+    #[test]
+    fn one_way_routes_are_never_oriented() {
+        let mut rng = StdRng::seed_from_u64(1974);
+        let instance = Arc::new(one_way(12, &mut rng));
+        let routes = [(1..=6).rev().collect::<Vec<u32>>(), (7..=12).collect()];
+        let mut sol = VRPSolution::from_routes_vec(&instance, &routes).unwrap();
+        sol.orient_for_output(&instance);
+        assert_eq!(sol.to_routes_vec(false), routes);
+    }
+
+    #[test]
+    fn reversed_and_reordered_routes_are_the_same_plan() {
+        let mut rng = StdRng::seed_from_u64(2044);
+        let instance = Arc::new(scattered(12, &mut rng));
+        let plan = |routes: &[Vec<u32>]| VRPSolution::from_routes_vec(&instance, routes).unwrap();
+        let sol = plan(&[vec![1, 2, 3, 4, 5, 6], vec![7, 8, 9, 10, 11, 12]]);
+
+        for same in [
+            plan(&[vec![6, 5, 4, 3, 2, 1], vec![7, 8, 9, 10, 11, 12]]),
+            plan(&[vec![7, 8, 9, 10, 11, 12], vec![1, 2, 3, 4, 5, 6]]),
+            plan(&[vec![12, 11, 10, 9, 8, 7], vec![6, 5, 4, 3, 2, 1]]),
+        ] {
+            assert_eq!(same.canonical_hash(), sol.canonical_hash(), "{same}");
+            assert!(same.is_same_plan(&sol), "{same}");
+        }
+
+        let swapped = plan(&[vec![1, 3, 2, 4, 5, 6], vec![7, 8, 9, 10, 11, 12]]);
+        assert_ne!(swapped.canonical_hash(), sol.canonical_hash());
+        assert!(!swapped.is_same_plan(&sol));
+        let moved = plan(&[vec![1, 2, 3, 4, 5], vec![6, 7, 8, 9, 10, 11, 12]]);
+        assert!(!moved.is_same_plan(&sol));
+    }
+
+    #[test]
+    fn reversed_routes_are_different_plans_when_direction_matters() {
+        let mut rng = StdRng::seed_from_u64(2044);
+        for instance in [
+            scattered(6, &mut rng)
+                .with_end_location(50.0, 50.0)
+                .unwrap(),
+            one_way(6, &mut rng),
+        ] {
+            let instance = Arc::new(instance);
+            let plan =
+                |routes: &[Vec<u32>]| VRPSolution::from_routes_vec(&instance, routes).unwrap();
+            let sol = plan(&[vec![1, 2, 3], vec![4, 5, 6]]);
+            let reversed = plan(&[vec![3, 2, 1], vec![4, 5, 6]]);
+            assert_ne!(reversed.canonical_hash(), sol.canonical_hash());
+            assert!(!reversed.is_same_plan(&sol));
+            // but the order of the routes still doesn't matter
+            assert!(plan(&[vec![4, 5, 6], vec![1, 2, 3]]).is_same_plan(&sol));
+        }
+    }
+
+    #[test]
+    fn solutions_round_trip_through_json() {
+        let mut rng = StdRng::seed_from_u64(2045);
+        for case in 0..20 {
+            let sol = random_solution(rng.random_range(1..30), 5, &mut rng);
+            let instance = sol.routes[0].instance.clone();
+            let json = serde_json::to_string(&sol).unwrap();
+            let data: SerializedSolution = serde_json::from_str(&json).unwrap();
+            assert_eq!(data.routes, sol.to_routes_vec(false), "case {case}");
+
+            let read = VRPSolution::from_serialized(&data, &instance).unwrap();
+            assert!(read.is_same_plan(&sol), "case {case}");
+            assert_eq!(read.cost(), sol.cost(), "case {case}");
+            assert_eq!(serde_json::to_string(&read).unwrap(), json, "case {case}");
+        }
+    }
+
+    #[test]
+    fn serialized_solutions_are_checked_against_the_instance() {
+        let instance = three_customers();
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1, 2], vec![3]]).unwrap();
+        assert_eq!(
+            serde_json::to_string(&sol).unwrap(),
+            r#"{"Routes":[[1,2],[3]]}"#
+        );
+
+        let read = |json| {
+            let data = serde_json::from_str::<SerializedSolution>(json).unwrap();
+            VRPSolution::from_serialized(&data, &instance)
+        };
+        // nothing but the customers is read, so the costs and loads are the instance's
+        let sol = read(r#"{"Routes":[[3],[2,1]]}"#).unwrap();
+        assert_eq!(sol.cost(), 8.0 + 12.0);
+        assert_eq!(sol.routes[1].used_capacity(), 7);
+        assert_eq!(
+            read(r#"{"Routes":[[1,2,3]]}"#).err(),
+            Some(VrpError::OverCapacity {
+                route: 0,
+                load: 12,
+                cap: 10
+            })
+        );
+        assert_eq!(
+            read(r#"{"Routes":[[1],[1]]}"#).err(),
+            Some(VrpError::DuplicateCustomer(CustomerId(1)))
+        );
+    }
+
+    #[test]
+    fn solutions_keep_one_spare_route_while_theres_a_vehicle_for_it() {
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            3,
+            10,
+            vec![0, 1, 1, 1, 1],
+            vec![0.0, 1.0, 2.0, 3.0, 4.0],
+            vec![0.0; 5],
+        ));
+        let shape = |sol: &VRPSolution| {
+            sol.routes
+                .iter()
+                .map(|route| route.stops().len())
+                .collect::<Vec<_>>()
+        };
+        let mut sol = VRPSolution::new(&instance);
+        assert_eq!(shape(&sol), [0]);
+        sol.add_stop(0, Stop::new(1, 1), 0);
+        assert_eq!(shape(&sol), [1, 0]);
+        // adding to a route in use doesn't open another
+        sol.add_stop(0, Stop::new(2, 1), 1);
+        assert_eq!(shape(&sol), [2, 0]);
+        sol.add_stop(1, Stop::new(3, 1), 0);
+        assert_eq!(shape(&sol), [2, 1, 0]);
+        // the last vehicle has no spare after it
+        sol.add_stop(2, Stop::new(4, 1), 0);
+        assert_eq!(shape(&sol), [2, 1, 1]);
+        sol.validate(&instance).unwrap();
+
+        // emptying routes and compacting leaves the rest in order, then the spare
+        sol.routes[0].remove_stop_at_index(1);
+        sol.routes[0].remove_stop_at_index(0);
+        sol.compact();
+        assert_eq!(shape(&sol), [1, 1, 0]);
+        assert_eq!(sol.to_routes_vec(false), [vec![3], vec![4]]);
+        for (i, route) in sol.routes.iter().enumerate() {
+            assert_eq!(route.id(), RouteIdx(i));
+        }
+    }
+
+    #[test]
+    fn output_has_a_route_for_every_vehicle() {
+        let vehicles = 8;
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            vehicles,
+            10,
+            [vec![0], vec![1; 10]].concat(),
+            (0..=10).map(f64::from).collect(),
+            vec![0.0; 11],
+        ));
+        // one route in use, and its spare
+        let sol = VRPSolution::from_routes_vec(&instance, &[(1..=10).collect()]).unwrap();
+        assert_eq!(sol.routes.len(), 2);
+        assert_eq!(sol.to_routes_vec(true).len(), vehicles);
+        assert_eq!(sol.to_file_string().lines().count(), vehicles + 1);
+        let read_back = VRPSolution::from_file_string(&sol.to_file_string(), &instance).unwrap();
+        assert_eq!(read_back.to_routes_vec(true), sol.to_routes_vec(true));
+    }
+
+    #[test]
+    fn solutions_can_be_cloned_into_ones_of_another_shape() {
+        let instance = Arc::new(crate::generate::random_instance(30, 50, 2003));
+        crate::random::seed(2003);
+        let many = crate::construct::clarke_wright_and_then_sweep(&instance);
+        let mut sol = VRPSolution::new(&instance);
+        sol.clone_from(&many);
+        assert_eq!(sol.routes.len(), many.routes.len());
+        assert_eq!(sol.to_string(), many.to_string());
+        sol.validate(&instance).unwrap();
+        // and back down to just the spare
+        sol.clone_from(&VRPSolution::new(&instance));
+        assert_eq!(sol.routes.len(), 1);
+        assert!(sol.routes[0].stops().is_empty());
+    }
+
+    /// What an instance with far more vehicles than it needs costs per iteration. Only the routes
+    /// in use and one spare are scanned for each insertion, not every vehicle. Run with
+    /// `cargo test --release -- --ignored --nocapture spare_route_scan_cost`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn spare_route_scan_cost() {
+        let small = crate::generate::random_instance(120, 100, 2003);
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            100,
+            small.vehicle_capacity,
+            small.demand_of_customer.clone(),
+            small.x_coord_of_customer.clone(),
+            small.y_coord_of_customer.clone(),
+        ));
+        let params = crate::solver::SolveParams {
+            terminate: crate::solver::TermCond::MaxIters(200_000),
+            ..crate::default_params(std::time::Duration::ZERO)
+        };
+        crate::random::seed(2003);
+        let start = std::time::Instant::now();
+        let initial = (params.constructor)(&instance);
+        let (sol, stats) = crate::solver::solve_from_with_stats::<crate::solvers::ALNSSolver>(
+            &instance, &params, initial,
+        );
+        let insertions = stats.operators["best_insertion"].used;
+        println!(
+            "{} vehicles, {} routes used: {:.1} routes with room per insertion, {:.2?} for 200000 \
+             iterations, cost {:.2}",
+            instance.num_vehicles,
+            sol.to_routes_vec(false).len(),
+            stats.insertion_scans.considered as f64 / insertions as f64,
+            start.elapsed(),
+            sol.cost()
+        );
+    }
 
-        for el in &self.stops {
-            assert!(!existing.contains(&el.cust_no));
-            existing.insert(el.cust_no);
+    /// Every ordering of up to three of customers 1 to 4.
+    fn short_routes() -> Vec<Vec<u32>> {
+        let mut routes = vec![vec![]];
+        let mut last = vec![vec![]];
+        for _ in 0..3 {
+            last = last
+                .iter()
+                .flat_map(|route: &Vec<u32>| {
+                    (1..=4)
+                        .filter(|c| !route.contains(c))
+                        .map(move |c| [route.clone(), vec![c]].concat())
+                })
+                .collect();
+            routes.extend(last.iter().cloned());
+        }
+        routes
+    }
+
+    #[test]
+    fn speculative_costs_match_a_recalculation_for_short_routes() {
+        let mut rng = StdRng::seed_from_u64(1989);
+        for case in 0..3 {
+            let mut instance = match case {
+                0 => scattered(6, &mut rng),
+                1 => one_way(6, &mut rng),
+                _ => scattered(6, &mut rng)
+                    .with_end_location(90.0, 10.0)
+                    .unwrap(),
+            };
+            // three stops fill a vehicle, so adding to them is infeasible
+            instance.vehicle_capacity = 3;
+            let instance = Arc::new(instance);
+            let recalculated = |customers: &[u32]| {
+                let mut route = Route::new(instance.clone(), RouteIdx(0));
+                route.stops = customers.iter().map(|&c| Stop::new(c, 1)).collect();
+                route.recalculate_cost()
+            };
+
+            for customers in short_routes() {
+                let mut route = Route::new(instance.clone(), RouteIdx(0));
+                for &c in &customers {
+                    route.add_stop_to_index(Stop::new(c, 1), route.stops().len());
+                }
+                let len = customers.len();
+                let fits = len < 3;
+
+                for new in [5, 6] {
+                    let stop = Stop::new(new, 1);
+                    let costs = (0..=len)
+                        .map(|i| {
+                            let mut with = customers.clone();
+                            with.insert(i, new);
+                            recalculated(&with)
+                        })
+                        .collect::<Vec<_>>();
+                    for (i, &cost) in costs.iter().enumerate() {
+                        let (speculated, feasible) = route.speculative_add_stop(&stop, i);
+                        assert!(
+                            (speculated - cost).abs() < 1e-9,
+                            "case {case}: adding {new} to {customers:?} at {i}"
+                        );
+                        assert_eq!(feasible, fits);
+                    }
+
+                    let ((speculated, feasible), best) = route.speculative_add_best(&stop);
+                    let cheapest = costs.iter().copied().fold(f64::INFINITY, f64::min);
+                    assert!(
+                        (speculated - cheapest).abs() < 1e-9,
+                        "case {case}: best spot for {new} in {customers:?}"
+                    );
+                    assert!((costs[best] - cheapest).abs() < 1e-9);
+                    assert_eq!(feasible, fits);
+                }
+
+                for i in 0..len {
+                    let mut without = customers.clone();
+                    without.remove(i);
+                    let (speculated, feasible) = route.speculative_remove_stop(i);
+                    assert!(
+                        (speculated - recalculated(&without)).abs() < 1e-9,
+                        "case {case}: removing {i} from {customers:?}"
+                    );
+                    assert!(feasible);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn durations_are_travel_plus_service_and_can_be_speculated() {
+        let mut rng = StdRng::seed_from_u64(2021);
+        let mut instance = scattered(6, &mut rng);
+        instance.service_time_of_customer = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let instance = Arc::new(instance);
+        let mut route = Route::new(instance.clone(), RouteIdx(0));
+        for c in [1, 3, 5] {
+            route.add_stop_to_index(Stop::new(c, 1), route.stops().len());
+        }
+        assert_eq!(route.duration(), route.cost() + 9.0);
+
+        let two = Stop::new(2, 1);
+        let after = route.duration_after(route.speculative_add_stop(&two, 1).0, Some(&two), None);
+        route.add_stop_to_index(two, 1);
+        assert!((route.duration() - after).abs() < 1e-9);
+        assert!((route.duration() - (route.cost() + 11.0)).abs() < 1e-9);
+
+        let six = Stop::new(6, 1);
+        let after = route.duration_after(
+            route.speculative_replace_stop(&six, 0).0,
+            Some(&six),
+            Some(0),
+        );
+        route.replace_stop_at_index(six, 0);
+        assert!((route.duration() - after).abs() < 1e-9);
+
+        let after = route.duration_after(route.speculative_remove_stop(2).0, None, Some(2));
+        route.remove_stop_at_index(2);
+        assert!((route.duration() - after).abs() < 1e-9);
+        // 6, 2 and 5 are left
+        assert!((route.duration() - (route.cost() + 13.0)).abs() < 1e-9);
+
+        route.drain_stops(|s| s.cust_no().index() == 2);
+        assert!((route.duration() - (route.cost() + 11.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn draining_stops_updates_the_route_like_a_recompute() {
+        let mut rng = StdRng::seed_from_u64(1995);
+        let n = 40;
+        for case in 0..300 {
+            let mut instance = match case % 3 {
+                0 => scattered(n, &mut rng),
+                1 => one_way(n, &mut rng),
+                _ => scattered(n, &mut rng)
+                    .with_end_location(90.0, 10.0)
+                    .unwrap(),
+            };
+            instance.service_time_of_customer =
+                (0..=n).map(|_| rng.random_range(0.0..5.0)).collect();
+            let instance = Arc::new(instance);
+
+            let mut customers = CustomerId::customers(instance.num_customers).collect::<Vec<_>>();
+            customers.shuffle(&mut rng);
+            let mut route = Route::new(instance.clone(), RouteIdx(0));
+            for &c in &customers[..rng.random_range(1..=n)] {
+                route.add_stop_to_index(Stop::new(c, 1), route.stops().len());
+            }
+            let before = route.stops().clone();
+
+            // mostly a few stops, which are patched around, but sometimes most of them
+            let chance = if case % 2 == 0 { 0.15 } else { 0.8 };
+            let picked = before
+                .iter()
+                .map(|_| rng.random_bool(chance))
+                .collect::<Vec<_>>();
+            let mut pick = picked.iter();
+            let removed = route.drain_stops(|_| *pick.next().unwrap());
+
+            let (kept, expected_removed): (Vec<_>, Vec<_>) =
+                before.iter().zip(&picked).partition(|&(_, &p)| !p);
+            let kept = kept.into_iter().map(|(&s, _)| s).collect::<Vec<_>>();
+            let expected_removed = expected_removed
+                .into_iter()
+                .map(|(&s, _)| s)
+                .collect::<Vec<_>>();
+            assert_eq!(removed, expected_removed, "case {case}");
+            assert_eq!(route.stops(), &kept, "case {case}");
+            assert_eq!(route.used_cap, route.recalculate_capacity(), "case {case}");
+            assert!(
+                (route.cost - route.recalculate_cost()).abs() < 1e-6,
+                "case {case}: {} kept, {} recomputed",
+                route.cost,
+                route.recalculate_cost()
+            );
+            assert!(
+                (route.service_time - route.recalculate_service_time()).abs() < 1e-6,
+                "case {case}"
+            );
+        }
+    }
+
+    /// The ALNS loop's iteration rate on 1,000 customers, and what copying its routes costs with
+    /// 8-byte stops against the same routes as 16-byte `(u32, usize)` pairs, which is what `Stop`
+    /// was with a `usize` capacity. Run with
+    /// `cargo test --release -- --ignored --nocapture stop_size_cost`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn stop_size_cost() {
+        use std::time::Instant;
+
+        let instance = Arc::new(crate::generate::random_instance(1_000, 100, 2028));
+        let params = crate::solver::SolveParams {
+            terminate: crate::solver::TermCond::MaxIters(50_000),
+            ..crate::default_params(std::time::Duration::ZERO)
+        };
+        crate::random::seed(2028);
+        let initial = (params.constructor)(&instance);
+        let start = Instant::now();
+        let sol =
+            crate::solver::solve_from::<crate::solvers::ALNSSolver>(&instance, &params, initial);
+        let elapsed = start.elapsed();
+        println!(
+            "ALNS: {:.0} iterations/s, cost {:.2}",
+            50_000.0 / elapsed.as_secs_f64(),
+            sol.cost()
+        );
+
+        let stops = sol
+            .routes
+            .iter()
+            .map(|r| r.stops().to_vec())
+            .collect::<Vec<_>>();
+        let wide = stops
+            .iter()
+            .map(|r| r.iter().map(|s| (s.cust_no().0, s.capacity())).collect())
+            .collect::<Vec<Vec<_>>>();
+        let mut copy = stops.clone();
+        let mut wide_copy = wide.clone();
+        let start = Instant::now();
+        for _ in 0..20_000 {
+            copy.clone_from(std::hint::black_box(&stops));
+        }
+        let narrow = start.elapsed();
+        let start = Instant::now();
+        for _ in 0..20_000 {
+            wide_copy.clone_from(std::hint::black_box(&wide));
+        }
+        println!(
+            "copying the routes: {:.2?} with 8-byte stops, {:.2?} with 16-byte ones",
+            narrow / 20_000,
+            start.elapsed() / 20_000
+        );
+    }
+
+    /// Depot at the origin, customers 1 to 4 at (3, 0), (3, 4), (0, 4) and (-1, 0), with demands
+    /// 1 to 4, and routes 1 -> 2 and 3 with room left for an empty one.
+    fn drawable() -> (Arc<VRPInstance>, VRPSolution) {
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            3,
+            10,
+            vec![0, 1, 2, 3, 4],
+            vec![0.0, 3.0, 3.0, 0.0, -1.0],
+            vec![0.0, 0.0, 4.0, 4.0, 0.0],
+        ));
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1, 2], vec![3, 4]]).unwrap();
+        (instance, sol)
+    }
+
+    #[test]
+    fn geojson_has_a_line_per_used_route_and_a_point_per_node() {
+        let (instance, sol) = drawable();
+        assert!(sol.routes.iter().any(|r| r.stops.is_empty()));
+        let geojson: serde_json::Value = serde_json::from_str(&sol.to_geojson(&instance)).unwrap();
+        assert_eq!(geojson["type"], "FeatureCollection");
+        let features = geojson["features"].as_array().unwrap();
+        // no line for the empty route
+        assert_eq!(features.len(), 2 + 5);
+
+        let first = &features[0];
+        assert_eq!(first["geometry"]["type"], "LineString");
+        assert_eq!(
+            first["geometry"]["coordinates"],
+            serde_json::json!([[0.0, 0.0], [3.0, 0.0], [3.0, 4.0], [0.0, 0.0]])
+        );
+        assert_eq!(first["properties"]["Load"], 3);
+        assert_eq!(first["properties"]["Cost"], 12.0);
+        assert_eq!(features[1]["properties"]["Load"], 7);
+
+        for (c, point) in features[2..].iter().enumerate() {
+            assert_eq!(point["geometry"]["type"], "Point");
+            assert_eq!(
+                point["geometry"]["coordinates"],
+                serde_json::json!([
+                    instance.x_coord_of_customer[c],
+                    instance.y_coord_of_customer[c]
+                ])
+            );
+            assert_eq!(point["properties"]["Customer"], c);
+            assert_eq!(point["properties"]["Depot"], c == 0);
+            assert_eq!(point["properties"]["Demand"], c);
+        }
+
+        // routes to an end location finish there
+        let ending = Arc::new(
+            VRPInstance::from_parts_unchecked(
+                3,
+                10,
+                vec![0, 1, 2, 3, 4],
+                instance.x_coord_of_customer.clone(),
+                instance.y_coord_of_customer.clone(),
+            )
+            .with_end_location(9.0, 9.0)
+            .unwrap(),
+        );
+        let sol = VRPSolution::from_routes_vec(&ending, &[vec![1, 2, 3, 4]]).unwrap();
+        let geojson: serde_json::Value = serde_json::from_str(&sol.to_geojson(&ending)).unwrap();
+        let line = geojson["features"][0]["geometry"]["coordinates"]
+            .as_array()
+            .unwrap();
+        assert_eq!(line.len(), 6);
+        assert_eq!(line[5], serde_json::json!([9.0, 9.0]));
+    }
+
+    #[test]
+    fn svgs_fit_the_bounding_box_in_the_middle_without_stretching_it() {
+        let (instance, sol) = drawable();
+        let svg = sol.to_svg(&instance, 220, 120);
+        assert!(svg.starts_with(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"220\" height=\"120\" \
+             viewBox=\"0 0 220 120\">"
+        ));
+        // the 4 x 4 box is 25px a unit to fit the height, and centred across the width
+        assert!(svg.contains(
+            "<polyline points=\"85.0,110.0 160.0,110.0 160.0,10.0 85.0,110.0\" fill=\"none\" \
+             stroke=\"#1f77b4\""
+        ));
+        assert!(svg.contains(
+            "<polyline points=\"85.0,110.0 85.0,10.0 60.0,110.0 85.0,110.0\" fill=\"none\" \
+             stroke=\"#ff7f0e\""
+        ));
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        // circles grow with demand, up to the largest
+        assert!(svg.contains("<circle cx=\"160.0\" cy=\"110.0\" r=\"3.0\""));
+        assert!(svg.contains("<circle cx=\"60.0\" cy=\"110.0\" r=\"6.0\""));
+        assert_eq!(svg.matches("<circle").count(), 4);
+        assert!(svg.contains("<rect x=\"79.0\" y=\"104.0\" width=\"12.0\" height=\"12.0\""));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn svg_route_colours_cycle_and_a_single_point_is_centred() {
+        let n = ROUTE_COLOURS.len() + 2;
+        let angles = (0..=n).map(|i| i as f64).collect::<Vec<_>>();
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            n,
+            1,
+            vec![1; n + 1],
+            angles.iter().map(|a| a.cos()).collect(),
+            angles.iter().map(|a| a.sin()).collect(),
+        ));
+        let routes = (1..=n as u32).map(|c| vec![c]).collect::<Vec<_>>();
+        let sol = VRPSolution::from_routes_vec(&instance, &routes).unwrap();
+        let svg = sol.to_svg(&instance, 100, 100);
+        let strokes = svg
+            .lines()
+            .filter(|line| line.starts_with("<polyline"))
+            .map(|line| line.split("stroke=\"").nth(1).unwrap()[..7].to_string())
+            .collect::<Vec<_>>();
+        let expected = (0..n).map(|i| ROUTE_COLOURS[i % ROUTE_COLOURS.len()]);
+        assert_eq!(strokes, expected.collect::<Vec<_>>());
+
+        let point = Arc::new(VRPInstance::from_parts_unchecked(
+            1,
+            10,
+            vec![0, 1],
+            vec![5.0, 5.0],
+            vec![5.0, 5.0],
+        ));
+        let sol = VRPSolution::from_routes_vec(&point, &[vec![1]]).unwrap();
+        let svg = sol.to_svg(&point, 100, 60);
+        assert!(svg.contains("<polyline points=\"50.0,30.0 50.0,30.0 50.0,30.0\""));
+    }
+
+    #[test]
+    fn csv_rows_add_up_to_each_route_with_or_without_the_way_back() {
+        let (instance, sol) = drawable();
+        assert_eq!(
+            sol.to_csv(&instance, true),
+            "route_id,sequence,customer_id,demand,x,y,leg_distance,cumulative_distance,\
+             cumulative_load\n\
+             0,1,1,1,3,0,3,3,1\n\
+             0,2,2,2,3,4,4,7,3\n\
+             0,3,0,0,0,0,5,12,3\n\
+             1,1,3,3,0,4,4,4,3\n\
+             1,2,4,4,-1,0,4.123105625617661,8.123105625617661,7\n\
+             1,3,0,0,0,0,1,9.123105625617661,7\n"
+        );
+
+        let instance = Arc::new(crate::generate::random_instance(40, 50, 2039));
+        crate::random::seed(2039);
+        let sol = crate::construct::clarke_wright_and_then_sweep(&instance);
+        // each route's cumulative distance and load on its last row
+        let totals = |return_leg| {
+            let mut totals = std::collections::HashMap::new();
+            for row in sol.to_csv(&instance, return_leg).lines().skip(1) {
+                let fields = row.split(',').collect::<Vec<_>>();
+                let total = (
+                    fields[7].parse::<f64>().unwrap(),
+                    fields[8].parse::<usize>().unwrap(),
+                );
+                totals.insert(fields[0].to_string(), total);
+            }
+            totals
+        };
+        let (with, without) = (totals(true), totals(false));
+        for route in sol.routes.iter().filter(|r| !r.stops.is_empty()) {
+            let id = route.id.to_string();
+            let back = instance
+                .distance_matrix
+                .dist(route.stops.last().unwrap().cust_no.index(), 0);
+            assert!((with[&id].0 - route.cost()).abs() < 1e-9);
+            assert!((without[&id].0 - (route.cost() - back)).abs() < 1e-9);
+            assert_eq!(without[&id].1, route.used_capacity());
+        }
+    }
+
+    #[test]
+    fn csv_uses_the_instances_own_ids_and_leaves_out_missing_coordinates() {
+        let distances = vec![
+            vec![0.0, 2.0, 3.0],
+            vec![2.0, 0.0, 4.0],
+            vec![3.0, 4.0, 0.0],
+        ];
+        let mut instance = VRPInstance::from_matrix(1, 10, vec![0, 1, 2], distances);
+        instance.customer_ids = Some(vec!["depot".into(), "a, b".into(), "say \"c\"".into()]);
+        let instance = Arc::new(instance);
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1, 2]]).unwrap();
+        assert_eq!(
+            sol.to_csv(&instance, false)
+                .lines()
+                .skip(1)
+                .collect::<Vec<_>>(),
+            ["0,1,\"a, b\",1,,,2,2,1", "0,2,\"say \"\"c\"\"\",2,,,4,6,3"]
+        );
+    }
+
+    #[test]
+    fn customer_numbers_past_u16_survive_stops_and_parsing() {
+        let big = CustomerId::try_from(70_000_usize).unwrap();
+        assert_eq!(big.index(), 70_000);
+        assert_eq!(big.to_string(), "70000");
+        let stop = Stop::new(big, 9);
+        assert_eq!((stop.cust_no(), stop.capacity()), (big, 9));
+        assert_eq!(size_of::<Stop>(), 8);
+        assert_eq!(
+            parse_routes("0 0 70000 65536 0 0 0", 0).unwrap(),
+            [vec![70_000, 65_536], vec![]]
+        );
+        assert_eq!(
+            parse_file_routes("1 0\n0 65536 4294967295 0\n", 0).unwrap(),
+            [vec![65_536, u32::MAX]]
+        );
+    }
+
+    #[test]
+    fn f32_distances_cost_the_same_within_tolerance() {
+        let n = 400;
+        let generate = || {
+            let mut instance = scattered(n, &mut StdRng::seed_from_u64(2014));
+            // a vehicle to spare, so Clarke-Wright doesn't need to pack them perfectly
+            (instance.num_vehicles, instance.vehicle_capacity) = (11, n / 10);
+            instance
+        };
+        let exact = Arc::new(generate());
+        let halved = Arc::new(generate().with_distance_precision(DistancePrecision::F32));
+        assert_eq!(halved.distance_matrix.precision(), DistancePrecision::F32);
+        // the distances take half the memory, next to which the row pointers are nothing
+        let rows = (n + 1) * size_of::<Box<[f64]>>();
+        assert_eq!(
+            (halved.distance_matrix.bytes() - rows) * 2,
+            exact.distance_matrix.bytes() - rows
+        );
+
+        crate::random::seed(2014);
+        let sol = crate::construct::clarke_wright(&halved).unwrap();
+        assert!(sol.check().is_ok());
+        let same = VRPSolution::from_routes_vec(&exact, &sol.to_routes_vec(true)).unwrap();
+        assert!(same.check().is_ok());
+        assert!(
+            (sol.cost() - same.cost()).abs() < 1e-3,
+            "{} with f32 distances, {} with f64",
+            sol.cost(),
+            same.cost()
+        );
+    }
+
+    #[test]
+    fn detailed_sol_files_add_a_header_and_a_line_per_route_and_read_back() {
+        let (instance, sol) = drawable();
+        // 3 + 4 + 5, then 4 + sqrt(17) + 1
+        assert_eq!(
+            sol.to_file_string(),
+            "21.12 0\n0 1 2 0\n0 3 4 0\n0 0\n",
+            "plain files keep no header and no comments"
+        );
+        let detailed = sol.to_detailed_file_string();
+        assert_eq!(
+            detailed,
+            "# vrp-sol 2\n\
+             21.12 0\n\
+             # route 0 load=3 dist=12.00\n\
+             0 1 2 0\n\
+             # route 1 load=7 dist=9.12\n\
+             0 3 4 0\n\
+             # route 2 load=0 dist=0.00\n\
+             0 0\n"
+        );
+        for file in [detailed, sol.to_file_string()] {
+            let read = VRPSolution::from_file_string(&file, &instance).unwrap();
+            assert_eq!(read.to_routes_vec(false), sol.to_routes_vec(false));
+            assert_eq!(read.cost(), sol.cost());
         }
+    }
 
-        assert!(existing.len() == self.stops.len());
+    #[test]
+    fn cvrplib_output_matches_the_golden_file() {
+        let (instance, sol) = drawable();
+        let rounded = Arc::new(
+            VRPInstance::from_parts_unchecked(
+                instance.num_vehicles,
+                instance.vehicle_capacity,
+                instance.demand_of_customer.clone(),
+                instance.x_coord_of_customer.clone(),
+                instance.y_coord_of_customer.clone(),
+            )
+            .with_distance_rounding(DistanceRounding::Nearest)
+            .unwrap(),
+        );
+        let rounded_sol =
+            VRPSolution::from_routes_vec(&rounded, &sol.to_routes_vec(false)).unwrap();
+        // the empty third route is left out, and sqrt(17) rounds to 4
+        assert_eq!(
+            rounded_sol.to_cvrplib_string(),
+            include_str!("../tests/golden/drawable.cvrplib")
+        );
+        // unrounded distances keep their fraction
+        let exact = sol.to_cvrplib_string();
+        assert_eq!(
+            exact.lines().last(),
+            Some(format!("Cost {}", 17.0 + 17f64.sqrt()).as_str())
+        );
     }
 }
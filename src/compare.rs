@@ -0,0 +1,333 @@
+//! `--compare`: how the runs in one results log did against the runs on the same instances in
+//! another, e.g. the whole benchmark set before and after a parameter change.
+
+use std::{collections::HashMap, fmt, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    check::{LogRun, read_log},
+    error::CheckError,
+};
+
+/// Results this close count as a tie. Logs round them to two decimals.
+const TIE_TOLERANCE: f64 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Outcome {
+    Improved,
+    Regressed,
+    Tied,
+}
+
+/// One instance both logs have a run on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InstanceComparison {
+    pub instance: String,
+    /// `None` for a run that failed, which loses to any result
+    pub old: Option<f64>,
+    pub new: Option<f64>,
+    /// `new - old`, when both have a result
+    pub delta: Option<f64>,
+    pub change_percent: Option<f64>,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Comparison {
+    /// the instances in both logs, the biggest improvements first and those without a delta last
+    pub instances: Vec<InstanceComparison>,
+    pub only_in_old: Vec<String>,
+    pub only_in_new: Vec<String>,
+    pub improved: usize,
+    pub regressed: usize,
+    pub tied: usize,
+    /// the mean of `change_percent`, `None` if no instance has one
+    pub mean_change_percent: Option<f64>,
+    /// the mean change in `GapPercent` (the gap to the lower bound), over instances where both
+    /// runs logged one
+    pub mean_gap_change: Option<f64>,
+    /// the total change in `Time`, over instances where both runs logged one
+    pub total_time_change: f64,
+}
+
+impl Comparison {
+    /// Whether the results got worse on average by more than `threshold` percent.
+    pub fn regressed_beyond(&self, threshold: f64) -> bool {
+        self.mean_change_percent
+            .is_some_and(|change| change > threshold)
+    }
+}
+
+/// Compare the runs in the log at `new_path` to those on the same instances in `old_path`. An
+/// instance can only have one run in each log.
+pub fn compare(old_path: &Path, new_path: &Path) -> Result<Comparison, CheckError> {
+    let old = by_instance(old_path)?;
+    let new = by_instance(new_path)?;
+
+    let mut only_in_old = old
+        .keys()
+        .filter(|name| !new.contains_key(*name))
+        .cloned()
+        .collect::<Vec<_>>();
+    only_in_old.sort();
+    let mut only_in_new = new
+        .keys()
+        .filter(|name| !old.contains_key(*name))
+        .cloned()
+        .collect::<Vec<_>>();
+    only_in_new.sort();
+
+    let (mut gap_changes, mut total_time_change) = (Vec::new(), 0.0);
+    let mut instances = Vec::new();
+    for (name, old_run) in &old {
+        let Some(new_run) = new.get(name) else {
+            continue;
+        };
+        if let (Some(a), Some(b)) = (old_run.gap_percent, new_run.gap_percent) {
+            gap_changes.push(b - a);
+        }
+        if let (Some(a), Some(b)) = (old_run.time, new_run.time) {
+            total_time_change += b - a;
+        }
+        instances.push(InstanceComparison::of(name, old_run.result, new_run.result));
+    }
+    instances.sort_by(|a, b| {
+        let key = |c: &InstanceComparison| c.change_percent.unwrap_or(f64::INFINITY);
+        key(a)
+            .total_cmp(&key(b))
+            .then_with(|| a.instance.cmp(&b.instance))
+    });
+
+    let count = |outcome| instances.iter().filter(|c| c.outcome == outcome).count();
+    let changes = instances
+        .iter()
+        .filter_map(|c| c.change_percent)
+        .collect::<Vec<_>>();
+    Ok(Comparison {
+        improved: count(Outcome::Improved),
+        regressed: count(Outcome::Regressed),
+        tied: count(Outcome::Tied),
+        mean_change_percent: mean(&changes),
+        mean_gap_change: mean(&gap_changes),
+        total_time_change,
+        instances,
+        only_in_old,
+        only_in_new,
+    })
+}
+
+/// The runs in the log at `path` by instance name.
+fn by_instance(path: &Path) -> Result<HashMap<String, LogRun>, CheckError> {
+    let mut runs: HashMap<String, LogRun> = HashMap::new();
+    for run in read_log(path)? {
+        if let Some(earlier) = runs.get(&run.instance) {
+            return Err(CheckError::BadLine {
+                path: path.display().to_string(),
+                line: run.line,
+                reason: format!("{} was already run on line {}", run.instance, earlier.line),
+            });
+        }
+        runs.insert(run.instance.clone(), run);
+    }
+    Ok(runs)
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+impl InstanceComparison {
+    fn of(instance: &str, old: Option<f64>, new: Option<f64>) -> Self {
+        let delta = old.zip(new).map(|(old, new)| new - old);
+        let outcome = match (old, new) {
+            (Some(old), Some(new)) if (new - old).abs() <= TIE_TOLERANCE => Outcome::Tied,
+            (Some(old), Some(new)) if new < old => Outcome::Improved,
+            (Some(_), _) => Outcome::Regressed,
+            (None, Some(_)) => Outcome::Improved,
+            (None, None) => Outcome::Tied,
+        };
+        InstanceComparison {
+            instance: instance.to_string(),
+            old,
+            new,
+            delta,
+            change_percent: old
+                .zip(delta)
+                .filter(|&(old, _)| old != 0.0)
+                .map(|(old, delta)| delta / old * 100.0),
+            outcome,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let result = |r: Option<f64>| r.map_or_else(|| "--".to_string(), |r| format!("{r:.2}"));
+        let signed = |r: Option<f64>| r.map_or_else(|| "--".to_string(), |r| format!("{r:+.2}"));
+        let percent = |r: Option<f64>| r.map_or_else(|| "--".to_string(), |r| format!("{r:+.2}%"));
+        writeln!(
+            f,
+            "{:<24} {:>10} {:>10} {:>9} {:>8}",
+            "instance", "old", "new", "delta", "change"
+        )?;
+        for c in &self.instances {
+            writeln!(
+                f,
+                "{:<24} {:>10} {:>10} {:>9} {:>8}  {:?}",
+                c.instance,
+                result(c.old),
+                result(c.new),
+                signed(c.delta),
+                percent(c.change_percent),
+                c.outcome
+            )?;
+        }
+        for (log, names) in [("old", &self.only_in_old), ("new", &self.only_in_new)] {
+            if !names.is_empty() {
+                writeln!(f, "only in the {log} log: {}", names.join(" "))?;
+            }
+        }
+        writeln!(
+            f,
+            "{} improved, {} regressed, {} tied",
+            self.improved, self.regressed, self.tied
+        )?;
+        writeln!(
+            f,
+            "mean change {}, mean gap change {}, total time change {:+.2}s",
+            percent(self.mean_change_percent),
+            signed(self.mean_gap_change),
+            self.total_time_change
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Compare an old and a new log made of `old` and `new`'s lines.
+    fn compare_logs(test: &str, old: &[&str], new: &[&str]) -> Result<Comparison, CheckError> {
+        let dir = std::env::temp_dir().join(format!("vrp-compare-{}-{test}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("old.log"), old.join("\n")).unwrap();
+        fs::write(dir.join("new.log"), new.join("\n")).unwrap();
+        let comparison = compare(&dir.join("old.log"), &dir.join("new.log"));
+        fs::remove_dir_all(&dir).unwrap();
+        comparison
+    }
+
+    fn two_runs() -> Comparison {
+        compare_logs(
+            "runs",
+            &[
+                r#"{"Instance": "a", "Time": 10.0, "Result": 100.0, "GapPercent": 5.0}"#,
+                r#"{"Instance": "b", "Time": 20.0, "Result": 200.0}"#,
+                r#"{"Instance": "c", "Result": 50.0}"#,
+                r#"{"Instance": "d", "Time": "--", "Result": "--"}"#,
+                r#"{"Instance": "gone", "Result": 1.0}"#,
+            ],
+            &[
+                r#"{"Instance": "new", "Result": 1.0}"#,
+                r#"{"Instance": "d", "Result": 10.0}"#,
+                r#"{"Instance": "c", "Result": 50.004}"#,
+                r#"{"Instance": "b", "Time": 15.0, "Result": 202.0}"#,
+                r#"{"Instance": "a", "Time": 12.0, "Result": 90.0, "GapPercent": 3.0}"#,
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn runs_are_joined_by_instance_and_sorted_by_their_change() {
+        let comparison = two_runs();
+        let rows = comparison
+            .instances
+            .iter()
+            .map(|c| (c.instance.as_str(), c.old, c.new, c.outcome))
+            .collect::<Vec<_>>();
+        // a failed run has no change, so it comes last
+        assert_eq!(
+            rows,
+            [
+                ("a", Some(100.0), Some(90.0), Outcome::Improved),
+                ("c", Some(50.0), Some(50.004), Outcome::Tied),
+                ("b", Some(200.0), Some(202.0), Outcome::Regressed),
+                ("d", None, Some(10.0), Outcome::Improved),
+            ]
+        );
+        assert_eq!(comparison.instances[0].delta, Some(-10.0));
+        assert_eq!(comparison.instances[0].change_percent, Some(-10.0));
+        assert_eq!(comparison.instances[3].delta, None);
+        assert_eq!(comparison.only_in_old, ["gone"]);
+        assert_eq!(comparison.only_in_new, ["new"]);
+
+        assert_eq!(
+            (comparison.improved, comparison.regressed, comparison.tied),
+            (2, 1, 1)
+        );
+        let mean = comparison.mean_change_percent.unwrap();
+        assert!((mean - (-10.0 + 0.008 + 1.0) / 3.0).abs() < 1e-9, "{mean}");
+        assert_eq!(comparison.mean_gap_change, Some(-2.0));
+        assert_eq!(comparison.total_time_change, 2.0 - 5.0);
+        assert!(!comparison.regressed_beyond(0.0));
+        assert!(comparison.regressed_beyond(-5.0));
+    }
+
+    #[test]
+    fn the_table_lists_every_instance_and_the_totals() {
+        let table = two_runs().to_string();
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1 + 4 + 2 + 2, "{table}");
+        assert_eq!(
+            lines[1],
+            "a                            100.00      90.00    -10.00  -10.00%  Improved"
+        );
+        assert_eq!(
+            lines[4],
+            "d                                --      10.00        --       --  Improved"
+        );
+        assert_eq!(lines[5], "only in the old log: gone");
+        assert_eq!(lines[6], "only in the new log: new");
+        assert_eq!(lines[7], "2 improved, 1 regressed, 1 tied");
+        assert_eq!(
+            lines[8],
+            "mean change -3.00%, mean gap change -2.00, total time change -3.00s"
+        );
+    }
+
+    #[test]
+    fn failing_a_run_is_a_regression_and_repeated_instances_are_refused() {
+        let comparison = compare_logs(
+            "failed",
+            &[r#"{"Instance": "a", "Result": 100.0}"#],
+            &[r#"{"Instance": "a", "Result": "--"}"#],
+        )
+        .unwrap();
+        assert_eq!(comparison.instances[0].outcome, Outcome::Regressed);
+        assert_eq!(comparison.mean_change_percent, None);
+        assert!(!comparison.regressed_beyond(0.0));
+
+        let repeated = compare_logs(
+            "repeated",
+            &[r#"{"Instance": "a", "Result": 100.0}"#],
+            &[
+                r#"{"Instance": "a", "Result": 90.0}"#,
+                "",
+                r#"{"Instance": "a", "Result": 80.0}"#,
+            ],
+        );
+        match repeated {
+            Err(CheckError::BadLine { line, reason, .. }) => {
+                assert_eq!(line, 3);
+                assert_eq!(reason, "a was already run on line 1");
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+}
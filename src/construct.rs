@@ -1,13 +1,20 @@
 use std::sync::Arc;
 
+use rand::Rng;
 use rand::seq::SliceRandom;
-use rand::{Rng, rng};
 use rand_distr::{Distribution, Normal};
 
 use crate::common::Route;
+use crate::ids::{CustomerId, RouteIdx};
+use crate::random::rng;
 use crate::{common::Stop, common::VRPSolution, vrp_instance::VRPInstance};
 use std::cmp::Reverse;
 
+/// The standard deviation of the noise added to each Clarke-Wright saving, as a fraction of the
+/// mean saving, so the randomization means the same whatever units the distances are in (about a
+/// saving of 1 on the bundled instances).
+const CW_NOISE_FRACTION: f64 = 0.05;
+
 pub fn greedy(vrp_instance: &Arc<VRPInstance>) -> VRPSolution {
     let mut customer_nos: Vec<usize> = (1..vrp_instance.num_customers).collect();
     customer_nos.sort_by_key(|&i| Reverse(vrp_instance.demand_of_customer[i]));
@@ -17,17 +24,21 @@ pub fn greedy(vrp_instance: &Arc<VRPInstance>) -> VRPSolution {
     for cust_no in customer_nos {
         let demand = vrp_instance.demand_of_customer[cust_no];
         let mut found = false;
-        for vehicle_idx in 0..vrp_instance.num_vehicles {
+        for vehicle_idx in 0..sol.routes.len() {
             if vrp_instance.vehicle_capacity - sol.routes[vehicle_idx].used_capacity() >= demand {
                 let len = sol.routes[vehicle_idx].stops().len();
-                sol.routes[vehicle_idx]
-                    .add_stop_to_index(Stop::new(u16::try_from(cust_no).unwrap(), demand), len);
+                let stop = Stop::new(u32::try_from(cust_no).unwrap(), demand);
+                sol.add_stop(vehicle_idx, stop, len);
 
                 found = true;
                 break;
             }
         }
-        assert!(found, "greedy strategy doesn't work here!!");
+        assert!(
+            found,
+            "customer {cust_no} (demand {demand}) doesn't fit in any vehicle; check the instance \
+             with VRPInstance::validate before solving it"
+        );
     }
     sol
 }
@@ -46,10 +57,10 @@ pub fn cheapest_insertion(vrp_instance: &Arc<VRPInstance>) -> VRPSolution {
         let mut best_vehicle_idx: Option<usize> = None;
         let mut best_cost_delta = f64::MAX;
 
-        for vehicle_idx in 0..vrp_instance.num_vehicles {
+        for vehicle_idx in 0..sol.routes.len() {
             let route = &sol.routes[vehicle_idx];
             let ((cost, feasible), stop_idx) =
-                route.speculative_add_best(&Stop::new(u16::try_from(cust_no).unwrap(), demand));
+                route.speculative_add_best(&Stop::new(u32::try_from(cust_no).unwrap(), demand));
             if feasible && cost - route.cost() < best_cost_delta {
                 best_cost_delta = cost - route.cost();
                 best_stop_idx = Some(stop_idx);
@@ -61,8 +72,9 @@ pub fn cheapest_insertion(vrp_instance: &Arc<VRPInstance>) -> VRPSolution {
             "Could not insert cust no: {cust_no}"
         );
 
-        sol.routes[best_vehicle_idx.unwrap()].add_stop_to_index(
-            Stop::new(u16::try_from(cust_no).unwrap(), demand),
+        sol.add_stop(
+            best_vehicle_idx.unwrap(),
+            Stop::new(u32::try_from(cust_no).unwrap(), demand),
             best_stop_idx.unwrap(),
         );
     }
@@ -70,25 +82,25 @@ pub fn cheapest_insertion(vrp_instance: &Arc<VRPInstance>) -> VRPSolution {
 }
 
 pub fn sweep(vrp_instance: &Arc<VRPInstance>) -> Result<VRPSolution, String> {
+    if !vrp_instance.has_coordinates {
+        return Err("sweep needs coordinates".to_string());
+    }
     let mut sol = VRPSolution::new(vrp_instance);
 
     let mut customer_nos: Vec<usize> = (1..vrp_instance.num_customers).collect();
-    customer_nos.sort_by(|&a, &b| {
-        let angle_a = calculate_polar_angle(vrp_instance, a);
-        let angle_b = calculate_polar_angle(vrp_instance, b);
-        angle_a.total_cmp(&angle_b)
-    });
+    let angles = &vrp_instance.polar_angle_of_customer;
+    customer_nos.sort_by(|&a, &b| angles[a].total_cmp(&angles[b]));
     let shuffle_seed = rng().random_range(0..customer_nos.len());
     customer_nos.rotate_left(shuffle_seed);
 
     for cust_no in customer_nos {
         let demand = vrp_instance.demand_of_customer[cust_no];
         let mut found = false;
-        for vehicle_idx in 0..vrp_instance.num_vehicles {
+        for vehicle_idx in 0..sol.routes.len() {
             if vrp_instance.vehicle_capacity - sol.routes[vehicle_idx].used_capacity() >= demand {
                 let len = sol.routes[vehicle_idx].stops().len();
-                sol.routes[vehicle_idx]
-                    .add_stop_to_index(Stop::new(u16::try_from(cust_no).unwrap(), demand), len);
+                let stop = Stop::new(u32::try_from(cust_no).unwrap(), demand);
+                sol.add_stop(vehicle_idx, stop, len);
 
                 found = true;
                 break;
@@ -101,59 +113,66 @@ pub fn sweep(vrp_instance: &Arc<VRPInstance>) -> Result<VRPSolution, String> {
     Ok(sol)
 }
 
-fn calculate_polar_angle(vrp_instance: &Arc<VRPInstance>, cust_no: usize) -> f64 {
-    let depot_x = vrp_instance.x_coord_of_customer[0];
-    let depot_y = vrp_instance.y_coord_of_customer[0];
-    let cust_x = vrp_instance.x_coord_of_customer[cust_no];
-    let cust_y = vrp_instance.y_coord_of_customer[cust_no];
-
-    let delta_x = depot_x - cust_x;
-    let delta_y = depot_y - cust_y;
-
-    delta_y.atan2(delta_x)
-}
-
 pub fn clarke_wright(vrp: &Arc<VRPInstance>) -> Result<VRPSolution, String> {
     let n = vrp.num_customers;
 
+    // these are only scratch routes, so their ids don't matter until they're copied into a solution
     let mut routes: Vec<Route> = (1..n)
-        .map(|cust_no| Route::new(vrp.clone(), cust_no))
+        .map(|i| Route::new(vrp.clone(), RouteIdx(i - 1)))
         .collect();
     for (i, r) in routes.iter_mut().enumerate() {
         r.add_stop_to_index(
-            Stop::new(u16::try_from(i + 1).unwrap(), vrp.demand_of_customer[i + 1]),
+            Stop::new(u32::try_from(i + 1).unwrap(), vrp.demand_of_customer[i + 1]),
             0,
         );
     }
 
     let mut rng = rng();
-    let normal = Normal::new(1.0, 1.0).unwrap();
 
-    let mut savings: Vec<(usize, usize, f64)> = Vec::with_capacity((n - 1) * (n - 2) / 2);
+    // when distances (or where routes end) depend on direction, merging across i -> j and across
+    // j -> i save different amounts, so each gets its own saving
+    let directed = !vrp.is_symmetric || !vrp.end_node.is_depot();
+    let mut savings: Vec<(usize, usize, f64)> =
+        Vec::with_capacity((n - 1) * (n - 2) / if directed { 1 } else { 2 });
     for i in 1..n {
-        for j in i + 1..n {
-            let s = vrp.distance_matrix.dist(i, 0) + vrp.distance_matrix.dist(0, j)
+        for j in 1..n {
+            if j == i || (!directed && j < i) {
+                continue;
+            }
+            let end = usize::from(vrp.end_node);
+            // never merge two routes across an edge they can't use
+            if vrp.distance_matrix.is_forbidden(i, j) {
+                continue;
+            }
+            let s = vrp.distance_matrix.dist(i, end) + vrp.distance_matrix.dist(0, j)
                 - vrp.distance_matrix.dist(i, j);
-            savings.push((i, j, s + normal.sample(&mut rng)));
+            savings.push((i, j, s));
         }
     }
-    savings.sort_unstable_by(|a, b| (b.2).partial_cmp(&a.2).unwrap());
+    #[allow(clippy::cast_precision_loss)]
+    let mean_saving =
+        savings.iter().map(|&(_, _, s)| s.abs()).sum::<f64>() / savings.len().max(1) as f64;
+    // a zero standard deviation is fine, it just means no noise
+    let normal = Normal::new(0.0, CW_NOISE_FRACTION * mean_saving).unwrap();
+    for (_, _, s) in &mut savings {
+        *s += normal.sample(&mut rng);
+    }
+    savings.sort_unstable_by(|a, b| b.2.total_cmp(&a.2));
 
     for (i, j, _s) in savings {
         if routes.len() <= vrp.num_vehicles {
-            println!("breaking");
             break;
         }
 
         let Some(ri) = routes
             .iter()
-            .position(|r| r.contains_stop(u16::try_from(i).unwrap()))
+            .position(|r| r.contains_stop(CustomerId::try_from(i).unwrap()))
         else {
             continue;
         };
         let Some(rj) = routes
             .iter()
-            .position(|r| r.contains_stop(u16::try_from(j).unwrap()))
+            .position(|r| r.contains_stop(CustomerId::try_from(j).unwrap()))
         else {
             continue;
         };
@@ -161,59 +180,39 @@ pub fn clarke_wright(vrp: &Arc<VRPInstance>) -> Result<VRPSolution, String> {
             continue;
         }
 
-        // check for the "tail-to-head" merge: route_i.last == i, route_j.first == j
-        let (last_i, first_j) = (routes[ri].last(), routes[rj].first());
-        if last_i == i && first_j == j {
-            let cap_i = routes[ri].used_capacity();
-            let cap_j = routes[rj].used_capacity();
-            if cap_i + cap_j <= vrp.vehicle_capacity {
-                // take route_j out, append its stops onto route_i
-                let (mut head, tail);
-                if ri < rj {
-                    head = routes.remove(rj);
-                    tail = routes.remove(ri);
-                } else {
-                    head = routes.remove(ri);
-                    tail = routes.remove(rj);
-                }
-                // merge head <- tail
-                for stop in tail.stops().iter().copied() {
-                    head.add_stop_to_index(stop, head.stops().len());
-                }
-                routes.push(head);
-            }
+        // "tail-to-head": route_i ends with i and route_j starts with j, so route_j goes after
+        // route_i; or, when direction doesn't matter, the other way round
+        let (head, tail) = if routes[ri].last() == i && routes[rj].first() == j {
+            (ri, rj)
+        } else if !directed && routes[rj].last() == j && routes[ri].first() == i {
+            (rj, ri)
         } else {
-            let (last_j, first_i) = (routes[rj].last(), routes[ri].first());
-            if last_j == j && first_i == i {
-                let cap_i = routes[ri].used_capacity();
-                let cap_j = routes[rj].used_capacity();
-                if cap_i + cap_j <= vrp.vehicle_capacity {
-                    let (mut head, tail);
-                    if ri < rj {
-                        head = routes.remove(rj);
-                        tail = routes.remove(ri);
-                    } else {
-                        head = routes.remove(ri);
-                        tail = routes.remove(rj);
-                    }
-                    // merge head <- tail
-                    for stop in tail.stops().iter().copied() {
-                        head.add_stop_to_index(stop, head.stops().len());
-                    }
-                    routes.push(head);
-                }
-            }
+            continue;
+        };
+        let cap = routes[head].used_capacity() + routes[tail].used_capacity();
+        let len = routes[head].stops().len() + routes[tail].stops().len();
+        if cap > vrp.vehicle_capacity || len > vrp.max_route_len {
+            continue;
+        }
+
+        // take the tail route out, and append its stops onto the head
+        let tail_route = routes.remove(tail);
+        let mut merged = routes.remove(if tail < head { head - 1 } else { head });
+        for stop in tail_route.stops().iter().copied() {
+            merged.add_stop_to_index(stop, merged.stops().len());
         }
+        routes.push(merged);
     }
     if routes.len() > vrp.num_vehicles {
         return Err("didn't work".to_string());
     }
 
-    let mut sol = VRPSolution::new(vrp);
-    for (i, route) in routes.iter().enumerate() {
-        sol.routes[i] = route.clone();
-    }
-    Ok(sol)
+    // the merged routes' ids no longer match their positions, so build the solution afresh
+    let routes = routes
+        .iter()
+        .map(|r| r.stops().iter().map(|s| s.cust_no().0).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    VRPSolution::from_routes_vec(vrp, &routes).map_err(|e| e.to_string())
 }
 
 pub fn clarke_wright_and_then_sweep(vrp: &Arc<VRPInstance>) -> VRPSolution {
@@ -232,3 +231,147 @@ pub fn clarke_wright_and_then_sweep(vrp: &Arc<VRPInstance>) -> VRPSolution {
 
     greedy(vrp)
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    #[test]
+    fn sweep_orders_customers_as_it_did_before_the_angles_were_cached() {
+        let mut rng = StdRng::seed_from_u64(2020);
+        let xs: Vec<f64> = (0..80).map(|_| rng.random_range(-50.0..50.0)).collect();
+        let ys: Vec<f64> = (0..80).map(|_| rng.random_range(-50.0..50.0)).collect();
+        let mut demands = vec![1; 80];
+        demands[0] = 0;
+        // one vehicle big enough for everyone, so its route is the sweep order
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            1,
+            100,
+            demands,
+            xs.clone(),
+            ys.clone(),
+        ));
+
+        // the angle sweep used to work out on every comparison
+        let angle = |c: usize| (ys[0] - ys[c]).atan2(xs[0] - xs[c]);
+        for c in 1..80 {
+            assert_eq!(instance.polar_angle_of_customer[c], angle(c));
+        }
+        let mut expected: Vec<u32> = (1..80).collect();
+        expected.sort_by(|&a, &b| angle(a as usize).total_cmp(&angle(b as usize)));
+
+        crate::random::seed(2020);
+        for _ in 0..5 {
+            let route = sweep(&instance).unwrap().to_routes_vec(false).remove(0);
+            // sweep starts somewhere random, but goes round in the same order
+            let start = expected.iter().position(|&c| c == route[0]).unwrap();
+            let mut rotated = expected.clone();
+            rotated.rotate_left(start);
+            assert_eq!(route, rotated);
+        }
+    }
+
+    #[test]
+    fn clarke_wright_noise_scales_with_the_savings() {
+        // the same customers in metres around 10^7 and in the unit box
+        let original = crate::generate::random_instance(60, 100, 2029);
+        let far = |coords: &[f64]| coords.iter().map(|c| 1.0e7 + c * 1000.0).collect();
+        let far = Arc::new(
+            VRPInstance::from_parts(
+                original.num_vehicles,
+                original.vehicle_capacity,
+                original.demand_of_customer.clone(),
+                far(&original.x_coord_of_customer),
+                far(&original.y_coord_of_customer),
+            )
+            .unwrap(),
+        );
+        let (unit, scale) = far.normalized().unwrap();
+        let unit = Arc::new(unit);
+
+        let mut plans = Vec::new();
+        for seed in 0..5 {
+            crate::random::seed(seed);
+            let far_sol = clarke_wright(&far).unwrap();
+            crate::random::seed(seed);
+            let unit_sol = clarke_wright(&unit).unwrap();
+            // the noise is as big a share of the savings either way, so it merges the same routes
+            assert_eq!(far_sol.to_routes_vec(true), unit_sol.to_routes_vec(true));
+            assert!((scale.to_original(unit_sol.cost()) - far_sol.cost()).abs() < 1e-3);
+            plans.push(far_sol.to_routes_vec(true));
+        }
+        // and there's still noise in metres
+        plans.dedup();
+        assert!(plans.len() > 1);
+    }
+
+    #[test]
+    fn clarke_wright_merges_one_way_legs_in_their_direction() {
+        // 0 -> 1 -> 2 -> 3 -> 0 costs 1 a leg, and any other trip 10
+        let distances = (0..4)
+            .map(|a: usize| {
+                (0..4)
+                    .map(|b: usize| match (b + 4 - a) % 4 {
+                        0 => 0.0,
+                        1 => 1.0,
+                        _ => 10.0,
+                    })
+                    .collect()
+            })
+            .collect();
+        let instance = Arc::new(VRPInstance::from_matrix(1, 3, vec![0, 1, 1, 1], distances));
+
+        let sol = clarke_wright(&instance).unwrap();
+        assert_eq!(sol.to_routes_vec(false), [vec![1, 2, 3]]);
+        assert_eq!(sol.cost(), 4.0);
+    }
+
+    #[test]
+    fn clarke_wright_keeps_routes_within_the_longest_a_route_can_be() {
+        // 200 customers of demand 1 in vehicles of 150 used to merge past `max_route_len`, which
+        // cloning the solution afterwards tripped over
+        let mut rng = StdRng::seed_from_u64(1964);
+        let mut demands = vec![1; 201];
+        demands[0] = 0;
+        let xs = (0..201).map(|_| rng.random_range(0.0..100.0)).collect();
+        let ys = (0..201).map(|_| rng.random_range(0.0..100.0)).collect();
+        let instance = Arc::new(VRPInstance::from_parts(3, 150, demands, xs, ys).unwrap());
+        assert_eq!(instance.max_route_len, 150);
+
+        crate::random::seed(1964);
+
+        let sol = clarke_wright(&instance).unwrap();
+        sol.validate(&instance).unwrap();
+        for route in &sol.routes {
+            assert!(route.stops().len() <= instance.max_route_len);
+        }
+
+        let mut copy = greedy(&instance);
+        copy.clone_from(&sol);
+        assert_eq!(copy.to_routes_vec(false), sol.to_routes_vec(false));
+    }
+
+    #[test]
+    fn every_constructor_numbers_routes_by_their_index() {
+        let instance = Arc::new(crate::generate::random_instance(60, 40, 1965));
+        crate::random::seed(1965);
+
+        let solutions = [
+            ("greedy", greedy(&instance)),
+            ("cheapest_insertion", cheapest_insertion(&instance)),
+            ("sweep", sweep(&instance).unwrap()),
+            ("clarke_wright", clarke_wright(&instance).unwrap()),
+            (
+                "clarke_wright_and_then_sweep",
+                clarke_wright_and_then_sweep(&instance),
+            ),
+        ];
+        for (name, sol) in solutions {
+            for (i, route) in sol.routes.iter().enumerate() {
+                assert_eq!(route.id(), RouteIdx(i), "{name}");
+            }
+        }
+    }
+}
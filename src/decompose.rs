@@ -0,0 +1,485 @@
+use std::{sync::Arc, thread};
+
+use rand::Rng;
+
+use crate::{
+    affinity,
+    common::VRPSolution,
+    dbg_println,
+    random::{self, rng},
+    solver::{self, IterativeSolver, SolveParams},
+    vrp_instance::VRPInstance,
+};
+
+/// Instances with at least this many customers are decomposed before solving.
+pub const DECOMPOSE_THRESHOLD: usize = 5000;
+
+/// Roughly how many vehicles' worth of demand goes into each cluster.
+pub const VEHICLES_PER_CLUSTER: usize = 4;
+
+/// Whether `instance` is big enough to be worth decomposing, and has the coordinates to cluster by.
+/// A seed for each of `n` sub-solve threads, drawn from this thread's generator so a seeded run
+/// stays reproducible, but each thread's search still starts its own way.
+fn sub_solve_seeds(n: usize) -> Vec<u64> {
+    (0..n).map(|_| rng().random::<u64>()).collect()
+}
+
+pub fn should_decompose(instance: &VRPInstance) -> bool {
+    instance.num_customers >= DECOMPOSE_THRESHOLD && instance.has_coordinates
+}
+
+/// Share of the budget spent solving the clusters independently, the rest goes to the global polish.
+const SUB_SOLVE_FRAC: f64 = 0.7;
+
+/// Clusters smaller than this are just constructed, since the LNS solvers need a few customers to destroy.
+pub const MIN_CLUSTER_FOR_LNS: usize = 10;
+
+/// Solve a (very large) instance by splitting it into `num_clusters` angular sectors around the
+/// depot, solving each sector as its own sub-VRP with a share of the fleet, merging the results,
+/// and then running a shorter global search over the combined solution.
+///
+/// Falls back to solving the full instance directly if the fleet can't be split between the clusters.
+pub fn solve_decomposed<S: IterativeSolver>(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    num_clusters: usize,
+    max_threads: usize,
+) -> VRPSolution {
+    let clusters = sweep_clusters(instance, num_clusters);
+    let Some(fleet) = split_fleet(instance, &clusters) else {
+        dbg_println!("couldn't split the fleet over {} clusters", clusters.len());
+        return solver::solve::<S>(instance, params);
+    };
+
+    // only run `max_threads` sub-solves at once, splitting the sub-solve budget between batches
+    let batch_size = max_threads.max(1);
+    let num_batches = clusters.len().div_ceil(batch_size);
+    #[allow(clippy::cast_precision_loss)]
+    let sub_params = SolveParams {
+        terminate: params.terminate.scaled(SUB_SOLVE_FRAC / num_batches as f64),
+        // calibrating is left to the global pass, the clusters are too short-lived to pay it back
+        calibration: None,
+        // the clusters' costs don't add up to the whole solution's until they're stitched together
+        target_cost: None,
+        // nor are their bests the whole solution's
+        on_improvement: None,
+        // a whole-instance solution can't start a cluster's search
+        initial_solution: None,
+        ..params.clone()
+    };
+
+    let seeds = sub_solve_seeds(clusters.len());
+
+    let mut sub_solutions = Vec::with_capacity(clusters.len());
+    for batch in clusters
+        .iter()
+        .zip(&fleet)
+        .zip(&seeds)
+        .collect::<Vec<_>>()
+        .chunks(batch_size)
+    {
+        thread::scope(|s| {
+            let handles = batch
+                .iter()
+                .enumerate()
+                .map(|(core, ((cluster, vehicles), seed))| {
+                    let sub_params = &sub_params;
+                    s.spawn(move || {
+                        affinity::pin_to_core(core);
+                        random::seed(**seed);
+                        let sub_instance = Arc::new(sub_instance(instance, 0, cluster, **vehicles));
+                        let sol = if cluster.len() < MIN_CLUSTER_FOR_LNS {
+                            (sub_params.constructor)(&sub_instance)
+                        } else {
+                            solver::solve::<S>(&sub_instance, sub_params)
+                        };
+                        to_global_routes(&sol, cluster)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for handle in handles {
+                sub_solutions.push(handle.join().unwrap());
+            }
+        });
+    }
+
+    let routes = sub_solutions.into_iter().flatten().collect::<Vec<_>>();
+    let mut merged = VRPSolution::from_routes_vec(instance, &routes)
+        .expect("each cluster's routes are feasible and use its own share of the fleet");
+
+    free_boundary_customers(&mut merged, instance, &clusters);
+
+    let global_params = SolveParams {
+        terminate: params.terminate.scaled(1.0 - SUB_SOLVE_FRAC),
+        ..params.clone()
+    };
+    solver::solve_from::<S>(instance, &global_params, merged)
+}
+
+/// Split the customers into sectors by their angle around the depot, each holding about the same demand.
+fn sweep_clusters(instance: &Arc<VRPInstance>, num_clusters: usize) -> Vec<Vec<u32>> {
+    let mut customer_nos: Vec<usize> = (1..instance.num_customers).collect();
+    let angles = &instance.polar_angle_of_customer;
+    customer_nos.sort_by(|&a, &b| angles[a].total_cmp(&angles[b]));
+
+    let total_demand: usize = instance.demand_of_customer.iter().sum();
+    let target = total_demand.div_ceil(num_clusters.max(1));
+
+    let mut clusters = vec![Vec::new()];
+    let mut cluster_demand = 0;
+    for cust_no in customer_nos {
+        if cluster_demand >= target && clusters.len() < num_clusters {
+            clusters.push(Vec::new());
+            cluster_demand = 0;
+        }
+        clusters
+            .last_mut()
+            .unwrap()
+            .push(u32::try_from(cust_no).unwrap());
+        cluster_demand += instance.demand_of_customer[cust_no];
+    }
+
+    clusters.retain(|c| !c.is_empty());
+    clusters
+}
+
+/// Give each cluster at least enough vehicles to carry its demand, sharing the rest out
+/// proportionally to demand. Returns `None` if the fleet isn't big enough for that.
+pub fn split_fleet(instance: &Arc<VRPInstance>, clusters: &[Vec<u32>]) -> Option<Vec<usize>> {
+    let demands = clusters
+        .iter()
+        .map(|c| {
+            c.iter()
+                .map(|cust_no| instance.demand_of_customer[*cust_no as usize])
+                .sum::<usize>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut fleet = demands
+        .iter()
+        .map(|d| d.div_ceil(instance.vehicle_capacity).max(1))
+        .collect::<Vec<_>>();
+    let used: usize = fleet.iter().sum();
+    if used > instance.num_vehicles {
+        return None;
+    }
+
+    let total_demand: usize = demands.iter().sum();
+    let spare = instance.num_vehicles - used;
+    for (vehicles, demand) in fleet.iter_mut().zip(&demands) {
+        *vehicles += spare * demand / total_demand.max(1);
+    }
+
+    Some(fleet)
+}
+
+/// A standalone instance containing `depot` (as its depot) and just the customers in `cluster`,
+/// where customer `i` of the sub-instance is `cluster[i - 1]` of the full one.
+pub fn sub_instance(
+    instance: &Arc<VRPInstance>,
+    depot: u32,
+    cluster: &[u32],
+    num_vehicles: usize,
+) -> VRPInstance {
+    let global_idxs = std::iter::once(depot)
+        .chain(cluster.iter().copied())
+        .map(|c| c as usize)
+        .collect::<Vec<_>>();
+
+    let (mut demands, mut xs, mut ys) = (Vec::new(), Vec::new(), Vec::new());
+    for &i in &global_idxs {
+        demands.push(instance.demand_of_customer[i]);
+        xs.push(instance.x_coord_of_customer[i]);
+        ys.push(instance.y_coord_of_customer[i]);
+    }
+
+    let mut sub = if instance.has_coordinates {
+        VRPInstance::from_parts_unchecked(num_vehicles, instance.vehicle_capacity, demands, xs, ys)
+            .with_distance_rounding(instance.distance_rounding)
+            .and_then(|sub| sub.with_metric(instance.metric))
+            .expect("the full instance's distances were already checked")
+            .with_distance_precision(instance.distance_matrix.precision())
+    } else {
+        let distances = global_idxs
+            .iter()
+            .map(|&i| {
+                global_idxs
+                    .iter()
+                    .map(|&j| instance.distance_matrix.dist(i, j))
+                    .collect()
+            })
+            .collect();
+        VRPInstance::from_matrix(num_vehicles, instance.vehicle_capacity, demands, distances)
+            .with_distance_precision(instance.distance_matrix.precision())
+    };
+    if let Some((x, y)) = instance.end_location {
+        sub = sub
+            .with_end_location(x, y)
+            .expect("the full instance's distances were already checked");
+    }
+    sub.service_time_of_customer = global_idxs
+        .iter()
+        .map(|&i| instance.service_time_of_customer[i])
+        .collect();
+
+    // carry over the forbidden edges between nodes that made it into the sub-instance
+    let local_index = |global: u32| {
+        if global == instance.end_node.0 {
+            Some(sub.end_node.0)
+        } else {
+            std::iter::once(depot)
+                .chain(cluster.iter().copied())
+                .position(|c| c == global)
+                .map(|i| u32::try_from(i).unwrap())
+        }
+    };
+    let local_edges = instance
+        .forbidden_edges
+        .iter()
+        .filter_map(|&(a, b)| Some((local_index(a)?, local_index(b)?)))
+        .collect::<Vec<_>>();
+    for (a, b) in local_edges {
+        sub.forbid_edge(a, b)
+            .expect("local indices are within the sub-instance");
+    }
+    sub
+}
+
+/// The non-empty routes of a sub-instance solution, translated back to the full instance's customer numbers.
+fn to_global_routes(sol: &VRPSolution, cluster: &[u32]) -> Vec<Vec<u32>> {
+    sol.to_routes_vec(false)
+        .into_iter()
+        .map(|r| r.iter().map(|c| cluster[*c as usize - 1]).collect())
+        .collect()
+}
+
+/// Customers that are closer to another cluster than to their own were likely placed badly by
+/// the split, so pull each one out and put it back wherever is cheapest in the merged solution.
+fn free_boundary_customers(
+    sol: &mut VRPSolution,
+    instance: &Arc<VRPInstance>,
+    clusters: &[Vec<u32>],
+) {
+    let mut cluster_of = vec![usize::MAX; instance.num_customers];
+    for (c, cluster) in clusters.iter().enumerate() {
+        for cust_no in cluster {
+            cluster_of[*cust_no as usize] = c;
+        }
+    }
+
+    let dm = &instance.distance_matrix;
+    for cust_no in 1..instance.num_customers {
+        let (mut nearest_same, mut nearest_other) = (f64::MAX, f64::MAX);
+        for other in 1..instance.num_customers {
+            if other == cust_no {
+                continue;
+            }
+            let d = dm.dist(cust_no, other);
+            if cluster_of[other] == cluster_of[cust_no] {
+                nearest_same = nearest_same.min(d);
+            } else {
+                nearest_other = nearest_other.min(d);
+            }
+        }
+        if nearest_other >= nearest_same {
+            continue;
+        }
+
+        let cust_no = u32::try_from(cust_no).unwrap();
+        let Some((r, i)) = sol
+            .routes
+            .iter()
+            .enumerate()
+            .find_map(|(r, route)| route.index_of_stop(cust_no.into()).map(|i| (r, i)))
+        else {
+            continue;
+        };
+        let stop = sol.routes[r].remove_stop_at_index(i);
+
+        // the route it came from always has room, so this always finds somewhere
+        let (mut best_r, mut best_i, mut best_increase) = (r, i, f64::MAX);
+        for (r, route) in sol.routes.iter().enumerate() {
+            if route.used_capacity() + stop.capacity() > instance.vehicle_capacity {
+                continue;
+            }
+            for i in 0..=route.stops().len() {
+                let increase = route.speculative_add_stop(&stop, i).0 - route.cost();
+                if increase < best_increase {
+                    (best_r, best_i, best_increase) = (r, i, increase);
+                }
+            }
+        }
+        sol.add_stop(best_r, stop, best_i);
+    }
+    sol.compact();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+    use std::time::{Duration, Instant};
+
+    use rand::{SeedableRng, rngs::StdRng};
+    use rand_distr::{Distribution, Normal};
+
+    use super::*;
+    use crate::solver::TermCond;
+    use crate::solvers::MultiLNSSolver;
+
+    /// `k` tight clusters of `per_cluster` customers each, evenly spaced on a ring around the
+    /// depot, with a vehicle or two more than their demand needs.
+    fn ring_of_clusters(k: usize, per_cluster: usize, seed: u64) -> VRPInstance {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let spread = Normal::new(0.0, 2.0).unwrap();
+        let (mut demands, mut xs, mut ys) = (vec![0], vec![50.0], vec![50.0]);
+        for cluster in 0..k {
+            let angle = TAU * cluster as f64 / k as f64;
+            let (cx, cy) = (50.0 + 40.0 * angle.cos(), 50.0 + 40.0 * angle.sin());
+            for _ in 0..per_cluster {
+                demands.push(rng.random_range(1..=5));
+                xs.push(cx + spread.sample(&mut rng));
+                ys.push(cy + spread.sample(&mut rng));
+            }
+        }
+        let capacity = 50;
+        let vehicles = demands.iter().sum::<usize>().div_ceil(capacity) + k;
+        VRPInstance::from_parts_unchecked(vehicles, capacity, demands, xs, ys)
+    }
+
+    #[test]
+    fn each_sub_solve_gets_its_own_reproducible_seed() {
+        random::seed(1979);
+        let seeds = sub_solve_seeds(8);
+        let distinct = seeds.iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(distinct.len(), 8);
+
+        random::seed(1979);
+        assert_eq!(sub_solve_seeds(8), seeds);
+        random::seed(1980);
+        assert_ne!(sub_solve_seeds(8), seeds);
+    }
+
+    #[test]
+    fn sweep_clusters_split_the_customers_by_angle_and_demand() {
+        let instance = Arc::new(ring_of_clusters(6, 30, 1959));
+        let clusters = sweep_clusters(&instance, 6);
+        assert_eq!(clusters.len(), 6);
+
+        let mut all = clusters.concat();
+        all.sort_unstable();
+        assert_eq!(all, (1..instance.num_customers as u32).collect::<Vec<_>>());
+
+        let angle = |c: &u32| instance.polar_angle_of_customer[*c as usize];
+        let demand = |cluster: &[u32]| {
+            cluster
+                .iter()
+                .map(|&c| instance.demand_of_customer[c as usize])
+                .sum::<usize>()
+        };
+        let target = instance
+            .demand_of_customer
+            .iter()
+            .sum::<usize>()
+            .div_ceil(6);
+        for pair in clusters.windows(2) {
+            // one sector ends before the next starts
+            let last = pair[0].iter().map(angle).fold(f64::MIN, f64::max);
+            let first = pair[1].iter().map(angle).fold(f64::MAX, f64::min);
+            assert!(last <= first);
+            // and each is cut off as soon as it reaches its share of the demand
+            assert!(demand(&pair[0]) >= target);
+            assert!(demand(&pair[0]) < target + 5);
+        }
+    }
+
+    #[test]
+    fn split_fleet_covers_each_clusters_demand() {
+        let instance = Arc::new(ring_of_clusters(6, 30, 1959));
+        let clusters = sweep_clusters(&instance, 6);
+        let fleet = split_fleet(&instance, &clusters).unwrap();
+        assert!(fleet.iter().sum::<usize>() <= instance.num_vehicles);
+        for (cluster, vehicles) in clusters.iter().zip(&fleet) {
+            let demand = cluster
+                .iter()
+                .map(|&c| instance.demand_of_customer[c as usize])
+                .sum::<usize>();
+            assert!(vehicles * instance.vehicle_capacity >= demand);
+        }
+
+        let mut short = ring_of_clusters(6, 30, 1959);
+        short.num_vehicles = 1;
+        assert_eq!(split_fleet(&Arc::new(short), &clusters), None);
+    }
+
+    #[test]
+    fn a_sub_instance_maps_back_to_the_full_one() {
+        let instance = Arc::new(ring_of_clusters(4, 10, 1959));
+        let cluster = vec![3, 17, 29, 40];
+        let sub = Arc::new(sub_instance(&instance, 0, &cluster, 2));
+        assert_eq!(sub.num_customers, 5);
+        assert_eq!(sub.num_vehicles, 2);
+        for (local, &global) in cluster.iter().enumerate() {
+            let local = local + 1;
+            assert_eq!(
+                sub.demand_of_customer[local],
+                instance.demand_of_customer[global as usize]
+            );
+            assert_eq!(
+                sub.distance_matrix.dist(0, local),
+                instance.distance_matrix.dist(0, global as usize)
+            );
+        }
+
+        let sol = VRPSolution::from_routes_vec(&sub, &[vec![2, 1], vec![4, 3]]).unwrap();
+        assert_eq!(
+            to_global_routes(&sol, &cluster),
+            [vec![17, 3], vec![40, 29]]
+        );
+    }
+
+    #[test]
+    fn a_decomposed_solve_covers_every_customer() {
+        let instance = Arc::new(ring_of_clusters(8, 25, 1959));
+        random::seed(1959);
+        let params = SolveParams {
+            terminate: TermCond::MaxIters(300),
+            ..crate::default_params(Duration::ZERO)
+        };
+        let sol = solve_decomposed::<MultiLNSSolver>(&instance, &params, 8, 2);
+        assert_eq!(sol.check(), Ok(()));
+    }
+
+    /// On a ring of tight clusters, solving each cluster on its own and then polishing beats
+    /// searching the whole instance for the same time. Run it with
+    /// `cargo test --release -- --ignored --nocapture decomposing_beats_the_flat_solver`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn decomposing_beats_the_flat_solver() {
+        let instance = Arc::new(ring_of_clusters(40, 75, 1959));
+        let budget = Duration::from_secs(5);
+        let params = SolveParams {
+            terminate: TermCond::TimeElapsed(budget),
+            ..crate::default_params(budget)
+        };
+
+        random::seed(1959);
+        let start = Instant::now();
+        let flat = solver::solve::<MultiLNSSolver>(&instance, &params);
+        let flat_time = start.elapsed();
+        random::seed(1959);
+        let start = Instant::now();
+        // one thread, like the flat solve, so the only difference is the decomposition
+        let decomposed = solve_decomposed::<MultiLNSSolver>(&instance, &params, 40, 1);
+        let decomposed_time = start.elapsed();
+
+        println!(
+            "{} customers: flat {:.1} in {flat_time:.1?}, decomposed {:.1} in {decomposed_time:.1?}",
+            instance.num_customers - 1,
+            flat.cost(),
+            decomposed.cost()
+        );
+        assert_eq!(decomposed.check(), Ok(()));
+        assert!(decomposed.cost() < flat.cost());
+    }
+}
@@ -0,0 +1,297 @@
+//! `--diff`: how two solutions to the same instance differ, for seeing what a change to the search
+//! does to the plans it finds and not just to their cost.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    sync::Arc,
+};
+
+use serde::Serialize;
+
+use crate::{
+    common::{Route, VRPSolution},
+    ids::RouteIdx,
+    vrp_instance::VRPInstance,
+};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SolutionDiff {
+    pub cost_a: f64,
+    pub cost_b: f64,
+    /// [`VRPSolution::distance`] between the two: the square root of how many pairs of customers
+    /// share a route in one solution but not the other
+    pub distance: f64,
+    /// the customers whose route in `b` isn't the one matched to their route in `a`
+    pub moved: Vec<u32>,
+    /// edges `a` uses and `b` doesn't, with the depot as 0 (and an end location as the node after
+    /// the last customer); either way round on symmetric instances
+    pub edges_only_in_a: Vec<(u32, u32)>,
+    pub edges_only_in_b: Vec<(u32, u32)>,
+    /// the non-empty routes of both, each paired with the route of the other that shares the most
+    /// customers with it, or with nothing if they run out
+    pub routes: Vec<RoutePair>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RoutePair {
+    pub a: Option<RouteIdx>,
+    pub b: Option<RouteIdx>,
+    /// how many customers the two routes have in common
+    pub shared: usize,
+    /// the routes' costs, 0 for a missing one
+    pub cost_a: f64,
+    pub cost_b: f64,
+}
+
+impl RoutePair {
+    pub fn delta(&self) -> f64 {
+        self.cost_b - self.cost_a
+    }
+}
+
+impl VRPSolution {
+    /// How `other` differs from `self`, see [`SolutionDiff`]. Both have to visit every customer
+    /// of `instance` once.
+    pub fn diff(&self, other: &Self, instance: &Arc<VRPInstance>) -> SolutionDiff {
+        let (a, b) = (used_routes(self), used_routes(other));
+
+        // pair routes greedily, the most shared customers first
+        let mut candidates = Vec::new();
+        for (i, ra) in a.iter().enumerate() {
+            for (j, rb) in b.iter().enumerate() {
+                let shared = ra
+                    .stops()
+                    .iter()
+                    .filter(|s| rb.contains_stop(s.cust_no()))
+                    .count();
+                if shared > 0 {
+                    candidates.push((shared, i, j));
+                }
+            }
+        }
+        candidates.sort_by(|x, y| y.0.cmp(&x.0).then((x.1, x.2).cmp(&(y.1, y.2))));
+        let (mut match_of_a, mut matched_b) = (vec![None; a.len()], vec![false; b.len()]);
+        let mut routes = Vec::new();
+        for (shared, i, j) in candidates {
+            if match_of_a[i].is_none() && !matched_b[j] {
+                match_of_a[i] = Some(j);
+                matched_b[j] = true;
+                routes.push(RoutePair {
+                    a: Some(a[i].id()),
+                    b: Some(b[j].id()),
+                    shared,
+                    cost_a: a[i].cost(),
+                    cost_b: b[j].cost(),
+                });
+            }
+        }
+        routes.sort_by_key(|pair| pair.a);
+        routes.extend(
+            (0..a.len())
+                .filter(|&i| match_of_a[i].is_none())
+                .map(|i| RoutePair {
+                    a: Some(a[i].id()),
+                    b: None,
+                    shared: 0,
+                    cost_a: a[i].cost(),
+                    cost_b: 0.0,
+                }),
+        );
+        routes.extend((0..b.len()).filter(|&j| !matched_b[j]).map(|j| RoutePair {
+            a: None,
+            b: Some(b[j].id()),
+            shared: 0,
+            cost_a: 0.0,
+            cost_b: b[j].cost(),
+        }));
+
+        let route_in_b = b
+            .iter()
+            .enumerate()
+            .flat_map(|(j, r)| r.stops().iter().map(move |s| (s.cust_no().0, j)))
+            .collect::<HashMap<_, _>>();
+        let mut moved = a
+            .iter()
+            .enumerate()
+            .flat_map(|(i, r)| r.stops().iter().map(move |s| (s.cust_no().0, i)))
+            .filter(|(c, i)| match_of_a[*i] != route_in_b.get(c).copied())
+            .map(|(c, _)| c)
+            .collect::<Vec<_>>();
+        moved.sort_unstable();
+
+        let (edges_a, edges_b) = (edges(&a, instance), edges(&b, instance));
+        SolutionDiff {
+            cost_a: self.cost(),
+            cost_b: other.cost(),
+            distance: VRPSolution::distance(self, other, instance),
+            moved,
+            edges_only_in_a: edges_a.difference(&edges_b).copied().collect(),
+            edges_only_in_b: edges_b.difference(&edges_a).copied().collect(),
+            routes,
+        }
+    }
+}
+
+fn used_routes(sol: &VRPSolution) -> Vec<&Route> {
+    sol.routes
+        .iter()
+        .filter(|r| !r.stops().is_empty())
+        .collect()
+}
+
+fn edges(routes: &[&Route], instance: &VRPInstance) -> BTreeSet<(u32, u32)> {
+    let end = instance.end_node.0;
+    let mut edges = BTreeSet::new();
+    for route in routes {
+        let nodes = std::iter::once(0)
+            .chain(route.stops().iter().map(|s| s.cust_no().0))
+            .chain(std::iter::once(end))
+            .collect::<Vec<_>>();
+        for pair in nodes.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            edges.insert(if instance.is_symmetric {
+                (from.min(to), from.max(to))
+            } else {
+                (from, to)
+            });
+        }
+    }
+    edges
+}
+
+impl fmt::Display for SolutionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "cost      {:.2} -> {:.2} ({:+.2})",
+            self.cost_a,
+            self.cost_b,
+            self.cost_b - self.cost_a
+        )?;
+        writeln!(f, "distance  {:.2}", self.distance)?;
+        writeln!(
+            f,
+            "edges     {} only in a, {} only in b",
+            self.edges_only_in_a.len(),
+            self.edges_only_in_b.len()
+        )?;
+        write!(f, "moved     {} customers", self.moved.len())?;
+        if !self.moved.is_empty() {
+            let moved = self.moved.iter().map(ToString::to_string);
+            write!(f, ": {}", moved.collect::<Vec<_>>().join(" "))?;
+        }
+        writeln!(f)?;
+        let route = |r: Option<RouteIdx>| r.map_or_else(|| "-".to_string(), |r| r.to_string());
+        for pair in &self.routes {
+            writeln!(
+                f,
+                "route {:>3} -> {:>3}  {:>3} shared  {:>9.2} -> {:>9.2} ({:+.2})",
+                route(pair.a),
+                route(pair.b),
+                pair.shared,
+                pair.cost_a,
+                pair.cost_b,
+                pair.delta()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Depot at the origin and customers 1 to 4 at (3, 0), (3, 4), (0, 4) and (-1, 0).
+    fn square() -> Arc<VRPInstance> {
+        Arc::new(VRPInstance::from_parts_unchecked(
+            3,
+            10,
+            vec![0, 1, 2, 3, 4],
+            vec![0.0, 3.0, 3.0, 0.0, -1.0],
+            vec![0.0, 0.0, 4.0, 4.0, 0.0],
+        ))
+    }
+
+    fn sol(instance: &Arc<VRPInstance>, routes: &[Vec<u32>]) -> VRPSolution {
+        VRPSolution::from_routes_vec(instance, routes).unwrap()
+    }
+
+    #[test]
+    fn a_solution_has_nothing_to_diff_against_itself() {
+        let instance = square();
+        let a = sol(&instance, &[vec![1, 2], vec![3, 4]]);
+        let diff = a.diff(&a.clone(), &instance);
+        assert_eq!(diff.distance, 0.0);
+        assert!(diff.moved.is_empty());
+        assert!(diff.edges_only_in_a.is_empty() && diff.edges_only_in_b.is_empty());
+        assert_eq!(diff.routes.len(), 2);
+        assert!(diff.routes.iter().all(|pair| pair.delta() == 0.0));
+    }
+
+    #[test]
+    fn moved_customers_edges_and_route_pairs_are_reported() {
+        let instance = square();
+        let a = sol(&instance, &[vec![1, 2], vec![3, 4]]);
+        let b = sol(&instance, &[vec![1, 2, 3], vec![4]]);
+        let diff = a.diff(&b, &instance);
+
+        assert_eq!(diff.moved, [3]);
+        assert_eq!(diff.edges_only_in_a, [(0, 2), (3, 4)]);
+        assert_eq!(diff.edges_only_in_b, [(2, 3)]);
+        let pairs = diff
+            .routes
+            .iter()
+            .map(|pair| (pair.a.map(|r| r.0), pair.b.map(|r| r.0), pair.shared))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, [(Some(0), Some(0), 2), (Some(1), Some(1), 1)]);
+        let deltas = diff.routes.iter().map(RoutePair::delta).sum::<f64>();
+        assert!((deltas - (b.cost() - a.cost())).abs() < 1e-9);
+        assert_eq!(diff.distance, VRPSolution::distance(&a, &b, &instance));
+        assert!(diff.distance > 0.0);
+    }
+
+    #[test]
+    fn routes_left_over_are_paired_with_nothing() {
+        let instance = square();
+        let a = sol(&instance, &[vec![1, 2, 3, 4]]);
+        let b = sol(&instance, &[vec![1, 2], vec![3], vec![4]]);
+        let diff = a.diff(&b, &instance);
+        assert_eq!(diff.moved, [3, 4]);
+        let unmatched = diff.routes.iter().filter(|pair| pair.a.is_none());
+        assert_eq!(unmatched.clone().count(), 2);
+        assert!(
+            unmatched
+                .clone()
+                .all(|pair| pair.cost_a == 0.0 && pair.shared == 0)
+        );
+
+        let text = diff.to_string();
+        assert!(text.starts_with(&format!(
+            "cost      {:.2} -> {:.2} ({:+.2})\n",
+            a.cost(),
+            b.cost(),
+            b.cost() - a.cost()
+        )));
+        assert!(text.contains("moved     2 customers: 3 4\n"));
+        assert!(text.contains("route   0 ->   0    2 shared"));
+        assert!(text.contains("route   - ->   1    0 shared"));
+    }
+
+    #[test]
+    fn one_way_edges_keep_their_direction() {
+        let distances = (0..4)
+            .map(|a: usize| (0..4).map(|b: usize| (a * 4 + b) as f64).collect())
+            .collect();
+        let instance = Arc::new(VRPInstance::from_matrix(1, 10, vec![0, 1, 1, 1], distances));
+        let a = sol(&instance, &[vec![1, 2, 3]]);
+        let b = sol(&instance, &[vec![3, 2, 1]]);
+        let diff = a.diff(&b, &instance);
+        assert!(diff.moved.is_empty());
+        assert_eq!(diff.edges_only_in_a, [(0, 1), (1, 2), (2, 3), (3, 0)]);
+        assert_eq!(diff.edges_only_in_b, [(0, 3), (1, 0), (2, 1), (3, 2)]);
+    }
+}
@@ -0,0 +1,278 @@
+//! An instance's distance matrix saved next to its file (as `<file>.dmat`), so big instances don't
+//! have to work it out again on every run. A saved matrix is only used if it was worked out from a
+//! file with the same contents and with the same options, so it's never stale.
+//!
+//! The file is a header (magic, key, bytes per distance, whether it's a triangle, number of
+//! nodes), then the matrix as [`DistanceMatrix::write_to`] writes it.
+
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cache::hash_bytes,
+    common::{DistanceMatrix, DistancePrecision},
+    output,
+    vrp_instance::ReadOptions,
+};
+
+// 3 since small symmetric matrices went back to full rows, so their saved triangles get replaced
+const MAGIC: &[u8; 8] = b"VRPDMAT3";
+
+/// Where the matrix for the instance at `instance_path` is kept.
+pub fn path_for(instance_path: &Path) -> PathBuf {
+    let mut path = OsString::from(instance_path);
+    path.push(".dmat");
+    path.into()
+}
+
+/// What a saved matrix has to have been worked out from to be used: the instance file's
+/// `contents`, and the options that change its distances.
+pub fn key(contents: &[u8], options: ReadOptions) -> u64 {
+    let ReadOptions {
+        depot,
+        metric,
+        rounding,
+        precision,
+        ..
+    } = options;
+    hash_bytes(
+        format!(
+            "{:016x} {depot} {metric} {rounding:?} {precision:?}",
+            hash_bytes(contents)
+        )
+        .as_bytes(),
+    )
+}
+
+/// The matrix saved at `path` for `key`, or `None` if there isn't one or it's for something else.
+pub fn load(path: &Path, key: u64) -> io::Result<Option<DistanceMatrix>> {
+    let file = match File::open(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        file => file?,
+    };
+    let cut_short = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => {
+            io::Error::new(io::ErrorKind::InvalidData, "it's cut short")
+        }
+        _ => e,
+    };
+    let mut input = BufReader::new(file);
+    let mut header = [0; 26];
+    input.read_exact(&mut header).map_err(cut_short)?;
+    let (magic, rest) = header.split_at(MAGIC.len());
+    let (saved_key, rest) = rest.split_at(size_of::<u64>());
+    let (bytes, triangle, len) = (rest[0], rest[1], &rest[2..]);
+    if magic != MAGIC || u64::from_le_bytes(saved_key.try_into().unwrap()) != key {
+        return Ok(None);
+    }
+    let precision = match usize::from(bytes) {
+        b if b == DistancePrecision::F64.bytes() => DistancePrecision::F64,
+        b if b == DistancePrecision::F32.bytes() => DistancePrecision::F32,
+        b => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown distance size {b}"),
+            ));
+        }
+    };
+    let len = usize::try_from(u64::from_le_bytes(len.try_into().unwrap()))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    DistanceMatrix::read_from(&mut input, len, precision, triangle != 0)
+        .map(Some)
+        .map_err(cut_short)
+}
+
+/// Save `distances` to `path` for `key`, replacing whatever was there.
+pub fn store(path: &Path, key: u64, distances: &DistanceMatrix) -> io::Result<()> {
+    output::write_atomic_with(path, |out| {
+        out.write_all(MAGIC)?;
+        out.write_all(&key.to_le_bytes())?;
+        out.write_all(&[
+            u8::try_from(distances.precision().bytes()).unwrap(),
+            u8::from(distances.is_triangle()),
+        ])?;
+        out.write_all(&(distances.len() as u64).to_le_bytes())?;
+        distances.write_to(out)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Instant};
+
+    use super::*;
+    use crate::{
+        generate,
+        vrp_instance::{DistanceRounding, Metric, VRPInstance},
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vrp-dmat-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn assert_bit_identical(a: &DistanceMatrix, b: &DistanceMatrix) {
+        assert_eq!(a.len(), b.len());
+        assert_eq!(a.precision(), b.precision());
+        assert_eq!(a.is_triangle(), b.is_triangle());
+        for i in 0..a.len() {
+            for j in 0..a.len() {
+                assert_eq!(a.dist(i, j).to_bits(), b.dist(i, j).to_bits(), "{i} -> {j}");
+            }
+        }
+    }
+
+    #[test]
+    fn saved_matrices_load_bit_identical() {
+        let dir = temp_dir("identical");
+        let instance = generate::random_instance(40, 50, 2023);
+        let symmetric = |precision| {
+            let dm = &instance.distance_matrix;
+            DistanceMatrix::from_fn(dm.len(), precision, |a, b| dm.dist(a, b))
+        };
+        // a different distance each way
+        let one_way = DistanceMatrix::new(
+            (0..10)
+                .map(|a| (0..10).map(|b| (a * 10 + b) as f64 / 3.0).collect())
+                .collect(),
+        );
+        for (i, matrix) in [
+            symmetric(DistancePrecision::F64),
+            symmetric(DistancePrecision::F32),
+            one_way,
+        ]
+        .iter()
+        .enumerate()
+        {
+            let path = dir.join(format!("{i}.dmat"));
+            store(&path, 7, matrix).unwrap();
+            assert_bit_identical(&load(&path, 7).unwrap().unwrap(), matrix);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn other_keys_and_missing_files_miss_but_cut_short_ones_are_errors() {
+        let dir = temp_dir("miss");
+        let path = dir.join("test.vrp.dmat");
+        assert!(load(&path, 7).unwrap().is_none());
+
+        let matrix = generate::random_instance(20, 50, 2023).distance_matrix;
+        store(&path, 7, &matrix).unwrap();
+        assert!(load(&path, 8).unwrap().is_none());
+
+        let saved = fs::read(&path).unwrap();
+        let mut old_magic = saved.clone();
+        old_magic[..MAGIC.len()].copy_from_slice(b"VRPDMAT2");
+        fs::write(&path, old_magic).unwrap();
+        assert!(load(&path, 7).unwrap().is_none());
+
+        for cut in [10, saved.len() - 1] {
+            fs::write(&path, &saved[..cut]).unwrap();
+            let e = load(&path, 7).err().unwrap();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{cut}");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_key_follows_the_contents_and_the_options_that_change_distances() {
+        let options = ReadOptions::default();
+        let base = key(b"3 1 10\n0 0 0\n1 1 1\n2 2 2\n", options);
+        assert_eq!(base, key(b"3 1 10\n0 0 0\n1 1 1\n2 2 2\n", options));
+        assert_ne!(base, key(b"3 1 10\n0 0 0\n1 1 1\n2 2 3\n", options));
+        for changed in [
+            ReadOptions {
+                depot: 1,
+                ..options
+            },
+            ReadOptions {
+                metric: Metric::Manhattan,
+                ..options
+            },
+            ReadOptions {
+                rounding: DistanceRounding::Nearest,
+                ..options
+            },
+            ReadOptions {
+                precision: DistancePrecision::F32,
+                ..options
+            },
+        ] {
+            assert_ne!(base, key(b"3 1 10\n0 0 0\n1 1 1\n2 2 2\n", changed));
+        }
+        // the rest don't change the distances
+        let strict = ReadOptions {
+            strict: true,
+            distance_cache: true,
+            ..options
+        };
+        assert_eq!(base, key(b"3 1 10\n0 0 0\n1 1 1\n2 2 2\n", strict));
+    }
+
+    #[test]
+    fn instances_use_their_saved_distances_until_the_file_changes() {
+        let dir = temp_dir("instance");
+        let file = dir.join("test.vrp");
+        let options = ReadOptions {
+            distance_cache: true,
+            ..ReadOptions::default()
+        };
+        let original = generate::random_instance(30, 50, 2023);
+        fs::write(&file, original.to_file_string().unwrap()).unwrap();
+
+        let first = VRPInstance::new(&file, options).unwrap();
+        assert!(path_for(&file).exists());
+        let second = VRPInstance::new(&file, options).unwrap();
+        assert_bit_identical(&first.distance_matrix, &second.distance_matrix);
+
+        // a saved matrix that doesn't match what's read is never used
+        let moved = generate::random_instance(30, 50, 2024);
+        fs::write(&file, moved.to_file_string().unwrap()).unwrap();
+        let reread = VRPInstance::new(&file, options).unwrap();
+        assert_bit_identical(&reread.distance_matrix, &moved.distance_matrix);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// How long the distances between 6,000 nodes take to work out from their coordinates, as
+    /// reading an instance does, and to load from a saved matrix, which also checks the loaded
+    /// triangle is bit-identical. Reading the rest of the instance costs the same either way. Run
+    /// with `cargo test --release -- --ignored --nocapture saved_distances_load_faster`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn saved_distances_load_faster() {
+        let dir = temp_dir("benchmark");
+        let path = dir.join("big.vrp.dmat");
+        let instance = generate::random_instance(6_000, 100, 2023);
+        let points = instance
+            .x_coord_of_customer
+            .iter()
+            .zip(&instance.y_coord_of_customer)
+            .map(|(&x, &y)| (x, y))
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let computed = DistanceMatrix::from_fn(points.len(), DistancePrecision::F64, |a, b| {
+            Metric::Euclidean.distance(points[a], points[b])
+        });
+        let computing = start.elapsed();
+        store(&path, 7, &computed).unwrap();
+        let start = Instant::now();
+        let loaded = load(&path, 7).unwrap().unwrap();
+        let loading = start.elapsed();
+
+        assert!(loaded.is_triangle());
+        assert_bit_identical(&loaded, &computed);
+        println!(
+            "{} nodes: {computing:.2?} working the distances out, {loading:.2?} loading them",
+            points.len()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
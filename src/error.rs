@@ -0,0 +1,450 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::common::FORBIDDEN;
+use crate::ids::{CustomerId, RouteIdx};
+use crate::vrp_instance::Metric;
+
+/// Errors produced while manipulating solutions outside of the main search loop.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VrpError {
+    /// A stop refers to a customer that doesn't exist in the instance.
+    UnknownCustomer(usize),
+    /// A customer is visited more than once.
+    DuplicateCustomer(CustomerId),
+    /// A route carries more than a vehicle can.
+    OverCapacity {
+        route: usize,
+        load: usize,
+        cap: usize,
+    },
+    /// There are more routes than vehicles to drive them.
+    TooManyRoutes { routes: usize, vehicles: usize },
+    /// No route can take this customer without going over capacity or using a forbidden edge.
+    NoFeasibleInsertion(CustomerId),
+    /// A route is over capacity and none of its stops can be moved elsewhere.
+    CannotRelieveRoute {
+        route: usize,
+        load: usize,
+        cap: usize,
+    },
+    /// A distance is NaN, negative, infinite or too large to tell apart from a forbidden edge.
+    InvalidDistance { from: usize, to: usize, value: f64 },
+    /// Even a single-threaded run is expected to need more memory than the budget allows.
+    OverMemoryBudget { needed_mb: usize, budget_mb: usize },
+    /// Something needs coordinates, but the instance only has a distance matrix.
+    NoCoordinates,
+    /// Something only works with Euclidean distances.
+    NotEuclidean(Metric),
+    /// Something only works with distances that aren't rounded.
+    RoundedDistances,
+}
+
+impl fmt::Display for VrpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VrpError::UnknownCustomer(c) => write!(f, "unknown customer {c}"),
+            VrpError::DuplicateCustomer(c) => write!(f, "customer {c} is visited more than once"),
+            VrpError::OverCapacity { route, load, cap } => {
+                write!(f, "route {route} is over capacity ({load} > {cap})")
+            }
+            VrpError::TooManyRoutes { routes, vehicles } => {
+                write!(f, "{routes} routes but only {vehicles} vehicles")
+            }
+            VrpError::NoFeasibleInsertion(c) => {
+                write!(
+                    f,
+                    "no route can take customer {c} without going over capacity or using a \
+                     forbidden edge"
+                )
+            }
+            VrpError::CannotRelieveRoute { route, load, cap } => write!(
+                f,
+                "route {route} is over capacity ({load} > {cap}) and no stop can be relocated"
+            ),
+            VrpError::InvalidDistance { from, to, value } => write!(
+                f,
+                "invalid distance {value} from {from} to {to}, expected a non-negative number below {FORBIDDEN}"
+            ),
+            VrpError::OverMemoryBudget {
+                needed_mb,
+                budget_mb,
+            } => write!(
+                f,
+                "a single thread needs about {needed_mb}MB, over the {budget_mb}MB memory budget"
+            ),
+            VrpError::NoCoordinates => {
+                write!(f, "the instance has no coordinates, only a distance matrix")
+            }
+            VrpError::NotEuclidean(metric) => {
+                write!(f, "this needs Euclidean distances, not {metric} ones")
+            }
+            VrpError::RoundedDistances => {
+                write!(f, "this needs exact distances, not rounded ones")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VrpError {}
+
+/// Something wrong with a solution, found by [`verify`](crate::verify::verify).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all_fields = "PascalCase")]
+pub enum SolutionViolation {
+    TooManyRoutes {
+        routes: usize,
+        vehicles: usize,
+    },
+    /// The route at `index` doesn't have `index` as its id.
+    WrongRouteId {
+        index: usize,
+        id: RouteIdx,
+    },
+    UnknownCustomer {
+        route: usize,
+        customer: usize,
+    },
+    DuplicateCustomer(CustomerId),
+    /// A stop's demand isn't its customer's.
+    WrongDemand(CustomerId),
+    /// A route's cached load isn't what its stops add up to.
+    StaleLoad {
+        route: usize,
+        cached: usize,
+        recomputed: usize,
+    },
+    OverCapacity {
+        route: usize,
+        load: usize,
+        cap: usize,
+    },
+    /// A route's cached cost is more than the tolerance off what its legs add up to.
+    StaleCost {
+        route: usize,
+        cached: f64,
+        recomputed: f64,
+    },
+    ForbiddenEdge {
+        route: usize,
+        from: CustomerId,
+        to: CustomerId,
+    },
+    MissingCustomer(CustomerId),
+}
+
+impl fmt::Display for SolutionViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolutionViolation::TooManyRoutes { routes, vehicles } => write!(
+                f,
+                "solution has {routes} routes but there are only {vehicles} vehicles"
+            ),
+            SolutionViolation::WrongRouteId { index, id } => {
+                write!(f, "route at index {index} has id {id}")
+            }
+            SolutionViolation::UnknownCustomer { route, customer } => {
+                write!(f, "route {route} visits unknown customer {customer}")
+            }
+            SolutionViolation::DuplicateCustomer(c) => {
+                write!(f, "customer {c} is visited more than once")
+            }
+            SolutionViolation::WrongDemand(c) => write!(f, "customer {c} has the wrong demand"),
+            SolutionViolation::StaleLoad {
+                route,
+                cached,
+                recomputed,
+            } => write!(
+                f,
+                "route {route} has a stale load ({cached}, but its stops add up to {recomputed})"
+            ),
+            SolutionViolation::OverCapacity { route, load, cap } => {
+                write!(f, "route {route} is over capacity ({load} > {cap})")
+            }
+            SolutionViolation::StaleCost {
+                route,
+                cached,
+                recomputed,
+            } => write!(
+                f,
+                "route {route} has a stale cost ({cached}, but its legs add up to {recomputed})"
+            ),
+            SolutionViolation::ForbiddenEdge { route, from, to } => {
+                write!(f, "route {route} uses the forbidden edge {from}-{to}")
+            }
+            SolutionViolation::MissingCustomer(c) => write!(f, "customer {c} isn't visited"),
+        }
+    }
+}
+
+/// Why [`VRPSolution::from_string`](crate::common::VRPSolution::from_string) or
+/// [`VRPSolution::from_file_string`](crate::common::VRPSolution::from_file_string) couldn't read
+/// a solution.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolutionParseError {
+    /// Something that should be a customer number (or for a `.sol` file, the cost) isn't one.
+    NotANumber(String),
+    /// Route `route` (counting from 0) doesn't start and end at the depot.
+    NotFromDepot { route: usize },
+    /// A `.sol` file's header gives a format version newer than this build can read.
+    UnknownVersion(String),
+    /// The routes could be read, but aren't a solution to the instance.
+    Invalid(VrpError),
+}
+
+impl fmt::Display for SolutionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolutionParseError::NotANumber(s) => write!(f, "{s:?} isn't a number"),
+            SolutionParseError::NotFromDepot { route } => {
+                write!(f, "route {route} doesn't start and end at the depot (0)")
+            }
+            SolutionParseError::UnknownVersion(version) => {
+                write!(f, "can't read version {version:?} of the .sol format")
+            }
+            SolutionParseError::Invalid(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SolutionParseError {}
+
+impl From<VrpError> for SolutionParseError {
+    fn from(e: VrpError) -> Self {
+        SolutionParseError::Invalid(e)
+    }
+}
+
+/// Why [`check`](crate::check::check) couldn't check a results log at all.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckError {
+    /// The log itself couldn't be read.
+    Unreadable { path: String, reason: String },
+    /// A line of the log isn't a run's JSON, or its solution can't be parsed.
+    BadLine {
+        path: String,
+        line: usize,
+        reason: String,
+    },
+    /// The instance a line was run on couldn't be loaded.
+    Instance { name: String, error: InstanceError },
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::Unreadable { path, reason } => write!(f, "can't read {path}: {reason}"),
+            CheckError::BadLine { path, line, reason } => {
+                write!(f, "{path}, line {line}: {reason}")
+            }
+            CheckError::Instance { name, error } => write!(f, "instance {name}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for CheckError {}
+
+/// Why an instance file couldn't be read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstanceError {
+    FileNotFound,
+    /// The file exists but reading it failed.
+    Unreadable(String),
+    Empty,
+    /// The `<customers> <vehicles> <capacity>` header is missing a number or has a bad one.
+    BadHeader {
+        line: usize,
+    },
+    /// A `<demand> <x> <y> [<service time>]` row is missing `field` or has a bad one.
+    BadCustomerRow {
+        line: usize,
+        field: &'static str,
+    },
+    /// There are fewer rows than the header says. `depot_missing` when it looks like that's
+    /// because the depot row was left out.
+    TooFewRows {
+        expected: usize,
+        found: usize,
+        depot_missing: bool,
+    },
+    /// The depot (row 0) has a nonzero demand, in strict mode. `native` for our own format, where
+    /// it usually means the depot row is missing.
+    DepotHasDemand {
+        demand: usize,
+        native: bool,
+    },
+    /// [`VRPInstance::from_parts`](crate::vrp_instance::VRPInstance::from_parts) got customer
+    /// data of different lengths, or none at all (not even a depot).
+    MismatchedParts {
+        demands: usize,
+        xs: usize,
+        ys: usize,
+    },
+    /// A TSPLIB or Solomon file that couldn't be parsed.
+    Format(String),
+    /// The coordinates give a distance the solver can't work with.
+    Distances(VrpError),
+}
+
+impl fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceError::FileNotFound => write!(f, "File not found"),
+            InstanceError::Unreadable(e) => write!(f, "Error reading file: {e}"),
+            InstanceError::Empty => write!(f, "File is empty"),
+            InstanceError::BadHeader { line } => write!(
+                f,
+                "line {line}: expected the header `<customers> <vehicles> <capacity>`"
+            ),
+            InstanceError::BadCustomerRow { line, field } => write!(
+                f,
+                "line {line}: missing or invalid {field}, expected `<demand> <x> <y> [<service time>]`"
+            ),
+            InstanceError::TooFewRows {
+                expected,
+                found,
+                depot_missing: true,
+            } => write!(
+                f,
+                "the header says {expected} customers (including the depot) but there are only \
+                 {found} rows, and the first has nonzero demand: the depot row looks to be \
+                 missing. Add it as line 2 (as `0 <x> <y>`, demand 0)"
+            ),
+            InstanceError::TooFewRows {
+                expected, found, ..
+            } => write!(
+                f,
+                "the header says {expected} customers (including the depot) but there are only \
+                 {found} rows after it"
+            ),
+            InstanceError::DepotHasDemand {
+                demand,
+                native: true,
+            } => write!(
+                f,
+                "the depot (line 2) has demand {demand}, but the depot must have demand 0; if line \
+                 2 is actually a customer, the depot row is missing (add it as line 2 and count it \
+                 in the header's customer count)"
+            ),
+            InstanceError::DepotHasDemand { demand, .. } => write!(
+                f,
+                "the depot has demand {demand}, but the depot must have demand 0"
+            ),
+            InstanceError::MismatchedParts { demands, xs, ys } => write!(
+                f,
+                "got {demands} demands, {xs} x coordinates and {ys} y coordinates, but there has to \
+                 be one of each for the depot and every customer"
+            ),
+            InstanceError::Format(e) => write!(f, "{e}"),
+            InstanceError::Distances(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for InstanceError {}
+
+/// Something about an instance that makes it impossible to solve, found by
+/// [`VRPInstance::validate`](crate::vrp_instance::VRPInstance::validate).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all_fields = "PascalCase")]
+pub enum InstanceViolation {
+    /// The depot (customer 0) has a nonzero demand.
+    DepotHasDemand(usize),
+    /// A customer needs more than a whole vehicle.
+    DemandOverCapacity {
+        customer: CustomerId,
+        demand: usize,
+        capacity: usize,
+    },
+    /// All the customers together need more than the whole fleet can carry.
+    FleetTooSmall {
+        total_demand: usize,
+        fleet_capacity: usize,
+    },
+    /// More customers need over half a vehicle's capacity than there are vehicles, and no two of
+    /// them can share one.
+    TooManyLargeDemands {
+        large_demands: usize,
+        num_vehicles: usize,
+        capacity: usize,
+    },
+    /// Even the smallest demands only fit so many customers on a route, and the whole fleet
+    /// can't visit every customer that way, however the demands add up.
+    TooManyCustomers {
+        customers: usize,
+        per_route: usize,
+        num_vehicles: usize,
+    },
+    /// The per-customer data doesn't have one entry per customer.
+    LengthMismatch {
+        num_customers: usize,
+        demands: usize,
+        xs: usize,
+        ys: usize,
+    },
+    /// A customer's coordinates are NaN or infinite.
+    NonFiniteCoordinate { customer: usize, x: f64, y: f64 },
+}
+
+impl fmt::Display for InstanceViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceViolation::DepotHasDemand(demand) => {
+                write!(
+                    f,
+                    "the depot has demand {demand}, but it must have demand 0"
+                )
+            }
+            InstanceViolation::DemandOverCapacity {
+                customer,
+                demand,
+                capacity,
+            } => write!(
+                f,
+                "customer {customer} has demand {demand}, more than a vehicle's capacity of {capacity}"
+            ),
+            InstanceViolation::FleetTooSmall {
+                total_demand,
+                fleet_capacity,
+            } => write!(
+                f,
+                "the total demand is {total_demand}, more than the fleet's capacity of {fleet_capacity}"
+            ),
+            InstanceViolation::TooManyLargeDemands {
+                large_demands,
+                num_vehicles,
+                capacity,
+            } => write!(
+                f,
+                "{large_demands} customers each need more than half a vehicle's capacity of \
+                 {capacity}, so they need a vehicle each, but there are only {num_vehicles}"
+            ),
+            InstanceViolation::TooManyCustomers {
+                customers,
+                per_route,
+                num_vehicles,
+            } => write!(
+                f,
+                "at most {per_route} customers fit on a route, so {num_vehicles} vehicles can't \
+                 visit all {customers} customers"
+            ),
+            InstanceViolation::LengthMismatch {
+                num_customers,
+                demands,
+                xs,
+                ys,
+            } => write!(
+                f,
+                "there are {num_customers} customers (including the depot), but {demands} demands, {xs} x coordinates and \
+                 {ys} y coordinates"
+            ),
+            InstanceViolation::NonFiniteCoordinate { customer, x, y } => {
+                write!(
+                    f,
+                    "customer {customer} is at ({x}, {y}), which isn't a finite point"
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! `--explain`: every setting a run would use, and where each one's value came from.
+//!
+//! Settings are resolved in `main`, with flags taking precedence over the `--alns-config` file,
+//! that over anything worked out automatically, and that over the defaults. Each step that sets
+//! something records it in a [`Provenance`], so what's left unrecorded is a default.
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+
+use crate::solver::SolveParams;
+
+/// Where a setting's value came from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    /// worked out at startup, for the given reason
+    Auto(&'static str),
+    /// the `--alns-config` file at this path
+    ConfigFile(String),
+    /// this flag
+    Cli(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Auto(why) => write!(f, "auto ({why})"),
+            Source::ConfigFile(path) => write!(f, "config file {path}"),
+            Source::Cli(flag) => write!(f, "{flag}"),
+        }
+    }
+}
+
+impl Serialize for Source {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The settings that didn't keep their default, by the name they're serialized under (with the
+/// ALNS config's as `alns.<field>`).
+#[derive(Debug, Default)]
+pub struct Provenance(BTreeMap<String, Source>);
+
+impl Provenance {
+    /// Record that `name` was last set from `source`.
+    pub fn set(&mut self, name: &str, source: Source) {
+        self.0.insert(name.to_string(), source);
+    }
+
+    pub fn source(&self, name: &str) -> Source {
+        self.0.get(name).cloned().unwrap_or(Source::Default)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Setting {
+    pub name: String,
+    pub value: Value,
+    pub source: Source,
+}
+
+/// Every field of `params` (the ALNS config's flattened out into `alns.<field>`), followed by
+/// `extra` settings that live outside `SolveParams`, each with its source.
+pub fn settings(
+    params: &SolveParams,
+    extra: Vec<(&str, Value)>,
+    provenance: &Provenance,
+) -> Vec<Setting> {
+    let Value::Object(fields) = serde_json::to_value(params).unwrap() else {
+        unreachable!("SolveParams serializes as a struct");
+    };
+    let mut flat = Vec::new();
+    for (name, value) in fields {
+        match value {
+            Value::Object(inner) if name == "alns" => {
+                flat.extend(inner.into_iter().map(|(k, v)| (format!("alns.{k}"), v)));
+            }
+            value => flat.push((name, value)),
+        }
+    }
+    flat.extend(extra.into_iter().map(|(name, v)| (name.to_string(), v)));
+
+    flat.into_iter()
+        .map(|(name, value)| Setting {
+            source: provenance.source(&name),
+            name,
+            value,
+        })
+        .collect()
+}
+
+/// `settings` as a table with a column each for the name, value and source.
+pub fn table(settings: &[Setting]) -> String {
+    let rows = settings
+        .iter()
+        .map(|s| [s.name.clone(), s.value.to_string(), s.source.to_string()])
+        .collect::<Vec<_>>();
+    let header = ["setting", "value", "source"].map(String::from);
+    let width = |col: usize| {
+        std::iter::once(&header)
+            .chain(&rows)
+            .map(|row| row[col].len())
+            .max()
+            .unwrap()
+    };
+    let (name_width, value_width) = (width(0), width(1));
+
+    std::iter::once(&header)
+        .chain(&rows)
+        .map(|[name, value, source]| {
+            format!("{name:name_width$}  {value:value_width$}  {source}\n")
+        })
+        .collect()
+}
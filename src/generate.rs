@@ -0,0 +1,259 @@
+use std::str::FromStr;
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use rand_distr::{Distribution, Normal};
+
+use crate::vrp_instance::VRPInstance;
+
+/// A random instance with customers spread uniformly over a 100x100 square around a central depot.
+/// Demands are between 1 and a quarter of `vehicle_capacity`, and there are enough vehicles that the
+/// total demand only fills about 75% of the fleet, so a feasible solution is easy to find.
+pub fn random_instance(num_customers: usize, vehicle_capacity: usize, seed: u64) -> VRPInstance {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let max_demand = (vehicle_capacity / 4).max(1);
+
+    let (mut demands, mut xs, mut ys) = (vec![0], vec![50.0], vec![50.0]);
+    for _ in 0..num_customers {
+        demands.push(rng.random_range(1..=max_demand));
+        xs.push(rng.random_range(0.0..100.0));
+        ys.push(rng.random_range(0.0..100.0));
+    }
+
+    let total_demand = demands.iter().sum::<usize>();
+    let num_vehicles = (total_demand * 4).div_ceil(vehicle_capacity * 3).max(1);
+
+    VRPInstance::from_parts_unchecked(num_vehicles, vehicle_capacity, demands, xs, ys)
+}
+
+/// Where [`generate`] puts the customers in its 100x100 square.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// spread evenly over the square
+    Uniform,
+    /// normally distributed around `k` random centres
+    Clustered { k: usize },
+    /// a coin flip per customer between the two
+    Mixed { k: usize },
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    /// `uniform`, `clustered:<k>` or `mixed:<k>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid layout {s:?}, expected uniform, clustered:<k> or mixed:<k>");
+        let layout = match s.split_once(':') {
+            None if s == "uniform" => Layout::Uniform,
+            Some(("clustered", k)) => Layout::Clustered {
+                k: k.parse().map_err(|_| err())?,
+            },
+            Some(("mixed", k)) => Layout::Mixed {
+                k: k.parse().map_err(|_| err())?,
+            },
+            _ => return Err(err()),
+        };
+        if let Layout::Clustered { k: 0 } | Layout::Mixed { k: 0 } = layout {
+            return Err("a clustered layout needs at least one cluster".to_string());
+        }
+        Ok(layout)
+    }
+}
+
+/// How far (as a standard deviation) clustered customers are from their centre.
+const CLUSTER_SPREAD: f64 = 5.0;
+
+/// A random instance with `num_customers` customers laid out by `layout` around a central depot,
+/// that `num_vehicles` vehicles of `vehicle_capacity` can always serve: demands are at most
+/// `vehicle_capacity / ceil(num_customers / num_vehicles)`, so splitting the customers evenly
+/// between the vehicles never overloads one. The same arguments always give the same instance.
+///
+/// Panics if a vehicle can't fit its share of customers with a demand of 1 each, or a clustered
+/// layout has no clusters.
+pub fn generate(
+    num_customers: usize,
+    num_vehicles: usize,
+    vehicle_capacity: usize,
+    layout: Layout,
+    seed: u64,
+) -> VRPInstance {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let per_vehicle = num_customers.div_ceil(num_vehicles.max(1));
+    let max_demand = vehicle_capacity / per_vehicle.max(1);
+    assert!(
+        max_demand >= 1,
+        "{num_vehicles} vehicles of capacity {vehicle_capacity} can't serve {num_customers} customers"
+    );
+
+    let centres = match layout {
+        Layout::Uniform => Vec::new(),
+        Layout::Clustered { k } | Layout::Mixed { k } => {
+            assert!(k > 0, "a clustered layout needs at least one cluster");
+            (0..k)
+                .map(|_| (rng.random_range(10.0..90.0), rng.random_range(10.0..90.0)))
+                .collect()
+        }
+    };
+    let spread = Normal::new(0.0, CLUSTER_SPREAD).unwrap();
+
+    let (mut demands, mut xs, mut ys) = (vec![0], vec![50.0], vec![50.0]);
+    for _ in 0..num_customers {
+        demands.push(rng.random_range(1..=max_demand));
+        let clustered = match layout {
+            Layout::Uniform => false,
+            Layout::Clustered { .. } => true,
+            Layout::Mixed { .. } => rng.random_bool(0.5),
+        };
+        let (x, y) = if clustered {
+            let (cx, cy) = centres[rng.random_range(0..centres.len())];
+            (
+                (cx + spread.sample(&mut rng)).clamp(0.0, 100.0),
+                (cy + spread.sample(&mut rng)).clamp(0.0, 100.0),
+            )
+        } else {
+            (rng.random_range(0.0..100.0), rng.random_range(0.0..100.0))
+        };
+        xs.push(x);
+        ys.push(y);
+    }
+
+    VRPInstance::from_parts_unchecked(num_vehicles, vehicle_capacity, demands, xs, ys)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{common::VRPSolution, construct, random};
+
+    const LAYOUTS: [Layout; 3] = [
+        Layout::Uniform,
+        Layout::Clustered { k: 4 },
+        Layout::Mixed { k: 3 },
+    ];
+
+    #[test]
+    fn the_same_seed_gives_the_same_instance() {
+        for layout in LAYOUTS {
+            let file = |seed| generate(50, 5, 100, layout, seed).to_file_string().unwrap();
+            assert_eq!(file(1), file(1), "{layout:?}");
+            assert_ne!(file(1), file(2), "{layout:?}");
+        }
+        let file = |seed| random_instance(50, 100, seed).to_file_string().unwrap();
+        assert_eq!(file(1), file(1));
+        assert_ne!(file(1), file(2));
+    }
+
+    #[test]
+    fn splitting_the_customers_evenly_between_the_vehicles_always_fits() {
+        for (num_customers, num_vehicles, capacity) in [(10, 3, 4), (101, 10, 50), (500, 7, 72)] {
+            for (seed, layout) in LAYOUTS.into_iter().enumerate() {
+                let instance = Arc::new(generate(
+                    num_customers,
+                    num_vehicles,
+                    capacity,
+                    layout,
+                    seed as u64,
+                ));
+                assert_eq!(instance.num_customers, num_customers + 1);
+                instance.validate().unwrap();
+                for (x, y) in instance
+                    .x_coord_of_customer
+                    .iter()
+                    .zip(&instance.y_coord_of_customer)
+                {
+                    assert!((0.0..=100.0).contains(x) && (0.0..=100.0).contains(y));
+                }
+                let per_vehicle = num_customers.div_ceil(num_vehicles);
+                let customers = (1..=u32::try_from(num_customers).unwrap()).collect::<Vec<_>>();
+                let routes = customers
+                    .chunks(per_vehicle)
+                    .map(<[u32]>::to_vec)
+                    .collect::<Vec<_>>();
+                VRPSolution::from_routes_vec(&instance, &routes)
+                    .unwrap()
+                    .validate(&instance)
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic = "can't serve"]
+    fn an_impossible_fleet_is_refused() {
+        generate(10, 2, 4, Layout::Uniform, 1);
+    }
+
+    #[test]
+    fn clustered_customers_are_closer_together() {
+        // the average distance from each customer to its nearest neighbour
+        let spacing = |layout| {
+            let instance = generate(200, 10, 400, layout, 2008);
+            let dm = &instance.distance_matrix;
+            let customers = || crate::ids::CustomerId::customers(instance.num_customers);
+            customers()
+                .map(|a| {
+                    customers()
+                        .filter(|&b| b != a)
+                        .map(|b| dm.dist(a, b))
+                        .fold(f64::INFINITY, f64::min)
+                })
+                .sum::<f64>()
+                / 200.0
+        };
+        let uniform = spacing(Layout::Uniform);
+        let mixed = spacing(Layout::Mixed { k: 4 });
+        let clustered = spacing(Layout::Clustered { k: 4 });
+        assert!(
+            clustered < mixed && mixed < uniform,
+            "{clustered} {mixed} {uniform}"
+        );
+    }
+
+    #[test]
+    fn layouts_parse_and_bad_ones_are_refused() {
+        assert_eq!("uniform".parse(), Ok(Layout::Uniform));
+        assert_eq!("clustered:5".parse(), Ok(Layout::Clustered { k: 5 }));
+        assert_eq!("mixed:2".parse(), Ok(Layout::Mixed { k: 2 }));
+        for bad in [
+            "",
+            "uniform:3",
+            "clustered",
+            "clustered:0",
+            "mixed:x",
+            "ring",
+        ] {
+            assert!(bad.parse::<Layout>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn every_constructor_solves_generated_instances_of_any_size() {
+        for (num_customers, num_vehicles) in [(5, 2), (60, 6), (400, 20)] {
+            for (seed, layout) in LAYOUTS.into_iter().enumerate() {
+                let instance = Arc::new(generate(
+                    num_customers,
+                    num_vehicles,
+                    100,
+                    layout,
+                    seed as u64,
+                ));
+                random::seed(2008);
+                for (name, sol) in [
+                    ("sweep", construct::sweep(&instance).unwrap()),
+                    (
+                        "clarke_wright_and_then_sweep",
+                        construct::clarke_wright_and_then_sweep(&instance),
+                    ),
+                    (
+                        "cheapest_insertion",
+                        construct::cheapest_insertion(&instance),
+                    ),
+                ] {
+                    sol.validate(&instance)
+                        .unwrap_or_else(|e| panic!("{name}, {num_customers} {layout:?}: {e}"));
+                }
+            }
+        }
+    }
+}
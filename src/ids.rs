@@ -0,0 +1,127 @@
+//! Newtypes for the two kinds of index that are easiest to mix up: customer numbers (which are
+//! also distance matrix indices) and positions in a solution's list of routes.
+//!
+//! Both convert to and from their raw types with `From`/`Into`, so getting at the number is
+//! always explicit:
+//!
+//! ```
+//! # use std::collections::HashMap;
+//! # use vehicle_routing::ids::{CustomerId, RouteIdx};
+//! fn visit(customer: CustomerId) {}
+//! visit(CustomerId(3));
+//! // as `DistanceMatrix::dist` takes its two nodes
+//! fn dist<T: Into<usize>>(a: T, b: T) {}
+//! dist(0usize, CustomerId(4).into());
+//! let counts: HashMap<RouteIdx, usize> = HashMap::new();
+//! counts.get(&RouteIdx(7));
+//! ```
+//!
+//! and passing one where the other is expected doesn't compile:
+//!
+//! ```compile_fail,E0308
+//! # use vehicle_routing::ids::{CustomerId, RouteIdx};
+//! fn visit(customer: CustomerId) {}
+//! visit(RouteIdx(3));
+//! ```
+//!
+//! ```compile_fail,E0308
+//! # use vehicle_routing::ids::CustomerId;
+//! // a raw index and a customer can't be mixed in one lookup
+//! fn dist<T: Into<usize>>(a: T, b: T) {}
+//! dist(0usize, CustomerId(4));
+//! ```
+//!
+//! ```compile_fail,E0308
+//! # use std::collections::HashMap;
+//! # use vehicle_routing::ids::RouteIdx;
+//! let counts: HashMap<RouteIdx, usize> = HashMap::new();
+//! counts.get(&7usize);
+//! ```
+
+use std::fmt;
+
+use serde::Serialize;
+
+/// A customer's number, which is also its row in the distance matrix. The depot is 0, and with
+/// an end location, the node routes finish at is one past the last customer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct CustomerId(pub u32);
+
+impl CustomerId {
+    pub const DEPOT: CustomerId = CustomerId(0);
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn is_depot(self) -> bool {
+        self == Self::DEPOT
+    }
+
+    /// Every customer (not the depot) of an instance with `num_customers` rows.
+    pub fn customers(num_customers: usize) -> impl Iterator<Item = CustomerId> {
+        (1..u32::try_from(num_customers).unwrap()).map(CustomerId)
+    }
+}
+
+impl From<u32> for CustomerId {
+    fn from(cust_no: u32) -> Self {
+        CustomerId(cust_no)
+    }
+}
+
+impl From<CustomerId> for u32 {
+    fn from(id: CustomerId) -> Self {
+        id.0
+    }
+}
+
+impl From<CustomerId> for usize {
+    fn from(id: CustomerId) -> Self {
+        id.index()
+    }
+}
+
+impl TryFrom<usize> for CustomerId {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(index: usize) -> Result<Self, Self::Error> {
+        u32::try_from(index).map(CustomerId)
+    }
+}
+
+impl fmt::Display for CustomerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Where a route is in its solution's `routes`, i.e. which vehicle drives it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct RouteIdx(pub usize);
+
+impl RouteIdx {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl From<usize> for RouteIdx {
+    fn from(index: usize) -> Self {
+        RouteIdx(index)
+    }
+}
+
+impl From<RouteIdx> for usize {
+    fn from(idx: RouteIdx) -> Self {
+        idx.0
+    }
+}
+
+impl fmt::Display for RouteIdx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -0,0 +1,166 @@
+use std::mem::size_of;
+
+use crate::common::{Route, Stop};
+
+/// Beyond this many (customer, route) pairs the cache isn't worth its memory and stays off.
+const MAX_ENTRIES: usize = 1 << 20;
+
+#[derive(Clone, Copy)]
+struct Entry {
+    /// the route's generation when it was scanned, 0 for never
+    generation: u64,
+    /// the first cheapest feasible position and the cost increase there
+    best: Option<(usize, f64)>,
+}
+
+/// The cheapest place to insert each customer into each route, remembered until the route changes.
+/// Between iterations only the few routes a destroy and repair touched change (and reverting to
+/// an earlier solution brings back routes scanned before), so most lookups skip the scan.
+pub struct InsertionCache {
+    num_routes: usize,
+    /// indexed by `cust_no * num_routes + route`, empty when the cache is off
+    entries: Vec<Entry>,
+}
+
+impl InsertionCache {
+    pub fn new(num_customers: usize, num_routes: usize) -> Self {
+        let len = num_customers * num_routes;
+        let empty = Entry {
+            generation: 0,
+            best: None,
+        };
+        InsertionCache {
+            num_routes,
+            entries: if len <= MAX_ENTRIES {
+                vec![empty; len]
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    /// How much memory the cache takes for an instance of this size.
+    pub fn bytes_for(num_customers: usize, num_routes: usize) -> usize {
+        let len = num_customers * num_routes;
+        if len <= MAX_ENTRIES {
+            len * size_of::<Entry>()
+        } else {
+            0
+        }
+    }
+
+    /// The remembered best spot for `stop` in route `r`, if `route` hasn't changed since.
+    /// The inner `None` means nowhere in the route was feasible.
+    pub fn get(&self, stop: &Stop, r: usize, route: &Route) -> Option<Option<(usize, f64)>> {
+        let best = self
+            .entries
+            .get(usize::from(stop.cust_no()) * self.num_routes + r)
+            .filter(|entry| entry.generation == route.generation())
+            .map(|entry| entry.best);
+        debug_assert!(best.is_none_or(|best| best == best_spot(stop, route)));
+        best
+    }
+
+    /// Scan `route` (route `r`) for the best spot for `stop`, remembering it.
+    pub fn scan(&mut self, stop: &Stop, r: usize, route: &Route) -> Option<(usize, f64)> {
+        let best = best_spot(stop, route);
+        if let Some(entry) = self
+            .entries
+            .get_mut(usize::from(stop.cust_no()) * self.num_routes + r)
+        {
+            *entry = Entry {
+                generation: route.generation(),
+                best,
+            };
+        }
+        best
+    }
+}
+
+/// The first cheapest feasible position for `stop` in `route`, and the cost increase there.
+fn best_spot(stop: &Stop, route: &Route) -> Option<(usize, f64)> {
+    let mut best: Option<(usize, f64)> = None;
+    for i in 0..=route.stops().len() {
+        let (new_cost, feasible) = route.speculative_add_stop(stop, i);
+        let increase = new_cost - route.cost();
+        if feasible && best.is_none_or(|(_, cheapest)| increase < cheapest) {
+            best = Some((i, increase));
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{construct, generate, random};
+
+    #[test]
+    fn cached_spots_match_fresh_scans_after_other_routes_change() {
+        let instance = Arc::new(generate::random_instance(60, 50, 1990));
+        random::seed(1990);
+        let mut sol = construct::clarke_wright_and_then_sweep(&instance);
+        let mut cache = InsertionCache::new(instance.num_customers, instance.num_vehicles);
+
+        // take a customer out of a few routes, to look for spots for
+        let busy = (0..sol.routes.len())
+            .filter(|&r| sol.routes[r].stops().len() >= 2)
+            .take(4)
+            .collect::<Vec<_>>();
+        assert_eq!(busy.len(), 4);
+        let removed = busy
+            .iter()
+            .map(|&r| sol.routes[r].remove_stop_at_index(0))
+            .collect::<Vec<_>>();
+        for stop in &removed {
+            for (r, route) in sol.routes.iter().enumerate() {
+                assert_eq!(cache.get(stop, r, route), None);
+                assert_eq!(cache.scan(stop, r, route), best_spot(stop, route));
+            }
+        }
+
+        // put the first back and take another out elsewhere, leaving the rest of the routes alone
+        let changed = [busy[0], busy[1]];
+        let before = sol.routes[busy[1]].clone();
+        sol.routes[busy[0]].add_stop_to_index(removed[0], 0);
+        sol.routes[busy[1]].remove_stop_at_index(0);
+        // until it's scanned again, going back to a route as it was brings back what was found
+        assert_eq!(
+            cache.get(&removed[1], busy[1], &before),
+            Some(best_spot(&removed[1], &before))
+        );
+        for stop in &removed[1..] {
+            for (r, route) in sol.routes.iter().enumerate() {
+                match cache.get(stop, r, route) {
+                    Some(best) => {
+                        assert!(
+                            !changed.contains(&r),
+                            "route {r} changed but was still cached"
+                        );
+                        assert_eq!(best, best_spot(stop, route));
+                    }
+                    None => {
+                        assert!(
+                            changed.contains(&r),
+                            "route {r} didn't change but wasn't cached"
+                        );
+                        cache.scan(stop, r, route);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_cache_too_big_to_be_worth_it_is_off() {
+        assert_eq!(InsertionCache::bytes_for(2001, 1000), 0);
+        let instance = Arc::new(generate::random_instance(10, 50, 1990));
+        let sol = construct::clarke_wright_and_then_sweep(&instance);
+        let mut cache = InsertionCache::new(2001, 1000);
+        let stop = sol.routes[0].stops()[0];
+        cache.scan(&stop, 0, &sol.routes[0]);
+        assert_eq!(cache.get(&stop, 0, &sol.routes[0]), None);
+    }
+}
@@ -3,9 +3,7 @@ use std::sync::Arc;
 use rand::seq::SliceRandom;
 
 use crate::{
-    common::{Stop, VRPSolution},
-    dbg_println,
-    vrp_instance::VRPInstance,
+    common::VRPSolution, dbg_println, ids::CustomerId, random::rng, vrp_instance::VRPInstance,
 };
 
 #[allow(clippy::needless_pass_by_value)]
@@ -13,13 +11,15 @@ pub fn random_jump(
     vrp_instance: &Arc<VRPInstance>,
     existing: VRPSolution,
     frac_dropped: f64,
-) -> VRPSolution {
+) -> Result<VRPSolution, String> {
+    let mut last_err = String::new();
     for _i in 0..5 {
-        if let Ok(sol) = random_drop(vrp_instance, existing.clone(), frac_dropped) {
-            return sol;
+        match random_drop(vrp_instance, existing.clone(), frac_dropped) {
+            Ok(sol) => return Ok(sol),
+            Err(e) => last_err = e,
         }
     }
-    panic!("random_jump failed");
+    Err(format!("random_jump failed after 5 attempts: {last_err}"))
 }
 
 pub fn random_drop(
@@ -31,47 +31,97 @@ pub fn random_drop(
         "JUMPING (*random drop technique* dropping {:?}%)",
         frac_dropped * 100f64
     );
-    let rng = &mut rand::rng();
+    let rng = &mut rng();
 
+    let mut removed_cust_nos =
+        CustomerId::customers(vrp_instance.num_customers).collect::<Vec<_>>();
+    // always drop something so the jump isn't a no-op, but never everyone, since refilling
+    // completely empty routes first-fit is what tends to fail on tight instances
+    let n = removed_cust_nos.len();
     #[allow(
         clippy::cast_possible_truncation,
         clippy::cast_sign_loss,
         clippy::cast_precision_loss
     )]
-    let to_remove = (vrp_instance.num_customers as f64 * frac_dropped) as usize;
+    let to_remove =
+        ((n as f64 * frac_dropped).round() as usize).clamp(1, n.saturating_sub(1).max(1));
 
-    let mut removed_cust_nos =
-        (1..u16::try_from(vrp_instance.num_customers).unwrap()).collect::<Vec<_>>();
     removed_cust_nos.shuffle(rng);
     removed_cust_nos.truncate(to_remove);
 
-    existing.routes.iter_mut().for_each(|r| {
-        r.retain_stops(|s| !removed_cust_nos.contains(&s.cust_no()));
-    });
-
-    let mut to_add = removed_cust_nos
-        .iter()
-        .map(|cust_no| Stop::new(*cust_no, vrp_instance.demand_of_customer[*cust_no as usize]))
+    let mut to_add = existing
+        .routes
+        .iter_mut()
+        .flat_map(|r| r.drain_stops(|s| removed_cust_nos.contains(&s.cust_no())))
         .collect::<Vec<_>>();
     to_add.sort_by_key(|t| std::cmp::Reverse(t.capacity()));
 
-    existing.routes.shuffle(rng);
+    // visit the routes in a random order, but leave them in place so each keeps its vehicle's index
+    let mut route_order = (0..existing.routes.len()).collect::<Vec<_>>();
+    route_order.shuffle(rng);
 
     for s in to_add {
         let mut was_added = false;
-        for r in &mut existing.routes {
+        // then any spare routes opened since
+        let opened = route_order.len()..existing.routes.len();
+        for i in route_order.iter().copied().chain(opened) {
+            let r = &existing.routes[i];
             if r.used_capacity() + s.capacity() <= vrp_instance.vehicle_capacity {
                 let index = r.speculative_add_best(&s).1;
-                r.add_stop_to_index(s, index);
+                existing.add_stop(i, s, index);
                 was_added = true;
-                r.assert_sanity();
+                existing.routes[i].assert_sanity();
                 break;
             }
         }
         if !was_added {
-            return Err("didn't work".to_string());
+            return Err(format!("no route has room for customer {}", s.cust_no()));
         }
     }
 
+    existing.compact();
     Ok(existing)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::RouteIdx;
+
+    #[test]
+    fn jumps_leave_each_route_at_its_vehicles_index() {
+        let instance = Arc::new(crate::generate::random_instance(60, 40, 1965));
+        crate::random::seed(1965);
+        let start = crate::construct::clarke_wright_and_then_sweep(&instance);
+
+        let jumped = random_jump(&instance, start.clone(), 0.3).unwrap();
+        let dropped = random_drop(&instance, start, 0.3).unwrap();
+        for sol in [jumped, dropped] {
+            sol.validate(&instance).unwrap();
+            for (i, route) in sol.routes.iter().enumerate() {
+                assert_eq!(route.id(), RouteIdx(i));
+            }
+        }
+    }
+
+    /// 30 customers of demand 5 filling 15 vehicles of 10 exactly.
+    fn tight_instance() -> Arc<VRPInstance> {
+        let mut demands = vec![5; 31];
+        demands[0] = 0;
+        let xs = (0..31).map(|i| f64::from(i % 6) * 10.0).collect();
+        let ys = (0..31).map(|i| f64::from(i / 6) * 10.0).collect();
+        Arc::new(VRPInstance::from_parts(15, 10, demands, xs, ys).unwrap())
+    }
+
+    #[test]
+    fn jumps_of_any_fraction_refill_a_tight_instance() {
+        let instance = tight_instance();
+        crate::random::seed(1967);
+        let start = crate::construct::greedy(&instance);
+
+        for frac in [0.0, 0.001, 0.02, 1.0] {
+            let sol = random_jump(&instance, start.clone(), frac).unwrap();
+            sol.validate(&instance).unwrap();
+        }
+    }
+}
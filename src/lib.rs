@@ -0,0 +1,4 @@
+//! The index newtypes, built as a library too so that their doc examples, which show what doesn't
+//! compile, are checked by `cargo test`. The binary uses them from here.
+
+pub mod ids;
@@ -1,73 +1,1161 @@
+mod acceptance;
+mod affinity;
+mod analysis;
+#[cfg(feature = "async")]
+mod async_solve;
+mod bounds;
+mod cache;
+mod calibrate;
+mod check;
+mod cli;
 mod common;
+mod compare;
 mod construct;
+mod decompose;
+mod diff;
+mod distance_cache;
+mod error;
+mod explain;
+mod generate;
+mod insertion_cache;
 mod jump;
+mod multi_depot;
+mod objective;
+mod output;
+mod phases;
+mod pool;
+mod random;
+mod repair;
+mod report;
+mod soak;
 mod solver;
 pub mod solvers;
+mod stats;
 mod swap;
+mod tabu;
+mod verify;
 mod vrp_instance;
 
-use solver::{SolveParams, TermCond};
+use vehicle_routing::ids;
+
+use cli::CliArgs;
+use common::{DistancePrecision, SolFormat, VRPSolution};
+use explain::{Provenance, Source};
+use ids::CustomerId;
+use report::RunMeta;
+use solver::{PatienceUnit, SolveParams, TermCond};
+use std::fmt::Write as _;
+use std::io::Read as _;
 use std::time::Duration;
 use std::{env, sync::Arc, time::Instant};
-use vrp_instance::VRPInstance;
+use vrp_instance::{ReadOptions, VRPInstance};
 
 use serde_json::json;
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The instance path that means reading the instance from stdin (or with `--generate`, writing
+/// it to stdout).
+const STDIN_PATH: &str = "-";
+
+/// What an instance read from stdin is called in the output.
+const STDIN_NAME: &str = "<stdin>";
+
+/// Exit status for an instance that can't be solved, e.g. because the fleet is too small, so
+/// scripts can tell it apart from other errors.
+const UNSOLVABLE_EXIT_CODE: i32 = 2;
+
+/// Width and height of the `--svg` drawing, in pixels.
+const SVG_SIZE: u32 = 800;
 
 fn get_filename_from_path(path: &str) -> &str {
+    if path == STDIN_PATH {
+        return STDIN_NAME;
+    }
     Path::new(path)
         .file_name()
         .and_then(|filename| filename.to_str())
         .unwrap_or("")
 }
 
+/// An iteration budget that should take about 90% of `time_limit` on this machine, rounded down to two
+/// significant figures so that small differences in machine load usually don't change it. Runs with the
+/// same budget and seed follow the same trajectory, but the budget itself still depends on machine speed.
+fn calibrated_iters(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    time_limit: Duration,
+) -> usize {
+    let calibration = time_limit.mul_f64(0.05).min(Duration::from_secs(1));
+    let ips =
+        solver::calibrate_iters_per_sec::<solvers::MultiLNSSolver>(instance, params, calibration);
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let iters = (ips * (time_limit - calibration).as_secs_f64() * 0.9) as usize;
+    let magnitude = 10usize.pow(iters.max(1).ilog10().saturating_sub(1));
+    (iters / magnitude * magnitude).max(1)
+}
+
+fn default_params(time_limit: Duration) -> SolveParams {
+    SolveParams {
+        // terminate: TermCond::MaxIters(50000),
+        terminate: TermCond::TimeElapsed(time_limit),
+        frac_dropped: 0.0,
+        // 50 non-improving iterations of 5 customer moves each
+        patience: 250,
+        patience_unit: PatienceUnit::CustomerMoves,
+        constructor: construct::clarke_wright_and_then_sweep,
+        jumper: jump::random_jump,
+        external_improver: None,
+        improver_interval: Duration::from_secs(1),
+        elite_count: 5,
+        elite_min_gap: 0.1,
+        progress: None,
+        on_improvement: None,
+        objective: objective::Objective::TotalDistance,
+        diversity_window: 0,
+        diversity_min_distinct: 0,
+        edge_tabu_tenure: 0,
+        destroy_size: None,
+        calibration: None,
+        cancel: None,
+        alns: solvers::ALNSConfig::default(),
+        target_cost: None,
+        acceptance: acceptance::Acceptance::default(),
+        initial_solution: None,
+    }
+}
+
+/// Solve a small generated instance for a couple of seconds, and a tiny one with fewer customers
+/// than a destroy step removes, and check the results, so the whole pipeline can be sanity checked
+/// without any instance files. Returns the exit code.
+fn smoke_test() -> i32 {
+    let instance = Arc::new(generate::random_instance(60, 100, 2951));
+    let params = default_params(Duration::from_secs(2));
+    let tiny = Arc::new(generate::random_instance(3, 100, 2951));
+    let tiny_params = SolveParams {
+        terminate: TermCond::MaxIters(2_000),
+        ..params.clone()
+    };
+    for (instance, params) in [(&instance, &params), (&tiny, &tiny_params)] {
+        let start = Instant::now();
+        let sol = solver::solve::<solvers::ALNSSolver>(instance, params);
+        let summary = format!(
+            "smoke test: {} customers, {} vehicles, cost {:.2} in {:.2}s",
+            instance.num_customers - 1,
+            instance.num_vehicles,
+            sol.cost(),
+            start.elapsed().as_secs_f64()
+        );
+
+        match sol.validate(instance) {
+            Ok(()) => println!("{summary}: ok"),
+            Err(e) => {
+                println!("{summary}: INVALID ({e})");
+                return 1;
+            }
+        }
+    }
+    chaos_test(&instance, &params)
+}
+
+/// Solve with failures injected into the solver (see [`solvers::ChaosSolver`]), and check the
+/// search survives them: the result is valid, and every failure was counted and reverted.
+fn chaos_test(instance: &Arc<VRPInstance>, params: &SolveParams) -> i32 {
+    let params = SolveParams {
+        terminate: TermCond::MaxIters(5_000),
+        ..params.clone()
+    };
+    let initial_solution = (params.constructor)(instance);
+    let (sol, stats) = solver::solve_from_with_stats::<solvers::ChaosSolver<solvers::MultiLNSSolver>>(
+        instance,
+        &params,
+        initial_solution,
+    );
+
+    let usage = |operator| stats.operators.get(operator).copied().unwrap_or_default();
+    let repairs = usage("chaos_repair");
+    let problems = [
+        sol.validate(instance).err(),
+        (repairs.used - repairs.failed != stats.iterations).then(|| {
+            format!(
+                "{} repairs with {} failures, but {} iterations",
+                repairs.used, repairs.failed, stats.iterations
+            )
+        }),
+        ["chaos_repair", "chaos_empty_destroy", "chaos_reshaped_jump"]
+            .into_iter()
+            .find(|operator| usage(operator).failed == 0)
+            .map(|operator| format!("no {operator} failures were injected")),
+    ];
+    let summary = format!(
+        "chaos test: cost {:.2} with {} failed repairs, {} empty destroys, {} reshaped jumps",
+        sol.cost(),
+        repairs.failed,
+        usage("chaos_empty_destroy").failed,
+        usage("chaos_reshaped_jump").failed
+    );
+    match problems.into_iter().flatten().next() {
+        None => {
+            println!("{summary}: ok");
+            0
+        }
+        Some(e) => {
+            println!("{summary}: FAILED ({e})");
+            1
+        }
+    }
+}
+
+/// The spread of the final costs over `--repeats` runs, along with each run's seed and cost.
+#[allow(clippy::cast_precision_loss)]
+fn repeat_summary(runs: &[(u64, f64)]) -> serde_json::Value {
+    let mut costs = runs.iter().map(|(_, cost)| *cost).collect::<Vec<_>>();
+    costs.sort_by(f64::total_cmp);
+    let n = costs.len() as f64;
+    let mean = costs.iter().sum::<f64>() / n;
+    let variance = costs.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / n;
+    let mid = costs.len() / 2;
+    let median = if costs.len() % 2 == 0 {
+        f64::midpoint(costs[mid - 1], costs[mid])
+    } else {
+        costs[mid]
+    };
+
+    json!({
+        "Runs": runs
+            .iter()
+            .map(|(seed, cost)| json!({"Seed": seed, "Result": cost}))
+            .collect::<Vec<_>>(),
+        "Min": costs[0],
+        "Median": median,
+        "Mean": mean,
+        "StdDev": variance.sqrt(),
+    })
+}
+
+/// Solve every instance file in `dir` in order, each in its own run of this binary with the same
+/// arguments (apart from `--batch`), so one instance crashing or running out of memory doesn't take
+/// the rest down with it. Prints each run's JSON line, or one with an `Error` for a run that
+/// failed without printing its own, and returns the exit code: nonzero if any run failed.
+fn run_batch(dir: &str, args: &[String]) -> i32 {
+    let files = vrp_instance::instance_files(dir).unwrap_or_else(|e| {
+        eprintln!("Error: can't list the instances in {dir}: {e}");
+        std::process::exit(-1);
+    });
+    let exe = env::current_exe().unwrap_or_else(|e| {
+        eprintln!("Error: can't find this program to run it for each instance: {e}");
+        std::process::exit(-1);
+    });
+    let mut shared_args = Vec::with_capacity(args.len());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--batch" {
+            args.next();
+        } else {
+            shared_args.push(arg);
+        }
+    }
+
+    let mut failed = 0;
+    for path in &files {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let run = Command::new(&exe)
+            .args(&shared_args)
+            .arg(path)
+            .stderr(Stdio::inherit())
+            .output();
+        let (succeeded, line) = match run {
+            Ok(run) => {
+                let stdout = String::from_utf8_lossy(&run.stdout);
+                let last = stdout
+                    .lines()
+                    .rev()
+                    .find(|line| serde_json::from_str::<serde_json::Value>(line).is_ok())
+                    .map(str::to_string);
+                let line = last.unwrap_or_else(|| {
+                    json!({"Instance": name, "Error": format!("exited with {}", run.status)})
+                        .to_string()
+                });
+                (run.status.success(), line)
+            }
+            Err(e) => (
+                false,
+                json!({"Instance": name, "Error": format!("couldn't run: {e}")}).to_string(),
+            ),
+        };
+        if !succeeded {
+            failed += 1;
+        }
+        println!("{line}");
+    }
+    eprintln!(
+        "solved {} of {} instances in {dir}",
+        files.len() - failed,
+        files.len()
+    );
+    i32::from(failed > 0)
+}
+
+/// One full solve with whichever approach the options ask for, returning the best solution along
+/// with the costs of the elites it was picked from and any per-phase stats.
+fn solve_once(
+    cli: &CliArgs,
+    vrp_instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    threads: usize,
+) -> (VRPSolution, Vec<f64>, Vec<phases::PhaseStats>) {
+    let mut phase_stats = Vec::new();
+    let (sol, elites) = if let Some(phases) = &cli.phases {
+        let (sol, stats) = phases::solve_phased(vrp_instance, params, phases);
+        phase_stats = stats;
+        (sol, Vec::new())
+    } else if decompose::should_decompose(vrp_instance) && params.initial_solution.is_none() {
+        // a warm start is already a whole solution, so there's nothing to decompose for
+        let num_clusters = (vrp_instance.num_vehicles / decompose::VEHICLES_PER_CLUSTER).max(2);
+        let sol = decompose::solve_decomposed::<solvers::MultiLNSSolver>(
+            vrp_instance,
+            params,
+            num_clusters,
+            threads,
+        );
+        (sol, Vec::new())
+    } else {
+        solver::solve_with_elites::<solvers::MultiLNSSolver>(vrp_instance, params)
+    };
+    let elite_costs = elites.iter().map(VRPSolution::cost).collect::<Vec<_>>();
+    let sol = std::iter::once(sol)
+        .chain(elites)
+        .min_by(|a, b| {
+            let score = |s: &VRPSolution| params.objective.evaluate(s);
+            score(a).total_cmp(&score(b))
+        })
+        .unwrap();
+    (sol, elite_costs, phase_stats)
+}
+
+/// Check the results log at `log` against the instances in `instance_dir`, printing a line per
+/// run to stderr and all the reports as JSON to stdout. Returns the exit code.
+fn run_check(log: &str, instance_dir: &str) -> i32 {
+    match check::check(Path::new(log), Path::new(instance_dir)) {
+        Ok(reports) => {
+            for report in &reports {
+                eprintln!("{report}");
+            }
+            let failed = reports.iter().filter(|r| !r.passed()).count();
+            eprintln!("{} runs checked, {failed} failed", reports.len());
+            println!("{}", serde_json::to_string(&reports).unwrap());
+            i32::from(failed > 0)
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            -1
+        }
+    }
+}
+
+/// Compare the results logs `old` and `new`, printing a table to stderr and the comparison as
+/// JSON to stdout. Returns the exit code, 1 if the results got worse by more than `threshold`
+/// percent on average.
+fn run_compare(old: &str, new: &str, threshold: f64) -> i32 {
+    match compare::compare(Path::new(old), Path::new(new)) {
+        Ok(comparison) => {
+            eprint!("{comparison}");
+            println!("{}", serde_json::to_string(&comparison).unwrap());
+            i32::from(comparison.regressed_beyond(threshold))
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            -1
+        }
+    }
+}
+
+/// A callback writing each new best solution as a JSON line to `path` (or stderr, for `-`), with
+/// its cost in the original units when the search runs on a copy scaled by `scale`. Lines are
+/// written whole, so they can be followed as the search goes.
+fn improvement_log(
+    path: &str,
+    scale: Option<vrp_instance::ScaleInfo>,
+) -> std::io::Result<solver::ImprovementCallback> {
+    let out: Box<dyn std::io::Write + Send> = if path == STDIN_PATH {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::fs::File::create(path)?)
+    };
+    let out = std::sync::Mutex::new(std::io::LineWriter::new(out));
+    Ok(Arc::new(move |event: &solver::ImprovementEvent| {
+        let line = json!({
+            "Iteration": event.iteration,
+            "Elapsed": event.elapsed.as_secs_f64(),
+            "Cost": scale.map_or(event.cost, |s| s.to_original(event.cost)),
+        });
+        // a full disk shouldn't stop the search, so a line that can't be written is dropped
+        let _ =
+            std::io::Write::write_all(&mut *out.lock().unwrap(), format!("{line}\n").as_bytes());
+    }))
+}
+
+/// Compare the solutions `a` and `b` on `instance`, printing a summary to stderr and the details
+/// as JSON. Returns the exit code.
+fn run_diff(a: &str, b: &str, instance: &Arc<VRPInstance>) -> i32 {
+    let read = |spec: &str| {
+        read_solution(spec, instance).map_err(|e| eprintln!("Error: can't use {spec}: {e}"))
+    };
+    let (Ok(a), Ok(b)) = (read(a), read(b)) else {
+        return -1;
+    };
+    let diff = a.diff(&b, instance);
+    eprint!("{diff}");
+    println!("{}", serde_json::to_string(&diff).unwrap());
+    0
+}
+
+/// The solution `spec` is, or is in the `.sol` file at that path, if it visits every customer of
+/// `instance` once within capacity.
+fn read_solution(spec: &str, instance: &Arc<VRPInstance>) -> Result<VRPSolution, String> {
+    let sol = if Path::new(spec).is_file() {
+        let contents = std::fs::read_to_string(spec).map_err(|e| e.to_string())?;
+        VRPSolution::from_file_string(&contents, instance)
+    } else {
+        VRPSolution::from_string(spec, instance)
+    }
+    .map_err(|e| e.to_string())?;
+    sol.validate(instance)?;
+    Ok(sol)
+}
+
+/// The solution in the `.sol` file at `path`, if it's a feasible solution to `instance`: every
+/// customer visited once, no route over capacity, and no forbidden edges. With `repair`, one that
+/// isn't is patched up with [`repair::repair_imported`] first.
+fn read_warm_start(
+    path: &str,
+    instance: &Arc<VRPInstance>,
+    repair: bool,
+) -> Result<VRPSolution, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let sol = if repair {
+        let rows = common::parse_file_routes(&contents, instance.file_row(0))
+            .map_err(|e| e.to_string())?;
+        let routes = instance.customers_of_rows(&rows);
+        let (sol, summary) =
+            repair::repair_imported(instance, &routes).map_err(|e| e.to_string())?;
+        if !summary.is_empty() {
+            eprintln!("Repaired {path}: {summary}");
+        }
+        sol
+    } else {
+        VRPSolution::from_file_string(&contents, instance).map_err(|e| e.to_string())?
+    };
+    sol.validate(instance)?;
+    Ok(sol)
+}
+
+/// Print anything wrong with the solution about to be reported. It's still reported, so that e.g.
+/// the rest of a `runAll.sh` or `--batch` run isn't lost to it.
+fn report_violations(sol: &VRPSolution) {
+    if let Err(violations) = sol.check() {
+        for violation in violations {
+            eprintln!("Error: the solution is invalid: {violation}");
+        }
+    }
+}
+
+fn soak_exit_code(res: Result<VRPSolution, String>) -> i32 {
+    match res {
+        Ok(sol) => {
+            eprintln!("soak finished cleanly, best {:.2}", sol.cost());
+            0
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            1
+        }
+    }
+}
+
 fn main() {
     // Check if a file name was provided as a command-line argument
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
         // If no arguments provided, run the test
-        return;
+        std::process::exit(smoke_test());
     }
 
-    let file_path = &args[1];
+    let cli = CliArgs::parse(&args[1..]).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        std::process::exit(-1);
+    });
+    if let Some(dir) = &cli.batch {
+        std::process::exit(run_batch(dir, &args[1..]));
+    }
+    if let Some((old, new)) = &cli.compare {
+        std::process::exit(run_compare(old, new, cli.regression_threshold));
+    }
+    if let Some(log) = &cli.check {
+        std::process::exit(run_check(log, &cli.instance_path));
+    }
+    let file_path = &cli.instance_path;
     let file_name = get_filename_from_path(file_path);
+    let from_stdin = file_path == STDIN_PATH;
+    // what to call the instance in errors
+    let source = if from_stdin { STDIN_NAME } else { file_path };
+    if from_stdin && cli.distance_cache {
+        eprintln!("Error: --distance-cache needs an instance file to keep the distances next to");
+        std::process::exit(-1);
+    }
 
-    let start = Instant::now();
-    let vrp_instance = VRPInstance::new(file_path);
-    let frac_dropped = 0.0;
-    // let patience = (vrp_instance.num_customers as f64 * frac_dropped) as usize;
-    let patience = 50;
+    let seed = cli.effective_seed();
+    if let Some(seed) = seed {
+        random::seed(seed);
+    }
+    let time_limit = cli.time_limit.unwrap_or(Duration::from_secs(15));
 
-    // 26.5k
+    if let Some((customers, vehicles, capacity, layout)) = cli.generate {
+        if !from_stdin && Path::new(file_path).exists() {
+            eprintln!("Error: {file_path} already exists, not overwriting it");
+            std::process::exit(-1);
+        }
+        let seed = seed.unwrap_or_else(|| rand::Rng::random(&mut random::rng()));
+        let instance = generate::generate(customers, vehicles, capacity, layout, seed);
+        if from_stdin {
+            print!(
+                "{}",
+                instance
+                    .to_file_string()
+                    .expect("generated instances have coordinates")
+            );
+            eprintln!("generated with seed {seed}");
+            return;
+        }
+        if let Err(e) = instance.write_to(file_path) {
+            eprintln!("Error: can't write {file_path}: {e}");
+            std::process::exit(-1);
+        }
+        eprintln!("wrote {file_path} with seed {seed}");
+        return;
+    }
 
+    let start = Instant::now();
+    let options = ReadOptions {
+        strict: cli.strict_input,
+        depot: cli.depots.first().copied().unwrap_or(0),
+        metric: cli.metric,
+        rounding: cli.rounding,
+        precision: cli.distance_precision,
+        distance_cache: cli.distance_cache,
+        matrix_limit: cli.matrix_limit,
+    };
+    // read all at once, since it can't be read again (e.g. for the cache key)
+    let stdin_input = from_stdin.then(|| {
+        let mut bytes = Vec::new();
+        if let Err(e) = std::io::stdin().read_to_end(&mut bytes) {
+            eprintln!("Error: can't read the instance from stdin: {e}");
+            std::process::exit(-1);
+        }
+        bytes
+    });
+    let loaded = match &stdin_input {
+        Some(bytes) => VRPInstance::from_reader(bytes.as_slice(), STDIN_NAME, options),
+        None => VRPInstance::new(file_path, options),
+    };
+    let mut vrp_instance = match loaded {
+        Ok(instance) => instance,
+        Err(e) => {
+            eprintln!("Error: in VRPInstance() {source}\n{e}");
+            std::process::exit(-1);
+        }
+    };
+    if let Some((x, y)) = cli.end_location {
+        vrp_instance = vrp_instance.with_end_location(x, y).unwrap_or_else(|e| {
+            eprintln!("Error: bad --end-location: {e}");
+            std::process::exit(-1);
+        });
+    }
+    vrp_instance = vrp_instance
+        .with_cost_units(cli.distance_unit.clone(), cli.cost_per_unit)
+        .unwrap_or_else(|e| {
+            eprintln!("Error: bad --cost-per-unit: {e}");
+            std::process::exit(-1);
+        });
+    for &(a, b) in &cli.forbidden_edges {
+        if let Err(e) = vrp_instance.forbid_edge(a, b) {
+            eprintln!("Error: can't forbid edge {a}-{b}: {e}");
+            std::process::exit(-1);
+        }
+    }
+    if cli.stats {
+        let stats = vrp_instance.stats();
+        eprint!("{stats}");
+        println!("{}", serde_json::to_string(&stats).unwrap());
+        return;
+    }
+    if let Some(out) = &cli.anonymize {
+        let seed = seed.unwrap_or_else(|| rand::Rng::random(&mut random::rng()));
+        let anonymized = vrp_instance.anonymize(seed).unwrap_or_else(|e| {
+            eprintln!("Error: can't anonymize the instance: {e}");
+            std::process::exit(-1);
+        });
+        if let Err(e) = anonymized.write_to(out) {
+            eprintln!("Error: can't write {out}: {e}");
+            std::process::exit(-1);
+        }
+        // the file format has no place for these, so say how to pass them along instead
+        let mut flags = String::new();
+        if let Some((x, y)) = anonymized.end_location {
+            flags += &format!(" --end-location {x},{y}");
+        }
+        if !anonymized.forbidden_edges.is_empty() {
+            let edges = anonymized
+                .forbidden_edges
+                .iter()
+                .map(|(a, b)| format!("{a}-{b}"));
+            flags += &format!(" --forbid {}", edges.collect::<Vec<_>>().join(","));
+        }
+        if !flags.is_empty() {
+            eprintln!("solve {out} with{flags}");
+        }
+        return;
+    }
+    if let Err(violations) = vrp_instance.validate() {
+        eprintln!("Error: {source} can't be solved:");
+        for violation in &violations {
+            eprintln!("  - {violation}");
+        }
+        let messages = violations
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        println!(
+            "{}",
+            json!({
+                "Error": "Unsolvable",
+                "Instance": file_name,
+                "Violations": violations,
+                "Messages": messages,
+            })
+        );
+        std::process::exit(UNSOLVABLE_EXIT_CODE);
+    }
+    for (flag, path) in [("--geojson", &cli.geojson), ("--svg", &cli.svg)] {
+        if path.is_some() && !vrp_instance.has_coordinates {
+            eprintln!("Error: {flag} needs coordinates, but {source} only has a distance matrix");
+            std::process::exit(-1);
+        }
+    }
     let vrp_instance = Arc::new(vrp_instance);
-    let params = SolveParams {
-        // terminate: TermCond::MaxIters(50000),
-        terminate: TermCond::TimeElapsed(Duration::from_secs(15)),
-        frac_dropped,
-        patience,
-        constructor: construct::clarke_wright_and_then_sweep,
-        jumper: jump::random_jump,
+    if let Some((a, b)) = &cli.diff {
+        std::process::exit(run_diff(a, b, &vrp_instance));
+    }
+    // with --normalize the search runs on a copy scaled into the unit box, and what it finds is
+    // moved back onto the original instance for everything that's reported
+    let (solve_instance, scale) = if cli.normalize {
+        let (normalized, scale) = vrp_instance.normalized().unwrap_or_else(|e| {
+            eprintln!("Error: can't normalize {source}: {e}");
+            std::process::exit(-1);
+        });
+        (Arc::new(normalized), Some(scale))
+    } else {
+        (vrp_instance.clone(), None)
+    };
+    let to_search_units = |cost: f64| scale.map_or(cost, |s| s.to_normalized(cost));
+    let to_original_units = |cost: f64| scale.map_or(cost, |s| s.to_original(cost));
+    let mut params = default_params(time_limit);
+    params.elite_min_gap = to_search_units(params.elite_min_gap);
+    // for --explain: where each setting that isn't a default came from
+    let mut provenance = Provenance::default();
+    if cli.time_limit.is_some() {
+        provenance.set("terminate", Source::Cli("--time"));
+    }
+    if cli.objective != objective::Objective::default() {
+        provenance.set("objective", Source::Cli("--objective"));
+    }
+    if cli.acceptance != acceptance::Acceptance::default() {
+        provenance.set("acceptance", Source::Cli("--acceptance"));
+    }
+    if cli.edge_tabu.is_some() {
+        provenance.set("edge_tabu_tenure", Source::Cli("--edge-tabu"));
+    }
+    if cli.calibration.is_some() {
+        provenance.set("calibration", Source::Cli("--calibrate"));
+        provenance.set(
+            "destroy_size",
+            Source::Auto("picked by --calibrate when solving"),
+        );
+    }
+    params.objective = cli.objective;
+    params.acceptance = cli.acceptance;
+    params.edge_tabu_tenure = cli.edge_tabu.unwrap_or(0);
+    params.calibration = cli.calibration;
+    if let Some(path) = &cli.alns_config {
+        let (alns, fields) = solvers::ALNSConfig::from_file(path).unwrap_or_else(|e| {
+            eprintln!("Error: bad --alns-config: {e}");
+            std::process::exit(-1);
+        });
+        params.alns = alns;
+        for field in fields {
+            provenance.set(&format!("alns.{field}"), Source::ConfigFile(path.clone()));
+        }
+    }
+    // an instance the --bks file doesn't know is still solved, just without a target
+    let bks = cli.bks.as_ref().and_then(|spec| {
+        bounds::parse_bks(spec, file_name).unwrap_or_else(|e| {
+            eprintln!("Error: bad --bks: {e}");
+            std::process::exit(-1);
+        })
+    });
+    // kept in original units for the report, since that's what the solution is reported in
+    let bks_target = bks.map(|bks| bounds::bks_target(bks, cli.bks_gap));
+    params.target_cost = bks_target.map(to_search_units);
+    if bks.is_some() {
+        provenance.set("target_cost", Source::Cli("--bks"));
+    }
+    if let Some((window, k)) = cli.diversity {
+        params.diversity_window = window;
+        params.diversity_min_distinct = k;
+        provenance.set("diversity_window", Source::Cli("--diversity"));
+        provenance.set("diversity_min_distinct", Source::Cli("--diversity"));
+    }
+    if let Some(path) = &cli.warm_start {
+        // a bad warm start only costs the head start, so it's not worth failing the run over
+        match read_warm_start(path, &solve_instance, cli.repair_warm_start) {
+            Ok(sol) => {
+                params.initial_solution = Some(sol);
+                provenance.set("initial_solution_cost", Source::Cli("--warm-start"));
+            }
+            Err(e) => eprintln!("Warning: not starting from {path}: {e}; constructing instead"),
+        }
+    }
+
+    // the search is memory bound, so hyperthreads mostly just compete for the same cache
+    let mut threads = if cli.deterministic {
+        provenance.set("threads", Source::Cli("--deterministic"));
+        1
+    } else if let Some(threads) = cli.threads {
+        provenance.set("threads", Source::Cli("--threads"));
+        threads.max(1)
+    } else {
+        provenance.set("threads", Source::Auto("physical cores"));
+        num_cpus::get_physical().max(1)
     };
-    let sol = solver::solve::<solvers::MultiLNSSolver>(&vrp_instance, &params);
+    if let Some(budget_mb) = cli.memory_budget_mb {
+        let estimate = analysis::MemoryEstimate::for_instance(&vrp_instance, params.elite_count);
+        let fit = estimate
+            .fit_threads(threads, budget_mb)
+            .unwrap_or_else(|e| {
+                eprintln!("Error: {e}");
+                std::process::exit(-1);
+            });
+        if fit < threads {
+            eprintln!(
+                "using {fit} of {threads} threads to stay within the {budget_mb}MB memory budget"
+            );
+            threads = fit;
+            provenance.set("threads", Source::Auto("cut to fit --memory-budget"));
+        }
+    }
+    if cli.progress {
+        params.progress = Some(Arc::new(|event: &solver::ProgressEvent| {
+            let left = match (event.eta, event.remaining_iters) {
+                (Some(eta), _) => format!("eta {:.1}s", eta.as_secs_f64()),
+                (None, Some(iters)) => format!("~{iters} iters left"),
+                (None, None) => String::new(),
+            };
+            let mut acceptance = String::new();
+            if let Some(temperature) = event.acceptance.temperature {
+                write!(acceptance, ", temperature {temperature:.3e}").unwrap();
+            }
+            if let Some(ratio) = event.acceptance_ratio {
+                write!(acceptance, ", accepting {:.1}%", ratio * 100.0).unwrap();
+            }
+            eprintln!(
+                "[{:.1}s] iter {}, {:.0} it/s, {left}, best {:.2}{acceptance}",
+                event.elapsed.as_secs_f64(),
+                event.iteration,
+                event.iters_per_sec,
+                event.best_cost
+            );
+        }));
+    }
+    if let Some(path) = &cli.improvement_log {
+        params.on_improvement = Some(improvement_log(path, scale).unwrap_or_else(|e| {
+            eprintln!("Error: can't write the improvement log to {path}: {e}");
+            std::process::exit(-1);
+        }));
+    }
+    let cache = cli.cache.as_ref().map(|dir| {
+        let key = cache::CacheKey {
+            instance_hash: match &stdin_input {
+                Some(bytes) => cache::hash_bytes(bytes),
+                None => cache::hash_bytes(&std::fs::read(file_path).unwrap_or_default()),
+            },
+            config_hash: cache::hash_bytes(
+                format!(
+                    "{}{}",
+                    cli.result_affecting(),
+                    serde_json::to_string(&params).unwrap()
+                )
+                .as_bytes(),
+            ),
+        };
+        (cache::ResultsCache::new(Path::new(dir)), key)
+    });
+    if let Some((cache, key)) = cache.as_ref().filter(|_| !cli.explain) {
+        if let Some((sol, mut report)) = cache.lookup(*key, &vrp_instance) {
+            report["Cached"] = json!(true);
+            println!("{}", serde_json::to_string(&report).unwrap());
+            write_sol_file(&cli, file_name, &sol_file_string(&cli, &sol));
+            write_drawings(&cli, &sol, &vrp_instance);
+            return;
+        }
+    }
+
+    if let Some(iters) = cli.iters {
+        params.terminate = TermCond::MaxIters(iters);
+        provenance.set("terminate", Source::Cli("--iters"));
+    } else if cli.deterministic {
+        params.terminate =
+            TermCond::MaxIters(calibrated_iters(&solve_instance, &params, time_limit));
+        provenance.set(
+            "terminate",
+            Source::Auto("--deterministic calibrates iterations to the time budget"),
+        );
+        // start the real run from the same RNG state regardless of what calibration did
+        random::seed(seed.unwrap());
+    }
+
+    if cli.explain {
+        if cli.seed.is_some() {
+            provenance.set("seed", Source::Cli("--seed"));
+        } else if cli.deterministic {
+            provenance.set("seed", Source::Cli("--deterministic"));
+        }
+        if cli.rounding != vrp_instance::DistanceRounding::default() {
+            provenance.set("distance_rounding", Source::Cli("--rounding"));
+        }
+        if cli.metric != vrp_instance::Metric::default() {
+            provenance.set("metric", Source::Cli("--metric"));
+        }
+        if cli.distance_precision != DistancePrecision::default() {
+            provenance.set("distance_precision", Source::Cli("--f32-distances"));
+        }
+        let settings = explain::settings(
+            &params,
+            vec![
+                ("seed", json!(seed)),
+                ("threads", json!(threads)),
+                ("distance_rounding", json!(cli.rounding)),
+                ("metric", json!(cli.metric)),
+                ("distance_precision", json!(cli.distance_precision)),
+            ],
+            &provenance,
+        );
+        eprint!("{}", explain::table(&settings));
+        println!("{}", serde_json::to_string(&settings).unwrap());
+        return;
+    }
+
+    if let Some(path) = &cli.soak_replay {
+        let res = soak::replay::<solvers::MultiLNSSolver>(&vrp_instance, &params, Path::new(path));
+        std::process::exit(soak_exit_code(res));
+    }
+    if let Some(duration) = cli.soak {
+        let dir = cli
+            .soak_dir
+            .as_ref()
+            .map_or_else(|| soak::default_dir(file_name), PathBuf::from);
+        let seed = seed.unwrap_or_else(|| rand::Rng::random(&mut random::rng()));
+        let res =
+            soak::soak::<solvers::MultiLNSSolver>(&vrp_instance, &params, duration, &dir, seed);
+        std::process::exit(soak_exit_code(res));
+    }
+
+    let mut meta = RunMeta::new::<solvers::MultiLNSSolver>(&params, seed, threads);
+    if cli.deterministic {
+        meta = meta.with_reproducible_id(file_name);
+    }
+    if cli.depots.len() > 1 {
+        solve_multi_depot(&cli, &vrp_instance, &params, threads, meta, start);
+        return;
+    }
+    let (mut sol, elite_costs, phase_stats, repeats) = match cli.repeats {
+        Some(k) => {
+            // every repeat gets the full budget, and its own seed so outliers can be rerun with --seed
+            let base_seed = seed.unwrap_or_else(|| rand::Rng::random(&mut random::rng()));
+            let mut runs = Vec::with_capacity(k);
+            let mut best: Option<VRPSolution> = None;
+            for i in 0..k {
+                let run_seed = base_seed.wrapping_add(i as u64);
+                random::seed(run_seed);
+                let (sol, _, _) = solve_once(&cli, &solve_instance, &params, threads);
+                runs.push((run_seed, to_original_units(sol.cost())));
+                if best
+                    .as_ref()
+                    .is_none_or(|b| params.objective.evaluate(&sol) < params.objective.evaluate(b))
+                {
+                    best = Some(sol);
+                }
+            }
+            (best.unwrap(), Vec::new(), Vec::new(), Some(runs))
+        }
+        None => {
+            let (sol, elite_costs, phase_stats) =
+                solve_once(&cli, &solve_instance, &params, threads);
+            (sol, elite_costs, phase_stats, None)
+        }
+    };
+    if scale.is_some() {
+        sol = VRPSolution::from_routes_vec(&vrp_instance, &sol.to_routes_vec(false))
+            .expect("the normalized instance has the same customers, demands and vehicles");
+    }
+    let elite_costs = elite_costs
+        .into_iter()
+        .map(to_original_units)
+        .collect::<Vec<_>>();
+    if cli.orient_output {
+        sol.orient_for_output(&vrp_instance);
+    }
+    report_violations(&sol);
     let duration = start.elapsed();
+    let lower_bound = bounds::lower_bound(&vrp_instance);
 
-    let output = json!({
+    let mut output = json!({
         "Instance": file_name,
         "Time": (duration.as_secs_f64() * 100.0).round() / 100.0,
         "Result": sol.cost(),
+        "MaxRouteCost": sol.max_route_cost(),
+        "LowerBound": lower_bound,
+        "GapPercent": bounds::gap_percent(sol.cost(), lower_bound),
         "Solution": sol.to_string(),
+        "EliteCosts": elite_costs,
+        "meta": meta,
     });
+    if let Some(bks) = bks {
+        let reached = bks_target.is_some_and(|target| sol.cost() <= target);
+        output["BKS"] = json!(bks);
+        output["BKSGapPercent"] = json!(bounds::bks_gap_percent(sol.cost(), bks));
+        output["ReachedBKSGap"] = json!(reached);
+        // the search stops as soon as it gets there, so that's (about) now
+        output["TimeToGap"] = json!(reached.then_some(duration.as_secs_f64()));
+    } else if cli.bks.is_some() {
+        for field in ["BKS", "BKSGapPercent", "ReachedBKSGap", "TimeToGap"] {
+            output[field] = serde_json::Value::Null;
+        }
+    }
+    if vrp_instance.customer_ids.is_some() {
+        output["Routes"] = sol.to_json(&vrp_instance);
+    }
+    // so that `--check` can read the instance back the same way
+    if vrp_instance.depot != 0 {
+        output["Depot"] = json!(vrp_instance.depot);
+    }
+    if let Some(costs) = report::CostReport::new(&sol, &vrp_instance) {
+        output["Costs"] = json!(costs);
+    }
+    if let Some(runs) = &repeats {
+        output["Repeats"] = repeat_summary(runs);
+    }
+    if !phase_stats.is_empty() {
+        output["Phases"] = phase_stats
+            .iter()
+            .map(|p| {
+                json!({
+                    "Label": p.label,
+                    "Solver": p.solver,
+                    "Iterations": p.stats.iterations,
+                    "Improvements": p.stats.improvements.len(),
+                    "ImprovementTemperatures": p.stats.improvement_temperatures,
+                    "Restarts": p.stats.restarts.len(),
+                    "DiversityRestarts": p.stats.diversity_restarts.len(),
+                    "Calibration": p.stats.calibration,
+                    "Operators": p.stats.operators,
+                    "InsertionScans": p.stats.insertion_scans,
+                })
+            })
+            .collect();
+    }
+    output["RouteSummaries"] = json!(sol.route_summaries());
+    output["LongestLegs"] = sol
+        .longest_legs(3)
+        .iter()
+        .map(|&(r, i, cost)| {
+            let (from, to) = sol.routes[r].gap(i);
+            let (from, to) = (vrp_instance.file_row(from.0), vrp_instance.file_row(to.0));
+            json!({"Route": r, "From": from, "To": to, "Distance": cost})
+        })
+        .collect();
+    if let Some(k) = cli.problem_customers {
+        let mut marginal_costs = sol.marginal_costs();
+        marginal_costs.sort_by(|a, b| b.1.total_cmp(&a.1));
+        marginal_costs.truncate(k);
+        output["ProblemCustomers"] = marginal_costs
+            .iter()
+            .map(|(cust_no, cost)| json!({"Customer": cust_no, "MarginalCost": cost}))
+            .collect();
+    }
+
+    print_output(&cli, &output);
+    if let Some((cache, key)) = &cache {
+        if let Err(e) = cache.store(*key, &sol, &output) {
+            eprintln!("couldn't cache the result: {e}");
+        }
+    }
+
+    write_sol_file(&cli, file_name, &sol_file_string(&cli, &sol));
+    write_drawings(&cli, &sol, &vrp_instance);
+}
 
-    println!("{}", serde_json::to_string(&output).unwrap());
+/// Print a run's JSON line. A `--deterministic` one leaves out the wall-clock times, so running it
+/// again prints exactly the same line.
+fn print_output(cli: &CliArgs, output: &serde_json::Value) {
+    if cli.deterministic {
+        let mut output = output.clone();
+        if let Some(fields) = output.as_object_mut() {
+            fields.remove("Time");
+            fields.remove("TimeToGap");
+        }
+        println!("{}", serde_json::to_string(&output).unwrap());
+    } else {
+        println!("{}", serde_json::to_string(output).unwrap());
+    }
+}
 
-    let sol_path = &format!("./{file_name}.sol");
-    let path = Path::new(sol_path);
-    let mut file = File::create(path).unwrap();
+/// Solve each depot's share of a multi-depot instance with a slice of the budget in proportion to
+/// its customers, then print and save the combined result.
+fn solve_multi_depot(
+    cli: &CliArgs,
+    vrp_instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    threads: usize,
+    meta: RunMeta,
+    start: Instant,
+) {
+    let file_name = get_filename_from_path(&cli.instance_path);
+    let shares = cli.depots[1..]
+        .iter()
+        .map(|&row| {
+            u32::try_from(row)
+                .map(|row| CustomerId(vrp_instance.customer_of_row(row)))
+                .map_err(|_| format!("there's no row {row} to be a depot"))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|depots| multi_depot::split(vrp_instance, &depots))
+        .unwrap_or_else(|e| {
+            eprintln!("Error: can't split {file_name} between its depots: {e}");
+            std::process::exit(-1);
+        });
 
-    // Write the string to the file
-    file.write_all(sol.to_file_string().as_bytes()).unwrap();
+    let num_served = shares
+        .iter()
+        .map(|share| share.customers.len())
+        .sum::<usize>();
+    let mut solved = Vec::with_capacity(shares.len());
+    for share in shares {
+        #[allow(clippy::cast_precision_loss)]
+        let sub_params = SolveParams {
+            terminate: params
+                .terminate
+                .scaled(share.customers.len() as f64 / num_served as f64),
+            // each depot's cost is only part of the whole
+            target_cost: None,
+            ..params.clone()
+        };
+        let mut sol = if share.customers.len() < decompose::MIN_CLUSTER_FOR_LNS {
+            (sub_params.constructor)(&share.instance)
+        } else {
+            solve_once(cli, &share.instance, &sub_params, threads).0
+        };
+        if cli.orient_output {
+            sol.orient_for_output(&share.instance);
+        }
+        report_violations(&sol);
+        solved.push((share, sol));
+    }
+
+    let id = |cust_no: u32| match &vrp_instance.customer_ids {
+        Some(ids) => json!(ids[cust_no as usize]),
+        None => json!(cust_no),
+    };
+    let depots = solved
+        .iter()
+        .map(|(share, sol)| {
+            let routes = sol
+                .to_routes_vec(false)
+                .iter()
+                .map(|route| share.to_global(route).into_iter().map(id).collect())
+                .collect::<Vec<serde_json::Value>>();
+            json!({
+                "Depot": id(share.depot.0),
+                "Vehicles": share.instance.num_vehicles,
+                "Cost": sol.cost(),
+                "Routes": routes,
+            })
+        })
+        .collect::<Vec<_>>();
+    let output = json!({
+        "Instance": file_name,
+        "Time": (start.elapsed().as_secs_f64() * 100.0).round() / 100.0,
+        "Result": multi_depot::cost(&solved),
+        "Solution": multi_depot::to_string(vrp_instance, &solved),
+        "Depots": depots,
+        // so that `--check` can read the instance back the same way and knows every depot
+        "DepotRows": cli.depots,
+        "meta": meta,
+    });
+    print_output(cli, &output);
+    write_sol_file(
+        cli,
+        file_name,
+        &multi_depot::to_file_string(vrp_instance, &solved),
+    );
+}
+
+/// Write `sol` as GeoJSON to the `--geojson` path, as SVG to the `--svg` path and as CSV to the
+/// `--csv` path, if they're given. Failing to is only worth a warning, since the `.sol` file already has the solution.
+fn write_drawings(cli: &CliArgs, sol: &VRPSolution, instance: &VRPInstance) {
+    let write = |path: &str, contents: String| {
+        if let Err(e) = output::write_atomic(Path::new(path), &contents) {
+            eprintln!("Warning: couldn't write {path}: {e}");
+        }
+    };
+    if let Some(path) = &cli.geojson {
+        write(path, sol.to_geojson(instance));
+    }
+    if let Some(path) = &cli.svg {
+        write(path, sol.to_svg(instance, SVG_SIZE, SVG_SIZE));
+    }
+    if let Some(path) = &cli.csv {
+        write(path, sol.to_csv(instance, cli.csv_return_leg));
+    }
+}
+
+/// What goes in the solution file, in the `--format` asked for and with each route's details if
+/// `--sol-details` asks for them.
+fn sol_file_string(cli: &CliArgs, sol: &VRPSolution) -> String {
+    match cli.sol_format {
+        SolFormat::Cvrplib => sol.to_cvrplib_string(),
+        SolFormat::Sol if cli.sol_details => sol.to_detailed_file_string(),
+        SolFormat::Sol => sol.to_file_string(),
+    }
+}
+
+fn write_sol_file(cli: &CliArgs, file_name: &str, sol_file: &str) {
+    let sol_path = cli.out.clone().unwrap_or_else(|| {
+        let stem = if cli.instance_path == STDIN_PATH {
+            "stdin"
+        } else {
+            file_name
+        };
+        format!("./{stem}.sol")
+    });
+    if let Err(e) = output::write_atomic(Path::new(&sol_path), sol_file) {
+        // don't lose the whole run's work just because we couldn't save it
+        eprintln!("Error: couldn't write {sol_path}: {e}");
+        print!("{sol_file}");
+        std::process::exit(1);
+    }
 }
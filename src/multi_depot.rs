@@ -0,0 +1,238 @@
+//! Instances with more than one depot (e.g. two warehouses), split up front into one instance per
+//! depot. Every customer is served from the depot nearest to it, each depot gets a share of the
+//! fleet in proportion to its customers' demand, and then each depot's customers are solved as a
+//! VRP of their own.
+//!
+//! This isn't a multi-depot search: routes don't have a depot of their own (every [`Route`] starts
+//! at customer 0 of its instance), and customers never move between depots after the split, so
+//! one that's only a little nearer one depot but would fit much better on another's routes stays
+//! where it was put. The output does give every route its own depot, though: [`to_string`] and
+//! [`to_file_string`] start and end each route at its depot's row in the instance file, which
+//! `--check` checks it against (see [`crate::verify::verify_routes`]).
+//!
+//! [`Route`]: crate::common::Route
+
+use std::sync::Arc;
+
+use crate::{common::VRPSolution, decompose, ids::CustomerId, vrp_instance::VRPInstance};
+
+/// One depot's part of a multi-depot instance.
+pub struct DepotShare {
+    /// the depot's customer number in the full instance
+    pub depot: CustomerId,
+    /// the customers it serves, which are customers 1, 2, ... of `instance`
+    pub customers: Vec<u32>,
+    /// just the depot (as customer 0) and its customers, with its share of the vehicles
+    pub instance: Arc<VRPInstance>,
+}
+
+impl DepotShare {
+    /// `route` in `instance`'s customer numbers, translated back to the full instance's.
+    pub fn to_global(&self, route: &[u32]) -> Vec<u32> {
+        route
+            .iter()
+            .map(|&c| self.customers[c as usize - 1])
+            .collect()
+    }
+}
+
+/// Split `instance` between its own depot and `extra_depots`, which have to be customers with no
+/// demand. Depots that end up without any customers are left out, and so get no vehicles.
+pub fn split(
+    instance: &Arc<VRPInstance>,
+    extra_depots: &[CustomerId],
+) -> Result<Vec<DepotShare>, String> {
+    for (i, &depot) in extra_depots.iter().enumerate() {
+        if depot.is_depot() || depot.index() >= instance.num_customers {
+            return Err(format!("there's no customer {depot} to be a depot"));
+        }
+        if extra_depots[..i].contains(&depot) {
+            return Err(format!("customer {depot} is given as a depot twice"));
+        }
+        let demand = instance.demand_of_customer[depot.index()];
+        if demand != 0 {
+            return Err(format!(
+                "customer {depot} has demand {demand}, so it can't be a depot"
+            ));
+        }
+    }
+
+    let depots = std::iter::once(CustomerId::DEPOT)
+        .chain(extra_depots.iter().copied())
+        .collect::<Vec<_>>();
+    let dm = &instance.distance_matrix;
+    let round_trip = |depot: CustomerId, c: CustomerId| dm.dist(depot, c) + dm.dist(c, depot);
+    let mut customers = vec![Vec::new(); depots.len()];
+    for c in CustomerId::customers(instance.num_customers).filter(|c| !depots.contains(c)) {
+        let nearest = (0..depots.len())
+            .min_by(|&a, &b| round_trip(depots[a], c).total_cmp(&round_trip(depots[b], c)))
+            .unwrap();
+        customers[nearest].push(c.0);
+    }
+    let (depots, customers): (Vec<_>, Vec<_>) = depots
+        .into_iter()
+        .zip(customers)
+        .filter(|(_, customers)| !customers.is_empty())
+        .unzip();
+
+    let fleet = decompose::split_fleet(instance, &customers).ok_or_else(|| {
+        format!(
+            "{} vehicles aren't enough to carry every depot's demand",
+            instance.num_vehicles
+        )
+    })?;
+    Ok(depots
+        .into_iter()
+        .zip(customers)
+        .zip(fleet)
+        .map(|((depot, customers), vehicles)| DepotShare {
+            instance: Arc::new(decompose::sub_instance(
+                instance, depot.0, &customers, vehicles,
+            )),
+            depot,
+            customers,
+        })
+        .collect())
+}
+
+/// Every depot's routes in the full instance's customer numbers, each starting and ending with
+/// its depot, optionally with a route that goes nowhere for every unused vehicle.
+pub fn global_routes(solved: &[(DepotShare, VRPSolution)], include_empty: bool) -> Vec<Vec<u32>> {
+    solved
+        .iter()
+        .flat_map(|(share, sol)| {
+            sol.to_routes_vec(include_empty).into_iter().map(|route| {
+                std::iter::once(share.depot.0)
+                    .chain(share.to_global(&route))
+                    .chain(std::iter::once(share.depot.0))
+                    .collect()
+            })
+        })
+        .collect()
+}
+
+pub fn cost(solved: &[(DepotShare, VRPSolution)]) -> f64 {
+    solved.iter().map(|(_, sol)| sol.cost()).sum()
+}
+
+/// What a `VRPSolution` of the full `instance` would display as, with each route's own depot at
+/// its ends, and everything given by its row in the instance file.
+pub fn to_string(instance: &VRPInstance, solved: &[(DepotShare, VRPSolution)]) -> String {
+    let routes = global_routes(solved, true)
+        .iter()
+        .map(|route| join(instance, route))
+        .collect::<Vec<_>>();
+    format!("0 {}", routes.join(" "))
+}
+
+/// What `VRPSolution::to_file_string` would write for the full `instance`, with each route's own
+/// depot at its ends.
+pub fn to_file_string(instance: &VRPInstance, solved: &[(DepotShare, VRPSolution)]) -> String {
+    let mut res = format!("{:.2} 0\n", cost(solved));
+    for route in global_routes(solved, true) {
+        res += &join(instance, &route);
+        res.push('\n');
+    }
+    res
+}
+
+fn join(instance: &VRPInstance, route: &[u32]) -> String {
+    route
+        .iter()
+        .map(|&c| instance.file_row(c).to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Customers 1 and 2 next to the depot, 4 and 5 next to a spot without demand at customer 3,
+    /// and customer 6 another one, far off on the same side as 3.
+    fn two_warehouses() -> Arc<VRPInstance> {
+        Arc::new(VRPInstance::from_parts_unchecked(
+            4,
+            20,
+            vec![0, 3, 4, 0, 5, 6, 0],
+            vec![0.0, 1.0, 2.0, 100.0, 99.0, 98.0, 500.0],
+            vec![0.0; 7],
+        ))
+    }
+
+    #[test]
+    fn customers_go_to_the_depot_with_the_shorter_round_trip() {
+        let instance = two_warehouses();
+        let shares = split(&instance, &[CustomerId(3)]).unwrap();
+        assert_eq!(shares.len(), 2);
+        assert_eq!(
+            (shares[0].depot, shares[0].customers.as_slice()),
+            (CustomerId::DEPOT, [1, 2].as_slice())
+        );
+        assert_eq!(
+            (shares[1].depot, shares[1].customers.as_slice()),
+            (CustomerId(3), [4, 5, 6].as_slice())
+        );
+        // the extra depot is customer 0 of its share, with the same distances to its customers
+        let (share, full) = (&shares[1].instance, &instance.distance_matrix);
+        assert_eq!(share.demand_of_customer, [0, 5, 6, 0]);
+        assert_eq!(
+            share.distance_matrix.dist(0_usize, 2_usize),
+            full.dist(3_usize, 5_usize)
+        );
+        // each needs a vehicle, and the other two are split 7 to 11 by demand
+        let vehicles = shares
+            .iter()
+            .map(|s| s.instance.num_vehicles)
+            .collect::<Vec<_>>();
+        assert_eq!(vehicles, [1, 2]);
+    }
+
+    #[test]
+    fn a_depot_without_customers_is_left_out() {
+        let shares = split(&two_warehouses(), &[CustomerId(3), CustomerId(6)]).unwrap();
+        let depots = shares.iter().map(|s| s.depot).collect::<Vec<_>>();
+        assert_eq!(depots, [CustomerId::DEPOT, CustomerId(3)]);
+    }
+
+    #[test]
+    fn only_customers_without_demand_can_be_depots() {
+        let instance = two_warehouses();
+        for (depots, complaint) in [
+            (vec![CustomerId(1)], "demand 3"),
+            (vec![CustomerId(3), CustomerId(3)], "twice"),
+            (vec![CustomerId(7)], "no customer 7"),
+            (vec![CustomerId::DEPOT], "no customer 0"),
+        ] {
+            let err = split(&instance, &depots).err().unwrap();
+            assert!(err.contains(complaint), "{depots:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn routes_are_written_with_their_own_depot_and_global_customers() {
+        let instance = two_warehouses();
+        let solved = split(&instance, &[CustomerId(3), CustomerId(6)])
+            .unwrap()
+            .into_iter()
+            .map(|share| {
+                let routes = vec![(1..=share.customers.len() as u32).rev().collect()];
+                let sol = VRPSolution::from_routes_vec(&share.instance, &routes).unwrap();
+                (share, sol)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            global_routes(&solved, false),
+            [vec![0, 2, 1, 0], vec![3, 5, 4, 3]]
+        );
+        let total = 2.0 * 2.0 + 2.0 * 2.0;
+        assert!((cost(&solved) - total).abs() < 1e-9);
+        // with an empty route for the extra depot's unused vehicle
+        assert_eq!(to_string(&instance, &solved), "0 0 2 1 0 3 5 4 3 3 3");
+        assert_eq!(
+            to_file_string(&instance, &solved),
+            "8.00 0\n0 2 1 0\n3 5 4 3\n3 3\n"
+        );
+    }
+}
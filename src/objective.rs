@@ -0,0 +1,112 @@
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::common::VRPSolution;
+
+/// How much the total distance counts under [`Objective::MinMaxDistance`], small enough that it
+/// only really decides between solutions with (nearly) the same longest route.
+const TIE_BREAK_WEIGHT: f64 = 1e-3;
+
+/// How strongly min-max repair avoids inserting into routes that are already nearly the longest.
+const MINMAX_REPAIR_BIAS: f64 = 1.0;
+
+/// What the search minimizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum Objective {
+    /// the sum of all the routes' distances
+    #[default]
+    TotalDistance,
+    /// the longest route's distance, to balance the work between drivers (the total breaks ties)
+    MinMaxDistance,
+}
+
+impl FromStr for Objective {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sum" => Ok(Objective::TotalDistance),
+            "minmax" => Ok(Objective::MinMaxDistance),
+            _ => Err(format!("unknown objective {s:?}, expected sum or minmax")),
+        }
+    }
+}
+
+impl Objective {
+    /// The score of `sol` under this objective, lower is better.
+    pub fn evaluate(self, sol: &VRPSolution) -> f64 {
+        match self {
+            Objective::TotalDistance => sol.cost(),
+            Objective::MinMaxDistance => sol.max_route_cost() + TIE_BREAK_WEIGHT * sol.cost(),
+        }
+    }
+
+    /// What to scale the cost of inserting into a route costing `route_cost` by, when the longest
+    /// route costs `max_route_cost`. Insertion deltas only see the total distance, so under min-max
+    /// this steers repairs away from the routes that set (or nearly set) the maximum.
+    pub fn insertion_weight(self, route_cost: f64, max_route_cost: f64) -> f64 {
+        match self {
+            Objective::TotalDistance => 1.0,
+            Objective::MinMaxDistance if max_route_cost > 0.0 => {
+                1.0 + MINMAX_REPAIR_BIAS * route_cost / max_route_cost
+            }
+            Objective::MinMaxDistance => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f64::consts::TAU, sync::Arc, time::Duration};
+
+    use super::*;
+    use crate::{
+        random,
+        solver::{self, SolveParams, TermCond},
+        solvers::ALNSSolver,
+        vrp_instance::VRPInstance,
+    };
+
+    /// Route costs, longest first, after solving for `objective`.
+    fn route_costs(instance: &Arc<VRPInstance>, objective: Objective) -> Vec<f64> {
+        random::seed(1976);
+        let params = SolveParams {
+            terminate: TermCond::MaxIters(1000),
+            objective,
+            ..crate::default_params(Duration::ZERO)
+        };
+        let initial_solution = params.starting_solution(instance);
+        let (sol, _) =
+            solver::solve_from_with_stats::<ALNSSolver>(instance, &params, initial_solution);
+        sol.validate(instance).unwrap();
+        let mut costs = sol.routes.iter().map(|r| r.cost()).collect::<Vec<_>>();
+        costs.sort_by(|a, b| b.total_cmp(a));
+        costs
+    }
+
+    #[test]
+    fn min_max_balances_the_routes_that_the_total_would_not() {
+        // 20 customers on a ring around the depot: one trip round it all is the shortest in total,
+        // but two trips round half each share the driving out evenly
+        let n = 20;
+        let (xs, ys) = (0..=n)
+            .map(|i| {
+                if i == 0 {
+                    return (0.0, 0.0);
+                }
+                let angle = TAU * f64::from(i) / f64::from(n);
+                (50.0 * angle.cos(), 50.0 * angle.sin())
+            })
+            .unzip();
+        let mut demands = vec![1; 21];
+        demands[0] = 0;
+        let instance = Arc::new(VRPInstance::from_parts(2, 20, demands, xs, ys).unwrap());
+
+        let total = route_costs(&instance, Objective::TotalDistance);
+        let min_max = route_costs(&instance, Objective::MinMaxDistance);
+        assert!(min_max[0] < 0.8 * total[0]);
+        let spread = |costs: &[f64]| costs[0] - costs.get(1).copied().unwrap_or(0.0);
+        assert!(spread(&min_max) < 0.25 * spread(&total));
+    }
+}
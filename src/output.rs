@@ -0,0 +1,124 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind, Write},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+/// How many times to try writing before giving up on errors that might go away on their own.
+const WRITE_ATTEMPTS: usize = 3;
+
+/// Write `contents` to `path` so that readers only ever see the old file or the complete new one:
+/// it's written to a temporary file next to `path` and then renamed over it. Missing parent
+/// directories are created, and transient errors are retried a couple of times.
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    write_atomic_with(path, |out| out.write_all(contents.as_bytes()))
+}
+
+/// [`write_atomic`] for contents that `write` writes out a piece at a time, e.g. ones too big to
+/// hold in memory all at once. It's called again from the start on a retry.
+pub fn write_atomic_with(
+    path: &Path,
+    write: impl Fn(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut attempt = 1;
+    loop {
+        match try_write_atomic(path, &write) {
+            Err(e) if attempt < WRITE_ATTEMPTS && is_transient(&e) => {
+                attempt += 1;
+                thread::sleep(Duration::from_millis(100));
+            }
+            res => return res,
+        }
+    }
+}
+
+fn try_write_atomic(
+    path: &Path,
+    write: &impl Fn(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "output path has no file name"))?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let res = File::create(&tmp_path)
+        .and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write(&mut out)?;
+            out.into_inner()
+                .map_err(io::IntoInnerError::into_error)?
+                .sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp_path, path));
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    res
+}
+
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A fresh, empty directory for one test.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vrp-output-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn writes_replace_the_file_and_make_its_directories() {
+        let dir = scratch_dir("replace");
+        let path = dir.join("a/b/out.sol");
+        write_atomic(&path, "first").unwrap();
+        write_atomic(&path, "second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        // and no temporary files are left behind
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_write_leaves_nothing_behind() {
+        let dir = scratch_dir("fail");
+        // a file where a directory should be can't be written under even by root, unlike one
+        // without write permission
+        let blocker = dir.join("not_a_dir");
+        fs::write(&blocker, "keep").unwrap();
+        assert!(write_atomic(&blocker.join("out.sol"), "lost").is_err());
+
+        // nor can a write fail halfway and leave its temporary file
+        let path = dir.join("out.sol");
+        let res = write_atomic_with(&path, |out| {
+            out.write_all(b"half")?;
+            Err(io::Error::other("disk full"))
+        });
+        assert_eq!(res.unwrap_err().to_string(), "disk full");
+        let left = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(left, ["not_a_dir"]);
+        assert_eq!(fs::read_to_string(&blocker).unwrap(), "keep");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
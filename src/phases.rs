@@ -0,0 +1,190 @@
+use std::{str::FromStr, sync::Arc};
+
+use serde::Serialize;
+
+use crate::{
+    common::VRPSolution,
+    solver::{self, SolveParams, SolveResult, stats::SolveStats},
+    solvers::{ALNSSolver, MultiLNSSolver, SimpleLNSSolver},
+    vrp_instance::VRPInstance,
+};
+
+/// Which solver a phase runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SolverChoice {
+    Alns,
+    MultiLns,
+    SimpleLns,
+}
+
+impl FromStr for SolverChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alns" => Ok(SolverChoice::Alns),
+            "multi" => Ok(SolverChoice::MultiLns),
+            "simple" => Ok(SolverChoice::SimpleLns),
+            _ => Err(format!(
+                "unknown solver {s:?}, expected alns, multi or simple"
+            )),
+        }
+    }
+}
+
+/// What one phase of a phased solve did.
+#[derive(Debug)]
+pub struct PhaseStats {
+    pub label: String,
+    pub solver: SolverChoice,
+    pub stats: SolveStats,
+}
+
+/// Parse phases written as `solver:fraction` pairs separated by commas, e.g. `multi:0.3,alns:0.7`.
+/// The fractions must be positive and add up to at most 1.
+pub fn parse_phases(s: &str) -> Result<Vec<(f64, SolverChoice)>, String> {
+    let phases = s
+        .split(',')
+        .map(|phase| {
+            let (solver, frac) = phase
+                .split_once(':')
+                .ok_or(format!("invalid phase {phase:?}, expected solver:fraction"))?;
+            let frac = frac
+                .parse::<f64>()
+                .map_err(|_| format!("invalid budget fraction in phase {phase:?}"))?;
+            if frac <= 0.0 {
+                return Err(format!("phase {phase:?} needs a positive budget fraction"));
+            }
+            Ok((frac, solver.parse()?))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if phases.iter().map(|(frac, _)| frac).sum::<f64>() > 1.0 + 1e-9 {
+        return Err("phase budget fractions add up to more than 1".to_string());
+    }
+    Ok(phases)
+}
+
+/// Run each phase's solver for its fraction of `params.terminate` in turn, each one starting from
+/// the best solution of the phase before it. Returns the final best and every phase's stats.
+pub fn solve_phased(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    phases: &[(f64, SolverChoice)],
+) -> (VRPSolution, Vec<PhaseStats>) {
+    let mut best = params.starting_solution(instance);
+    let mut phase_stats = Vec::with_capacity(phases.len());
+
+    for (i, &(frac, solver)) in phases.iter().enumerate() {
+        let phase_params = SolveParams {
+            terminate: params.terminate.scaled(frac),
+            calibration: params.calibration.map(|d| d.mul_f64(frac)),
+            ..params.clone()
+        };
+        let (phase_best, stats): SolveResult = match solver {
+            SolverChoice::Alns => {
+                solver::solve_from_with_stats::<ALNSSolver>(instance, &phase_params, best)
+            }
+            SolverChoice::MultiLns => {
+                solver::solve_from_with_stats::<MultiLNSSolver>(instance, &phase_params, best)
+            }
+            SolverChoice::SimpleLns => {
+                solver::solve_from_with_stats::<SimpleLNSSolver>(instance, &phase_params, best)
+            }
+        };
+
+        best = phase_best;
+        phase_stats.push(PhaseStats {
+            label: format!("phase {} ({solver:?})", i + 1),
+            solver,
+            stats,
+        });
+    }
+
+    (best, phase_stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{generate, random, solver::TermCond};
+
+    fn params(iters: usize) -> SolveParams {
+        SolveParams {
+            terminate: TermCond::MaxIters(iters),
+            ..crate::default_params(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn phases_parse_and_bad_ones_are_refused() {
+        assert_eq!(
+            parse_phases("multi:0.3,alns:0.5,simple:0.2"),
+            Ok(vec![
+                (0.3, SolverChoice::MultiLns),
+                (0.5, SolverChoice::Alns),
+                (0.2, SolverChoice::SimpleLns)
+            ])
+        );
+        for bad in [
+            "alns",
+            "alns:x",
+            "alns:0",
+            "alns:-0.5",
+            "tabu:0.5",
+            "alns:0.6,multi:0.6",
+        ] {
+            assert!(parse_phases(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn each_phase_gets_its_fraction_of_the_budget_and_its_own_label() {
+        let instance = Arc::new(generate::random_instance(40, 50, 1973));
+        random::seed(1973);
+        let phases = parse_phases("multi:0.3,alns:0.5,simple:0.2").unwrap();
+        let (sol, stats) = solve_phased(&instance, &params(1000), &phases);
+        sol.validate(&instance).unwrap();
+
+        let ran = stats
+            .iter()
+            .map(|phase| {
+                (
+                    phase.label.as_str(),
+                    phase.solver,
+                    phase.stats.total_iterations,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ran,
+            [
+                ("phase 1 (MultiLns)", SolverChoice::MultiLns, 300),
+                ("phase 2 (Alns)", SolverChoice::Alns, 500),
+                ("phase 3 (SimpleLns)", SolverChoice::SimpleLns, 200)
+            ]
+        );
+    }
+
+    #[test]
+    fn each_phase_starts_from_the_last_ones_best() {
+        let instance = Arc::new(generate::random_instance(40, 50, 1973));
+        random::seed(1973);
+        let start = params(0).starting_solution(&instance);
+
+        // a phase with no budget hands its start straight on
+        random::seed(1973);
+        let (sol, _) = solve_phased(&instance, &params(1000), &[(0.0, SolverChoice::Alns)]);
+        assert_eq!(sol.to_string(), start.to_string());
+
+        // and each later phase can only improve on what it's given
+        random::seed(1973);
+        let phases = [(0.0, SolverChoice::MultiLns), (0.5, SolverChoice::Alns)];
+        let (sol, stats) = solve_phased(&instance, &params(1000), &phases);
+        assert!(sol.cost() <= start.cost());
+        let improvements = &stats[1].stats.improvements;
+        assert!(improvements.iter().all(|&(_, cost)| cost < start.cost()));
+    }
+}
@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use crate::{common::VRPSolution, vrp_instance::VRPInstance};
+
+/// Hands out solution buffers and takes them back when they're done with, so copying solutions
+/// around the solve loop can reuse allocations through `clone_from` instead of `clone`.
+pub struct SolutionPool {
+    instance: Arc<VRPInstance>,
+    free: Vec<VRPSolution>,
+}
+
+impl SolutionPool {
+    pub fn new(instance: Arc<VRPInstance>) -> Self {
+        SolutionPool {
+            instance,
+            free: Vec::new(),
+        }
+    }
+
+    /// A copy of `source` in a reused buffer (only allocating if the pool is empty, or for routes
+    /// the buffer doesn't have yet).
+    pub fn take_copy(&mut self, source: &VRPSolution) -> VRPSolution {
+        let mut sol = self
+            .free
+            .pop()
+            .unwrap_or_else(|| VRPSolution::new(&self.instance));
+        sol.clone_from(source);
+        sol
+    }
+
+    /// Return a buffer to the pool.
+    pub fn give_back(&mut self, sol: VRPSolution) {
+        self.free.push(sol);
+    }
+}
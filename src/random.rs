@@ -0,0 +1,37 @@
+use std::cell::RefCell;
+
+use rand::{RngCore, SeedableRng, rngs::StdRng};
+
+thread_local! {
+    static THREAD_RNG: RefCell<StdRng> = RefCell::new(StdRng::from_os_rng());
+}
+
+/// A handle to this thread's random number generator, which is seeded from the OS unless
+/// [`seed`] has been called. Everything random in the solver should come from here, so that
+/// seeding it makes a run reproducible.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SeededRng;
+
+/// Drop-in replacement for `rand::rng()`.
+pub fn rng() -> SeededRng {
+    SeededRng
+}
+
+/// Reseed this thread's generator, making everything that follows on this thread deterministic.
+pub fn seed(seed: u64) {
+    THREAD_RNG.with_borrow_mut(|r| *r = StdRng::seed_from_u64(seed));
+}
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        THREAD_RNG.with_borrow_mut(RngCore::next_u32)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        THREAD_RNG.with_borrow_mut(RngCore::next_u64)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        THREAD_RNG.with_borrow_mut(|r| r.fill_bytes(dst));
+    }
+}
@@ -0,0 +1,348 @@
+use std::{fmt, sync::Arc};
+
+use crate::{
+    common::{Stop, VRPSolution},
+    error::VrpError,
+    ids::{CustomerId, RouteIdx},
+    vrp_instance::VRPInstance,
+};
+
+/// What `repair_imported` had to change to make a solution feasible.
+#[derive(Debug, Default, Clone)]
+pub struct RepairSummary {
+    /// customers that showed up more than once, and had the extra copies dropped
+    pub duplicates_removed: Vec<CustomerId>,
+    /// (`cust_no`, from route, to route) for each stop moved off an over-capacity route
+    pub relocated: Vec<(CustomerId, RouteIdx, RouteIdx)>,
+    /// customers that weren't visited at all and had to be inserted
+    pub inserted: Vec<CustomerId>,
+}
+
+impl RepairSummary {
+    /// Whether nothing had to be changed.
+    pub fn is_empty(&self) -> bool {
+        self.duplicates_removed.is_empty() && self.relocated.is_empty() && self.inserted.is_empty()
+    }
+}
+
+impl fmt::Display for RepairSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |customers: &mut dyn Iterator<Item = CustomerId>| {
+            customers
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut parts = Vec::new();
+        if !self.duplicates_removed.is_empty() {
+            let customers = list(&mut self.duplicates_removed.iter().copied());
+            parts.push(format!("dropped extra visits to {customers}"));
+        }
+        if !self.relocated.is_empty() {
+            let customers = list(&mut self.relocated.iter().map(|&(c, _, _)| c));
+            parts.push(format!("moved {customers} off over-capacity routes"));
+        }
+        if !self.inserted.is_empty() {
+            let customers = list(&mut self.inserted.iter().copied());
+            parts.push(format!("inserted missing {customers}"));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Build a solution from each route's customers (e.g. read from a warm start file) and patch it
+/// up, so that every customer is visited exactly once and every route is within capacity.
+///
+/// Duplicates are removed first (keeping the occurrence that's cheapest to have, whether the copies
+/// are on different routes or the same one), then stops are relocated off over-capacity routes,
+/// and finally missing customers are added with regret insertion. Unknown customers and more
+/// routes than vehicles can't be patched up, and are errors.
+pub fn repair_imported(
+    instance: &Arc<VRPInstance>,
+    routes: &[Vec<u32>],
+) -> Result<(VRPSolution, RepairSummary), VrpError> {
+    if routes.len() > instance.num_vehicles {
+        return Err(VrpError::TooManyRoutes {
+            routes: routes.len(),
+            vehicles: instance.num_vehicles,
+        });
+    }
+    if let Some(&bad) = routes
+        .iter()
+        .flatten()
+        .find(|&&c| c == 0 || c as usize >= instance.num_customers)
+    {
+        return Err(VrpError::UnknownCustomer(bad as usize));
+    }
+
+    let mut summary = RepairSummary::default();
+    let mut routes = routes.to_vec();
+    remove_duplicates(&mut routes, instance, &mut summary);
+    let mut sol = VRPSolution::from_routes_unchecked(instance, &routes);
+    relieve_over_capacity(&mut sol, instance, &mut summary)?;
+    insert_missing(&mut sol, instance, &mut summary)?;
+    sol.compact();
+
+    Ok((sol, summary))
+}
+
+fn remove_duplicates(
+    routes: &mut [Vec<u32>],
+    instance: &Arc<VRPInstance>,
+    summary: &mut RepairSummary,
+) {
+    let dm = &instance.distance_matrix;
+    let mut occurrences = vec![Vec::new(); instance.num_customers];
+    for (r, route) in routes.iter().enumerate() {
+        for (i, &c) in route.iter().enumerate() {
+            occurrences[c as usize].push((r, i));
+        }
+    }
+
+    // (route, index) of every copy to drop
+    let mut dropped: Vec<(usize, usize)> = Vec::new();
+    for (c, copies) in occurrences.iter().enumerate() {
+        if copies.len() <= 1 {
+            continue;
+        }
+        // how much a copy's route would save without it
+        let saving = |&(r, i): &(usize, usize)| {
+            let route = &routes[r];
+            let before = if i == 0 { 0 } else { route[i - 1] as usize };
+            let after = route
+                .get(i + 1)
+                .map_or(instance.end_node.index(), |&c| c as usize);
+            dm.dist(before, c) + dm.dist(c, after) - dm.dist(before, after)
+        };
+        // the cheapest placement is the one that saves the least when removed
+        let keep = copies
+            .iter()
+            .min_by(|a, b| saving(a).total_cmp(&saving(b)))
+            .unwrap();
+        dropped.extend(copies.iter().filter(|copy| *copy != keep));
+        summary
+            .duplicates_removed
+            .push(CustomerId::try_from(c).unwrap());
+    }
+
+    // from the back, so the indices of copies still to drop don't shift
+    dropped.sort_unstable_by(|a, b| b.cmp(a));
+    for (r, i) in dropped {
+        routes[r].remove(i);
+    }
+}
+
+fn relieve_over_capacity(
+    sol: &mut VRPSolution,
+    instance: &Arc<VRPInstance>,
+    summary: &mut RepairSummary,
+) -> Result<(), VrpError> {
+    let cap = instance.vehicle_capacity;
+
+    for r in 0..sol.routes.len() {
+        while sol.routes[r].used_capacity() > cap {
+            // (index in r, destination route, destination index, total cost change)
+            let mut best: Option<(usize, usize, usize, f64)> = None;
+
+            for (i, stop) in sol.routes[r].stops().iter().enumerate() {
+                let removal_delta =
+                    sol.routes[r].speculative_remove_stop(i).0 - sol.routes[r].cost();
+
+                for (other_r, other) in sol.routes.iter().enumerate() {
+                    if other_r == r || other.used_capacity() + stop.capacity() > cap {
+                        continue;
+                    }
+                    for j in 0..=other.stops().len() {
+                        let (new_cost, feasible) = other.speculative_add_stop(stop, j);
+                        let delta = new_cost - other.cost() + removal_delta;
+                        if feasible && best.is_none_or(|b| delta < b.3) {
+                            best = Some((i, other_r, j, delta));
+                        }
+                    }
+                }
+            }
+
+            let Some((i, to_r, j, _)) = best else {
+                return Err(VrpError::CannotRelieveRoute {
+                    route: r,
+                    load: sol.routes[r].used_capacity(),
+                    cap,
+                });
+            };
+
+            let stop = sol.routes[r].remove_stop_at_index(i);
+            sol.add_stop(to_r, stop, j);
+            summary
+                .relocated
+                .push((stop.cust_no(), RouteIdx(r), RouteIdx(to_r)));
+        }
+    }
+
+    Ok(())
+}
+
+fn insert_missing(
+    sol: &mut VRPSolution,
+    instance: &Arc<VRPInstance>,
+    summary: &mut RepairSummary,
+) -> Result<(), VrpError> {
+    let mut missing = CustomerId::customers(instance.num_customers)
+        .filter(|&c| !sol.routes.iter().any(|r| r.contains_stop(c)))
+        .map(|c| Stop::new(c, instance.demand_of_customer[c.index()]))
+        .collect::<Vec<_>>();
+
+    while !missing.is_empty() {
+        // (index into missing, route, position, regret)
+        let mut chosen: Option<(usize, usize, usize, f64)> = None;
+
+        for (m, stop) in missing.iter().enumerate() {
+            let Some(((r, i), regret)) = best_two_insertions(sol, instance, stop) else {
+                return Err(VrpError::NoFeasibleInsertion(stop.cust_no()));
+            };
+            if chosen.is_none_or(|c| regret > c.3) {
+                chosen = Some((m, r, i, regret));
+            }
+        }
+
+        let (m, r, i, _) = chosen.unwrap();
+        let stop = missing.swap_remove(m);
+        sol.add_stop(r, stop, i);
+        summary.inserted.push(stop.cust_no());
+    }
+
+    Ok(())
+}
+
+/// The best feasible (route, index) to insert `stop`, along with its regret: how much worse
+/// the best insertion into any *other* route would be.
+fn best_two_insertions(
+    sol: &VRPSolution,
+    instance: &Arc<VRPInstance>,
+    stop: &Stop,
+) -> Option<((usize, usize), f64)> {
+    let mut best: Option<(usize, usize, f64)> = None;
+    let mut second_best = f64::MAX;
+
+    for (r, route) in sol.routes.iter().enumerate() {
+        if route.used_capacity() + stop.capacity() > instance.vehicle_capacity {
+            continue;
+        }
+
+        let mut route_best = (usize::MAX, f64::MAX);
+        for i in 0..=route.stops().len() {
+            let (new_cost, feasible) = route.speculative_add_stop(stop, i);
+            let delta = new_cost - route.cost();
+            if feasible && delta < route_best.1 {
+                route_best = (i, delta);
+            }
+        }
+        if route_best.0 == usize::MAX {
+            continue;
+        }
+
+        match best {
+            Some((_, _, best_delta)) if route_best.1 >= best_delta => {
+                second_best = second_best.min(route_best.1);
+            }
+            _ => {
+                if let Some((_, _, best_delta)) = best {
+                    second_best = best_delta;
+                }
+                best = Some((r, route_best.0, route_best.1));
+            }
+        }
+    }
+
+    best.map(|(r, i, delta)| ((r, i), second_best - delta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Customers 1 to 6 a unit apart along a line out from the depot, each with demand 3, and
+    /// `vehicles` vehicles that can carry four of them.
+    fn line(vehicles: usize) -> VRPInstance {
+        let xs = (0..=6).map(f64::from).collect();
+        VRPInstance::from_parts_unchecked(vehicles, 12, vec![0, 3, 3, 3, 3, 3, 3], xs, vec![0.0; 7])
+    }
+
+    fn repaired(instance: VRPInstance, routes: &[Vec<u32>]) -> (VRPSolution, RepairSummary) {
+        let instance = Arc::new(instance);
+        let (sol, summary) = repair_imported(&instance, routes).unwrap();
+        assert_eq!(sol.validate(&instance), Ok(()));
+        (sol, summary)
+    }
+
+    fn route_of(sol: &VRPSolution, c: u32) -> Vec<u32> {
+        sol.to_routes_vec(false)
+            .into_iter()
+            .find(|route| route.contains(&c))
+            .unwrap()
+    }
+
+    #[test]
+    fn feasible_solution_is_left_alone() {
+        let routes = [vec![1, 2, 3], vec![4, 5, 6]];
+        let (sol, summary) = repaired(line(3), &routes);
+        assert!(summary.is_empty());
+        assert_eq!(sol.to_routes_vec(false), routes);
+    }
+
+    #[test]
+    fn duplicate_on_another_route_keeps_the_cheaper_copy() {
+        // 3 is a detour of 2 at the end of the first route, but on the way to 4 on the second
+        let (sol, summary) = repaired(line(3), &[vec![1, 2, 3], vec![3, 4, 5, 6]]);
+        assert_eq!(summary.duplicates_removed, [CustomerId(3)]);
+        assert_eq!(route_of(&sol, 3), [3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn duplicate_on_the_same_route_is_removed() {
+        let (sol, summary) = repaired(line(3), &[vec![1, 2, 1, 1, 3], vec![4, 5, 6]]);
+        assert_eq!(summary.duplicates_removed, [CustomerId(1)]);
+        assert_eq!(route_of(&sol, 1), [1, 2, 3]);
+    }
+
+    #[test]
+    fn over_capacity_route_is_relieved() {
+        let (sol, summary) = repaired(line(3), &[vec![1, 2, 3, 4, 5], vec![6]]);
+        assert_eq!(summary.relocated.len(), 1);
+        assert!(sol.routes.iter().all(|r| r.used_capacity() <= 12));
+    }
+
+    #[test]
+    fn missing_customers_are_inserted() {
+        let (_, summary) = repaired(line(3), &[vec![1, 2], vec![4, 5]]);
+        let mut inserted = summary.inserted;
+        inserted.sort_unstable();
+        assert_eq!(inserted, [CustomerId(3), CustomerId(6)]);
+    }
+
+    #[test]
+    fn relocation_never_uses_a_forbidden_edge() {
+        // with two vehicles, 1 to 5 can only move next to 6
+        let mut instance = line(2);
+        for c in 1..=5 {
+            instance.forbid_edge(c, 6).unwrap();
+        }
+        let err = repair_imported(&Arc::new(instance), &[vec![1, 2, 3, 4, 5], vec![6]]);
+        assert!(matches!(
+            err,
+            Err(VrpError::CannotRelieveRoute { route: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn insertion_never_uses_a_forbidden_edge() {
+        let mut instance = line(2);
+        for c in [0, 1, 2, 4, 5, 6] {
+            instance.forbid_edge(c, 3).unwrap();
+        }
+        let err = repair_imported(&Arc::new(instance), &[vec![1, 2], vec![4, 5, 6]]);
+        assert_eq!(
+            err.map(|_| ()),
+            Err(VrpError::NoFeasibleInsertion(CustomerId(3)))
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use rand::Rng;
+use serde::Serialize;
+
+use crate::{
+    cache::hash_bytes, common::VRPSolution, solver::SolveParams, vrp_instance::VRPInstance,
+};
+
+/// Identifies a single run of the solver, so every artifact it produces can be joined back together.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunMeta {
+    pub run_id: String,
+    pub version: &'static str,
+    pub git_describe: Option<&'static str>,
+    pub solver: String,
+    pub params: serde_json::Value,
+    pub seed: Option<u64>,
+    pub threads: usize,
+    pub hostname: Option<String>,
+}
+
+impl RunMeta {
+    pub fn new<S>(params: &SolveParams, seed: Option<u64>, threads: usize) -> Self {
+        RunMeta {
+            run_id: new_run_id(),
+            version: env!("CARGO_PKG_VERSION"),
+            git_describe: option_env!("VRP_GIT_DESCRIBE"),
+            solver: solver_name::<S>().to_string(),
+            params: serde_json::to_value(params).unwrap(),
+            seed,
+            threads,
+            hostname: hostname(),
+        }
+    }
+
+    /// Swap the random run id for one worked out from the instance's name, the seed and the
+    /// parameters, so running the same deterministic run again gives the same id.
+    pub fn with_reproducible_id(mut self, instance: &str) -> Self {
+        let what = format!("{instance}\0{:?}\0{}", self.seed, self.params);
+        let (a, b) = (
+            hash_bytes(what.as_bytes()),
+            hash_bytes(self.params.to_string().as_bytes()),
+        );
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&a.to_le_bytes());
+        bytes[8..].copy_from_slice(&b.to_le_bytes());
+        // version 8, i.e. laid out however we like
+        bytes[6] = (bytes[6] & 0x0f) | 0x80;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        self.run_id = uuid(bytes);
+        self
+    }
+}
+
+/// The bare type name of a solver (e.g. `MultiLNSSolver`), without its module path.
+pub fn solver_name<S>() -> &'static str {
+    let full = std::any::type_name::<S>();
+    full.rsplit("::").next().unwrap_or(full)
+}
+
+/// A random (version 4) UUID.
+fn new_run_id() -> String {
+    let mut bytes: [u8; 16] = rand::rng().random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    uuid(bytes)
+}
+
+fn uuid(bytes: [u8; 16]) -> String {
+    let hex = bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+}
+
+/// Distances in the instance's unit, along with what they cost when it has a cost per unit.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CostReport {
+    pub distance_unit: Option<String>,
+    pub cost_per_unit: Option<f64>,
+    pub total_distance: f64,
+    /// rounded to cents
+    pub total_cost: Option<f64>,
+    pub routes: Vec<RouteCost>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RouteCost {
+    pub route: usize,
+    pub distance: f64,
+    /// rounded to cents
+    pub cost: Option<f64>,
+}
+
+impl CostReport {
+    /// The report for `sol`, or `None` if the instance has neither a distance unit nor a cost per
+    /// unit, in which case there's nothing to add to the plain `Result`.
+    pub fn new(sol: &VRPSolution, instance: &VRPInstance) -> Option<Self> {
+        if instance.distance_unit.is_none() && instance.cost_per_unit.is_none() {
+            return None;
+        }
+        let price = |distance: f64| instance.cost_per_unit.map(|rate| to_cents(distance * rate));
+        let routes = sol
+            .routes
+            .iter()
+            .enumerate()
+            .filter(|(_, route)| !route.stops().is_empty())
+            .map(|(i, route)| RouteCost {
+                route: i,
+                distance: route.cost(),
+                cost: price(route.cost()),
+            })
+            .collect();
+        Some(CostReport {
+            distance_unit: instance.distance_unit.clone(),
+            cost_per_unit: instance.cost_per_unit,
+            total_distance: sol.cost(),
+            // priced from the unrounded total, so it can be a cent off the sum of the routes
+            total_cost: price(sol.cost()),
+            routes,
+        })
+    }
+}
+
+/// One non-empty route of a solution, so it can be looked at without re-parsing the `Solution`
+/// string and working its distance out again.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RouteSummary {
+    pub route: usize,
+    /// by their rows in the instance file, as in the `Solution` string
+    pub stops: Vec<u32>,
+    pub num_stops: usize,
+    pub load: usize,
+    /// the load as a percentage of the vehicle capacity
+    pub capacity_used_percent: f64,
+    pub distance: f64,
+}
+
+impl VRPSolution {
+    /// A [`RouteSummary`] for each non-empty route, in route order.
+    pub fn route_summaries(&self) -> Vec<RouteSummary> {
+        self.routes
+            .iter()
+            .enumerate()
+            .filter(|(_, route)| !route.stops().is_empty())
+            .map(|(i, route)| RouteSummary {
+                route: i,
+                stops: route
+                    .stops()
+                    .iter()
+                    .map(|s| route.instance.file_row(s.cust_no().0))
+                    .collect(),
+                num_stops: route.stops().len(),
+                load: route.used_capacity(),
+                capacity_used_percent: 100.0 * route.used_capacity() as f64
+                    / route.instance.vehicle_capacity as f64,
+                distance: route.cost(),
+            })
+            .collect()
+    }
+}
+
+/// Money is reported to the cent, rounding halves away from zero.
+fn to_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// One route out to (3, 4) and back (10), and one up to (0, 2) and back (4).
+    fn solved(distance_unit: Option<&str>, cost_per_unit: Option<f64>) -> Option<CostReport> {
+        let instance = Arc::new(
+            VRPInstance::from_parts_unchecked(
+                2,
+                10,
+                vec![0, 1, 1, 1],
+                vec![0.0, 3.0, 0.0, 0.0],
+                vec![0.0, 4.0, 1.0, 2.0],
+            )
+            .with_cost_units(distance_unit.map(String::from), cost_per_unit)
+            .unwrap(),
+        );
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1], vec![2, 3]]).unwrap();
+        CostReport::new(&sol, &instance)
+    }
+
+    #[test]
+    fn distances_are_priced_per_route_and_in_total() {
+        let report = solved(Some("km"), Some(0.125)).unwrap();
+        assert_eq!(report.distance_unit.as_deref(), Some("km"));
+        assert_eq!(report.total_distance, 14.0);
+        assert_eq!(report.total_cost, Some(1.75));
+        let routes = report
+            .routes
+            .iter()
+            .map(|route| (route.route, route.distance, route.cost))
+            .collect::<Vec<_>>();
+        assert_eq!(routes, [(0, 10.0, Some(1.25)), (1, 4.0, Some(0.5))]);
+    }
+
+    #[test]
+    fn money_is_rounded_to_cents_and_the_total_from_the_whole_distance() {
+        let report = solved(None, Some(0.0004)).unwrap();
+        // 10 and 4 at 0.04 cents each round down to nothing, but the 14 in total is 0.56 cents
+        assert_eq!(report.routes[0].cost, Some(0.0));
+        assert_eq!(report.routes[1].cost, Some(0.0));
+        assert_eq!(report.total_cost, Some(0.01));
+        // and halves round away from zero
+        assert_eq!(
+            solved(None, Some(0.00125)).unwrap().routes[1].cost,
+            Some(0.01)
+        );
+    }
+
+    #[test]
+    fn without_a_rate_there_are_no_costs_and_without_either_no_report() {
+        let report = solved(Some("m"), None).unwrap();
+        assert_eq!(report.total_distance, 14.0);
+        assert_eq!(report.total_cost, None);
+        assert!(report.routes.iter().all(|route| route.cost.is_none()));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["TotalCost"], serde_json::Value::Null);
+        assert_eq!(json["DistanceUnit"], "m");
+
+        assert!(solved(None, None).is_none());
+    }
+
+    #[test]
+    fn rates_have_to_be_non_negative_numbers() {
+        let instance =
+            || VRPInstance::from_parts_unchecked(1, 10, vec![0, 1], vec![0.0, 1.0], vec![0.0; 2]);
+        for bad in [-0.5, f64::NAN, f64::INFINITY] {
+            assert!(
+                instance().with_cost_units(None, Some(bad)).is_err(),
+                "{bad}"
+            );
+        }
+        assert!(instance().with_cost_units(None, Some(0.0)).is_ok());
+    }
+
+    #[test]
+    fn route_summaries_list_each_used_route() {
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            3,
+            8,
+            vec![0, 2, 1, 3],
+            vec![0.0, 3.0, 0.0, 0.0],
+            vec![0.0, 4.0, 1.0, 2.0],
+        ));
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1], vec![3, 2]]).unwrap();
+        let summaries = sol.route_summaries();
+        assert_eq!(
+            summaries
+                .iter()
+                .map(|s| (s.route, s.stops.clone(), s.num_stops, s.load))
+                .collect::<Vec<_>>(),
+            [(0, vec![1], 1, 2), (1, vec![3, 2], 2, 4)]
+        );
+        assert_eq!(summaries[0].capacity_used_percent, 25.0);
+        assert_eq!(summaries[1].capacity_used_percent, 50.0);
+        assert_eq!(summaries[0].distance, 10.0);
+        assert_eq!(summaries[1].distance, 4.0);
+
+        let json = serde_json::to_value(&summaries[1]).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "Route": 1,
+                "Stops": [3, 2],
+                "NumStops": 2,
+                "Load": 4,
+                "CapacityUsedPercent": 50.0,
+                "Distance": 4.0,
+            })
+        );
+    }
+}
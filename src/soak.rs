@@ -0,0 +1,156 @@
+//! A long-running mode for hunting crashes that only show up after a long time searching. The
+//! search runs in rounds of a fixed number of iterations, each starting from the last round's
+//! best with a seed of its own, and each round's starting point is written to disk before it
+//! runs. When a round panics or ends up with an invalid solution, its snapshot is all it takes
+//! to rerun exactly that round with `--soak-replay`.
+//!
+//! Debug builds are best for this: they also check every route after every change.
+
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{VRPSolution, solution_clone_counts},
+    output, random,
+    solver::{self, IterativeSolver, SolveParams, TermCond},
+    vrp_instance::VRPInstance,
+};
+
+/// Iterations per round, i.e. between snapshots.
+const ROUND_ITERS: usize = 20_000;
+
+/// Older snapshots than the last this many are deleted as the soak goes.
+const KEEP_SNAPSHOTS: usize = 5;
+
+/// Everything needed to rerun one round.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Snapshot {
+    pub round: usize,
+    pub seed: u64,
+    pub iters: usize,
+    /// the cost of the solution the round starts from
+    pub cost: f64,
+    /// the round's starting solution, one list of customers per vehicle
+    pub routes: Vec<Vec<u32>>,
+    /// (`clone` calls, `clone_from` calls) on solutions before the round, see
+    /// [`solution_clone_counts`]
+    pub clones: (usize, usize),
+}
+
+impl Snapshot {
+    fn file_name(round: usize) -> String {
+        format!("round-{round:06}.json")
+    }
+
+    /// Read a snapshot back, along with the solution it starts from.
+    pub fn load(path: &Path, instance: &Arc<VRPInstance>) -> Result<(Self, VRPSolution), String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+        let snapshot: Self =
+            serde_json::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        let sol = VRPSolution::from_routes_vec(instance, &snapshot.routes)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        sol.validate(instance)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok((snapshot, sol))
+    }
+}
+
+/// Search for `duration` in rounds, snapshotting each round to `dir` before it runs, and checking
+/// each round's result in full. Returns the best solution, or what went wrong and which snapshot
+/// reproduces it.
+pub fn soak<S: IterativeSolver>(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    duration: Duration,
+    dir: &Path,
+    seed: u64,
+) -> Result<VRPSolution, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("can't create {}: {e}", dir.display()))?;
+    let start = Instant::now();
+
+    random::seed(seed);
+    let mut best = params.starting_solution(instance);
+    let mut round = 0;
+    while start.elapsed() < duration {
+        let snapshot = Snapshot {
+            round,
+            seed: seed.wrapping_add(round as u64 + 1),
+            iters: ROUND_ITERS,
+            cost: best.cost(),
+            routes: best.to_routes_vec(true),
+            clones: solution_clone_counts(),
+        };
+        let path = dir.join(Snapshot::file_name(round));
+        let json = serde_json::to_string(&snapshot).unwrap();
+        output::write_atomic(&path, &json)
+            .map_err(|e| format!("can't write {}: {e}", path.display()))?;
+        if let Some(old) = round.checked_sub(KEEP_SNAPSHOTS) {
+            // it may already be gone if someone's tidying up by hand, which is fine
+            let _ = fs::remove_file(dir.join(Snapshot::file_name(old)));
+        }
+
+        // the assertion failures we're after are panics, so turn them into errors that say
+        // which snapshot to replay
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            run_round::<S>(instance, params, &snapshot, best)
+        }))
+        .unwrap_or_else(|_| Err(format!("round {round} panicked")));
+        let sol =
+            res.map_err(|e| format!("{e} (rerun it with --soak-replay {})", path.display()))?;
+        eprintln!(
+            "[{:.0}s] soak round {round}: {:.2}",
+            start.elapsed().as_secs_f64(),
+            sol.cost()
+        );
+        best = sol;
+        round += 1;
+    }
+    Ok(best)
+}
+
+/// Rerun the round a snapshot (from [`soak`]) was taken before.
+pub fn replay<S: IterativeSolver>(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    path: &Path,
+) -> Result<VRPSolution, String> {
+    let (snapshot, sol) = Snapshot::load(path, instance)?;
+    run_round::<S>(instance, params, &snapshot, sol)
+}
+
+fn run_round<S: IterativeSolver>(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    snapshot: &Snapshot,
+    initial_solution: VRPSolution,
+) -> Result<VRPSolution, String> {
+    let round_params = SolveParams {
+        terminate: TermCond::MaxIters(snapshot.iters),
+        // timing-dependent, so would make rounds impossible to replay
+        calibration: None,
+        ..params.clone()
+    };
+    random::seed(snapshot.seed);
+    let (sol, _) = solver::solve_from_with_stats::<S>(instance, &round_params, initial_solution);
+    sol.validate(instance).map_err(|e| {
+        format!(
+            "round {} ended with an invalid solution: {e}",
+            snapshot.round
+        )
+    })?;
+    Ok(sol)
+}
+
+/// Where snapshots go when no `--soak-dir` is given.
+pub fn default_dir(file_name: &str) -> PathBuf {
+    PathBuf::from(format!("./{file_name}.soak"))
+}
@@ -1,28 +1,273 @@
 use std::{
-    sync::Arc,
+    collections::{HashSet, VecDeque},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     time::{Duration, Instant},
 };
 
 use rand::Rng;
+use serde::{Serialize, ser::SerializeStruct};
 use stats::SolveStats;
 
-use crate::{common::VRPSolution, dbg_println, vrp_instance::VRPInstance};
+use crate::{
+    acceptance::{Acceptance, AcceptanceSnapshot, AcceptanceWindow},
+    calibrate,
+    common::{VRPSolution, solution_clone_counts},
+    dbg_println,
+    objective::Objective,
+    pool::SolutionPool,
+    random::rng,
+    solvers::ALNSConfig,
+    vrp_instance::VRPInstance,
+};
 
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub enum TermCond {
     MaxIters(usize),
     TimeElapsed(Duration),
 }
 
+impl TermCond {
+    /// The same kind of termination condition, but with only `frac` of the budget.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn scaled(&self, frac: f64) -> TermCond {
+        match *self {
+            TermCond::MaxIters(max) => TermCond::MaxIters(((max as f64) * frac) as usize),
+            TermCond::TimeElapsed(d) => TermCond::TimeElapsed(d.mul_f64(frac)),
+        }
+    }
+}
+
+/// What `SolveParams::patience` is measured in.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PatienceUnit {
+    /// non-improving iterations, which means very different amounts of search for solvers
+    /// that move a different number of customers per iteration
+    Iterations,
+    /// customers moved without improvement, i.e. non-improving iterations × the solver's destroy size
+    CustomerMoves,
+    /// milliseconds of wall-clock time since the last improvement
+    Millis,
+}
+
+/// Hands the current best solution to something outside the search (e.g. an exact TSP solver
+/// reordering each route), which may return an improved solution to adopt.
+pub type ExternalImprover =
+    Arc<dyn Fn(&VRPSolution, &VRPInstance) -> Option<VRPSolution> + Send + Sync>;
+
+/// A periodic snapshot of how a solve is going, see [`SolveParams::progress`].
+#[derive(Debug, Clone, Copy)]
+pub struct ProgressEvent {
+    pub iteration: usize,
+    pub elapsed: Duration,
+    pub best_cost: f64,
+    /// averaged over the last few seconds
+    pub iters_per_sec: f64,
+    /// time left at the current rate, when stopping after a number of iterations
+    pub eta: Option<Duration>,
+    /// iterations left at the current rate, when stopping after some amount of time
+    pub remaining_iters: Option<usize>,
+    /// the acceptance criterion's state, e.g. its temperature
+    pub acceptance: AcceptanceSnapshot,
+    /// how many of the latest non-improving candidates were kept (`None` before the first)
+    pub acceptance_ratio: Option<f64>,
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// A new best solution, see [`SolveParams::on_improvement`].
+#[derive(Debug, Clone, Copy)]
+pub struct ImprovementEvent {
+    pub iteration: usize,
+    pub elapsed: Duration,
+    /// the new best solution's total distance
+    pub cost: f64,
+}
+
+pub type ImprovementCallback = Arc<dyn Fn(&ImprovementEvent) + Send + Sync>;
+
+/// Shared flag for stopping a solve early from another thread. The search notices within about
+/// [`RATE_SAMPLE_EVERY`] iterations and returns the best solution so far.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    #[allow(dead_code)]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Where the search is up to, handed to the solver before every iteration so it can adapt how
+/// much effort it puts in (see [`LNSSolver::update_context`]).
+#[derive(Debug, Clone, Copy)]
+pub struct SearchContext {
+    pub elapsed: Duration,
+    /// what's left of the time budget, or the time the remaining iterations should take at the
+    /// current rate (`None` until there's a rate)
+    pub remaining: Option<Duration>,
+    pub iteration: usize,
+    #[allow(dead_code)]
+    pub stagnant_iterations: usize,
+    /// the iteration budget, if the search stops after a number of iterations
+    pub max_iterations: Option<usize>,
+}
+
+impl SearchContext {
+    /// How much of the budget is left, from 1 down to 0. Counted in iterations when there's an
+    /// iteration budget, so runs stay reproducible, and in time otherwise.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn fraction_left(&self) -> Option<f64> {
+        match (self.max_iterations, self.remaining) {
+            (Some(max), _) if max > 0 => {
+                Some(max.saturating_sub(self.iteration) as f64 / max as f64)
+            }
+            (Some(_), _) => Some(0.0),
+            (None, Some(remaining)) => {
+                let total = (self.elapsed + remaining).as_secs_f64();
+                Some(if total > 0.0 {
+                    remaining.as_secs_f64() / total
+                } else {
+                    0.0
+                })
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+/// How often progress is reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Iterations between samples of the clock for the rolling rate.
+const RATE_SAMPLE_EVERY: usize = 1024;
+
+/// Rolling iterations-per-second over the last few (iteration, time) samples.
+struct RateWindow {
+    samples: VecDeque<(usize, Instant)>,
+    len: usize,
+}
+
+impl RateWindow {
+    fn new(len: usize) -> Self {
+        RateWindow {
+            samples: VecDeque::with_capacity(len),
+            len,
+        }
+    }
+
+    fn push(&mut self, iter: usize, at: Instant) {
+        if self.samples.len() == self.len {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((iter, at));
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn iters_per_sec(&self) -> f64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((first_iter, first_at)), Some((last_iter, last_at))) if last_at > first_at => {
+                (last_iter - first_iter) as f64 / (*last_at - *first_at).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct SolveParams {
     pub terminate: TermCond,
     pub frac_dropped: f64,
-    /// jump after this many stagnant iterations
+    /// jump after this much stagnation, measured in `patience_unit`s
     pub patience: usize,
+    pub patience_unit: PatienceUnit,
     // should be set of constructors to use one after the other...
     pub constructor: fn(&Arc<VRPInstance>) -> VRPSolution,
     // could also be a set of jumpers to use randomly between them
-    pub jumper: fn(&Arc<VRPInstance>, VRPSolution, f64) -> VRPSolution,
+    pub jumper: fn(&Arc<VRPInstance>, VRPSolution, f64) -> Result<VRPSolution, String>,
+    /// called on new global bests, at most once per `improver_interval`
+    pub external_improver: Option<ExternalImprover>,
+    pub improver_interval: Duration,
+    /// how many of the best distinct solutions found along the way to keep (see [`EliteSet`])
+    pub elite_count: usize,
+    /// solutions whose costs are closer than this are considered the same
+    pub elite_min_gap: f64,
+    /// called about once every `PROGRESS_INTERVAL` with how the search is going
+    pub progress: Option<ProgressCallback>,
+    /// called every time the search finds a new best solution
+    pub on_improvement: Option<ImprovementCallback>,
+    pub objective: Objective,
+    /// restart when the last `diversity_window` accepted solutions (0 to never check) include
+    /// fewer than `diversity_min_distinct` different ones, i.e. the search is going in circles
+    pub diversity_window: usize,
+    pub diversity_min_distinct: usize,
+    /// for how many iterations repair avoids recreating the edges a destroy just broke (0 for never)
+    pub edge_tabu_tenure: usize,
+    /// how many customers each destroy removes, or `None` for the solver's own default
+    pub destroy_size: Option<usize>,
+    /// spend this long up front trying a few destroy sizes and keep the one that improves fastest
+    /// (see [`crate::calibrate`]), taken out of the time budget
+    pub calibration: Option<Duration>,
+    /// stop early (with the best solution so far) once this is cancelled
+    pub cancel: Option<CancelToken>,
+    /// only read by the ALNS solver
+    pub alns: ALNSConfig,
+    /// stop as soon as the best solution's total distance is at most this
+    pub target_cost: Option<f64>,
+    /// whether to carry on from candidates that are no better than where they came from
+    pub acceptance: Acceptance,
+    /// start from this (e.g. a previous run's result) instead of calling `constructor`
+    pub initial_solution: Option<VRPSolution>,
+}
+
+impl SolveParams {
+    /// What a search of `instance` starts from: `initial_solution` if there is one, and otherwise
+    /// whatever `constructor` builds.
+    pub fn starting_solution(&self, instance: &Arc<VRPInstance>) -> VRPSolution {
+        match &self.initial_solution {
+            Some(sol) => sol.clone(),
+            None => (self.constructor)(instance),
+        }
+    }
+}
+
+// the constructor/jumper are plain fn pointers, so only the tunable knobs get serialized
+impl Serialize for SolveParams {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("SolveParams", 16)?;
+        s.serialize_field("terminate", &self.terminate)?;
+        s.serialize_field("frac_dropped", &self.frac_dropped)?;
+        s.serialize_field("patience", &self.patience)?;
+        s.serialize_field("patience_unit", &self.patience_unit)?;
+        s.serialize_field("elite_count", &self.elite_count)?;
+        s.serialize_field("elite_min_gap", &self.elite_min_gap)?;
+        s.serialize_field("objective", &self.objective)?;
+        s.serialize_field("diversity_window", &self.diversity_window)?;
+        s.serialize_field("diversity_min_distinct", &self.diversity_min_distinct)?;
+        s.serialize_field("edge_tabu_tenure", &self.edge_tabu_tenure)?;
+        s.serialize_field("destroy_size", &self.destroy_size)?;
+        s.serialize_field("calibration", &self.calibration)?;
+        s.serialize_field("alns", &self.alns)?;
+        s.serialize_field("target_cost", &self.target_cost)?;
+        s.serialize_field("acceptance", &self.acceptance)?;
+        s.serialize_field(
+            "initial_solution_cost",
+            &self.initial_solution.as_ref().map(VRPSolution::cost),
+        )?;
+        s.end()
+    }
 }
 
 // trait for a large neighborhood search (LNS) solver
@@ -39,12 +284,25 @@ pub trait LNSSolver {
     /// Repair the solution and return the result.
     fn repair(&mut self, res: Self::DestroyResult) -> Result<(), String>;
 
+    /// How many customers a single destroy removes.
+    fn destroy_size(&self) -> usize {
+        1
+    }
+
     fn get_stats_mut(&mut self) -> &mut SolveStats;
 
     fn jump_to_solution(&mut self, sol: &VRPSolution);
 
     // Optionally update the tabu for the solver.
     fn update_tabu(&mut self, _res: &Self::DestroyResult) {}
+
+    /// Pick up the settings from `params` that the solver itself cares about (e.g. the objective,
+    /// for solvers whose repair can bias towards it).
+    fn configure(&mut self, _params: &SolveParams) {}
+
+    /// Called before every iteration with where the search is up to, e.g. to do less work per
+    /// iteration near the end of the budget.
+    fn update_context(&mut self, _ctx: &SearchContext) {}
 }
 
 pub trait IterativeSolver {
@@ -59,32 +317,98 @@ pub trait IterativeSolver {
     fn get_stats_mut(&mut self) -> &mut SolveStats;
 
     fn cost(&self) -> f64;
+
+    /// How many customers get moved around by each call to `find_new_solution`.
+    fn moves_per_iteration(&self) -> usize {
+        1
+    }
+
+    fn configure(&mut self, _params: &SolveParams) {}
+
+    fn update_context(&mut self, _ctx: &SearchContext) {}
 }
 
 pub mod stats {
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::Serialize;
 
+    use crate::calibrate::CalibrationRow;
     use crate::common::VRPSolution;
+    use crate::ids::{CustomerId, RouteIdx};
+    use crate::report::RunMeta;
+
+    /// How often a destroy or repair operator was picked, and how often it couldn't do its job.
+    #[derive(Debug, Default, Clone, Copy, Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct OperatorUsage {
+        pub used: usize,
+        pub failed: usize,
+    }
+
+    /// How many routes best insertion considered for a customer, and how many of those it didn't
+    /// need to scan because their [`crate::common::Route::insertion_lower_bound`] couldn't beat the
+    /// best spot already found.
+    #[derive(Debug, Default, Clone, Copy, Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct InsertionScans {
+        pub considered: usize,
+        pub pruned: usize,
+    }
+
+    impl InsertionScans {
+        #[allow(clippy::cast_precision_loss)]
+        pub fn skip_rate(&self) -> f64 {
+            if self.considered == 0 {
+                return 0.0;
+            }
+            self.pruned as f64 / self.considered as f64
+        }
+    }
 
     #[derive(Debug)]
     pub struct SolveStats {
+        /// which run produced these stats, so they can be joined with the rest of its output
+        pub meta: Option<RunMeta>,
+        /// iterations that produced a solution
         pub iterations: usize,
+        /// every iteration run, including those whose repair failed
+        pub total_iterations: usize,
         pub improvements: Vec<(usize, f64)>,
+        /// (iteration, temperature) at each improvement, for acceptance criteria with a temperature
+        pub improvement_temperatures: Vec<(usize, f64)>,
         pub restarts: Vec<usize>,
-        pub cust_change_freq: HashMap<usize, usize>,
-        pub route_remove_freq: HashMap<usize, usize>,
-        pub route_add_freq: HashMap<usize, usize>,
+        /// restarts because the search kept revisiting the same few solutions, not counted in `restarts`
+        pub diversity_restarts: Vec<usize>,
+        pub cust_change_freq: HashMap<CustomerId, usize>,
+        pub route_remove_freq: HashMap<RouteIdx, usize>,
+        pub route_add_freq: HashMap<RouteIdx, usize>,
+        /// over the whole solve
+        pub avg_iters_per_sec: f64,
+        /// how each destroy size did in the warm-up, if there was one
+        pub calibration: Vec<CalibrationRow>,
+        /// per operator, for solvers that pick between several
+        pub operators: BTreeMap<&'static str, OperatorUsage>,
+        pub insertion_scans: InsertionScans,
     }
 
     impl SolveStats {
         pub fn new() -> Self {
             SolveStats {
+                meta: None,
                 iterations: 0,
+                total_iterations: 0,
                 improvements: Vec::new(),
+                improvement_temperatures: Vec::new(),
                 restarts: Vec::new(),
+                diversity_restarts: Vec::new(),
                 cust_change_freq: HashMap::new(),
                 route_add_freq: HashMap::new(),
                 route_remove_freq: HashMap::new(),
+                avg_iters_per_sec: 0.0,
+                calibration: Vec::new(),
+                operators: BTreeMap::new(),
+                insertion_scans: InsertionScans::default(),
             }
         }
 
@@ -93,52 +417,262 @@ pub mod stats {
             iter: usize,
             new_sol: &VRPSolution,
             improvement_on_best: f64,
+            temperature: Option<f64>,
         ) {
             if improvement_on_best > 0.01 {
                 self.improvements.push((iter, new_sol.cost()));
+                if let Some(temperature) = temperature {
+                    self.improvement_temperatures.push((iter, temperature));
+                }
             }
             self.iterations += 1;
         }
 
+        /// Count a use of `operator`, when it's picked, so failed attempts are counted too.
+        pub fn on_operator_used(&mut self, operator: &'static str, failed: bool) {
+            let usage = self.operators.entry(operator).or_default();
+            usage.used += 1;
+            usage.failed += usize::from(failed);
+        }
+
         pub fn on_restart(&mut self, iter: usize) {
             self.restarts.push(iter);
         }
+
+        pub fn on_diversity_restart(&mut self, iter: usize) {
+            self.diversity_restarts.push(iter);
+        }
+
+        #[allow(dead_code)]
+        pub fn attach_meta(&mut self, meta: RunMeta) {
+            self.meta = Some(meta);
+        }
+    }
+
+    impl Default for SolveStats {
+        fn default() -> Self {
+            Self::new()
+        }
     }
 }
 
-#[allow(dead_code)]
-type SolveResult = (VRPSolution, SolveStats);
+pub type SolveResult = (VRPSolution, SolveStats);
+
+/// The best few distinct solutions seen during a solve, kept sorted by their objective score (best first).
+pub struct EliteSet {
+    capacity: usize,
+    min_gap: f64,
+    objective: Objective,
+    solutions: Vec<VRPSolution>,
+}
+
+impl EliteSet {
+    pub fn new(capacity: usize, min_gap: f64, objective: Objective) -> Self {
+        EliteSet {
+            capacity,
+            min_gap,
+            objective,
+            solutions: Vec::with_capacity(capacity + 1),
+        }
+    }
+
+    /// Keep a copy of `sol` if it's distinct from what we have and good enough to make the cut.
+    pub fn offer(&mut self, sol: &VRPSolution) {
+        let score = |s: &VRPSolution| self.objective.evaluate(s);
+        let cost = score(sol);
+        if self.capacity == 0
+            || self
+                .solutions
+                .iter()
+                .any(|s| (score(s) - cost).abs() < self.min_gap)
+            || (self.solutions.len() == self.capacity
+                && cost >= score(self.solutions.last().unwrap()))
+        {
+            return;
+        }
 
-/// Completely solve a VRP instance and return the best solution found.
+        let index = self.solutions.partition_point(|s| score(s) < cost);
+        self.solutions.insert(index, sol.clone());
+        self.solutions.truncate(self.capacity);
+    }
+
+    pub fn into_vec(self) -> Vec<VRPSolution> {
+        self.solutions
+    }
+}
+
+/// Completely solve a VRP instance and return the best solution found. The instance should pass
+/// [`VRPInstance::validate`] first, or constructing the first solution can panic.
 pub fn solve<S: IterativeSolver>(instance: &Arc<VRPInstance>, params: &SolveParams) -> VRPSolution {
-    let initial_solution = (params.constructor)(instance);
+    let initial_solution = params.starting_solution(instance);
+    solve_from::<S>(instance, params, initial_solution)
+}
+
+/// Like [`solve`], but also return up to `params.elite_count` of the best distinct solutions found
+/// (the final best included), cheapest first.
+pub fn solve_with_elites<S: IterativeSolver>(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+) -> (VRPSolution, Vec<VRPSolution>) {
+    let initial_solution = params.starting_solution(instance);
+    let (best, elites, _) = solve_from_with_elites::<S>(instance, params, initial_solution);
+    (best, elites)
+}
+
+/// Like [`solve`], but start searching from `initial_solution` instead of constructing one.
+pub fn solve_from<S: IterativeSolver>(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    initial_solution: VRPSolution,
+) -> VRPSolution {
+    solve_from_with_elites::<S>(instance, params, initial_solution).0
+}
+
+/// Like [`solve_from`], but also hand back the solver's stats for the run.
+pub fn solve_from_with_stats<S: IterativeSolver>(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    initial_solution: VRPSolution,
+) -> SolveResult {
+    let (best, _, stats) = solve_from_with_elites::<S>(instance, params, initial_solution);
+    (best, stats)
+}
+
+fn solve_from_with_elites<S: IterativeSolver>(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    initial_solution: VRPSolution,
+) -> (VRPSolution, Vec<VRPSolution>, SolveStats) {
     let start_time = std::time::Instant::now();
-    let mut solver = S::new(instance.clone(), initial_solution.clone());
+
+    // every copy of a solution in the loop comes from (and goes back to) the pool, so
+    // `clone_from` can reuse their allocations
+    let mut pool = SolutionPool::new(instance.clone());
+    let mut initial_solution = initial_solution;
+    initial_solution.compact();
+    let calibration = calibrate::calibrate::<S>(instance, params, &initial_solution);
+    let calibrated_params;
+    let params = match calibrate::best_destroy_size(&calibration) {
+        Some(destroy_size) => {
+            dbg_println!("calibrated destroy size: {destroy_size}");
+            calibrated_params = SolveParams {
+                terminate: match params.terminate {
+                    TermCond::TimeElapsed(d) => {
+                        TermCond::TimeElapsed(d.saturating_sub(start_time.elapsed()))
+                    }
+                    other => other,
+                },
+                destroy_size: Some(destroy_size),
+                ..params.clone()
+            };
+            &calibrated_params
+        }
+        None => params,
+    };
+
+    let mut solver = S::new(instance.clone(), pool.take_copy(&initial_solution));
+    solver.configure(params);
+    solver.get_stats_mut().calibration = calibration;
+    let score = |sol: &VRPSolution| params.objective.evaluate(sol);
 
     let mut best = initial_solution;
-    let mut best_for_jump = best.clone();
-    let mut best_cost = best.cost();
-    let mut best_cost_for_jump = best.cost();
+    let mut best_for_jump = pool.take_copy(&best);
+    let mut best_cost = score(&best);
+    let mut best_cost_for_jump = score(&best);
     let mut stagnant_iterations = 0;
+    let mut last_improvement = Instant::now();
     let mut _iterations_since_prev_new_best = 0;
-    let mut last_cost = best.cost();
-    let mut rng = rand::rng();
+    let mut last_cost = score(&best);
+    let mut rng = rng();
+    let mut acceptance = params.acceptance.criterion();
+    let mut acceptance_window = AcceptanceWindow::new();
+    let mut last_improver_call: Option<Instant> = None;
+    // the best of each restart epoch is a good, and usually quite different, solution
+    let mut elites = EliteSet::new(params.elite_count, params.elite_min_gap, params.objective);
 
-    let mut iters: Box<dyn Iterator<Item = usize>> = match params.terminate {
+    let iters: Box<dyn Iterator<Item = usize>> = match params.terminate {
         TermCond::MaxIters(max) => Box::new(0..max),
         TermCond::TimeElapsed(_) => Box::new(0..),
     };
 
     let start = Instant::now();
-    let mut new_solution = best.clone();
-    let mut old_solution = best.clone();
-    for iter in &mut iters {
+    let mut new_solution = pool.take_copy(&best);
+    let mut old_solution = pool.take_copy(&best);
+    let mut rate = RateWindow::new(16);
+    let mut recent_solutions = VecDeque::with_capacity(params.diversity_window);
+    let mut last_progress = start;
+    let max_iterations = match params.terminate {
+        TermCond::MaxIters(max) => Some(max),
+        TermCond::TimeElapsed(_) => None,
+    };
+    let mut remaining_at_rate = None;
+    let reaches_target = |sol: &VRPSolution| params.target_cost.is_some_and(|t| sol.cost() <= t);
+    let mut reached_target = reaches_target(&best);
+    // how many iterations were started, which is fewer than the budget after stopping early
+    let mut total_iters = 0;
+    for iter in iters {
+        if reached_target {
+            dbg_println!("reached the target cost by iter {iter}");
+            break;
+        }
+        let elapsed = start.elapsed();
         if let TermCond::TimeElapsed(max_time) = params.terminate {
-            if start.elapsed() > max_time {
+            if elapsed > max_time {
                 break;
             }
         }
 
+        if iter % RATE_SAMPLE_EVERY == 0 {
+            if params
+                .cancel
+                .as_ref()
+                .is_some_and(CancelToken::is_cancelled)
+            {
+                dbg_println!("cancelled at iter {iter}");
+                break;
+            }
+            let now = Instant::now();
+            rate.push(iter, now);
+            if let Some(max) = max_iterations {
+                let iters_per_sec = rate.iters_per_sec();
+                remaining_at_rate = (iters_per_sec > 0.0)
+                    .then(|| Duration::from_secs_f64((max - iter) as f64 / iters_per_sec));
+            }
+            if now - last_progress >= PROGRESS_INTERVAL {
+                last_progress = now;
+                let mut event =
+                    progress_event(params, iter, start, best_cost, rate.iters_per_sec());
+                event.acceptance = acceptance.snapshot();
+                event.acceptance_ratio = acceptance_window.ratio();
+                dbg_println!(
+                    "{:?}: iter {} ({:.0} it/s, eta {:?}, ~{:?} iters left): best {:.2}, temperature {:?}, accepting {:?}",
+                    event.elapsed,
+                    event.iteration,
+                    event.iters_per_sec,
+                    event.eta,
+                    event.remaining_iters,
+                    event.best_cost,
+                    event.acceptance.temperature,
+                    event.acceptance_ratio
+                );
+                if let Some(progress) = &params.progress {
+                    progress(&event);
+                }
+            }
+        }
+
+        total_iters = iter + 1;
+        solver.update_context(&SearchContext {
+            elapsed,
+            remaining: match params.terminate {
+                TermCond::TimeElapsed(max_time) => Some(max_time.saturating_sub(elapsed)),
+                TermCond::MaxIters(_) => remaining_at_rate,
+            },
+            iteration: iter,
+            stagnant_iterations,
+            max_iterations,
+        });
+
         // save old solution first
         old_solution.clone_from(solver.current());
 
@@ -152,10 +686,13 @@ pub fn solve<S: IterativeSolver>(instance: &Arc<VRPInstance>, params: &SolvePara
         }
         new_solution.clone_from(solver.current());
 
-        let new_cost = new_solution.cost();
-        solver
-            .get_stats_mut()
-            .update_on_iter(iter, &new_solution, best_cost - new_cost);
+        let mut new_cost = score(&new_solution);
+        solver.get_stats_mut().update_on_iter(
+            iter,
+            &new_solution,
+            best_cost - new_cost,
+            acceptance.snapshot().temperature,
+        );
 
         if new_cost + 0.1 < best_cost_for_jump {
             best_for_jump.clone_from(&new_solution);
@@ -164,60 +701,213 @@ pub fn solve<S: IterativeSolver>(instance: &Arc<VRPInstance>, params: &SolvePara
         if new_cost + 0.1 < best_cost {
             best.clone_from(&new_solution);
             best_cost = new_cost;
+            reached_target = reaches_target(&best);
             _iterations_since_prev_new_best = 0;
             dbg_println!("new_best: {}", best_cost);
+
+            if let Some(improver) = &params.external_improver {
+                if last_improver_call.is_none_or(|t| t.elapsed() >= params.improver_interval) {
+                    last_improver_call = Some(Instant::now());
+
+                    // don't let a buggy improver corrupt the run, only take valid solutions that are strictly better
+                    if let Some(improved) = improver(&best, instance)
+                        .filter(|sol| sol.validate(instance).is_ok() && score(sol) < best_cost)
+                    {
+                        dbg_println!("adopting externally improved solution: {}", improved.cost());
+                        let mut improved = improved;
+                        improved.compact();
+                        best.clone_from(&improved);
+                        best_cost = score(&best);
+                        reached_target = reaches_target(&best);
+                        best_for_jump.clone_from(&improved);
+                        best_cost_for_jump = best_cost;
+                        new_cost = best_cost;
+                        solver.jump_to_solution(&improved);
+                        pool.give_back(improved);
+                    }
+                }
+            }
+
+            if let Some(on_improvement) = &params.on_improvement {
+                on_improvement(&ImprovementEvent {
+                    iteration: iter,
+                    elapsed: start.elapsed(),
+                    cost: best.cost(),
+                });
+            }
         } else {
             _iterations_since_prev_new_best += 1;
         }
 
-        if new_cost + 0.1 < last_cost {
+        // with the edge tabu on, repair rarely lands back on the solution it started from, so
+        // measuring against the previous (possibly reverted) attempt would count most sideways or
+        // worse moves as improvements and never run out of patience
+        let baseline = if params.edge_tabu_tenure > 0 {
+            score(&old_solution)
+        } else {
+            last_cost
+        };
+        if new_cost + 0.1 < baseline {
             // improvement
             stagnant_iterations = 0;
+            last_improvement = Instant::now();
         } else {
             // no improvement
             stagnant_iterations += 1;
 
-            // simulated annealing — with 0.1 probability, do not revert to the old solution (i.e. accept the new, worse solution)
-            if rng.random_bool(0.9) {
+            // carry on from the new, worse solution if the acceptance criterion says so
+            let accepted = acceptance.accept((new_cost - baseline).max(0.0), &mut rng);
+            acceptance_window.record(accepted);
+            if !accepted {
                 solver.jump_to_solution(&old_solution);
             }
         }
+        acceptance.tick();
         if iter % 10000 == 0 {
             dbg_println!("iter {:?} has cost {:?}", iter, solver.cost());
         }
 
         last_cost = new_cost;
 
-        #[allow(clippy::cast_precision_loss)]
-        if f64::from(stagnant_iterations) > (params.patience as f64) {
-            dbg_println!("Restarting with patience {}...", params.patience);
+        let out_of_patience = match params.patience_unit {
+            PatienceUnit::Iterations => stagnant_iterations > params.patience,
+            PatienceUnit::CustomerMoves => {
+                stagnant_iterations * solver.moves_per_iteration() > params.patience
+            }
+            PatienceUnit::Millis => {
+                last_improvement.elapsed().as_millis() > params.patience as u128
+            }
+        };
+        let going_in_circles = params.diversity_window > 0 && {
+            if recent_solutions.len() == params.diversity_window {
+                recent_solutions.pop_front();
+            }
+            recent_solutions.push_back(solver.current().canonical_hash());
+            recent_solutions.len() == params.diversity_window
+                && recent_solutions.iter().collect::<HashSet<_>>().len()
+                    < params.diversity_min_distinct
+        };
+        if out_of_patience || going_in_circles {
+            dbg_println!(
+                "Restarting with patience {} (going in circles: {going_in_circles})...",
+                params.patience
+            );
+            recent_solutions.clear();
             stagnant_iterations = 0;
+            last_improvement = Instant::now();
+            elites.offer(&best_for_jump);
 
-            let new_sol = if rng.random_bool(0.2) {
+            let jumped = if rng.random_bool(0.2) {
                 dbg_println!("Jumping from current jump best...");
-                (params.jumper)(instance, best_for_jump.clone(), params.frac_dropped)
+                (params.jumper)(
+                    instance,
+                    pool.take_copy(&best_for_jump),
+                    params.frac_dropped,
+                )
             } else {
                 dbg_println!("Jumping from globally found best...");
-                (params.jumper)(instance, best.clone(), params.frac_dropped)
+                (params.jumper)(instance, pool.take_copy(&best), params.frac_dropped)
+            };
+            // a failed jump just means carrying on from where we are until patience runs out again
+            let new_sol = match jumped {
+                Ok(sol) => sol,
+                Err(e) => {
+                    dbg_println!("Jump failed, skipping restart: {e}");
+                    continue;
+                }
             };
 
-            solver.get_stats_mut().on_restart(iter);
-            best_cost_for_jump = new_sol.cost();
+            if out_of_patience {
+                solver.get_stats_mut().on_restart(iter);
+            } else {
+                solver.get_stats_mut().on_diversity_restart(iter);
+            }
+            best_cost_for_jump = score(&new_sol);
             best_for_jump.clone_from(&new_sol);
             solver.jump_to_solution(&new_sol);
+            pool.give_back(new_sol);
         }
     }
 
-    let total_iters = match params.terminate {
-        TermCond::MaxIters(max) => max,
-        TermCond::TimeElapsed(_) => iters.next().unwrap() - 1,
-    };
+    solver.get_stats_mut().total_iterations = total_iters;
+    #[allow(clippy::cast_precision_loss)]
+    {
+        solver.get_stats_mut().avg_iters_per_sec =
+            total_iters as f64 / start.elapsed().as_secs_f64();
+    }
 
-    println!("ran for {:#?}", start_time.elapsed());
-    println!("got through {total_iters:?} iters");
+    dbg_println!("ran for {:#?}", start_time.elapsed());
+    dbg_println!("got through {total_iters:?} iters");
 
     dbg_println!("Stats: {:?}", solver.get_stats_mut());
-    best
+    dbg_println!("(clones, clone_froms): {:?}", solution_clone_counts());
+    elites.offer(&best);
+    let stats = std::mem::take(solver.get_stats_mut());
+    (best, elites.into_vec(), stats)
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn progress_event(
+    params: &SolveParams,
+    iteration: usize,
+    start: Instant,
+    best_cost: f64,
+    iters_per_sec: f64,
+) -> ProgressEvent {
+    let elapsed = start.elapsed();
+    let (eta, remaining_iters) = match params.terminate {
+        TermCond::MaxIters(max) => (
+            (iters_per_sec > 0.0)
+                .then(|| Duration::from_secs_f64((max - iteration) as f64 / iters_per_sec)),
+            None,
+        ),
+        TermCond::TimeElapsed(max_time) => (
+            None,
+            Some((max_time.saturating_sub(elapsed).as_secs_f64() * iters_per_sec) as usize),
+        ),
+    };
+
+    ProgressEvent {
+        iteration,
+        elapsed,
+        best_cost,
+        iters_per_sec,
+        eta,
+        remaining_iters,
+        acceptance: AcceptanceSnapshot::default(),
+        acceptance_ratio: None,
+    }
+}
+
+/// Roughly how many solve loop iterations per second `S` manages on `instance`, measured by
+/// searching from a freshly constructed solution for `duration`.
+pub fn calibrate_iters_per_sec<S: IterativeSolver>(
+    instance: &Arc<VRPInstance>,
+    params: &SolveParams,
+    duration: Duration,
+) -> f64 {
+    let initial_solution = (params.constructor)(instance);
+    let mut old_solution = initial_solution.clone();
+    let mut solver = S::new(instance.clone(), initial_solution);
+
+    let start = Instant::now();
+    let mut iters = 0;
+    while start.elapsed() < duration {
+        old_solution.clone_from(solver.current());
+        if solver.find_new_solution().is_none() {
+            solver.jump_to_solution(&old_solution);
+        }
+        iters += 1;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    {
+        iters as f64 / start.elapsed().as_secs_f64()
+    }
 }
 
 impl<T> IterativeSolver for T
@@ -258,4 +948,755 @@ where
     fn cost(&self) -> f64 {
         self.current().cost()
     }
+
+    fn moves_per_iteration(&self) -> usize {
+        self.destroy_size()
+    }
+
+    fn configure(&mut self, params: &SolveParams) {
+        LNSSolver::configure(self, params);
+    }
+
+    fn update_context(&mut self, ctx: &SearchContext) {
+        LNSSolver::update_context(self, ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+        sync::Mutex,
+    };
+
+    use super::*;
+    use crate::{
+        generate, random,
+        solvers::{ALNSSolver, MultiLNSSolver},
+    };
+
+    #[test]
+    fn the_result_is_never_worse_than_a_warm_start() {
+        let instance = Arc::new(generate::random_instance(60, 100, 2032));
+        let params = |iters, initial_solution| SolveParams {
+            terminate: TermCond::MaxIters(iters),
+            initial_solution,
+            ..crate::default_params(Duration::ZERO)
+        };
+        random::seed(2032);
+        let good = solve::<ALNSSolver>(&instance, &params(5_000, None));
+        let warm = params(300, Some(good.clone()));
+        assert_eq!(
+            warm.starting_solution(&instance).to_routes_vec(true),
+            good.to_routes_vec(true)
+        );
+
+        for seed in 0..5 {
+            random::seed(seed);
+            let alns = solve::<ALNSSolver>(&instance, &warm);
+            random::seed(seed);
+            let multi = solve::<MultiLNSSolver>(&instance, &warm);
+            for sol in [alns, multi] {
+                sol.validate(&instance).unwrap();
+                assert!(
+                    sol.cost() <= good.cost(),
+                    "seed {seed}: {} > {}",
+                    sol.cost(),
+                    good.cost()
+                );
+            }
+        }
+    }
+
+    /// Solve a small instance for up to `iters` iterations, stopping early once the best solution
+    /// is `improvement` cheaper than the first, if given. Also returns the first solution's cost.
+    fn solve_for(iters: usize, improvement: Option<f64>) -> (SolveResult, f64) {
+        solve_with(iters, improvement, None)
+    }
+
+    fn solve_with(
+        iters: usize,
+        improvement: Option<f64>,
+        cancel: Option<CancelToken>,
+    ) -> (SolveResult, f64) {
+        let instance = Arc::new(generate::random_instance(30, 100, 1998));
+        random::seed(1998);
+        let mut params = SolveParams {
+            terminate: TermCond::MaxIters(iters),
+            cancel,
+            ..crate::default_params(Duration::ZERO)
+        };
+        let initial_solution = params.starting_solution(&instance);
+        let initial_cost = initial_solution.cost();
+        params.target_cost = improvement.map(|improvement| initial_cost - improvement);
+        let result = solve_from_with_stats::<MultiLNSSolver>(&instance, &params, initial_solution);
+        (result, initial_cost)
+    }
+
+    #[test]
+    fn a_full_budget_counts_every_iteration() {
+        let ((_, stats), _) = solve_for(300, None);
+        assert_eq!(stats.total_iterations, 300);
+        assert!(stats.iterations <= stats.total_iterations);
+    }
+
+    #[test]
+    fn stopping_at_the_target_counts_only_the_iterations_run() {
+        let ((best, stats), initial_cost) = solve_for(1_000_000, Some(1.0));
+        assert!(best.cost() <= initial_cost - 1.0);
+        assert!(stats.total_iterations < 1_000_000);
+        assert!(stats.iterations <= stats.total_iterations);
+    }
+
+    #[test]
+    fn a_cancelled_solve_counts_only_the_iterations_run() {
+        let cancel = CancelToken::default();
+        cancel.cancel();
+        let ((best, stats), initial_cost) = solve_with(1_000_000, None, Some(cancel));
+        // cancellation is checked before the first iteration too
+        assert_eq!(stats.total_iterations, 0);
+        assert_eq!(best.cost(), initial_cost);
+    }
+
+    /// Flips between two fixed solutions, the second a little cheaper, forever.
+    struct Oscillating {
+        pair: [VRPSolution; 2],
+        current: VRPSolution,
+        stats: SolveStats,
+    }
+
+    impl IterativeSolver for Oscillating {
+        fn new(instance: Arc<VRPInstance>, initial_solution: VRPSolution) -> Self {
+            let cheaper = VRPSolution::from_routes_vec(&instance, &[vec![1], vec![2, 3]]).unwrap();
+            Oscillating {
+                pair: [initial_solution.clone(), cheaper],
+                current: initial_solution,
+                stats: SolveStats::new(),
+            }
+        }
+
+        fn current(&self) -> &VRPSolution {
+            &self.current
+        }
+
+        fn find_new_solution(&mut self) -> Option<()> {
+            let [a, b] = &self.pair;
+            let next = if self.current.canonical_hash() == a.canonical_hash() {
+                b
+            } else {
+                a
+            };
+            self.current.clone_from(next);
+            Some(())
+        }
+
+        fn jump_to_solution(&mut self, sol: &VRPSolution) {
+            self.current.clone_from(sol);
+        }
+
+        fn get_stats_mut(&mut self) -> &mut SolveStats {
+            &mut self.stats
+        }
+
+        fn cost(&self) -> f64 {
+            self.current.cost()
+        }
+    }
+
+    #[test]
+    fn going_round_in_circles_restarts_when_cost_stagnation_never_would() {
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            3,
+            10,
+            vec![0, 3, 4, 5],
+            vec![0.0, 0.0, 4.0, 0.0],
+            vec![0.0, 3.0, 0.0, -4.0],
+        ));
+        // 20, against 19.66 for the pair's other solution, so every other step is an improvement
+        let start = VRPSolution::from_routes_vec(&instance, &[vec![1, 2], vec![3]]).unwrap();
+
+        for diversity_window in [0, 10] {
+            random::seed(1978);
+            let params = SolveParams {
+                terminate: TermCond::MaxIters(200),
+                patience: 20,
+                patience_unit: PatienceUnit::Iterations,
+                acceptance: Acceptance::Random(1.0),
+                diversity_window,
+                diversity_min_distinct: 3,
+                ..crate::default_params(Duration::ZERO)
+            };
+            let (_, stats) =
+                solve_from_with_stats::<Oscillating>(&instance, &params, start.clone());
+            assert!(stats.restarts.is_empty(), "window {diversity_window}");
+            if diversity_window == 0 {
+                assert!(stats.diversity_restarts.is_empty());
+            } else {
+                // each restart starts the window over, so once every window's worth of iterations
+                assert_eq!(stats.diversity_restarts.len(), 20);
+            }
+        }
+    }
+
+    std::thread_local! {
+        static CONTEXTS: std::cell::RefCell<Vec<SearchContext>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    /// Never changes its solution, only records the context it's given each iteration.
+    struct Recording {
+        current: VRPSolution,
+        stats: SolveStats,
+    }
+
+    impl IterativeSolver for Recording {
+        fn new(_instance: Arc<VRPInstance>, initial_solution: VRPSolution) -> Self {
+            Recording {
+                current: initial_solution,
+                stats: SolveStats::new(),
+            }
+        }
+
+        fn current(&self) -> &VRPSolution {
+            &self.current
+        }
+
+        fn find_new_solution(&mut self) -> Option<()> {
+            Some(())
+        }
+
+        fn jump_to_solution(&mut self, sol: &VRPSolution) {
+            self.current.clone_from(sol);
+        }
+
+        fn get_stats_mut(&mut self) -> &mut SolveStats {
+            &mut self.stats
+        }
+
+        fn cost(&self) -> f64 {
+            self.current.cost()
+        }
+
+        fn update_context(&mut self, ctx: &SearchContext) {
+            CONTEXTS.with_borrow_mut(|contexts| contexts.push(*ctx));
+        }
+    }
+
+    #[test]
+    fn the_context_follows_the_search_along() {
+        let instance = Arc::new(generate::random_instance(20, 50, 1987));
+        random::seed(1987);
+        let params = SolveParams {
+            terminate: TermCond::MaxIters(100),
+            patience: 1000,
+            ..crate::default_params(Duration::ZERO)
+        };
+        CONTEXTS.with_borrow_mut(Vec::clear);
+        solve::<Recording>(&instance, &params);
+        let contexts = CONTEXTS.take();
+
+        assert_eq!(contexts.len(), 100);
+        for (i, ctx) in contexts.iter().enumerate() {
+            assert_eq!(ctx.iteration, i);
+            assert_eq!(ctx.max_iterations, Some(100));
+            // nothing ever changes, so nothing ever improves
+            assert_eq!(ctx.stagnant_iterations, i);
+            #[allow(clippy::cast_precision_loss)]
+            let left = (100 - i) as f64 / 100.0;
+            assert_eq!(ctx.fraction_left(), Some(left));
+        }
+        assert!(contexts.windows(2).all(|w| w[0].elapsed <= w[1].elapsed));
+    }
+
+    #[test]
+    fn a_time_budget_is_left_in_time() {
+        let ctx = |elapsed, remaining: Option<u64>| SearchContext {
+            elapsed: Duration::from_secs(elapsed),
+            remaining: remaining.map(Duration::from_secs),
+            iteration: 0,
+            stagnant_iterations: 0,
+            max_iterations: None,
+        };
+        assert_eq!(ctx(1, Some(3)).fraction_left(), Some(0.75));
+        assert_eq!(ctx(4, Some(0)).fraction_left(), Some(0.0));
+        assert_eq!(ctx(0, Some(0)).fraction_left(), Some(0.0));
+        assert_eq!(ctx(1, None).fraction_left(), None);
+    }
+
+    #[test]
+    fn the_rate_is_over_the_last_few_samples_only() {
+        let t0 = Instant::now();
+        let mut rate = RateWindow::new(3);
+        assert_eq!(rate.iters_per_sec(), 0.0);
+        rate.push(0, t0);
+        assert_eq!(rate.iters_per_sec(), 0.0);
+        rate.push(1000, t0 + Duration::from_secs(1));
+        assert!((rate.iters_per_sec() - 1000.0).abs() < 1e-6);
+        // the slow first second drops out once there are more than three samples
+        rate.push(5000, t0 + Duration::from_secs(2));
+        rate.push(9000, t0 + Duration::from_secs(3));
+        assert!((rate.iters_per_sec() - 4000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn progress_projects_what_is_left_at_the_current_rate() {
+        let params = SolveParams {
+            terminate: TermCond::MaxIters(5000),
+            ..crate::default_params(Duration::ZERO)
+        };
+        let event = progress_event(&params, 1000, Instant::now(), 1.0, 1000.0);
+        assert_eq!(event.eta, Some(Duration::from_secs(4)));
+        assert_eq!(event.remaining_iters, None);
+        // no rate yet, so no guess
+        assert_eq!(
+            progress_event(&params, 0, Instant::now(), 1.0, 0.0).eta,
+            None
+        );
+
+        let params = SolveParams {
+            terminate: TermCond::TimeElapsed(Duration::from_secs(10)),
+            ..params
+        };
+        let start = Instant::now().checked_sub(Duration::from_secs(4)).unwrap();
+        let event = progress_event(&params, 4000, start, 1.0, 1000.0);
+        assert_eq!(event.eta, None);
+        // 6 seconds left, less however long getting here took
+        let remaining = event.remaining_iters.unwrap();
+        assert!((5900..=6000).contains(&remaining), "{remaining}");
+    }
+
+    #[test]
+    fn the_average_rate_matches_the_wall_clock() {
+        let start = Instant::now();
+        let ((_, stats), _) = solve_for(3000, None);
+        #[allow(clippy::cast_precision_loss)]
+        let measured = stats.total_iterations as f64 / start.elapsed().as_secs_f64();
+        // the solver's clock starts after construction, so it can only be a little quicker
+        let ratio = stats.avg_iters_per_sec / measured;
+        assert!(
+            (1.0..2.0).contains(&ratio),
+            "{} vs {measured}",
+            stats.avg_iters_per_sec
+        );
+    }
+
+    #[test]
+    fn the_elite_set_keeps_the_cheapest_distinct_solutions_in_order() {
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            3,
+            12,
+            vec![0, 3, 4, 5],
+            vec![0.0, 0.0, 4.0, 0.0],
+            vec![0.0, 3.0, 0.0, -4.0],
+        ));
+        let sol = |routes: &[Vec<u32>]| VRPSolution::from_routes_vec(&instance, routes).unwrap();
+        let offers = [
+            sol(&[vec![1], vec![2], vec![3]]),
+            sol(&[vec![1, 2], vec![3]]),
+            // a different plan, but costing the same as the first
+            sol(&[vec![1, 3], vec![2]]),
+            sol(&[vec![2, 3], vec![1]]),
+            sol(&[vec![1, 2, 3]]),
+            sol(&[vec![3, 2, 1]]),
+        ];
+
+        let mut elites = EliteSet::new(3, 0.1, Objective::TotalDistance);
+        for offer in &offers {
+            elites.offer(offer);
+        }
+        let kept = elites.into_vec();
+        let costs = kept.iter().map(VRPSolution::cost).collect::<Vec<_>>();
+        assert_eq!(costs.len(), 3);
+        assert!(
+            (costs[0] - (12.0 + 32_f64.sqrt())).abs() < 1e-9,
+            "{costs:?}"
+        );
+        assert!(
+            (costs[1] - (14.0 + 32_f64.sqrt())).abs() < 1e-9,
+            "{costs:?}"
+        );
+        assert_eq!(costs[2], 20.0);
+        assert_eq!(kept[0].to_routes_vec(false), [vec![1, 2, 3]]);
+
+        let mut none = EliteSet::new(0, 0.1, Objective::TotalDistance);
+        none.offer(&offers[0]);
+        assert!(none.into_vec().is_empty());
+    }
+
+    #[test]
+    fn a_solve_returns_its_elites_cheapest_first() {
+        let instance = Arc::new(generate::random_instance(30, 100, 1963));
+        random::seed(1963);
+        let params = SolveParams {
+            terminate: TermCond::MaxIters(3000),
+            patience: 10,
+            elite_count: 4,
+            elite_min_gap: 0.5,
+            ..crate::default_params(Duration::ZERO)
+        };
+        let (best, elites) = solve_with_elites::<MultiLNSSolver>(&instance, &params);
+        assert!(elites.len() > 1 && elites.len() <= 4, "{}", elites.len());
+        assert_eq!(elites[0].cost(), best.cost());
+        for pair in elites.windows(2) {
+            assert!(pair[1].cost() - pair[0].cost() >= 0.5);
+        }
+        for elite in &elites {
+            assert_eq!(elite.check(), Ok(()));
+        }
+    }
+
+    /// The iterations `MultiLNSSolver` restarted at on `instance`, with the given patience.
+    fn restarts(
+        instance: &Arc<VRPInstance>,
+        iters: usize,
+        patience: usize,
+        patience_unit: PatienceUnit,
+    ) -> Vec<usize> {
+        random::seed(1962);
+        let params = SolveParams {
+            terminate: TermCond::MaxIters(iters),
+            patience,
+            patience_unit,
+            destroy_size: Some(5),
+            ..crate::default_params(Duration::ZERO)
+        };
+        let initial_solution = params.starting_solution(instance);
+        let (_, stats) =
+            solve_from_with_stats::<MultiLNSSolver>(instance, &params, initial_solution);
+        stats.restarts
+    }
+
+    #[test]
+    fn patience_in_customer_moves_is_patience_in_iterations_times_the_destroy_size() {
+        let instance = Arc::new(generate::random_instance(30, 100, 1962));
+        let by_moves = restarts(&instance, 2000, 100, PatienceUnit::CustomerMoves);
+        assert!(!by_moves.is_empty());
+        assert_eq!(
+            by_moves,
+            restarts(&instance, 2000, 20, PatienceUnit::Iterations)
+        );
+        assert_ne!(
+            by_moves,
+            restarts(&instance, 2000, 100, PatienceUnit::Iterations)
+        );
+    }
+
+    /// How often the search restarts on a 50- and a 500-customer instance, with patience as the
+    /// old 5% of the customers in iterations, and as a fixed number of customer moves at either
+    /// size. Run it with
+    /// `cargo test --release -- --ignored --nocapture restarts_are_as_frequent_at_any_size`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn restarts_are_as_frequent_at_any_size() {
+        let iters = 20_000;
+        let sizes = [50, 500];
+        let instances = sizes.map(|n| Arc::new(generate::random_instance(n, 100, 1962)));
+        let per_1000 = |restarts: Vec<usize>| restarts.len() as f64 * 1000.0 / iters as f64;
+        let spread = |rates: &[f64]| rates[0].max(rates[1]) / rates[0].min(rates[1]);
+
+        let by_size = sizes
+            .iter()
+            .zip(&instances)
+            .map(|(n, instance)| {
+                per_1000(restarts(instance, iters, n / 20, PatienceUnit::Iterations))
+            })
+            .collect::<Vec<_>>();
+        println!("5% of the customers in iterations: {by_size:.1?} restarts per 1000 iterations");
+        for moves in [10, 25, 50] {
+            let by_moves = instances
+                .iter()
+                .map(|instance| {
+                    per_1000(restarts(
+                        instance,
+                        iters,
+                        moves,
+                        PatienceUnit::CustomerMoves,
+                    ))
+                })
+                .collect::<Vec<_>>();
+            println!("{moves} customer moves: {by_moves:.1?} restarts per 1000 iterations");
+            assert!(spread(&by_moves) < 2.0);
+            assert!(spread(&by_moves) < spread(&by_size));
+        }
+    }
+
+    /// Solve `instance` for `iters` iterations with `improver` called on every new best, returning
+    /// the best and the cost of every new best the search reported.
+    fn solve_improved(
+        instance: &Arc<VRPInstance>,
+        iters: usize,
+        improver: ExternalImprover,
+    ) -> (VRPSolution, Vec<f64>) {
+        random::seed(1960);
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let on_improvement: ImprovementCallback = {
+            let reported = reported.clone();
+            Arc::new(move |event: &ImprovementEvent| reported.lock().unwrap().push(event.cost))
+        };
+        let params = SolveParams {
+            terminate: TermCond::MaxIters(iters),
+            external_improver: Some(improver),
+            improver_interval: Duration::ZERO,
+            on_improvement: Some(on_improvement),
+            ..crate::default_params(Duration::ZERO)
+        };
+        let best = solve::<MultiLNSSolver>(instance, &params);
+        let reported = reported.lock().unwrap().clone();
+        (best, reported)
+    }
+
+    #[test]
+    fn each_new_best_is_reported_once_in_order() {
+        let instance = Arc::new(generate::random_instance(30, 100, 2043));
+        random::seed(2043);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let on_improvement: ImprovementCallback = {
+            let events = events.clone();
+            Arc::new(move |event: &ImprovementEvent| events.lock().unwrap().push(*event))
+        };
+        let params = SolveParams {
+            terminate: TermCond::MaxIters(2000),
+            on_improvement: Some(on_improvement),
+            ..crate::default_params(Duration::ZERO)
+        };
+        let best = solve::<MultiLNSSolver>(&instance, &params);
+        let events = events.lock().unwrap();
+        assert!(!events.is_empty());
+        assert!(events.is_sorted_by(|a, b| a.iteration < b.iteration));
+        assert!(events.is_sorted_by(|a, b| a.elapsed <= b.elapsed));
+        assert!(events.is_sorted_by(|a, b| a.cost > b.cost), "{events:?}");
+        assert!(events.iter().all(|e| e.iteration < 2000));
+        assert_eq!(events.last().unwrap().cost, best.cost());
+    }
+
+    #[test]
+    fn the_improver_sees_every_new_best() {
+        let instance = Arc::new(generate::random_instance(30, 100, 1960));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let improver: ExternalImprover = {
+            let seen = seen.clone();
+            Arc::new(move |sol: &VRPSolution, _: &VRPInstance| {
+                seen.lock().unwrap().push(sol.cost());
+                None
+            })
+        };
+        let (best, reported) = solve_improved(&instance, 2000, improver);
+        let seen = seen.lock().unwrap();
+        assert!(!seen.is_empty());
+        assert_eq!(*seen, reported);
+        assert!(seen.is_sorted_by(|a, b| a > b), "{seen:?}");
+        assert_eq!(seen.last(), Some(&best.cost()));
+    }
+
+    #[test]
+    fn a_cheaper_valid_solution_from_the_improver_is_adopted() {
+        // every leg costs three times as much one way as the other, so the direction a route is
+        // driven in matters
+        let generated = generate::random_instance(30, 100, 1960);
+        let n = generated.num_customers;
+        let distances = (0..n)
+            .map(|a| {
+                (0..n)
+                    .map(|b| generated.distance_matrix.dist(a, b) * if a < b { 3.0 } else { 1.0 })
+                    .collect()
+            })
+            .collect();
+        let instance = Arc::new(VRPInstance::from_matrix(
+            generated.num_vehicles,
+            generated.vehicle_capacity,
+            generated.demand_of_customer.clone(),
+            distances,
+        ));
+
+        // turn round every route that's cheaper the other way
+        let improved = Arc::new(Mutex::new(Vec::new()));
+        let improver: ExternalImprover = {
+            let improved = improved.clone();
+            Arc::new(move |sol: &VRPSolution, _: &VRPInstance| {
+                let instance = sol.routes[0].instance.clone();
+                let routes = sol.to_routes_vec(false);
+                let reversed = routes
+                    .iter()
+                    .map(|r| {
+                        let mut turned = r.clone();
+                        turned.reverse();
+                        let cost = |r: &[u32]| {
+                            VRPSolution::from_routes_vec(&instance, &[r.to_vec()])
+                                .unwrap()
+                                .cost()
+                        };
+                        if cost(&turned) < cost(r) {
+                            turned
+                        } else {
+                            r.clone()
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let better = VRPSolution::from_routes_vec(&instance, &reversed).unwrap();
+                (better.cost() < sol.cost()).then(|| {
+                    improved.lock().unwrap().push(better.cost());
+                    better
+                })
+            })
+        };
+        let (best, reported) = solve_improved(&instance, 2000, improver);
+        let improved = improved.lock().unwrap();
+        assert!(!improved.is_empty());
+        // a new best the improver made better is reported as what the improver made of it
+        for cost in improved.iter() {
+            assert!(reported.contains(cost), "{cost} isn't in {reported:?}");
+        }
+        assert!(best.check().is_ok());
+        assert!(best.cost() <= improved.last().copied().unwrap());
+    }
+
+    #[test]
+    fn worse_or_invalid_solutions_from_the_improver_are_ignored() {
+        let instance = Arc::new(generate::random_instance(30, 100, 1960));
+        let first_best = Arc::new(Mutex::new(None::<VRPSolution>));
+        let calls = Arc::new(Mutex::new(0));
+        let improver: ExternalImprover = {
+            let (first_best, calls) = (first_best.clone(), calls.clone());
+            Arc::new(move |sol: &VRPSolution, _: &VRPInstance| {
+                let mut calls = calls.lock().unwrap();
+                *calls += 1;
+                let mut first_best = first_best.lock().unwrap();
+                let Some(first) = &*first_best else {
+                    *first_best = Some(sol.clone());
+                    return None;
+                };
+                if *calls % 2 == 0 {
+                    // far cheaper, but only because it leaves a customer out
+                    let mut routes = sol.to_routes_vec(false);
+                    routes.sort_by_key(Vec::len);
+                    routes.last_mut().unwrap().pop();
+                    Some(VRPSolution::from_routes_unchecked(
+                        &sol.routes[0].instance,
+                        &routes,
+                    ))
+                } else {
+                    // valid, but an older and worse best
+                    Some(first.clone())
+                }
+            })
+        };
+        let (best, reported) = solve_improved(&instance, 2000, improver);
+        assert!(*calls.lock().unwrap() >= 3);
+        assert!(best.check().is_ok());
+        // nothing the improver offered was taken, so every best was cheaper than the last
+        assert!(reported.is_sorted_by(|a, b| a > b), "{reported:?}");
+        assert_eq!(reported.last(), Some(&best.cost()));
+    }
+
+    /// Customer numbers past `u16::MAX` all the way through a solve. It takes about half a minute
+    /// even in a release build, so run it with
+    /// `cargo test --release -- --ignored more_customers_than_fit_in_a_u16`.
+    #[test]
+    #[ignore = "slow"]
+    fn more_customers_than_fit_in_a_u16() {
+        let instance = Arc::new(generate::generate(
+            70_000,
+            700,
+            1000,
+            generate::Layout::Uniform,
+            2022,
+        ));
+        assert!(instance.num_customers > usize::from(u16::MAX) + 1);
+        random::seed(2022);
+        let params = SolveParams {
+            terminate: TermCond::MaxIters(300),
+            // Clarke-Wright's savings list alone would need tens of gigabytes
+            constructor: |instance| crate::construct::sweep(instance).unwrap(),
+            ..crate::default_params(Duration::ZERO)
+        };
+        let initial = params.starting_solution(&instance);
+        let initial_cost = initial.cost();
+        let (best, stats) = solve_from_with_stats::<MultiLNSSolver>(&instance, &params, initial);
+        assert_eq!(stats.total_iterations, 300);
+        assert!(best.cost() <= initial_cost);
+        assert_eq!(best.check(), Ok(()));
+        assert!(best.routes.iter().any(|r| {
+            r.stops()
+                .iter()
+                .any(|s| s.cust_no().0 > u32::from(u16::MAX))
+        }));
+    }
+
+    /// Counts the heap allocations made on the threads that ask for it, so a test can see what the
+    /// solve loop allocates without the tests running alongside it adding to the count.
+    struct CountingAllocator;
+
+    std::thread_local! {
+        static COUNTING: Cell<bool> = const { Cell::new(false) };
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn count_allocation() {
+        if COUNTING.get() {
+            ALLOCATIONS.set(ALLOCATIONS.get() + 1);
+        }
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count_allocation();
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            // the harness's captured output is a byte buffer that grows as the solve prints its
+            // summary, which has nothing to do with the search, so bytes growing aren't counted
+            if layout.align() > 1 {
+                count_allocation();
+            }
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// How many allocations running `f` on this thread makes.
+    fn allocations_in(f: impl FnOnce()) -> usize {
+        ALLOCATIONS.set(0);
+        COUNTING.set(true);
+        f();
+        COUNTING.set(false);
+        ALLOCATIONS.get()
+    }
+
+    #[test]
+    fn the_warmed_up_solve_loop_does_not_allocate() {
+        // so the harness's output capture has its buffer before anything is counted
+        println!("counting allocations");
+        assert_eq!(
+            solve_allocations::<MultiLNSSolver>(3000),
+            solve_allocations::<MultiLNSSolver>(2000)
+        );
+        assert_eq!(
+            solve_allocations::<ALNSSolver>(3000),
+            solve_allocations::<ALNSSolver>(2000)
+        );
+    }
+
+    /// How many allocations solving a small instance for `iters` iterations makes. The same seed
+    /// gives the same search, so a longer run only adds the iterations past the shorter one's.
+    fn solve_allocations<S: IterativeSolver>(iters: usize) -> usize {
+        let instance = Arc::new(generate::random_instance(40, 100, 1961));
+        random::seed(1961);
+        let params = SolveParams {
+            terminate: TermCond::MaxIters(iters),
+            // restarts and the jumps they make build new solutions, which isn't the steady state
+            patience: usize::MAX,
+            ..crate::default_params(Duration::ZERO)
+        };
+        let initial_solution = params.starting_solution(&instance);
+        allocations_in(|| {
+            solve_from_with_stats::<S>(&instance, &params, initial_solution);
+        })
+    }
 }
@@ -1,35 +1,132 @@
 use std::{cmp::Reverse, collections::VecDeque, sync::Arc};
 
-use rand::{Rng, rng, rngs::ThreadRng};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::common::{Stop, VRPSolution};
-use crate::solver::LNSSolver;
+use crate::ids::{CustomerId, RouteIdx};
+use crate::insertion_cache::InsertionCache;
+use crate::objective::Objective;
+use crate::random::{SeededRng, rng};
 use crate::solver::stats::SolveStats;
+use crate::solver::{LNSSolver, SearchContext, SolveParams};
+use crate::tabu::EdgeTabu;
 use crate::vrp_instance::VRPInstance;
 
+/// How many customers each destroy removes, unless [`SolveParams::destroy_size`] says otherwise.
+const DESTROY_SIZE: usize = 5;
+
+/// In the last this much of the budget, destroys get smaller: there's no time left for big
+/// repairs to pay off, and small ones mean more (cheaper) tries at polishing the solution.
+const ENDGAME_FRACTION: f64 = 0.05;
+
+/// The ALNS solver's own knobs, which it reads from [`SolveParams::alns`]. Fields left out of a
+/// config file keep their defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ALNSConfig {
+    /// see [`ENDGAME_FRACTION`]
+    pub endgame_fraction: f64,
+    /// how often repair puts a customer in a random feasible spot instead of the cheapest one
+    pub random_spot_probability: f64,
+    // operators can be switched off one by one, e.g. to see what each is worth
+    pub random_removal: bool,
+    /// with this off, every customer goes into a random feasible spot
+    pub best_insertion: bool,
+    pub replace_and_cascade: bool,
+    /// skip routes whose [`crate::common::Route::insertion_lower_bound`] rules them out; the search
+    /// is the same without it, only slower
+    pub insertion_pruning: bool,
+}
+
+impl Default for ALNSConfig {
+    fn default() -> Self {
+        ALNSConfig {
+            endgame_fraction: ENDGAME_FRACTION,
+            random_spot_probability: 0.02,
+            random_removal: true,
+            best_insertion: true,
+            replace_and_cascade: true,
+            insertion_pruning: true,
+        }
+    }
+}
+
+impl ALNSConfig {
+    /// Read a config from a JSON file, e.g. `{"replace_and_cascade": false}`, along with the names
+    /// of the fields it set (the rest keep their defaults).
+    pub fn from_file(path: &str) -> Result<(Self, Vec<String>), String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("can't read {path}: {e}"))?;
+        let value: serde_json::Value =
+            serde_json::from_str(&text).map_err(|e| format!("{path}: {e}"))?;
+        let fields = value
+            .as_object()
+            .map(|fields| fields.keys().cloned().collect())
+            .unwrap_or_default();
+        let config: Self = serde_json::from_value(value).map_err(|e| format!("{path}: {e}"))?;
+        config.validate()?;
+        Ok((config, fields))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.random_removal {
+            return Err("random_removal is the only destroy operator, so can't be disabled".into());
+        }
+        for (name, value) in [
+            ("endgame_fraction", self.endgame_fraction),
+            ("random_spot_probability", self.random_spot_probability),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!("{name} must be between 0 and 1, not {value}"));
+            }
+        }
+        Ok(())
+    }
+}
+
 /// An LNS solver which greedily **removes the highest cost stop** from the solution,
 /// **inserting it at the lowest cost location**.
 pub struct ALNSSolver {
     instance: Arc<VRPInstance>,
-    stop_tabu: VecDeque<usize>,
-    stop_not_tabu: Vec<usize>,
+    stop_tabu: VecDeque<CustomerId>,
+    stop_not_tabu: Vec<CustomerId>,
     current: VRPSolution,
     stats: SolveStats,
-    rng: ThreadRng,
+    rng: SeededRng,
+    objective: Objective,
+    edge_tabu: EdgeTabu,
+    /// the cheapest solution this solver has reached, for the edge tabu's aspiration
+    best_seen: f64,
+    destroy_size: usize,
+    insertion_cache: InsertionCache,
+    /// whether the search is in its last `config.endgame_fraction` of the budget
+    endgame: bool,
+    config: ALNSConfig,
+    /// the last destroy's result, handed back by the repair so the next destroy can reuse it
+    removed: Vec<(Stop, RouteIdx)>,
 }
 
 impl LNSSolver for ALNSSolver {
     /// corresponding to the (`cust_no`, route #) that was removed
-    type DestroyResult = Vec<(Stop, usize)>;
+    type DestroyResult = Vec<(Stop, RouteIdx)>;
 
     fn new(instance: Arc<VRPInstance>, initial_solution: VRPSolution) -> Self {
+        let insertion_cache = InsertionCache::new(instance.num_customers, instance.num_vehicles);
         ALNSSolver {
             stop_tabu: VecDeque::new(),
             current: initial_solution,
-            stop_not_tabu: (1..instance.num_customers).collect(),
+            stop_not_tabu: CustomerId::customers(instance.num_customers).collect(),
             instance,
             stats: SolveStats::new(),
-            rng: rand::rng(),
+            rng: rng(),
+            objective: Objective::default(),
+            edge_tabu: EdgeTabu::new(0),
+            best_seen: f64::MAX,
+            destroy_size: DESTROY_SIZE,
+            insertion_cache,
+            endgame: false,
+            config: ALNSConfig::default(),
+            removed: Vec::new(),
         }
     }
 
@@ -38,13 +135,15 @@ impl LNSSolver for ALNSSolver {
     }
 
     fn destroy(&mut self) -> Self::DestroyResult {
-        let removed_stops = self.remove_n_random_stops(5);
+        let mut removed_stops = std::mem::take(&mut self.removed);
+        self.remove_n_random_stops(LNSSolver::destroy_size(self), &mut removed_stops);
+        self.stats.on_operator_used("random_removal", false);
 
         for (stop, route_idx) in &removed_stops {
             *self
                 .stats
                 .cust_change_freq
-                .entry(stop.cust_no().into())
+                .entry(stop.cust_no())
                 .or_insert(0) += 1;
             *self.stats.route_remove_freq.entry(*route_idx).or_insert(0) += 1;
         }
@@ -55,25 +154,56 @@ impl LNSSolver for ALNSSolver {
         &mut self.stats
     }
 
-    fn repair(&mut self, res: Self::DestroyResult) -> Result<(), String> {
-        let route_idxs = self.reinsert_n_stops_in_best_spots(&res)?;
+    fn destroy_size(&self) -> usize {
+        if self.endgame {
+            self.destroy_size.div_ceil(2)
+        } else {
+            self.destroy_size
+        }
+    }
+
+    fn repair(&mut self, mut res: Self::DestroyResult) -> Result<(), String> {
+        let reinserted = self.reinsert_n_stops_in_best_spots(&mut res);
+        self.removed = res;
+        reinserted?;
+        // drop any routes the destroy emptied and the repair didn't refill
+        self.current.compact();
 
-        for route_idx in route_idxs {
-            *self.stats.route_add_freq.entry(route_idx).or_insert(0) += 1;
+        if self.edge_tabu.is_enabled() {
+            self.best_seen = self.best_seen.min(self.current.cost());
         }
         Ok(())
     }
 
     fn jump_to_solution(&mut self, sol: &VRPSolution) {
         self.current.clone_from(sol); // clone directly into exising allocations
-        self.stop_not_tabu = (1..self.instance.num_customers).collect();
+        self.stop_not_tabu.clear();
+        self.stop_not_tabu
+            .extend(CustomerId::customers(self.instance.num_customers));
         self.stop_tabu.clear();
+        // rejected moves come back through here too, so keep the edge tabu (and its tenure) going
+    }
+
+    fn configure(&mut self, params: &SolveParams) {
+        self.objective = params.objective;
+        self.config = params.alns;
+        self.edge_tabu = EdgeTabu::new(params.edge_tabu_tenure);
+        if let Some(destroy_size) = params.destroy_size {
+            // up to a tenth of the customers can be tabu, and the rest must be enough to destroy
+            let n = self.instance.num_customers;
+            self.destroy_size = destroy_size.clamp(1, (n - 1 - n / 10).max(1));
+        }
+    }
+
+    fn update_context(&mut self, ctx: &SearchContext) {
+        self.endgame = ctx
+            .fraction_left()
+            .is_some_and(|left| left < self.config.endgame_fraction);
     }
 
     fn update_tabu(&mut self, res: &Self::DestroyResult) {
         for (stop, _) in res {
-            let cust_no = stop.cust_no().into();
-            self.stop_tabu.push_back(cust_no);
+            self.stop_tabu.push_back(stop.cust_no());
         }
 
         while self.stop_tabu.len() > (self.instance.num_customers / 10) {
@@ -94,59 +224,57 @@ impl ALNSSolver {
         let alpha = 1.0;
         let beta = 0.1;
 
-        for cust_no in 1..self.instance.num_customers {
-            if cust_no != seed_cust_no {
-                let dist = self.instance.distance_matrix.dist(seed_cust_no, cust_no);
-                #[allow(clippy::cast_precision_loss)]
-                let demand_diff = (self.instance.demand_of_customer[seed_cust_no] as f64
-                    - self.instance.demand_of_customer[cust_no] as f64)
-                    .abs();
-                let score = alpha * dist + beta * demand_diff;
-                similarity_scores.push((cust_no, score));
-            }
+        // only the seed's nearest customers can be similar enough to be worth scoring
+        for &cust_no in self.instance.nearest(u32::try_from(seed_cust_no).unwrap()) {
+            let cust_no = cust_no as usize;
+            let dist = self.instance.distance_matrix.dist(seed_cust_no, cust_no);
+            #[allow(clippy::cast_precision_loss)]
+            let demand_diff = (self.instance.demand_of_customer[seed_cust_no] as f64
+                - self.instance.demand_of_customer[cust_no] as f64)
+                .abs();
+            let score = alpha * dist + beta * demand_diff;
+            similarity_scores.push((cust_no, score));
         }
 
         Vec::new()
     }
 
-    fn remove_n_random_stops(&mut self, n: usize) -> Vec<(Stop, usize)> {
+    /// Remove `n` random customers that aren't tabu into `res`, which starts out empty. On an
+    /// instance with fewer than `n` of them, all of them are removed.
+    fn remove_n_random_stops(&mut self, n: usize, res: &mut Vec<(Stop, RouteIdx)>) {
         assert!(n > 0);
         self.assert_tabu_sanity();
 
         let sol = &mut self.current;
+        res.clear();
+        self.edge_tabu.tick();
 
-        let mut customer_nos = Vec::new();
         for _ in 0..n {
+            if self.stop_not_tabu.is_empty() {
+                break;
+            }
             let rem_index = self.rng.random_range(0..self.stop_not_tabu.len());
-            customer_nos.push(self.stop_not_tabu.swap_remove(rem_index));
-        }
-        assert!(customer_nos.len() == n);
-
-        let mut res = Vec::new();
-
-        for cust_no in customer_nos {
+            let cust_no = self.stop_not_tabu.swap_remove(rem_index);
             for (route_idx, route) in sol.routes.iter_mut().enumerate() {
-                if let Some(index) = route.index_of_stop(u16::try_from(cust_no).unwrap()) {
+                if let Some(index) = route.index_of_stop(cust_no) {
+                    if self.edge_tabu.is_enabled() {
+                        let (before, after) = (route.gap(index).0, route.gap(index + 1).1);
+                        self.edge_tabu.forbid(before, cust_no);
+                        self.edge_tabu.forbid(cust_no, after);
+                    }
                     let removed_stop = route.remove_stop_at_index(index);
-                    res.push((removed_stop, route_idx));
+                    res.push((removed_stop, RouteIdx(route_idx)));
                     break;
                 }
             }
         }
-        res
     }
 
     #[cfg(debug_assertions)]
     fn assert_tabu_sanity(&self) {
-        let full_tabu = self
-            .stop_tabu
-            .iter()
-            .chain(self.stop_not_tabu.iter())
-            .collect::<Vec<_>>();
-
-        assert!(full_tabu.len() == (self.instance.num_customers - 1));
-        for cust_no in 1..self.instance.num_customers {
-            assert!(full_tabu.contains(&&cust_no));
+        assert!(self.stop_tabu.len() + self.stop_not_tabu.len() == self.instance.num_customers - 1);
+        for cust_no in CustomerId::customers(self.instance.num_customers) {
+            assert!(self.stop_tabu.contains(&cust_no) || self.stop_not_tabu.contains(&cust_no));
         }
     }
     #[cfg(not(debug_assertions))]
@@ -155,49 +283,527 @@ impl ALNSSolver {
 
     fn reinsert_n_stops_in_best_spots(
         &mut self,
-        removed_stops: &[(Stop, usize)],
-    ) -> Result<Vec<usize>, String> {
-        let mut res = Vec::new();
-        let mut removed_stops = removed_stops.to_owned();
+        removed_stops: &mut [(Stop, RouteIdx)],
+    ) -> Result<(), String> {
         removed_stops.sort_by_key(|x| Reverse(x.0.capacity()));
-        for (stop, _) in removed_stops {
-            res.push(self.reinsert_in_best_spot(stop)?);
+        for &(stop, _) in &*removed_stops {
+            let route_idx = self.reinsert_in_best_spot(stop)?;
+            *self
+                .stats
+                .route_add_freq
+                .entry(RouteIdx(route_idx))
+                .or_insert(0) += 1;
         }
-        Ok(res)
+        Ok(())
     }
 
     fn reinsert_in_best_spot(&mut self, stop: Stop) -> Result<usize, String> {
+        if !self.config.best_insertion {
+            return self.reinsert_in_random_spot(stop);
+        }
         let (mut best_spot_r, mut best_spot_i, mut best_spot_cost_increase) =
             (usize::MAX, usize::MAX, f64::MAX);
-
-        let mut valid = Vec::with_capacity(self.instance.num_customers);
+        let vehicle_capacity = self.instance.vehicle_capacity;
+        let max_route_cost = match self.objective {
+            Objective::TotalDistance => 0.0,
+            Objective::MinMaxDistance => self.current.max_route_cost(),
+        };
+        // where the stop would go if every non-tabu spot is full
+        let (mut tabu_spot_r, mut tabu_spot_i, mut tabu_spot_cost_increase) =
+            (usize::MAX, usize::MAX, f64::MAX);
+        let partial_cost = if self.edge_tabu.is_enabled() {
+            self.current.cost()
+        } else {
+            0.0
+        };
 
         for (r, route) in self.current.routes.iter().enumerate() {
+            if route.used_capacity() + stop.capacity() > vehicle_capacity {
+                continue;
+            }
+            let weight = self
+                .objective
+                .insertion_weight(route.cost(), max_route_cost);
+            let prune = self.config.insertion_pruning;
+            let lower_bound_rules_out = || {
+                prune
+                    && route.insertion_lower_bound(&stop) * weight - 1e-9 >= best_spot_cost_increase
+            };
+            self.stats.insertion_scans.considered += 1;
+
+            // without the edge tabu a route's best spot only depends on the route itself
+            if !self.edge_tabu.is_enabled() {
+                let best = match self.insertion_cache.get(&stop, r, route) {
+                    Some(best) => best,
+                    None if lower_bound_rules_out() => {
+                        self.stats.insertion_scans.pruned += 1;
+                        continue;
+                    }
+                    None => self.insertion_cache.scan(&stop, r, route),
+                };
+                if let Some((i, increase)) = best {
+                    if increase * weight < best_spot_cost_increase {
+                        (best_spot_r, best_spot_i) = (r, i);
+                        best_spot_cost_increase = increase * weight;
+                    }
+                }
+                continue;
+            }
+
+            // skip the per-position scan when no position here could beat what we already have
+            if lower_bound_rules_out() {
+                self.stats.insertion_scans.pruned += 1;
+                continue;
+            }
+
             for i in 0..=route.stops().len() {
-                let (new_cost, feas) = route.speculative_add_stop(&stop, i);
+                let (new_cost, feasible) = route.speculative_add_stop(&stop, i);
+                if !feasible {
+                    continue;
+                }
 
                 // we want the one that will increase the new cost by the least, so minimize
-                let cost_increase = new_cost - route.cost();
-                if feas {
-                    valid.push((r, i));
+                let cost_increase = (new_cost - route.cost()) * weight;
+                if self.edge_tabu.is_enabled() {
+                    let (before, after) = route.gap(i);
+                    let recreates_tabu_edge = self.edge_tabu.is_tabu(before, stop.cust_no())
+                        || self.edge_tabu.is_tabu(stop.cust_no(), after);
+                    // aspiration: a tabu edge is fine if it could still lead to a new best
+                    let aspires = partial_cost + new_cost - route.cost() < self.best_seen;
+                    if recreates_tabu_edge && !aspires {
+                        if cost_increase < tabu_spot_cost_increase {
+                            (tabu_spot_r, tabu_spot_i) = (r, i);
+                            tabu_spot_cost_increase = cost_increase;
+                        }
+                        continue;
+                    }
                 }
-                if feas && cost_increase < best_spot_cost_increase {
+                if cost_increase < best_spot_cost_increase {
                     (best_spot_r, best_spot_i) = (r, i);
                     best_spot_cost_increase = cost_increase;
                 }
             }
         }
         if best_spot_r == usize::MAX {
-            return Err("no place to put customer".to_string());
+            (best_spot_r, best_spot_i) = (tabu_spot_r, tabu_spot_i);
+        }
+        self.stats
+            .on_operator_used("best_insertion", best_spot_r == usize::MAX);
+        if best_spot_r == usize::MAX {
+            return self.make_room_for(stop);
         }
 
-        if rng().random_bool(0.02_f64) {
-            let i = rng().random_range(0..valid.len());
-            (best_spot_r, best_spot_i) = *valid.get(i).unwrap();
+        if rng().random_bool(self.config.random_spot_probability) {
+            // only go through the valid spots when we actually need to pick from them
+            let i = rng().random_range(0..self.valid_spots(&stop).count());
+            (best_spot_r, best_spot_i) = self.valid_spots(&stop).nth(i).unwrap();
         }
         // dbg_println!("Reinserting: {:?} at {}", stop, best_spot_r);
-        self.current.routes[best_spot_r].add_stop_to_index(stop, best_spot_i);
+        self.current.add_stop(best_spot_r, stop, best_spot_i);
 
         Ok(best_spot_r)
     }
+
+    /// Every feasible (route, index) to insert `stop` at. Counting them and then going back for
+    /// the one picked saves collecting them, which would allocate on every call.
+    fn valid_spots(&self, stop: &Stop) -> impl Iterator<Item = (usize, usize)> {
+        let vehicle_capacity = self.instance.vehicle_capacity;
+        self.current
+            .routes
+            .iter()
+            .enumerate()
+            .filter(move |(_, route)| route.used_capacity() + stop.capacity() <= vehicle_capacity)
+            .flat_map(|(r, route)| {
+                (0..=route.stops().len())
+                    .filter(|&i| route.speculative_add_stop(stop, i).1)
+                    .map(move |i| (r, i))
+            })
+    }
+
+    /// Repair without best insertion: anywhere feasible will do.
+    fn reinsert_in_random_spot(&mut self, stop: Stop) -> Result<usize, String> {
+        let valid = self.valid_spots(&stop).count();
+        self.stats.on_operator_used("random_insertion", valid == 0);
+        if valid == 0 {
+            return self.make_room_for(stop);
+        }
+        let (r, i) = self
+            .valid_spots(&stop)
+            .nth(rng().random_range(0..valid))
+            .unwrap();
+        self.current.add_stop(r, stop, i);
+        Ok(r)
+    }
+
+    /// Nowhere has room for `stop`, so try making room by bumping someone else.
+    fn make_room_for(&mut self, stop: Stop) -> Result<usize, String> {
+        if !self.config.replace_and_cascade {
+            return Err("no place to put customer".to_string());
+        }
+        let res = self.replace_and_cascade(stop);
+        self.stats
+            .on_operator_used("replace_and_cascade", res.is_err());
+        res
+    }
+
+    /// Put `stop` in place of some customer already in the solution, then move that displaced
+    /// customer to its own cheapest spot in another route. Picks the cheapest such pair of moves,
+    /// and only goes one level deep (the displaced customer has to fit somewhere directly).
+    /// Returns the route `stop` ended up in.
+    fn replace_and_cascade(&mut self, stop: Stop) -> Result<usize, String> {
+        let vehicle_capacity = self.instance.vehicle_capacity;
+        let routes = &self.current.routes;
+
+        // (route, index) to replace, and (route, index) to move the displaced customer to
+        let mut best = None;
+        let mut best_cost_increase = f64::MAX;
+
+        for (r, route) in routes.iter().enumerate() {
+            for (i, displaced) in route.stops().iter().enumerate() {
+                let (replaced_cost, within_capacity) = route.speculative_replace_stop(&stop, i);
+                let replace_increase = replaced_cost - route.cost();
+                if !within_capacity || replace_increase >= best_cost_increase {
+                    continue;
+                }
+
+                for (r2, other) in routes.iter().enumerate() {
+                    if r2 == r
+                        || other.used_capacity() + displaced.capacity() > vehicle_capacity
+                        || (self.config.insertion_pruning
+                            && replace_increase + other.insertion_lower_bound(displaced) - 1e-9
+                                >= best_cost_increase)
+                    {
+                        continue;
+                    }
+
+                    let ((new_cost, _), j) = other.speculative_add_best(displaced);
+                    let cost_increase = replace_increase + new_cost - other.cost();
+                    if cost_increase < best_cost_increase {
+                        best = Some((r, i, r2, j));
+                        best_cost_increase = cost_increase;
+                    }
+                }
+            }
+        }
+
+        let Some((r, i, r2, j)) = best else {
+            return Err("no place to put customer".to_string());
+        };
+        let displaced = self.current.routes[r].replace_stop_at_index(stop, i);
+        self.current.add_stop(r2, displaced, j);
+
+        Ok(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::generate::{self, Layout};
+    use crate::random;
+    use crate::solver::{self, SolveResult, TermCond};
+
+    fn solve(instance: &Arc<VRPInstance>, iters: usize, alns: ALNSConfig) -> SolveResult {
+        solve_with(instance, iters, alns, |_| {})
+    }
+
+    fn solve_with(
+        instance: &Arc<VRPInstance>,
+        iters: usize,
+        alns: ALNSConfig,
+        tweak: impl Fn(&mut SolveParams),
+    ) -> SolveResult {
+        let mut params = SolveParams {
+            terminate: TermCond::MaxIters(iters),
+            alns,
+            ..crate::default_params(Duration::ZERO)
+        };
+        tweak(&mut params);
+        random::seed(1957);
+        let initial_solution = (params.constructor)(instance);
+        solver::solve_from_with_stats::<ALNSSolver>(instance, &params, initial_solution)
+    }
+
+    #[test]
+    fn replace_and_cascade_makes_room_where_insertion_cant() {
+        // vehicles of 9 holding 1 and 2 (5 + 3) and 3 (6), so 4 (4) fits in neither, but swapping
+        // it in for 2 leaves room for 2 alongside 3
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            2,
+            9,
+            vec![0, 5, 3, 6, 4],
+            vec![0.0, 1.0, 2.0, -1.0, 2.0],
+            vec![0.0, 1.0, 1.0, 0.0, 2.0],
+        ));
+        let without_4 = VRPSolution::from_routes_unchecked(&instance, &[vec![1, 2], vec![3]]);
+        let four = Stop::new(4, 4);
+
+        for replace_and_cascade in [false, true] {
+            let mut solver = ALNSSolver::new(instance.clone(), without_4.clone());
+            solver.config.replace_and_cascade = replace_and_cascade;
+            let res = solver.reinsert_in_best_spot(four);
+            if !replace_and_cascade {
+                assert!(res.is_err(), "4 shouldn't fit anywhere directly");
+                continue;
+            }
+            assert_eq!(res, Ok(0));
+            assert_eq!(
+                solver.current.to_routes_vec(false),
+                [vec![1, 4], vec![2, 3]]
+            );
+            solver.current.validate(&instance).unwrap();
+        }
+    }
+
+    #[test]
+    fn insertion_avoids_tabu_edges_unless_they_would_make_a_new_best() {
+        // 1, 2 and 3 in a line heading out from the depot, and 4 off to the side
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            2,
+            10,
+            vec![0, 1, 1, 1, 1],
+            vec![0.0, 10.0, 20.0, 30.0, 0.0],
+            vec![0.0, 0.0, 0.0, 0.0, 10.0],
+        ));
+        let without_2 = VRPSolution::from_routes_unchecked(&instance, &[vec![1, 3], vec![4]]);
+        let two = Stop::new(2, 1);
+
+        // (2, 3) is tabu, which rules out both free spots next to 3, leaving the one before 1
+        // (20 more) as the cheapest; unless the free spot would beat the best so far (80)
+        for (best_seen, expected) in [(80.0, [2, 1, 3]), (1000.0, [1, 2, 3])] {
+            let mut solver = ALNSSolver::new(instance.clone(), without_2.clone());
+            solver.config.random_spot_probability = 0.0;
+            solver.edge_tabu = EdgeTabu::new(10);
+            solver.edge_tabu.forbid(CustomerId(2), CustomerId(3));
+            solver.best_seen = best_seen;
+            assert_eq!(solver.reinsert_in_best_spot(two), Ok(0));
+            assert_eq!(
+                solver.current.to_routes_vec(false),
+                [expected.to_vec(), vec![4]]
+            );
+        }
+    }
+
+    #[test]
+    fn the_endgame_destroys_less() {
+        let instance = Arc::new(generate::random_instance(40, 50, 1987));
+        random::seed(1987);
+        let start = crate::construct::clarke_wright_and_then_sweep(&instance);
+        let mut solver = ALNSSolver::new(instance, start);
+        let at = |iteration| SearchContext {
+            elapsed: Duration::ZERO,
+            remaining: None,
+            iteration,
+            stagnant_iterations: 0,
+            max_iterations: Some(1000),
+        };
+
+        // the endgame is the last 5% of the iterations
+        for (iteration, destroy_size) in [(0, 5), (949, 5), (951, 3), (999, 3)] {
+            solver.update_context(&at(iteration));
+            assert_eq!(
+                LNSSolver::destroy_size(&solver),
+                destroy_size,
+                "iteration {iteration}"
+            );
+            let removed = solver.destroy();
+            assert_eq!(removed.len(), destroy_size);
+            solver.update_tabu(&removed);
+            solver.repair(removed).unwrap();
+        }
+    }
+
+    #[test]
+    fn config_files_set_only_their_fields_and_bad_ones_are_refused() {
+        let dir = std::env::temp_dir().join(format!("vrp-alns-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let read = |json: &str| {
+            let path = dir.join("alns.json");
+            std::fs::write(&path, json).unwrap();
+            ALNSConfig::from_file(path.to_str().unwrap())
+        };
+
+        let (config, fields) = read(r#"{"replace_and_cascade": false}"#).unwrap();
+        assert_eq!(
+            config,
+            ALNSConfig {
+                replace_and_cascade: false,
+                ..ALNSConfig::default()
+            }
+        );
+        assert_eq!(fields, ["replace_and_cascade"]);
+        assert_eq!(read("{}").unwrap(), (ALNSConfig::default(), vec![]));
+
+        for bad in [
+            r#"{"random_removal": false}"#,
+            r#"{"endgame_fraction": 1.5}"#,
+            r#"{"random_spot_probability": -0.1}"#,
+            r#"{"best_insertoin": false}"#,
+            r#"{"best_insertion": "no"}"#,
+        ] {
+            assert!(read(bad).is_err(), "{bad}");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn with_one_operator_of_each_kind_left_only_those_are_used() {
+        let instance = Arc::new(generate::random_instance(40, 50, 1993));
+        let only_random_insertion = ALNSConfig {
+            best_insertion: false,
+            replace_and_cascade: false,
+            ..ALNSConfig::default()
+        };
+        let (sol, stats) = solve(&instance, 300, only_random_insertion);
+        sol.validate(&instance).unwrap();
+        assert_eq!(
+            stats.operators.keys().copied().collect::<Vec<_>>(),
+            ["random_insertion", "random_removal"]
+        );
+        // random removal is the only destroy operator, so it's used once an iteration
+        assert_eq!(stats.operators["random_removal"].used, 300);
+        assert_eq!(stats.total_iterations, 300);
+        // and every customer it removes is put back at random
+        let moved = stats.cust_change_freq.values().sum::<usize>();
+        assert_eq!(stats.operators["random_insertion"].used, moved);
+    }
+
+    #[test]
+    fn failed_repairs_are_counted_against_the_operator_that_failed() {
+        // every vehicle filled to the brim, so removed customers often can't all go back in
+        let mut rng = StdRng::seed_from_u64(1988);
+        let (mut demands, mut xs, mut ys, mut routes) = (vec![0], vec![50.0], vec![50.0], vec![]);
+        for _ in 0..8 {
+            let mut route = vec![];
+            let mut left = 20;
+            while left > 0 {
+                let demand = rng.random_range(1..=left.min(9));
+                left -= demand;
+                route.push(u32::try_from(demands.len()).unwrap());
+                demands.push(demand);
+                xs.push(rng.random_range(0.0..100.0));
+                ys.push(rng.random_range(0.0..100.0));
+            }
+            routes.push(route);
+        }
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(8, 20, demands, xs, ys));
+        let start = VRPSolution::from_routes_vec(&instance, &routes).unwrap();
+
+        for replace_and_cascade in [false, true] {
+            let params = SolveParams {
+                terminate: TermCond::MaxIters(500),
+                alns: ALNSConfig {
+                    random_spot_probability: 0.0,
+                    replace_and_cascade,
+                    ..ALNSConfig::default()
+                },
+                ..crate::default_params(Duration::ZERO)
+            };
+            random::seed(1988);
+            let (sol, stats) =
+                solver::solve_from_with_stats::<ALNSSolver>(&instance, &params, start.clone());
+            sol.validate(&instance).unwrap();
+            let ops = &stats.operators;
+            let failed_repairs = stats.total_iterations - stats.iterations;
+
+            // one removal per iteration, whether or not its repair went on to fail
+            assert_eq!(ops["random_removal"].used, stats.total_iterations);
+            assert_eq!(ops["random_removal"].failed, 0);
+            let best = ops["best_insertion"];
+            assert!(
+                best.failed > 0,
+                "no insertion failed, so nothing was tested"
+            );
+            assert!(best.used > stats.total_iterations);
+            assert!(!ops.contains_key("random_insertion"));
+            if replace_and_cascade {
+                // every customer best insertion couldn't place got a cascade, and a repair only
+                // fails when one of those does
+                let cascade = ops["replace_and_cascade"];
+                assert_eq!(cascade.used, best.failed);
+                assert_eq!(cascade.failed, failed_repairs);
+            } else {
+                // the first customer that doesn't fit ends the repair
+                assert!(!ops.contains_key("replace_and_cascade"));
+                assert_eq!(best.failed, failed_repairs);
+            }
+        }
+    }
+
+    #[test]
+    fn insertion_pruning_never_changes_the_search() {
+        let unpruned = ALNSConfig {
+            insertion_pruning: false,
+            ..ALNSConfig::default()
+        };
+        let mut pruned_any = false;
+        for (seed, layout) in [
+            (1, Layout::Uniform),
+            (2, Layout::Clustered { k: 4 }),
+            (3, Layout::Mixed { k: 3 }),
+        ] {
+            let instance = Arc::new(generate::generate(40, 5, 100, layout, seed));
+            for (edge_tabu_tenure, objective) in [
+                (0, Objective::TotalDistance),
+                (10, Objective::TotalDistance),
+                (0, Objective::MinMaxDistance),
+            ] {
+                let tweak = |params: &mut SolveParams| {
+                    params.edge_tabu_tenure = edge_tabu_tenure;
+                    params.objective = objective;
+                };
+                let (pruned, stats) = solve_with(&instance, 300, ALNSConfig::default(), tweak);
+                let (scanned, full_stats) = solve_with(&instance, 300, unpruned, tweak);
+                // the whole run, not just one insertion: any different choice would send the
+                // two searches their own ways
+                assert_eq!(
+                    pruned.to_string(),
+                    scanned.to_string(),
+                    "{layout:?} seed {seed}, tenure {edge_tabu_tenure}, {objective:?}"
+                );
+                assert_eq!(full_stats.insertion_scans.pruned, 0);
+                assert_eq!(
+                    stats.insertion_scans.considered,
+                    full_stats.insertion_scans.considered
+                );
+                pruned_any |= stats.insertion_scans.pruned > 0;
+            }
+        }
+        assert!(pruned_any, "nothing was pruned, so nothing was tested");
+    }
+
+    /// How many routes pruning skips on a large clustered instance, and what it saves. Run with
+    /// `cargo test --release -- --ignored --nocapture insertion_pruning_skip_rate`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn insertion_pruning_skip_rate() {
+        let instance = Arc::new(generate::generate(
+            2000,
+            100,
+            200,
+            Layout::Clustered { k: 20 },
+            1957,
+        ));
+        for insertion_pruning in [true, false] {
+            let alns = ALNSConfig {
+                insertion_pruning,
+                ..ALNSConfig::default()
+            };
+            let start = Instant::now();
+            let (sol, stats) = solve(&instance, 5000, alns);
+            let scans = stats.insertion_scans;
+            println!(
+                "pruning {insertion_pruning}: {} of {} routes skipped ({:.1}%), {:.2?}, cost {:.2}",
+                scans.pruned,
+                scans.considered,
+                scans.skip_rate() * 100.0,
+                start.elapsed(),
+                sol.cost()
+            );
+        }
+    }
 }
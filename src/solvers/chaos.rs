@@ -0,0 +1,202 @@
+//! A wrapper that makes another solver fail on purpose, to exercise the solve loop's rarely-hit
+//! paths: repairs that fail, destroys that don't remove anything, and jumps to solutions laid out
+//! differently from the one the solver had. Run by the smoke test.
+
+use std::sync::Arc;
+
+use rand::{Rng, seq::SliceRandom};
+
+use crate::{
+    common::VRPSolution,
+    random,
+    solver::{LNSSolver, SearchContext, SolveParams, stats::SolveStats},
+    vrp_instance::VRPInstance,
+};
+
+/// How often repair fails without trying.
+const REPAIR_FAILURE: f64 = 0.05;
+
+/// How often destroy leaves the solution as it is.
+const EMPTY_DESTROY: f64 = 0.05;
+
+/// How often a jump gets a solution with its routes shuffled and flipped.
+const RESHAPED_JUMP: f64 = 0.05;
+
+/// What `S` does, except when it doesn't. What got injected is counted in the stats' operators:
+/// - `chaos_repair`: every repair, failed if it was made to fail or `S`'s own repair failed. The
+///   solve loop reverts failed repairs without counting an iteration, so the stats' `iterations`
+///   should be `used - failed`.
+/// - `chaos_empty_destroy`: every destroy, failed if it was skipped. Repairing after a skipped
+///   destroy succeeds and changes nothing.
+/// - `chaos_reshaped_jump`: every jump, failed if the solution was reshaped first.
+pub struct ChaosSolver<S: LNSSolver> {
+    inner: S,
+    instance: Arc<VRPInstance>,
+}
+
+impl<S: LNSSolver> ChaosSolver<S> {
+    /// `sol` with its non-empty routes in a random order, each maybe reversed (where that doesn't
+    /// change its cost), and its empty routes dropped.
+    fn reshape(&self, sol: &VRPSolution) -> VRPSolution {
+        let mut rng = random::rng();
+        let mut routes = sol
+            .routes
+            .iter()
+            .filter(|route| !route.stops().is_empty())
+            .map(|route| {
+                let mut cust_nos = route
+                    .stops()
+                    .iter()
+                    .map(|stop| u32::from(stop.cust_no()))
+                    .collect::<Vec<_>>();
+                if route.is_reversible() && rng.random_bool(0.5) {
+                    cust_nos.reverse();
+                }
+                cust_nos
+            })
+            .collect::<Vec<_>>();
+        routes.shuffle(&mut rng);
+        VRPSolution::from_routes_vec(&self.instance, &routes)
+            .expect("the same customers in the same routes are still a solution")
+    }
+}
+
+impl<S: LNSSolver> LNSSolver for ChaosSolver<S> {
+    /// `None` when the destroy was skipped.
+    type DestroyResult = Option<S::DestroyResult>;
+
+    fn new(instance: Arc<VRPInstance>, initial_solution: VRPSolution) -> Self {
+        ChaosSolver {
+            inner: S::new(instance.clone(), initial_solution),
+            instance,
+        }
+    }
+
+    fn current(&self) -> &VRPSolution {
+        self.inner.current()
+    }
+
+    fn destroy(&mut self) -> Self::DestroyResult {
+        let skip = random::rng().random_bool(EMPTY_DESTROY);
+        self.get_stats_mut()
+            .on_operator_used("chaos_empty_destroy", skip);
+        (!skip).then(|| self.inner.destroy())
+    }
+
+    fn repair(&mut self, res: Self::DestroyResult) -> Result<(), String> {
+        let inject = random::rng().random_bool(REPAIR_FAILURE);
+        let res = match res {
+            // the destroyed customers are dropped, which the solve loop has to undo
+            Some(_) if inject => Err("chaos: injected repair failure".to_string()),
+            Some(res) => self.inner.repair(res),
+            None => Ok(()),
+        };
+        self.get_stats_mut()
+            .on_operator_used("chaos_repair", res.is_err());
+        res
+    }
+
+    fn destroy_size(&self) -> usize {
+        self.inner.destroy_size()
+    }
+
+    fn get_stats_mut(&mut self) -> &mut SolveStats {
+        self.inner.get_stats_mut()
+    }
+
+    fn jump_to_solution(&mut self, sol: &VRPSolution) {
+        let reshape = random::rng().random_bool(RESHAPED_JUMP);
+        self.get_stats_mut()
+            .on_operator_used("chaos_reshaped_jump", reshape);
+        if reshape {
+            let reshaped = self.reshape(sol);
+            self.inner.jump_to_solution(&reshaped);
+        } else {
+            self.inner.jump_to_solution(sol);
+        }
+    }
+
+    fn update_tabu(&mut self, res: &Self::DestroyResult) {
+        if let Some(res) = res {
+            self.inner.update_tabu(res);
+        }
+    }
+
+    fn configure(&mut self, params: &SolveParams) {
+        self.inner.configure(params);
+    }
+
+    fn update_context(&mut self, ctx: &SearchContext) {
+        self.inner.update_context(ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        generate,
+        solver::{self, TermCond},
+        solvers::ALNSSolver,
+    };
+
+    #[test]
+    fn the_search_survives_and_counts_every_injected_failure() {
+        let instance = Arc::new(generate::random_instance(40, 50, 2000));
+        for (seed, edge_tabu_tenure) in [(1, 0), (2, 10), (3, 0)] {
+            let params = SolveParams {
+                terminate: TermCond::MaxIters(3000),
+                // run out of patience often, for plenty of jumps
+                patience: 50,
+                edge_tabu_tenure,
+                ..crate::default_params(Duration::ZERO)
+            };
+            random::seed(seed);
+            let initial_solution = (params.constructor)(&instance);
+            let (sol, stats) = solver::solve_from_with_stats::<ChaosSolver<ALNSSolver>>(
+                &instance,
+                &params,
+                initial_solution,
+            );
+            sol.validate(&instance).unwrap();
+
+            let usage = |operator| stats.operators[operator];
+            let (repairs, destroys) = (usage("chaos_repair"), usage("chaos_empty_destroy"));
+            assert_eq!(repairs.used, stats.total_iterations, "seed {seed}");
+            assert_eq!(destroys.used, stats.total_iterations, "seed {seed}");
+            assert_eq!(
+                repairs.used - repairs.failed,
+                stats.iterations,
+                "seed {seed}"
+            );
+            assert!(!stats.restarts.is_empty(), "seed {seed}");
+            for operator in ["chaos_repair", "chaos_empty_destroy", "chaos_reshaped_jump"] {
+                assert!(
+                    usage(operator).failed > 0,
+                    "seed {seed}: no {operator} failures"
+                );
+            }
+            // a skipped destroy is never what makes a repair fail
+            assert!(
+                repairs.failed <= repairs.used - destroys.failed,
+                "seed {seed}"
+            );
+        }
+    }
+
+    #[test]
+    fn reshaping_keeps_the_same_plan() {
+        let instance = Arc::new(generate::random_instance(40, 50, 2000));
+        random::seed(2000);
+        let sol = crate::construct::clarke_wright_and_then_sweep(&instance);
+        let solver = ChaosSolver::<ALNSSolver>::new(instance.clone(), sol.clone());
+        for _ in 0..20 {
+            let reshaped = solver.reshape(&sol);
+            reshaped.validate(&instance).unwrap();
+            assert!((reshaped.cost() - sol.cost()).abs() < 1e-9);
+            assert_eq!(reshaped.canonical_hash(), sol.canonical_hash());
+        }
+    }
+}
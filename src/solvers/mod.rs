@@ -1,7 +1,9 @@
 mod adaptive;
+mod chaos;
 mod multineighbor;
 mod neighbor;
 
-pub use adaptive::ALNSSolver;
+pub use adaptive::{ALNSConfig, ALNSSolver};
+pub use chaos::ChaosSolver;
 pub use multineighbor::MultiLNSSolver;
 pub use neighbor::SimpleLNSSolver;
@@ -1,35 +1,58 @@
 use std::{cmp::Reverse, collections::VecDeque, sync::Arc};
 
-use rand::{Rng, rng, rngs::ThreadRng};
+use rand::Rng;
 
 use crate::common::{Stop, VRPSolution};
-use crate::solver::LNSSolver;
+use crate::ids::{CustomerId, RouteIdx};
+use crate::insertion_cache::InsertionCache;
+use crate::objective::Objective;
+use crate::random::{SeededRng, rng};
 use crate::solver::stats::SolveStats;
+use crate::solver::{LNSSolver, SolveParams};
+use crate::tabu::EdgeTabu;
 use crate::vrp_instance::VRPInstance;
 
+/// How many customers each destroy removes, unless [`SolveParams::destroy_size`] says otherwise.
+const DESTROY_SIZE: usize = 5;
+
 /// An LNS solver which greedily **removes the highest cost stop** from the solution,
 /// **inserting it at the lowest cost location**.
 pub struct MultiLNSSolver {
     instance: Arc<VRPInstance>,
-    stop_tabu: VecDeque<usize>,
-    stop_not_tabu: Vec<usize>,
+    stop_tabu: VecDeque<CustomerId>,
+    stop_not_tabu: Vec<CustomerId>,
     current: VRPSolution,
     stats: SolveStats,
-    rng: ThreadRng,
+    rng: SeededRng,
+    objective: Objective,
+    edge_tabu: EdgeTabu,
+    /// the cheapest solution this solver has reached, for the edge tabu's aspiration
+    best_seen: f64,
+    destroy_size: usize,
+    insertion_cache: InsertionCache,
+    /// the last destroy's result, handed back by the repair so the next destroy can reuse it
+    removed: Vec<(Stop, RouteIdx)>,
 }
 
 impl LNSSolver for MultiLNSSolver {
     /// corresponding to the (`cust_no`, route #) that was removed
-    type DestroyResult = Vec<(Stop, usize)>;
+    type DestroyResult = Vec<(Stop, RouteIdx)>;
 
     fn new(instance: Arc<VRPInstance>, initial_solution: VRPSolution) -> Self {
+        let insertion_cache = InsertionCache::new(instance.num_customers, instance.num_vehicles);
         MultiLNSSolver {
             stop_tabu: VecDeque::new(),
             current: initial_solution,
-            stop_not_tabu: (1..instance.num_customers).collect(),
+            stop_not_tabu: CustomerId::customers(instance.num_customers).collect(),
             instance,
             stats: SolveStats::new(),
-            rng: rand::rng(),
+            rng: rng(),
+            objective: Objective::default(),
+            edge_tabu: EdgeTabu::new(0),
+            best_seen: f64::MAX,
+            destroy_size: DESTROY_SIZE,
+            insertion_cache,
+            removed: Vec::new(),
         }
     }
 
@@ -38,13 +61,14 @@ impl LNSSolver for MultiLNSSolver {
     }
 
     fn destroy(&mut self) -> Self::DestroyResult {
-        let removed_stops = self.remove_n_random_stops(5);
+        let mut removed_stops = std::mem::take(&mut self.removed);
+        self.remove_n_random_stops(self.destroy_size, &mut removed_stops);
 
         for (stop, route_idx) in &removed_stops {
             *self
                 .stats
                 .cust_change_freq
-                .entry(stop.cust_no().into())
+                .entry(stop.cust_no())
                 .or_insert(0) += 1;
             *self.stats.route_remove_freq.entry(*route_idx).or_insert(0) += 1;
         }
@@ -55,25 +79,45 @@ impl LNSSolver for MultiLNSSolver {
         &mut self.stats
     }
 
-    fn repair(&mut self, res: Self::DestroyResult) -> Result<(), String> {
-        let route_idxs = self.reinsert_n_stops_in_best_spots(&res)?;
+    fn destroy_size(&self) -> usize {
+        self.destroy_size
+    }
+
+    fn repair(&mut self, mut res: Self::DestroyResult) -> Result<(), String> {
+        let reinserted = self.reinsert_n_stops_in_best_spots(&mut res);
+        self.removed = res;
+        reinserted?;
+        // drop any routes the destroy emptied and the repair didn't refill
+        self.current.compact();
 
-        for route_idx in route_idxs {
-            *self.stats.route_add_freq.entry(route_idx).or_insert(0) += 1;
+        if self.edge_tabu.is_enabled() {
+            self.best_seen = self.best_seen.min(self.current.cost());
         }
         Ok(())
     }
 
     fn jump_to_solution(&mut self, sol: &VRPSolution) {
         self.current.clone_from(sol);
-        self.stop_not_tabu = (1..self.instance.num_customers).collect();
+        self.stop_not_tabu.clear();
+        self.stop_not_tabu
+            .extend(CustomerId::customers(self.instance.num_customers));
         self.stop_tabu.clear();
+        // rejected moves come back through here too, so keep the edge tabu (and its tenure) going
+    }
+
+    fn configure(&mut self, params: &SolveParams) {
+        self.objective = params.objective;
+        self.edge_tabu = EdgeTabu::new(params.edge_tabu_tenure);
+        if let Some(destroy_size) = params.destroy_size {
+            // up to a tenth of the customers can be tabu, and the rest must be enough to destroy
+            let n = self.instance.num_customers;
+            self.destroy_size = destroy_size.clamp(1, (n - 1 - n / 10).max(1));
+        }
     }
 
     fn update_tabu(&mut self, res: &Self::DestroyResult) {
         for (stop, _) in res {
-            let cust_no = stop.cust_no().into();
-            self.stop_tabu.push_back(cust_no);
+            self.stop_tabu.push_back(stop.cust_no());
         }
 
         while self.stop_tabu.len() > (self.instance.num_customers / 10) {
@@ -86,44 +130,42 @@ impl LNSSolver for MultiLNSSolver {
 }
 
 impl MultiLNSSolver {
-    fn remove_n_random_stops(&mut self, n: usize) -> Vec<(Stop, usize)> {
+    /// Remove `n` random customers that aren't tabu into `res`, which starts out empty. On an
+    /// instance with fewer than `n` of them, all of them are removed.
+    fn remove_n_random_stops(&mut self, n: usize, res: &mut Vec<(Stop, RouteIdx)>) {
         assert!(n > 0);
         self.assert_tabu_sanity();
 
         let sol = &mut self.current;
+        res.clear();
+        self.edge_tabu.tick();
 
-        let mut customer_nos = Vec::new();
         for _ in 0..n {
+            if self.stop_not_tabu.is_empty() {
+                break;
+            }
             let rem_index = self.rng.random_range(0..self.stop_not_tabu.len());
-            customer_nos.push(self.stop_not_tabu.swap_remove(rem_index));
-        }
-        assert!(customer_nos.len() == n);
-
-        let mut res = Vec::new();
-
-        for cust_no in customer_nos {
+            let cust_no = self.stop_not_tabu.swap_remove(rem_index);
             for (route_idx, route) in sol.routes.iter_mut().enumerate() {
-                if let Some(index) = route.index_of_stop(u16::try_from(cust_no).unwrap()) {
+                if let Some(index) = route.index_of_stop(cust_no) {
+                    if self.edge_tabu.is_enabled() {
+                        let (before, after) = (route.gap(index).0, route.gap(index + 1).1);
+                        self.edge_tabu.forbid(before, cust_no);
+                        self.edge_tabu.forbid(cust_no, after);
+                    }
                     let removed_stop = route.remove_stop_at_index(index);
-                    res.push((removed_stop, route_idx));
+                    res.push((removed_stop, RouteIdx(route_idx)));
                     break;
                 }
             }
         }
-        res
     }
 
     #[cfg(debug_assertions)]
     fn assert_tabu_sanity(&self) {
-        let full_tabu = self
-            .stop_tabu
-            .iter()
-            .chain(self.stop_not_tabu.iter())
-            .collect::<Vec<_>>();
-
-        assert!(full_tabu.len() == (self.instance.num_customers - 1));
-        for cust_no in 1..self.instance.num_customers {
-            assert!(full_tabu.contains(&&cust_no));
+        assert!(self.stop_tabu.len() + self.stop_not_tabu.len() == self.instance.num_customers - 1);
+        for cust_no in CustomerId::customers(self.instance.num_customers) {
+            assert!(self.stop_tabu.contains(&cust_no) || self.stop_not_tabu.contains(&cust_no));
         }
     }
     #[cfg(not(debug_assertions))]
@@ -132,48 +174,131 @@ impl MultiLNSSolver {
 
     fn reinsert_n_stops_in_best_spots(
         &mut self,
-        removed_stops: &[(Stop, usize)],
-    ) -> Result<Vec<usize>, String> {
-        let mut res = Vec::new();
-        let mut removed_stops = removed_stops.to_owned();
+        removed_stops: &mut [(Stop, RouteIdx)],
+    ) -> Result<(), String> {
         removed_stops.sort_by_key(|x| Reverse(x.0.capacity()));
-        for (stop, _) in removed_stops {
-            res.push(self.reinsert_in_best_spot(stop)?);
+        for &(stop, _) in &*removed_stops {
+            let route_idx = self.reinsert_in_best_spot(stop)?;
+            *self
+                .stats
+                .route_add_freq
+                .entry(RouteIdx(route_idx))
+                .or_insert(0) += 1;
         }
-        Ok(res)
+        Ok(())
     }
 
     fn reinsert_in_best_spot(&mut self, stop: Stop) -> Result<usize, String> {
         let (mut best_spot_r, mut best_spot_i, mut best_spot_cost_increase) =
             (usize::MAX, usize::MAX, f64::MAX);
-
-        // OPT: use with_capacity here to avoid continuous reallocation on push
-        let mut valid = Vec::with_capacity(self.instance.num_customers);
+        let vehicle_capacity = self.instance.vehicle_capacity;
+        let max_route_cost = match self.objective {
+            Objective::TotalDistance => 0.0,
+            Objective::MinMaxDistance => self.current.max_route_cost(),
+        };
+        // where the stop would go if every non-tabu spot is full
+        let (mut tabu_spot_r, mut tabu_spot_i, mut tabu_spot_cost_increase) =
+            (usize::MAX, usize::MAX, f64::MAX);
+        let partial_cost = if self.edge_tabu.is_enabled() {
+            self.current.cost()
+        } else {
+            0.0
+        };
 
         for (r, route) in self.current.routes.iter().enumerate() {
+            // checking each route's (cached) load is cheaper than keeping the routes sorted by
+            // how much room they have left, even on tight instances where most are full
+            if route.used_capacity() + stop.capacity() > vehicle_capacity {
+                continue;
+            }
+            let weight = self
+                .objective
+                .insertion_weight(route.cost(), max_route_cost);
+            let lower_bound_rules_out =
+                || route.insertion_lower_bound(&stop) * weight - 1e-9 >= best_spot_cost_increase;
+            self.stats.insertion_scans.considered += 1;
+
+            // without the edge tabu a route's best spot only depends on the route itself
+            if !self.edge_tabu.is_enabled() {
+                let best = match self.insertion_cache.get(&stop, r, route) {
+                    Some(best) => best,
+                    None if lower_bound_rules_out() => {
+                        self.stats.insertion_scans.pruned += 1;
+                        continue;
+                    }
+                    None => self.insertion_cache.scan(&stop, r, route),
+                };
+                if let Some((i, increase)) = best {
+                    if increase * weight < best_spot_cost_increase {
+                        (best_spot_r, best_spot_i) = (r, i);
+                        best_spot_cost_increase = increase * weight;
+                    }
+                }
+                continue;
+            }
+
+            // skip the per-position scan when no position here could beat what we already have
+            if lower_bound_rules_out() {
+                self.stats.insertion_scans.pruned += 1;
+                continue;
+            }
+
             for i in 0..=route.stops().len() {
-                let (new_cost, feas) = route.speculative_add_stop(&stop, i);
+                let (new_cost, feasible) = route.speculative_add_stop(&stop, i);
+                if !feasible {
+                    continue;
+                }
 
                 // we want the one that will increase the new cost by the least, so minimize
-                let cost_increase = new_cost - route.cost();
-                if feas {
-                    valid.push((r, i));
+                let cost_increase = (new_cost - route.cost()) * weight;
+                if self.edge_tabu.is_enabled() {
+                    let (before, after) = route.gap(i);
+                    let recreates_tabu_edge = self.edge_tabu.is_tabu(before, stop.cust_no())
+                        || self.edge_tabu.is_tabu(stop.cust_no(), after);
+                    // aspiration: a tabu edge is fine if it could still lead to a new best
+                    let aspires = partial_cost + new_cost - route.cost() < self.best_seen;
+                    if recreates_tabu_edge && !aspires {
+                        if cost_increase < tabu_spot_cost_increase {
+                            (tabu_spot_r, tabu_spot_i) = (r, i);
+                            tabu_spot_cost_increase = cost_increase;
+                        }
+                        continue;
+                    }
                 }
-                if feas && cost_increase < best_spot_cost_increase {
+                if cost_increase < best_spot_cost_increase {
                     (best_spot_r, best_spot_i) = (r, i);
                     best_spot_cost_increase = cost_increase;
                 }
             }
         }
+        if best_spot_r == usize::MAX {
+            (best_spot_r, best_spot_i) = (tabu_spot_r, tabu_spot_i);
+        }
         if best_spot_r == usize::MAX {
             return Err("no place to put customer".to_string());
         }
 
         if rng().random_bool(0.02_f64) {
-            let i = rng().random_range(0..valid.len());
-            (best_spot_r, best_spot_i) = *valid.get(i).unwrap();
+            // only go through the valid spots when we actually need to pick from them, counting
+            // them and going back for the one picked rather than collecting them
+            let valid = || {
+                self.current
+                    .routes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, route)| {
+                        route.used_capacity() + stop.capacity() <= vehicle_capacity
+                    })
+                    .flat_map(|(r, route)| {
+                        (0..=route.stops().len())
+                            .filter(|&i| route.speculative_add_stop(&stop, i).1)
+                            .map(move |i| (r, i))
+                    })
+            };
+            let i = rng().random_range(0..valid().count());
+            (best_spot_r, best_spot_i) = valid().nth(i).unwrap();
         }
-        self.current.routes[best_spot_r].add_stop_to_index(stop, best_spot_i);
+        self.current.add_stop(best_spot_r, stop, best_spot_i);
 
         Ok(best_spot_r)
     }
@@ -3,9 +3,11 @@ use std::{
     sync::Arc,
 };
 
-use rand::{Rng, rng};
+use rand::Rng;
 
 use crate::common::{Stop, VRPSolution};
+use crate::ids::RouteIdx;
+use crate::random::rng;
 use crate::solver::LNSSolver;
 use crate::solver::stats::SolveStats;
 use crate::vrp_instance::VRPInstance;
@@ -22,7 +24,7 @@ pub struct SimpleLNSSolver {
 
 impl LNSSolver for SimpleLNSSolver {
     /// corresponding to the (stop, route #) that was removed
-    type DestroyResult = (Stop, usize);
+    type DestroyResult = (Stop, RouteIdx);
 
     fn new(instance: Arc<VRPInstance>, initial_solution: VRPSolution) -> Self {
         SimpleLNSSolver {
@@ -43,7 +45,7 @@ impl LNSSolver for SimpleLNSSolver {
         *self
             .stats
             .cust_change_freq
-            .entry(stop.cust_no().into())
+            .entry(stop.cust_no())
             .or_insert(0) += 1;
         *self.stats.route_remove_freq.entry(route_idx).or_insert(0) += 1;
         (stop, route_idx)
@@ -55,7 +57,12 @@ impl LNSSolver for SimpleLNSSolver {
 
     fn repair(&mut self, res: Self::DestroyResult) -> Result<(), String> {
         let route_idx = Self::reinsert_in_best_spot(&mut self.current, res.0);
-        *self.stats.route_add_freq.entry(route_idx).or_insert(0) += 1;
+        self.current.compact();
+        *self
+            .stats
+            .route_add_freq
+            .entry(RouteIdx(route_idx))
+            .or_insert(0) += 1;
         Ok(())
     }
 
@@ -76,7 +83,7 @@ impl LNSSolver for SimpleLNSSolver {
 }
 
 impl SimpleLNSSolver {
-    fn remove_random_stop(&mut self) -> (Stop, usize) {
+    fn remove_random_stop(&mut self) -> (Stop, RouteIdx) {
         let tabu = &self.stop_tabu;
         let sol = &mut self.current;
 
@@ -94,18 +101,37 @@ impl SimpleLNSSolver {
             .get(rng().random_range(0..feas_vals.len()))
             .unwrap();
         let res = sol.routes[chosen_spot_r].remove_stop_at_index(chosen_spot_i);
-        (res, chosen_spot_r)
+        (res, RouteIdx(chosen_spot_r))
     }
 
     fn reinsert_in_best_spot(sol: &mut VRPSolution, stop: Stop) -> usize {
+        let ((mut best_spot_r, mut best_spot_i), valid) = Self::best_and_valid_spots(sol, &stop);
+
+        if rng().random_bool(0.02_f64) {
+            let i = rng().random_range(0..valid.len());
+            (best_spot_r, best_spot_i) = *valid.get(i).unwrap();
+        }
+        sol.add_stop(best_spot_r, stop, best_spot_i);
+        best_spot_r
+    }
+
+    /// The cheapest feasible (route, position) for `stop`, and every feasible one.
+    fn best_and_valid_spots(
+        sol: &VRPSolution,
+        stop: &Stop,
+    ) -> ((usize, usize), Vec<(usize, usize)>) {
         let (mut best_spot_r, mut best_spot_i, mut best_spot_cost_increase) =
             (100_000, 100_000, f64::MAX);
 
         let mut valid = Vec::new();
 
         for (r, route) in sol.routes.iter().enumerate() {
+            // no position in a route without room is feasible, so don't work out what each costs
+            if route.used_capacity() + stop.capacity() > route.instance.vehicle_capacity {
+                continue;
+            }
             for i in 0..=route.stops().len() {
-                let (new_cost, feas) = route.speculative_add_stop(&stop, i);
+                let (new_cost, feas) = route.speculative_add_stop(stop, i);
 
                 // we want the one that will increase the new cost by the least, so minimize
                 let cost_increase = new_cost - route.cost();
@@ -118,12 +144,130 @@ impl SimpleLNSSolver {
                 }
             }
         }
+        ((best_spot_r, best_spot_i), valid)
+    }
+}
 
-        if rng().random_bool(0.02_f64) {
-            let i = rng().random_range(0..valid.len());
-            (best_spot_r, best_spot_i) = *valid.get(i).unwrap();
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    /// The cheapest spot and every feasible one, as [`SimpleLNSSolver::best_and_valid_spots`] finds.
+    type Spots = ((usize, usize), Vec<(usize, usize)>);
+
+    /// `vehicles` vehicles of 100, each filled to the brim with customers, and the solution that
+    /// fills them.
+    fn packed(vehicles: usize, seed: u64) -> (Arc<VRPInstance>, VRPSolution) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (mut demands, mut xs, mut ys, mut routes) = (vec![0], vec![50.0], vec![50.0], vec![]);
+        for _ in 0..vehicles {
+            let mut route = vec![];
+            let mut left = 100;
+            while left > 0 {
+                let demand = rng.random_range(1..=left.min(30));
+                left -= demand;
+                route.push(u32::try_from(demands.len()).unwrap());
+                demands.push(demand);
+                xs.push(rng.random_range(0.0..100.0));
+                ys.push(rng.random_range(0.0..100.0));
+            }
+            routes.push(route);
         }
-        sol.routes[best_spot_r].add_stop_to_index(stop, best_spot_i);
-        best_spot_r
+        let instance = Arc::new(VRPInstance::from_parts_unchecked(
+            vehicles, 100, demands, xs, ys,
+        ));
+        let sol = VRPSolution::from_routes_vec(&instance, &routes).unwrap();
+        (instance, sol)
+    }
+
+    /// What [`SimpleLNSSolver::best_and_valid_spots`] finds, but pricing every position of every
+    /// route, full or not.
+    fn unfiltered_spots(sol: &VRPSolution, stop: &Stop) -> Spots {
+        let mut best = ((100_000, 100_000), f64::MAX);
+        let mut valid = Vec::new();
+        for (r, route) in sol.routes.iter().enumerate() {
+            for i in 0..=route.stops().len() {
+                let (new_cost, feas) = route.speculative_add_stop(stop, i);
+                if feas {
+                    valid.push((r, i));
+                    if new_cost - route.cost() < best.1 {
+                        best = ((r, i), new_cost - route.cost());
+                    }
+                }
+            }
+        }
+        (best.0, valid)
+    }
+
+    /// A few customers taken out of `sol` at random, so there's room for them again.
+    fn take_out(sol: &mut VRPSolution, n: usize, rng: &mut StdRng) -> Vec<Stop> {
+        (0..n)
+            .map(|_| {
+                let r = loop {
+                    let r = rng.random_range(0..sol.routes.len());
+                    if !sol.routes[r].stops().is_empty() {
+                        break r;
+                    }
+                };
+                let i = rng.random_range(0..sol.routes[r].stops().len());
+                sol.routes[r].remove_stop_at_index(i)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn skipping_full_routes_finds_the_same_spots() {
+        let mut rng = StdRng::seed_from_u64(2002);
+        let (_, mut sol) = packed(20, 2002);
+        for _ in 0..200 {
+            let mut removed = take_out(&mut sol, 3, &mut rng);
+            removed.sort_by_key(|stop| std::cmp::Reverse(stop.capacity()));
+            for stop in removed {
+                let (best, valid) = SimpleLNSSolver::best_and_valid_spots(&sol, &stop);
+                assert_eq!((best, valid), unfiltered_spots(&sol, &stop));
+                sol.add_stop(best.0, stop, best.1);
+            }
+        }
+        sol.validate(&sol.routes[0].instance).unwrap();
+    }
+
+    /// How many routes a reinsertion skips on a tight instance, and what that saves. Run with
+    /// `cargo test --release -- --ignored --nocapture full_route_skip_rate`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn full_route_skip_rate() {
+        let mut rng = StdRng::seed_from_u64(2002);
+        let (instance, mut sol) = packed(200, 2002);
+        let stops = take_out(&mut sol, 10, &mut rng);
+        let full = sol
+            .routes
+            .iter()
+            .filter(|route| {
+                stops
+                    .iter()
+                    .all(|stop| route.used_capacity() + stop.capacity() > instance.vehicle_capacity)
+            })
+            .count();
+        let time = |scan: &dyn Fn(&VRPSolution, &Stop) -> Spots| {
+            let start = Instant::now();
+            for _ in 0..1000 {
+                for stop in &stops {
+                    std::hint::black_box(scan(&sol, stop));
+                }
+            }
+            start.elapsed()
+        };
+        println!(
+            "{full} of {} routes too full for any of the {} stops; {:.2?} skipping them, {:.2?} \
+             scanning every route",
+            sol.routes.len(),
+            stops.len(),
+            time(&SimpleLNSSolver::best_and_valid_spots),
+            time(&unfiltered_spots),
+        );
     }
 }
@@ -0,0 +1,311 @@
+//! A summary of what an instance looks like, for choosing solver settings before solving it.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{ids::CustomerId, vrp_instance::VRPInstance};
+
+/// Past this many pairs of nodes, the distance percentiles come from an evenly spread sample of
+/// them rather than every one (the mean still uses every pair).
+const MAX_SAMPLED_PAIRS: usize = 1_000_000;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InstanceStats {
+    /// not counting the depot
+    pub customers: usize,
+    pub vehicles: usize,
+    pub capacity: usize,
+    pub demand: Spread,
+    pub total_demand: usize,
+    /// total demand over the whole fleet's capacity, so how full the vehicles have to be on average
+    pub fill_ratio: f64,
+    /// the distances between every two nodes, the depot included
+    pub distance: Spread,
+    /// `None` when the instance only has a distance matrix
+    pub bounding_box: Option<BoundingBox>,
+    /// the depot's mean distance to the customers over `distance.mean`: below 1 when the depot is
+    /// in the middle of them, above 1 when it's off to one side
+    pub depot_centrality: f64,
+}
+
+/// How a set of numbers is spread out.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Spread {
+    pub min: f64,
+    pub p10: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct BoundingBox {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl VRPInstance {
+    /// Summary statistics of the instance, see [`InstanceStats`].
+    pub fn stats(&self) -> InstanceStats {
+        let n = self.num_customers;
+        let customers = &self.demand_of_customer[1..];
+        let total_demand = customers.iter().sum::<usize>();
+        let demand = Spread::of(customers.iter().map(|&d| d as f64).collect(), None);
+
+        let distance = self.distance_spread();
+        let from_depot = CustomerId::customers(n)
+            .map(|c| self.distance_matrix.dist(CustomerId::DEPOT, c))
+            .sum::<f64>()
+            / (n - 1).max(1) as f64;
+
+        InstanceStats {
+            customers: n - 1,
+            vehicles: self.num_vehicles,
+            capacity: self.vehicle_capacity,
+            demand,
+            total_demand,
+            fill_ratio: total_demand as f64 / (self.num_vehicles * self.vehicle_capacity) as f64,
+            distance,
+            bounding_box: self.has_coordinates.then(|| self.bounding_box()),
+            depot_centrality: from_depot / distance.mean,
+        }
+    }
+
+    /// The distances between every two of the depot and customers (both ways round, if they can
+    /// differ).
+    fn distance_spread(&self) -> Spread {
+        let n = self.num_customers;
+        let dm = &self.distance_matrix;
+        let pairs = |a: usize| if self.is_symmetric { a + 1..n } else { 0..n };
+        let num_pairs = if self.is_symmetric {
+            n * (n - 1) / 2
+        } else {
+            n * (n - 1)
+        };
+        let step = num_pairs.div_ceil(MAX_SAMPLED_PAIRS).max(1);
+
+        let (mut sum, mut sample) = (0.0, Vec::with_capacity(num_pairs.min(MAX_SAMPLED_PAIRS)));
+        let mut seen = 0_usize;
+        for a in 0..n {
+            for b in pairs(a).filter(|&b| b != a) {
+                let d = dm.dist(a, b);
+                sum += d;
+                if seen % step == 0 {
+                    sample.push(d);
+                }
+                seen += 1;
+            }
+        }
+        Spread::of(sample, Some(sum / num_pairs.max(1) as f64))
+    }
+
+    /// The smallest box around the depot and customers. Needs coordinates.
+    pub(crate) fn bounding_box(&self) -> BoundingBox {
+        let (xs, ys) = (
+            &self.x_coord_of_customer[..self.num_customers],
+            &self.y_coord_of_customer[..self.num_customers],
+        );
+        let min = |v: &[f64]| v.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = |v: &[f64]| v.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        BoundingBox {
+            min_x: min(xs),
+            min_y: min(ys),
+            max_x: max(xs),
+            max_y: max(ys),
+        }
+    }
+}
+
+impl Spread {
+    /// The spread of `values`, with `mean` instead of their own mean if given (for when they're a
+    /// sample). All zeros when there are no values.
+    fn of(mut values: Vec<f64>, mean: Option<f64>) -> Self {
+        if values.is_empty() {
+            return Spread {
+                min: 0.0,
+                p10: 0.0,
+                median: 0.0,
+                p90: 0.0,
+                max: 0.0,
+                mean: mean.unwrap_or(0.0),
+            };
+        }
+        values.sort_unstable_by(f64::total_cmp);
+        let percentile = |p: usize| values[(values.len() - 1) * p / 100];
+        Spread {
+            min: values[0],
+            p10: percentile(10),
+            median: percentile(50),
+            p90: percentile(90),
+            max: values[values.len() - 1],
+            mean: mean.unwrap_or_else(|| values.iter().sum::<f64>() / values.len() as f64),
+        }
+    }
+}
+
+impl fmt::Display for Spread {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.2} / {:.2} / {:.2} / {:.2} / {:.2}, mean {:.2}",
+            self.min, self.p10, self.median, self.p90, self.max, self.mean
+        )
+    }
+}
+
+impl fmt::Display for InstanceStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "customers         {}", self.customers)?;
+        writeln!(
+            f,
+            "fleet             {} x capacity {}",
+            self.vehicles, self.capacity
+        )?;
+        writeln!(
+            f,
+            "total demand      {} ({:.1}% of the fleet's capacity)",
+            self.total_demand,
+            self.fill_ratio * 100.0
+        )?;
+        writeln!(
+            f,
+            "demand            {} (min / p10 / median / p90 / max)",
+            self.demand
+        )?;
+        writeln!(f, "distance          {} (likewise)", self.distance)?;
+        if let Some(b) = self.bounding_box {
+            writeln!(
+                f,
+                "bounding box      ({}, {}) to ({}, {})",
+                b.min_x, b.min_y, b.max_x, b.max_y
+            )?;
+        }
+        writeln!(f, "depot centrality  {:.2}", self.depot_centrality)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The depot and two customers on a 3-4-5 right triangle.
+    fn triangle() -> VRPInstance {
+        VRPInstance::from_parts_unchecked(
+            2,
+            10,
+            vec![0, 2, 5],
+            vec![0.0, 3.0, 0.0],
+            vec![0.0, 0.0, 4.0],
+        )
+    }
+
+    #[test]
+    fn a_tiny_instance_has_the_stats_worked_out_by_hand() {
+        let stats = triangle().stats();
+        assert_eq!(
+            (stats.customers, stats.vehicles, stats.capacity),
+            (2, 2, 10)
+        );
+        assert_eq!(stats.total_demand, 7);
+        assert_eq!(stats.fill_ratio, 0.35);
+        assert_eq!(
+            stats.demand,
+            Spread {
+                min: 2.0,
+                p10: 2.0,
+                median: 2.0,
+                p90: 2.0,
+                max: 5.0,
+                mean: 3.5,
+            }
+        );
+        assert_eq!(
+            stats.distance,
+            Spread {
+                min: 3.0,
+                p10: 3.0,
+                median: 4.0,
+                p90: 4.0,
+                max: 5.0,
+                mean: 4.0,
+            }
+        );
+        assert_eq!(
+            stats.bounding_box,
+            Some(BoundingBox {
+                min_x: 0.0,
+                min_y: 0.0,
+                max_x: 3.0,
+                max_y: 4.0,
+            })
+        );
+        // 3.5 from the depot, against 4 between any two
+        assert_eq!(stats.depot_centrality, 0.875);
+    }
+
+    #[test]
+    fn one_way_distances_count_both_ways_and_there_is_no_box() {
+        let distances = vec![
+            vec![0.0, 1.0, 2.0],
+            vec![3.0, 0.0, 4.0],
+            vec![5.0, 6.0, 0.0],
+        ];
+        let stats = VRPInstance::from_matrix(1, 10, vec![0, 1, 1], distances).stats();
+        assert_eq!(stats.distance.min, 1.0);
+        assert_eq!(stats.distance.max, 6.0);
+        assert_eq!(stats.distance.mean, 3.5);
+        assert_eq!(stats.bounding_box, None);
+        assert_eq!(stats.depot_centrality, 1.5 / 3.5);
+    }
+
+    #[test]
+    fn sampled_distances_keep_the_exact_mean() {
+        // past a million pairs
+        let instance = crate::generate::random_instance(1_500, 100, 2025);
+        let n = instance.num_customers;
+        let all = (0..n)
+            .flat_map(|a| (a + 1..n).map(move |b| (a, b)))
+            .map(|(a, b)| instance.distance_matrix.dist(a, b))
+            .collect::<Vec<_>>();
+        assert!(all.len() > MAX_SAMPLED_PAIRS);
+        let exact = Spread::of(all, None);
+
+        let sampled = instance.stats().distance;
+        assert!((sampled.mean - exact.mean).abs() < 1e-9 * exact.mean);
+        // the extremes are the sample's, so only within the real ones
+        assert!(sampled.min >= exact.min && sampled.max <= exact.max);
+        for (sampled, exact) in [
+            (sampled.p10, exact.p10),
+            (sampled.median, exact.median),
+            (sampled.p90, exact.p90),
+        ] {
+            assert!(
+                (sampled - exact).abs() < 0.01 * exact,
+                "{sampled} vs {exact}"
+            );
+        }
+    }
+
+    #[test]
+    fn stats_print_as_a_table_and_as_json() {
+        let stats = triangle().stats();
+        let table = stats.to_string();
+        assert!(table.contains("total demand      7 (35.0% of the fleet's capacity)"));
+        assert!(table.contains("distance          3.00 / 3.00 / 4.00 / 4.00 / 5.00, mean 4.00"));
+        assert!(table.contains("bounding box      (0, 0) to (3, 4)"));
+        assert!(table.ends_with("depot centrality  0.88\n"));
+
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["TotalDemand"], 7);
+        assert_eq!(json["Distance"]["Median"], 4.0);
+        assert_eq!(json["BoundingBox"]["MaxY"], 4.0);
+        assert_eq!(json["DepotCentrality"], 0.875);
+    }
+}
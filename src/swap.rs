@@ -27,7 +27,7 @@ pub mod single_swap {
     use crate::{common::VRPSolution, vrp_instance::VRPInstance};
 
     use super::SwapResult;
-    use rand::rng;
+    use crate::random::rng;
 
     #[allow(dead_code)]
     fn naive_random(_sol: &VRPSolution) -> (VRPSolution, SwapResult) {
@@ -40,16 +40,18 @@ pub mod single_swap {
         vrp_instance: &Arc<VRPInstance>,
     ) -> (VRPSolution, Option<SwapResult>) {
         let mut rng = rng();
-        // shuffle routes
-        sol.routes.shuffle(&mut rng);
+        // shuffle the order we look at routes in (without moving them, since their index is their vehicle)
+        let mut route_order = (0..sol.routes.len()).collect::<Vec<_>>();
+        route_order.shuffle(&mut rng);
 
         let mut swap = None;
 
-        'full_loop: for (a_route_i, a_route) in sol.routes.iter().enumerate() {
-            for (b_route_i, b_route) in sol.routes.iter().enumerate() {
-                if a_route_i <= b_route_i {
+        'full_loop: for (a_pos, &a_route_i) in route_order.iter().enumerate() {
+            for (b_pos, &b_route_i) in route_order.iter().enumerate() {
+                if a_pos <= b_pos {
                     continue;
                 }
+                let (a_route, b_route) = (&sol.routes[a_route_i], &sol.routes[b_route_i]);
 
                 let initial_cost = a_route.cost() + b_route.cost();
                 for (a_i, a) in a_route.stops().iter().enumerate() {
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use crate::ids::CustomerId;
+
+/// Edges that were recently broken up by a destroy, which repair shouldn't put straight back.
+/// Edges are undirected, and each stays tabu for `tenure` iterations after it was broken.
+pub struct EdgeTabu {
+    tenure: usize,
+    iteration: usize,
+    /// the iteration each tabu edge stops being tabu
+    expiry: HashMap<(CustomerId, CustomerId), usize>,
+}
+
+impl EdgeTabu {
+    pub fn new(tenure: usize) -> Self {
+        EdgeTabu {
+            tenure,
+            iteration: 0,
+            expiry: HashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tenure > 0
+    }
+
+    /// Move on to the next iteration, forgetting edges whose tenure is up every so often.
+    pub fn tick(&mut self) {
+        self.iteration += 1;
+        if self.iteration % 1024 == 0 {
+            let now = self.iteration;
+            self.expiry.retain(|_, expires| *expires > now);
+        }
+    }
+
+    pub fn forbid(&mut self, a: CustomerId, b: CustomerId) {
+        if self.is_enabled() {
+            self.expiry.insert(key(a, b), self.iteration + self.tenure);
+        }
+    }
+
+    pub fn is_tabu(&self, a: CustomerId, b: CustomerId) -> bool {
+        self.is_enabled()
+            && self
+                .expiry
+                .get(&key(a, b))
+                .is_some_and(|expires| *expires > self.iteration)
+    }
+}
+
+fn key(a: CustomerId, b: CustomerId) -> (CustomerId, CustomerId) {
+    (a.min(b), a.max(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_are_tabu_both_ways_for_their_tenure() {
+        let (a, b, c) = (CustomerId(1), CustomerId(2), CustomerId(3));
+        let mut tabu = EdgeTabu::new(3);
+        tabu.forbid(a, b);
+        for _ in 0..3 {
+            assert!(tabu.is_tabu(a, b) && tabu.is_tabu(b, a));
+            assert!(!tabu.is_tabu(a, c));
+            tabu.tick();
+        }
+        assert!(!tabu.is_tabu(a, b));
+
+        // breaking an edge again starts its tenure over
+        tabu.forbid(a, b);
+        tabu.tick();
+        tabu.forbid(b, a);
+        tabu.tick();
+        tabu.tick();
+        assert!(tabu.is_tabu(a, b));
+    }
+
+    #[test]
+    fn a_tenure_of_zero_is_off() {
+        let mut tabu = EdgeTabu::new(0);
+        assert!(!tabu.is_enabled());
+        tabu.forbid(CustomerId(1), CustomerId(2));
+        assert!(!tabu.is_tabu(CustomerId(1), CustomerId(2)));
+    }
+}
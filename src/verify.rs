@@ -0,0 +1,372 @@
+//! Checking a solution against its instance from scratch: every customer visited exactly once, no
+//! route over capacity, no forbidden edges, and the costs it carries matching what its legs add up
+//! to. Both the solver's own check of what it's about to report and `--check` go through here.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{
+    common::{FORBIDDEN, VRPSolution},
+    error::SolutionViolation,
+    ids::{CustomerId, RouteIdx},
+    vrp_instance::VRPInstance,
+};
+
+/// How far a route's cached cost can be from the recomputed one before it counts as stale.
+pub const DEFAULT_TOLERANCE: f64 = 0.01;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct VerificationReport {
+    /// the total distance of the routes, recomputed from the instance (skipping any customer it
+    /// doesn't have)
+    pub cost: f64,
+    /// everything wrong, empty for a valid solution
+    pub violations: Vec<SolutionViolation>,
+}
+
+impl VerificationReport {
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Everything wrong with `sol` as a solution to `instance`, see [`SolutionViolation`]. A route's
+/// cached cost counts as stale when it's more than `tol` off.
+pub fn verify(sol: &VRPSolution, instance: &VRPInstance, tol: f64) -> VerificationReport {
+    let mut violations = Vec::new();
+    for (r, route) in sol.routes.iter().enumerate() {
+        if route.id() != RouteIdx(r) {
+            violations.push(SolutionViolation::WrongRouteId {
+                index: r,
+                id: route.id(),
+            });
+        }
+        for stop in route.stops() {
+            let c = stop.cust_no();
+            if !c.is_depot()
+                && c.index() < instance.num_customers
+                && stop.capacity() != instance.demand_of_customer[c.index()]
+            {
+                violations.push(SolutionViolation::WrongDemand(c));
+            }
+        }
+        let load = route.stops().iter().map(|s| s.capacity()).sum::<usize>();
+        let (_, cached_load) = route.cached_cost_and_load();
+        if cached_load != load {
+            violations.push(SolutionViolation::StaleLoad {
+                route: r,
+                cached: cached_load,
+                recomputed: load,
+            });
+        }
+    }
+
+    let routes = sol
+        .routes
+        .iter()
+        .map(|r| {
+            let customers = r.stops().iter().map(|s| s.cust_no().0).collect();
+            (CustomerId::DEPOT, customers)
+        })
+        .collect::<Vec<_>>();
+    let checked = check_routes(instance, &[CustomerId::DEPOT], &routes);
+    for (r, (route, cost)) in sol.routes.iter().zip(&checked.route_costs).enumerate() {
+        // the legs to a customer that doesn't exist can't be costed
+        if let Some(cost) = *cost {
+            let (cached_cost, _) = route.cached_cost_and_load();
+            if (cached_cost - cost).abs() > tol {
+                violations.push(SolutionViolation::StaleCost {
+                    route: r,
+                    cached: cached_cost,
+                    recomputed: cost,
+                });
+            }
+        }
+    }
+    violations.extend(checked.violations);
+
+    VerificationReport {
+        cost: checked.cost,
+        violations,
+    }
+}
+
+/// The checks of [`verify`] that only need each route's customers, for routes that didn't come
+/// from a [`VRPSolution`] (which couldn't hold e.g. an over-capacity route). Each route is given
+/// with the depot it starts from, one of `depots`, which is only ever [`CustomerId::DEPOT`] but for
+/// a multi-depot run (see [`crate::multi_depot`]). A depot is never a customer to visit, and a
+/// route goes back to its own depot unless the instance has an end location.
+pub fn verify_routes(
+    instance: &VRPInstance,
+    depots: &[CustomerId],
+    routes: &[(CustomerId, Vec<u32>)],
+) -> VerificationReport {
+    let checked = check_routes(instance, depots, routes);
+    VerificationReport {
+        cost: checked.cost,
+        violations: checked.violations,
+    }
+}
+
+struct CheckedRoutes {
+    /// every route's legs, those to and from unknown customers skipped
+    cost: f64,
+    /// each route's cost, `None` for one visiting a customer the instance doesn't have (whose cost
+    /// can't be known)
+    route_costs: Vec<Option<f64>>,
+    violations: Vec<SolutionViolation>,
+}
+
+fn check_routes(
+    instance: &VRPInstance,
+    depots: &[CustomerId],
+    routes: &[(CustomerId, Vec<u32>)],
+) -> CheckedRoutes {
+    let (n, cap) = (instance.num_customers, instance.vehicle_capacity);
+    let dm = &instance.distance_matrix;
+    let mut violations = Vec::new();
+    if routes.len() > instance.num_vehicles {
+        violations.push(SolutionViolation::TooManyRoutes {
+            routes: routes.len(),
+            vehicles: instance.num_vehicles,
+        });
+    }
+
+    let mut seen = vec![false; n];
+    let (mut total, mut route_costs) = (0.0, Vec::with_capacity(routes.len()));
+    for (r, (depot, route)) in routes.iter().enumerate() {
+        let (mut load, mut known) = (0, true);
+        let mut path = vec![*depot];
+        for &c in route {
+            let c = CustomerId(c);
+            if depots.contains(&c) || c.index() >= n {
+                violations.push(SolutionViolation::UnknownCustomer {
+                    route: r,
+                    customer: c.index(),
+                });
+                known = false;
+                continue;
+            }
+            if std::mem::replace(&mut seen[c.index()], true) {
+                violations.push(SolutionViolation::DuplicateCustomer(c));
+            }
+            load += instance.demand_of_customer[c.index()];
+            path.push(c);
+        }
+        if !route.is_empty() {
+            path.push(if instance.end_location.is_some() {
+                instance.end_node
+            } else {
+                *depot
+            });
+        }
+        let mut cost = 0.0;
+        for leg in path.windows(2) {
+            let (from, to) = (leg[0], leg[1]);
+            let d = dm.dist(from, to);
+            if d >= FORBIDDEN {
+                violations.push(SolutionViolation::ForbiddenEdge { route: r, from, to });
+            }
+            cost += d;
+        }
+        if load > cap {
+            violations.push(SolutionViolation::OverCapacity {
+                route: r,
+                load,
+                cap,
+            });
+        }
+        total += cost;
+        route_costs.push(known.then_some(cost));
+    }
+
+    violations.extend(
+        CustomerId::customers(n)
+            .filter(|c| !seen[c.index()] && !depots.contains(c))
+            .map(SolutionViolation::MissingCustomer),
+    );
+    CheckedRoutes {
+        cost: total,
+        route_costs,
+        violations,
+    }
+}
+
+impl fmt::Display for VerificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_valid() {
+            return write!(f, "valid, cost {:.2}", self.cost);
+        }
+        write!(f, "invalid, cost {:.2}", self.cost)?;
+        for violation in &self.violations {
+            write!(f, "\n    {violation}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::common::Stop;
+
+    /// Customers 3 up, 4 right and 4 down from the depot, with demands 3, 4 and 5.
+    fn three_customers() -> VRPInstance {
+        VRPInstance::from_parts_unchecked(
+            3,
+            10,
+            vec![0, 3, 4, 5],
+            vec![0.0, 0.0, 4.0, 0.0],
+            vec![0.0, 3.0, 0.0, -4.0],
+        )
+    }
+
+    fn solved() -> (Arc<VRPInstance>, VRPSolution) {
+        let instance = Arc::new(three_customers());
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1, 2], vec![3]]).unwrap();
+        (instance, sol)
+    }
+
+    #[test]
+    fn a_valid_solution_reports_its_recomputed_cost() {
+        let (instance, sol) = solved();
+        let report = verify(&sol, &instance, DEFAULT_TOLERANCE);
+        assert!(report.is_valid());
+        // 3 up, 5 across and 4 back, then 4 down and back
+        assert_eq!(report.cost, 20.0);
+        assert_eq!(report.to_string(), "valid, cost 20.00");
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({"Cost": 20.0, "Violations": []})
+        );
+        let routes = sol
+            .to_routes_vec(true)
+            .into_iter()
+            .map(|r| (CustomerId::DEPOT, r));
+        assert_eq!(
+            verify_routes(&instance, &[CustomerId::DEPOT], &routes.collect::<Vec<_>>()),
+            report
+        );
+    }
+
+    #[test]
+    fn wrong_demands_and_route_ids_are_found() {
+        let (instance, mut sol) = solved();
+        sol.routes[0].replace_stop_at_index(Stop::new(1, 1), 0);
+        sol.routes[1].set_id(RouteIdx(5));
+        let report = verify(&sol, &instance, DEFAULT_TOLERANCE);
+        assert_eq!(
+            report.violations,
+            [
+                SolutionViolation::WrongDemand(CustomerId(1)),
+                SolutionViolation::WrongRouteId {
+                    index: 1,
+                    id: RouteIdx(5)
+                },
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "invalid, cost 20.00\n    customer 1 has the wrong demand\n    \
+             route at index 1 has id 5"
+        );
+    }
+
+    #[test]
+    fn costs_come_from_the_instances_own_distances_within_the_tolerance() {
+        let (_, sol) = solved();
+        // the same legs, every one a hundredth of a unit longer one way than the plane says
+        let plane = three_customers();
+        let n = plane.num_customers;
+        let distances = (0..n)
+            .map(|a| {
+                (0..n)
+                    .map(|b| plane.distance_matrix.dist(a, b) + if a < b { 0.01 } else { 0.0 })
+                    .collect()
+            })
+            .collect();
+        let one_way = VRPInstance::from_matrix(3, 10, plane.demand_of_customer.clone(), distances);
+
+        // route 0 gains 0.02 and route 1 0.01
+        let report = verify(&sol, &one_way, 0.015);
+        assert!((report.cost - 20.03).abs() < 1e-9, "{}", report.cost);
+        assert!(matches!(
+            report.violations[..],
+            [SolutionViolation::StaleCost { route: 0, .. }]
+        ));
+        assert!(verify(&sol, &one_way, 0.025).is_valid());
+    }
+
+    #[test]
+    fn forbidden_edges_are_found() {
+        let (_, sol) = solved();
+        let mut forbidden = three_customers();
+        forbidden.forbid_edge(2, 1).unwrap();
+        let routes = sol
+            .to_routes_vec(false)
+            .into_iter()
+            .map(|r| (CustomerId::DEPOT, r));
+        let report = verify_routes(
+            &forbidden,
+            &[CustomerId::DEPOT],
+            &routes.collect::<Vec<_>>(),
+        );
+        assert_eq!(
+            report.violations,
+            [SolutionViolation::ForbiddenEdge {
+                route: 0,
+                from: CustomerId(1),
+                to: CustomerId(2)
+            }]
+        );
+    }
+
+    #[test]
+    fn routes_are_checked_against_their_own_depot() {
+        // a depot at each end of a line, with two customers next to each
+        let instance = VRPInstance::from_parts_unchecked(
+            2,
+            10,
+            vec![0, 1, 2, 0, 3, 4],
+            vec![0.0, 1.0, 2.0, 100.0, 99.0, 98.0],
+            vec![0.0; 6],
+        );
+        let depots = [CustomerId::DEPOT, CustomerId(3)];
+        let report = verify_routes(
+            &instance,
+            &depots,
+            &[(CustomerId::DEPOT, vec![1, 2]), (CustomerId(3), vec![4, 5])],
+        );
+        // 1 + 1 + 2 from each depot, and depot 3 is never missed
+        assert_eq!(
+            report,
+            VerificationReport {
+                cost: 8.0,
+                violations: vec![]
+            }
+        );
+
+        // the same customers from the other depot cost the trip across, and a depot can't be
+        // visited on the way
+        let report = verify_routes(
+            &instance,
+            &depots,
+            &[
+                (CustomerId::DEPOT, vec![4, 5]),
+                (CustomerId(3), vec![2, 3, 1]),
+            ],
+        );
+        assert_eq!(
+            report.violations,
+            [SolutionViolation::UnknownCustomer {
+                route: 1,
+                customer: 3
+            }]
+        );
+        assert_eq!(report.cost, (99.0 + 1.0 + 98.0) + (98.0 + 1.0 + 99.0));
+    }
+}
@@ -1,10 +1,252 @@
+use std::collections::HashSet;
+use std::f64::consts::TAU;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
-use std::process;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use crate::common::DistanceMatrix;
+use flate2::bufread::MultiGzDecoder;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+
+use crate::common::{DistanceMatrix, DistancePrecision};
 use crate::dbg_println;
+use crate::distance_cache;
+use crate::error::{InstanceError, InstanceViolation, VrpError};
+use crate::ids::CustomerId;
+use crate::output;
+
+/// What an instance file says, with the depot first.
+struct ParsedInstance {
+    num_vehicles: usize,
+    vehicle_capacity: usize,
+    demands: Vec<usize>,
+    xs: Vec<f64>,
+    ys: Vec<f64>,
+    /// given explicitly instead of worked out from the coordinates, which are then all 0
+    distances: Option<Vec<Vec<f64>>>,
+    customer_ids: Option<Vec<String>>,
+    /// how long is spent at each customer, when the file says
+    service_times: Option<Vec<f64>>,
+}
+
+impl ParsedInstance {
+    /// Move the row at `depot` to the front, for files that don't put the depot first, keeping
+    /// every row's original index as its customer id.
+    fn move_depot_first(&mut self, depot: usize) -> Result<(), String> {
+        let rows = self.demands.len();
+        if depot >= rows {
+            return Err(format!(
+                "the depot is row {depot}, but there are only rows 0 to {}",
+                rows - 1
+            ));
+        }
+        let order = std::iter::once(depot)
+            .chain((0..rows).filter(|&row| row != depot))
+            .collect::<Vec<_>>();
+        let reorder = |values: &[f64]| order.iter().map(|&row| values[row]).collect();
+        self.xs = reorder(&self.xs);
+        self.ys = reorder(&self.ys);
+        self.demands = order.iter().map(|&row| self.demands[row]).collect();
+        self.service_times = self.service_times.as_deref().map(reorder);
+        self.customer_ids = Some(order.iter().map(ToString::to_string).collect());
+        Ok(())
+    }
+}
+
+/// What [`VRPInstance::from_json`] reads.
+#[derive(Deserialize)]
+struct JsonInstance {
+    depot: JsonPoint,
+    vehicles: JsonFleet,
+    customers: Vec<JsonCustomer>,
+}
+
+#[derive(Deserialize)]
+struct JsonPoint {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Deserialize)]
+struct JsonFleet {
+    count: usize,
+    capacity: usize,
+}
+
+#[derive(Deserialize)]
+struct JsonCustomer {
+    id: serde_json::Value,
+    x: f64,
+    y: f64,
+    demand: usize,
+    #[serde(default)]
+    service_time: f64,
+}
+
+/// How distances are rounded when the distance matrix is built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum DistanceRounding {
+    #[default]
+    Exact,
+    /// to the nearest whole number, as in TSPLIB's `EUC_2D` and so CVRPLIB's published costs
+    Nearest,
+    /// down to a whole number
+    Truncate,
+}
+
+impl DistanceRounding {
+    #[inline]
+    pub fn apply(self, distance: f64) -> f64 {
+        match self {
+            DistanceRounding::Exact => distance,
+            DistanceRounding::Nearest => distance.round(),
+            DistanceRounding::Truncate => distance.trunc(),
+        }
+    }
+}
+
+impl FromStr for DistanceRounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(DistanceRounding::Exact),
+            "nearest" => Ok(DistanceRounding::Nearest),
+            "truncate" => Ok(DistanceRounding::Truncate),
+            _ => Err(format!(
+                "invalid rounding {s:?}, expected exact, nearest or truncate"
+            )),
+        }
+    }
+}
+
+/// How the distance between two points is worked out from their coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum Metric {
+    #[default]
+    Euclidean,
+    /// `|dx| + |dy|`, for street grids
+    Manhattan,
+    /// the great-circle distance in kilometres, with `x` the longitude and `y` the latitude in
+    /// degrees
+    Haversine,
+}
+
+impl Metric {
+    #[inline]
+    pub fn distance(self, (x1, y1): (f64, f64), (x2, y2): (f64, f64)) -> f64 {
+        match self {
+            Metric::Euclidean => ((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt(),
+            Metric::Manhattan => (x1 - x2).abs() + (y1 - y2).abs(),
+            Metric::Haversine => {
+                let (lat1, lat2) = (y1.to_radians(), y2.to_radians());
+                let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+                    + lat1.cos() * lat2.cos() * ((x2 - x1).to_radians() / 2.0).sin().powi(2);
+                // clamped since rounding can put antipodal points a hair over 1
+                2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+            }
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "euclidean" => Ok(Metric::Euclidean),
+            "manhattan" => Ok(Metric::Manhattan),
+            "haversine" => Ok(Metric::Haversine),
+            _ => Err(format!(
+                "invalid metric {s:?}, expected euclidean, manhattan or haversine"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Metric::Euclidean => write!(f, "euclidean"),
+            Metric::Manhattan => write!(f, "manhattan"),
+            Metric::Haversine => write!(f, "haversine"),
+        }
+    }
+}
+
+/// The Earth's mean radius, for [`Metric::Haversine`].
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// How [`VRPInstance::new`] reads an instance and works out its distances.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadOptions {
+    /// reject a depot with nonzero demand, instead of warning and treating its demand as 0
+    pub strict: bool,
+    /// which row of the customer table is the depot (counting from 0) in our own and Solomon's
+    /// formats, moved to the front to be customer 0 if it isn't there already
+    pub depot: usize,
+    /// only for coordinates; explicit distances can't be given any other metric than the default
+    pub metric: Metric,
+    /// applied to explicit distances too
+    pub rounding: DistanceRounding,
+    /// [`DistancePrecision::F32`] is used right from the start, so there's never a full `f64`
+    /// matrix computed from coordinates
+    pub precision: DistancePrecision,
+    /// keep the distance matrix worked out from the coordinates next to the file, and use it
+    /// instead of working it out again while the file and the options stay the same (see
+    /// [`crate::distance_cache`]); only [`VRPInstance::new`] has a file to keep it next to
+    pub distance_cache: bool,
+    /// work out the distances between more nodes than this (the default
+    /// [`DEFAULT_MATRIX_LIMIT`] if `None`) from the coordinates whenever they're needed, instead
+    /// of keeping a matrix of them
+    pub matrix_limit: Option<usize>,
+}
+
+impl ReadOptions {
+    fn matrix_limit(&self) -> usize {
+        self.matrix_limit.unwrap_or(DEFAULT_MATRIX_LIMIT)
+    }
+}
+
+/// What every gzip stream starts with.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// How far [`VRPInstance::anonymize`] moves each point, as a fraction of the instance's extent.
+const ANONYMIZE_JITTER: f64 = 1e-3;
+
+/// How many customers [`VRPInstance::nearest`] lists for each node, unless
+/// [`VRPInstance::with_num_neighbors`] says otherwise.
+pub const DEFAULT_NUM_NEIGHBORS: usize = 20;
+
+/// How many nodes' distances are kept in a matrix by default: 1.6 GB of them as an `f64`
+/// triangle. Past that they're computed as they're needed, see [`ReadOptions::matrix_limit`].
+pub const DEFAULT_MATRIX_LIMIT: usize = 20_000;
+
+/// How [`VRPInstance::normalized`] moved and scaled the coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScaleInfo {
+    /// taken off every x coordinate before scaling
+    pub min_x: f64,
+    /// taken off every y coordinate before scaling
+    pub min_y: f64,
+    /// how long one unit of distance in the normalized instance is in the original
+    pub scale: f64,
+}
+
+impl ScaleInfo {
+    /// A distance (or cost, or service time) in the normalized instance, in the original's units.
+    pub fn to_original(self, distance: f64) -> f64 {
+        distance * self.scale
+    }
+
+    /// A distance in the original instance's units, in the normalized instance's.
+    pub fn to_normalized(self, distance: f64) -> f64 {
+        distance / self.scale
+    }
+}
 
 pub struct VRPInstance {
     pub num_customers: usize,
@@ -13,86 +255,888 @@ pub struct VRPInstance {
     pub demand_of_customer: Vec<usize>,
     pub x_coord_of_customer: Vec<f64>,
     pub y_coord_of_customer: Vec<f64>,
+    /// how long is spent at each customer, on top of the travel to it; 0 unless the file says
+    pub service_time_of_customer: Vec<f64>,
+    /// each customer's angle around the depot, which the sweep constructor orders them by
+    pub polar_angle_of_customer: Vec<f64>,
     pub distance_matrix: DistanceMatrix,
     pub max_route_len: usize,
+    /// where every route finishes, if that isn't back at the depot
+    pub end_location: Option<(f64, f64)>,
+    /// the distance matrix index routes end at: the depot (0), or the extra node just past the
+    /// last customer when there's an `end_location`
+    pub end_node: CustomerId,
+    /// edges no route may use, as pairs of distance matrix indices
+    pub forbidden_edges: Vec<(u32, u32)>,
+    /// what distances are measured in (e.g. "m"), only used when reporting
+    pub distance_unit: Option<String>,
+    /// what a unit of distance costs, for reporting costs in money as well as distance
+    pub cost_per_unit: Option<f64>,
+    /// false when the distances were given explicitly, in which case the coordinates are all 0
+    /// and only the distance matrix means anything
+    pub has_coordinates: bool,
+    /// whether every distance is the same both ways, which it always is between coordinates but
+    /// needn't be in a matrix given explicitly (e.g. travel times with one-way streets)
+    pub is_symmetric: bool,
+    pub distance_rounding: DistanceRounding,
+    /// how distances were worked out from the coordinates, meaningless without them
+    pub metric: Metric,
+    /// the caller's id for each customer number, from a JSON instance (the depot's is `depot`) or
+    /// a file whose depot wasn't its first row (each customer's row in the file)
+    pub customer_ids: Option<Vec<String>>,
+    /// the row of the instance file the depot was read from (see [`ReadOptions::depot`]), which the
+    /// rest follow in their order in the file; `Solution` strings and `.sol` files give customers
+    /// by their rows (see [`VRPInstance::file_row`])
+    pub depot: usize,
+    /// the customers nearest each node (the depot included), nearest first; see
+    /// [`VRPInstance::nearest`]
+    pub neighbors: Vec<Vec<u32>>,
 }
 
 impl VRPInstance {
-    pub fn new<P: AsRef<Path>>(file_name: P) -> Self {
-        let lines = Self::read_lines_from_file(&file_name);
-
-        let (num_customers, num_vehicles, vehicle_capacity) =
-            Self::parse_first_line(&lines[0], &file_name);
+    /// Read an instance file, in our own format, TSPLIB's (as used by CVRPLIB), Solomon's or JSON
+    /// (see [`VRPInstance::from_json`]), gzipped or not, as `options` say.
+    pub fn new<P: AsRef<Path>>(file_name: P, options: ReadOptions) -> Result<Self, InstanceError> {
+        let mut file = File::open(&file_name).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => InstanceError::FileNotFound,
+            _ => InstanceError::Unreadable(e.to_string()),
+        })?;
+        let name = file_name.as_ref().to_string_lossy();
+        if !options.distance_cache {
+            return Self::read(file, &name, options, None);
+        }
 
-        // Initialize arrays for customer data
-        let mut demand_of_customer = vec![0; num_customers];
-        let mut x_coord_of_customer = vec![0.0; num_customers];
-        let mut y_coord_of_customer = vec![0.0; num_customers];
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)
+            .map_err(|e| InstanceError::Unreadable(e.to_string()))?;
+        let cache_path = distance_cache::path_for(file_name.as_ref());
+        let key = distance_cache::key(&contents, options);
+        let cached = distance_cache::load(&cache_path, key).unwrap_or_else(|e| {
+            eprintln!(
+                "Warning: ignoring the saved distances in {}: {e}",
+                cache_path.display()
+            );
+            None
+        });
+        let was_cached = cached.is_some();
+        let instance = Self::read(contents.as_slice(), &name, options, cached)?;
+        if !was_cached && instance.has_coordinates && !instance.distance_matrix.is_computed() {
+            if let Err(e) = distance_cache::store(&cache_path, key, &instance.distance_matrix) {
+                eprintln!(
+                    "Warning: couldn't save the distances to {}: {e}",
+                    cache_path.display()
+                );
+            }
+        }
+        Ok(instance)
+    }
 
-        // Parse customer data
-        for i in 0..num_customers {
-            if i + 1 < lines.len() {
-                let customer_data: Vec<&str> = lines[i + 1].split_whitespace().collect();
-                if customer_data.len() < 3 {
-                    eprintln!(
-                        "Error: in VRPInstance() {}\nInvalid customer data format at line {}",
-                        file_name.as_ref().display(),
-                        i + 2
-                    );
-                    process::exit(-1);
-                }
+    /// [`VRPInstance::new`] for an instance that isn't in a file, e.g. one piped in on stdin.
+    /// `name` is only used in warnings and, like a file name, for a TSPLIB instance's number of
+    /// vehicles if nothing else gives it.
+    pub fn from_reader(
+        reader: impl Read,
+        name: &str,
+        options: ReadOptions,
+    ) -> Result<Self, InstanceError> {
+        Self::read(reader, name, options, None)
+    }
 
-                demand_of_customer[i] = customer_data[0].parse::<usize>().unwrap_or_else(|e| {
-                    eprintln!("Error parsing customer demand: {e}");
-                    process::exit(-1);
-                });
+    /// [`VRPInstance::from_reader`], with the `distances` between the coordinates if they're
+    /// already known.
+    fn read(
+        reader: impl Read,
+        name: &str,
+        options: ReadOptions,
+        distances: Option<DistanceMatrix>,
+    ) -> Result<Self, InstanceError> {
+        let ReadOptions { strict, depot, .. } = options;
+        let lines = Self::read_lines(reader)?;
 
-                x_coord_of_customer[i] = customer_data[1].parse::<f64>().unwrap_or_else(|e| {
-                    eprintln!("Error parsing x coordinate: {e}");
-                    process::exit(-1);
-                });
+        if depot != 0 && (Self::is_json(&lines) || Self::is_tsplib(&lines)) {
+            return Err(InstanceError::Format(
+                "only our own and Solomon's formats have a depot row to choose; TSPLIB files name \
+                 the depot in a DEPOT_SECTION, and JSON ones give it separately"
+                    .to_string(),
+            ));
+        }
+        if Self::is_json(&lines) {
+            return Self::from_parsed(
+                Self::parse_json(&lines.join("\n")).map_err(InstanceError::Format)?,
+                options,
+                distances,
+            );
+        }
+        let native = !Self::is_tsplib(&lines) && !Self::is_solomon(&lines);
+        let mut parsed = if native {
+            Self::parse_native(&lines)?
+        } else if Self::is_tsplib(&lines) {
+            Self::parse_tsplib(&lines, name).map_err(InstanceError::Format)?
+        } else {
+            Self::parse_solomon(&lines).map_err(InstanceError::Format)?
+        };
+        if depot != 0 {
+            parsed
+                .move_depot_first(depot)
+                .map_err(InstanceError::Format)?;
+        }
 
-                y_coord_of_customer[i] = customer_data[2].parse::<f64>().unwrap_or_else(|e| {
-                    eprintln!("Error parsing y coordinate: {e}");
-                    process::exit(-1);
-                });
+        if let Some(&demand) = parsed.demands.first().filter(|&&d| d != 0) {
+            // the hint that the depot row is missing only makes sense if it's meant to be first
+            let err = InstanceError::DepotHasDemand {
+                demand,
+                native: native && depot == 0,
+            };
+            if strict {
+                return Err(err);
             }
+            eprintln!("Warning: in VRPInstance() {name}\n{err}; treating its demand as 0");
+            parsed.demands[0] = 0;
         }
 
         // Print customer data
-        for i in 0..num_customers {
-            dbg_println!(
-                "{} {} {}",
-                demand_of_customer[i],
-                x_coord_of_customer[i],
-                y_coord_of_customer[i]
-            );
+        for i in 0..parsed.demands.len() {
+            dbg_println!("{} {} {}", parsed.demands[i], parsed.xs[i], parsed.ys[i]);
         }
 
-        let distance_matrix: Vec<Vec<f64>> = (0..num_customers)
-            .map(|i| {
-                (0..num_customers)
-                    .map(|j| {
-                        ((x_coord_of_customer[i] - x_coord_of_customer[j]).powi(2)
-                            + (y_coord_of_customer[i] - y_coord_of_customer[j]).powi(2))
-                        .sqrt()
-                    })
-                    .collect()
-            })
-            .collect();
+        Self::from_parsed(parsed, options, distances)
+    }
+
+    /// Read an instance in the JSON a dispatch system exports:
+    /// `{"depot": {"x", "y"}, "vehicles": {"count", "capacity"}, "customers": [{"id", "x", "y",
+    /// "demand", "service_time"?}, ...]}`. Customers are numbered from 1 in the order they're listed, and their ids
+    /// (strings or numbers, each used once) are kept as `customer_ids` for reporting. Files in this
+    /// format can be passed to [`VRPInstance::new`] too.
+    pub fn from_json(mut reader: impl Read) -> Result<Self, InstanceError> {
+        let mut text = String::new();
+        reader
+            .read_to_string(&mut text)
+            .map_err(|e| InstanceError::Unreadable(e.to_string()))?;
+        Self::from_parsed(
+            Self::parse_json(&text).map_err(InstanceError::Format)?,
+            ReadOptions::default(),
+            None,
+        )
+    }
+
+    /// The instance `parsed` describes, with `known_distances` between its coordinates if they've
+    /// already been worked out with these options.
+    fn from_parsed(
+        parsed: ParsedInstance,
+        options: ReadOptions,
+        known_distances: Option<DistanceMatrix>,
+    ) -> Result<Self, InstanceError> {
+        let ReadOptions {
+            rounding,
+            metric,
+            precision,
+            ..
+        } = options;
+        let ParsedInstance {
+            num_vehicles,
+            vehicle_capacity,
+            demands,
+            xs,
+            ys,
+            distances,
+            customer_ids,
+            service_times,
+        } = parsed;
+        if let Some((customer, time)) = service_times
+            .iter()
+            .flatten()
+            .enumerate()
+            .find(|(_, time)| !(time.is_finite() && **time >= 0.0))
+        {
+            return Err(InstanceError::Format(format!(
+                "customer {customer} has service time {time}, expected a non-negative number"
+            )));
+        }
+        let instance = match distances {
+            Some(_) if metric != Metric::default() => {
+                return Err(InstanceError::Distances(VrpError::NoCoordinates));
+            }
+            Some(mut distances) => {
+                for d in distances.iter_mut().flatten() {
+                    *d = rounding.apply(*d);
+                }
+                VRPInstance {
+                    distance_rounding: rounding,
+                    ..Self::from_matrix(num_vehicles, vehicle_capacity, demands, distances)
+                }
+                .with_distance_precision(precision)
+            }
+            None => {
+                let computed = xs.len() > options.matrix_limit();
+                let distance_matrix = known_distances
+                    .filter(|known| known.len() == xs.len() && !computed)
+                    .unwrap_or_else(|| {
+                        Self::distance_matrix(&xs, &ys, None, rounding, metric, precision, computed)
+                    });
+                VRPInstance {
+                    distance_rounding: rounding,
+                    metric,
+                    ..Self::from_coordinates(
+                        num_vehicles,
+                        vehicle_capacity,
+                        demands,
+                        xs,
+                        ys,
+                        distance_matrix,
+                    )
+                }
+            }
+        };
+        instance
+            .distance_matrix
+            .check()
+            .map_err(InstanceError::Distances)?;
+        Ok(VRPInstance {
+            customer_ids,
+            depot: options.depot,
+            service_time_of_customer: service_times
+                .unwrap_or_else(|| vec![0.0; instance.num_customers]),
+            ..instance
+        })
+    }
+
+    /// Build an instance straight from its customer data (depot at index 0), computing the
+    /// distance matrix and `max_route_len` the same way as when reading from a file. The three
+    /// vectors have to be the same, nonzero, length, and the depot has to have demand 0.
+    pub fn from_parts(
+        num_vehicles: usize,
+        vehicle_capacity: usize,
+        demands: Vec<usize>,
+        xs: Vec<f64>,
+        ys: Vec<f64>,
+    ) -> Result<Self, InstanceError> {
+        if demands.is_empty() || xs.len() != demands.len() || ys.len() != demands.len() {
+            return Err(InstanceError::MismatchedParts {
+                demands: demands.len(),
+                xs: xs.len(),
+                ys: ys.len(),
+            });
+        }
+        if demands[0] != 0 {
+            return Err(InstanceError::DepotHasDemand {
+                demand: demands[0],
+                native: false,
+            });
+        }
+        let instance = Self::from_parts_unchecked(num_vehicles, vehicle_capacity, demands, xs, ys);
+        instance
+            .distance_matrix
+            .check()
+            .map_err(InstanceError::Distances)?;
+        Ok(instance)
+    }
+
+    /// [`VRPInstance::from_parts`] without its checks, for data that's known to be good.
+    pub fn from_parts_unchecked(
+        num_vehicles: usize,
+        vehicle_capacity: usize,
+        demand_of_customer: Vec<usize>,
+        x_coord_of_customer: Vec<f64>,
+        y_coord_of_customer: Vec<f64>,
+    ) -> Self {
+        let distance_matrix = Self::distance_matrix(
+            &x_coord_of_customer,
+            &y_coord_of_customer,
+            None,
+            DistanceRounding::Exact,
+            Metric::Euclidean,
+            DistancePrecision::F64,
+            x_coord_of_customer.len() > DEFAULT_MATRIX_LIMIT,
+        );
+        Self::from_coordinates(
+            num_vehicles,
+            vehicle_capacity,
+            demand_of_customer,
+            x_coord_of_customer,
+            y_coord_of_customer,
+            distance_matrix,
+        )
+    }
+
+    /// An instance with `distance_matrix` between its coordinates, which is taken to be exact and
+    /// Euclidean unless the caller says otherwise.
+    fn from_coordinates(
+        num_vehicles: usize,
+        vehicle_capacity: usize,
+        demand_of_customer: Vec<usize>,
+        x_coord_of_customer: Vec<f64>,
+        y_coord_of_customer: Vec<f64>,
+        distance_matrix: DistanceMatrix,
+    ) -> Self {
+        let num_customers = demand_of_customer.len();
 
         VRPInstance {
             num_customers,
             num_vehicles,
             vehicle_capacity,
             max_route_len: Self::max_route_len(&demand_of_customer, vehicle_capacity),
+            service_time_of_customer: vec![0.0; num_customers],
+            polar_angle_of_customer: Self::polar_angles(&x_coord_of_customer, &y_coord_of_customer),
             demand_of_customer,
             x_coord_of_customer,
             y_coord_of_customer,
-            distance_matrix: DistanceMatrix::new(distance_matrix),
+            neighbors: Self::nearest_neighbors(
+                &distance_matrix,
+                num_customers,
+                DEFAULT_NUM_NEIGHBORS,
+            ),
+            distance_matrix,
+            end_location: None,
+            end_node: CustomerId::DEPOT,
+            forbidden_edges: Vec::new(),
+            distance_unit: None,
+            cost_per_unit: None,
+            has_coordinates: true,
+            is_symmetric: true,
+            distance_rounding: DistanceRounding::Exact,
+            metric: Metric::Euclidean,
+            customer_ids: None,
+            depot: 0,
         }
     }
 
+    /// Build an instance from its demands (depot at index 0) and the distances between every pair
+    /// of them, e.g. travel times over a road network. It has no coordinates, so the sweep
+    /// constructor, decomposition, end locations and anonymizing aren't available.
+    pub fn from_matrix(
+        num_vehicles: usize,
+        vehicle_capacity: usize,
+        demand_of_customer: Vec<usize>,
+        distances: Vec<Vec<f64>>,
+    ) -> Self {
+        let num_customers = demand_of_customer.len();
+        // the distance matrix skips bounds checks, so it has to really be square
+        assert!(
+            distances.len() == num_customers
+                && distances.iter().all(|row| row.len() == num_customers),
+            "the distance matrix has to be {num_customers}x{num_customers}"
+        );
+        let is_symmetric =
+            (0..num_customers).all(|i| (0..i).all(|j| distances[i][j] == distances[j][i]));
+        let distance_matrix = DistanceMatrix::new(distances);
+
+        VRPInstance {
+            num_customers,
+            num_vehicles,
+            vehicle_capacity,
+            max_route_len: Self::max_route_len(&demand_of_customer, vehicle_capacity),
+            demand_of_customer,
+            x_coord_of_customer: vec![0.0; num_customers],
+            y_coord_of_customer: vec![0.0; num_customers],
+            service_time_of_customer: vec![0.0; num_customers],
+            polar_angle_of_customer: vec![0.0; num_customers],
+            neighbors: Self::nearest_neighbors(
+                &distance_matrix,
+                num_customers,
+                DEFAULT_NUM_NEIGHBORS,
+            ),
+            distance_matrix,
+            end_location: None,
+            end_node: CustomerId::DEPOT,
+            forbidden_edges: Vec::new(),
+            distance_unit: None,
+            cost_per_unit: None,
+            has_coordinates: false,
+            is_symmetric,
+            distance_rounding: DistanceRounding::Exact,
+            metric: Metric::Euclidean,
+            customer_ids: None,
+            depot: 0,
+        }
+    }
+
+    /// Check the instance can be solved at all, returning everything that's wrong with it if not.
+    /// Passing doesn't guarantee a solution exists, since the demands might still not pack into
+    /// the vehicles.
+    pub fn validate(&self) -> Result<(), Vec<InstanceViolation>> {
+        let mut violations = Vec::new();
+        let (demands, xs, ys) = (
+            &self.demand_of_customer,
+            &self.x_coord_of_customer,
+            &self.y_coord_of_customer,
+        );
+        if [demands.len(), xs.len(), ys.len()] != [self.num_customers; 3] {
+            // nothing else can be checked customer by customer
+            return Err(vec![InstanceViolation::LengthMismatch {
+                num_customers: self.num_customers,
+                demands: demands.len(),
+                xs: xs.len(),
+                ys: ys.len(),
+            }]);
+        }
+
+        if let Some(&demand) = demands.first().filter(|&&d| d != 0) {
+            violations.push(InstanceViolation::DepotHasDemand(demand));
+        }
+        for customer in CustomerId::customers(self.num_customers) {
+            let demand = demands[customer.index()];
+            if demand > self.vehicle_capacity {
+                violations.push(InstanceViolation::DemandOverCapacity {
+                    customer,
+                    demand,
+                    capacity: self.vehicle_capacity,
+                });
+            }
+        }
+        let total_demand = demands.iter().sum::<usize>();
+        let fleet_capacity = self.num_vehicles * self.vehicle_capacity;
+        if total_demand > fleet_capacity {
+            violations.push(InstanceViolation::FleetTooSmall {
+                total_demand,
+                fleet_capacity,
+            });
+        }
+        let customers = demands.get(1..).unwrap_or_default();
+        let large_demands = customers
+            .iter()
+            .filter(|&&d| 2 * d > self.vehicle_capacity)
+            .count();
+        if large_demands > self.num_vehicles {
+            violations.push(InstanceViolation::TooManyLargeDemands {
+                large_demands,
+                num_vehicles: self.num_vehicles,
+                capacity: self.vehicle_capacity,
+            });
+        }
+        let per_route = Self::most_customers_per_route(customers, self.vehicle_capacity);
+        if customers.len() > per_route * self.num_vehicles {
+            violations.push(InstanceViolation::TooManyCustomers {
+                customers: customers.len(),
+                per_route,
+                num_vehicles: self.num_vehicles,
+            });
+        }
+        for (customer, (&x, &y)) in xs.iter().zip(ys).enumerate() {
+            if !(x.is_finite() && y.is_finite()) {
+                violations.push(InstanceViolation::NonFiniteCoordinate { customer, x, y });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Have every route end at `(x, y)` instead of going back to the depot.
+    pub fn with_end_location(mut self, x: f64, y: f64) -> Result<Self, VrpError> {
+        if !self.has_coordinates {
+            return Err(VrpError::NoCoordinates);
+        }
+        self.end_location = Some((x, y));
+        self.end_node = CustomerId::try_from(self.num_customers).unwrap();
+        self.rebuild_distance_matrix()?;
+        Ok(self)
+    }
+
+    /// Round distances with `rounding` from now on, rebuilding the distance matrix from the
+    /// coordinates.
+    pub fn with_distance_rounding(mut self, rounding: DistanceRounding) -> Result<Self, VrpError> {
+        if rounding == self.distance_rounding {
+            return Ok(self);
+        }
+        if !self.has_coordinates {
+            return Err(VrpError::NoCoordinates);
+        }
+        self.distance_rounding = rounding;
+        self.rebuild_distance_matrix()?;
+        Ok(self)
+    }
+
+    /// Measure distances with `metric` from now on, rebuilding the distance matrix from the
+    /// coordinates.
+    pub fn with_metric(mut self, metric: Metric) -> Result<Self, VrpError> {
+        if metric == self.metric {
+            return Ok(self);
+        }
+        if !self.has_coordinates {
+            return Err(VrpError::NoCoordinates);
+        }
+        self.metric = metric;
+        self.rebuild_distance_matrix()?;
+        Ok(self)
+    }
+
+    /// Store distances at `precision` from now on, converting the ones there are. Going back to
+    /// `F64` doesn't get the lost precision back for explicit distances.
+    pub fn with_distance_precision(mut self, precision: DistancePrecision) -> Self {
+        self.distance_matrix.set_precision(precision);
+        self
+    }
+
+    /// List the `k` nearest customers to each node from now on, instead of
+    /// [`DEFAULT_NUM_NEIGHBORS`].
+    pub fn with_num_neighbors(mut self, k: usize) -> Self {
+        self.neighbors = Self::nearest_neighbors(&self.distance_matrix, self.num_customers, k);
+        self
+    }
+
+    /// Customer `cust_no`'s row in the instance file, which is how `Solution` strings and `.sol`
+    /// files number it: the depot is row [`VRPInstance::depot`], and the customers before it in
+    /// the file are one row down from their numbers.
+    pub fn file_row(&self, cust_no: u32) -> u32 {
+        let depot = u32::try_from(self.depot).unwrap();
+        match cust_no {
+            0 => depot,
+            c if c <= depot => c - 1,
+            c => c,
+        }
+    }
+
+    /// The customer in row `row` of the instance file, undoing [`VRPInstance::file_row`].
+    pub fn customer_of_row(&self, row: u32) -> u32 {
+        let depot = u32::try_from(self.depot).unwrap();
+        match row {
+            r if r == depot => 0,
+            r if r < depot => r + 1,
+            r => r,
+        }
+    }
+
+    /// [`VRPInstance::customer_of_row`] for every stop of `routes`, as read from a `Solution`
+    /// string or `.sol` file.
+    pub fn customers_of_rows(&self, routes: &[Vec<u32>]) -> Vec<Vec<u32>> {
+        routes
+            .iter()
+            .map(|route| route.iter().map(|&row| self.customer_of_row(row)).collect())
+            .collect()
+    }
+
+    /// The customers nearest `cust` (the depot, or any customer), nearest first. Neither the
+    /// depot nor `cust` itself is ever among them.
+    pub fn nearest(&self, cust: u32) -> &[u32] {
+        &self.neighbors[cust as usize]
+    }
+
+    /// The `k` customers nearest each node, by the distance from it to them. Picking them out with
+    /// a partial sort keeps this about as quick as building the distance matrix.
+    fn nearest_neighbors(dm: &DistanceMatrix, num_customers: usize, k: usize) -> Vec<Vec<u32>> {
+        let mut others = Vec::with_capacity(num_customers);
+        (0..num_customers)
+            .map(|i| {
+                others.clear();
+                others.extend(
+                    (1..num_customers)
+                        .filter(|&j| j != i)
+                        .map(|j| (dm.dist(i, j), u32::try_from(j).unwrap())),
+                );
+                let by_distance = |a: &(f64, u32), b: &(f64, u32)| a.0.total_cmp(&b.0);
+                if others.len() > k {
+                    others.select_nth_unstable_by(k, by_distance);
+                    others.truncate(k);
+                }
+                others.sort_unstable_by(by_distance);
+                others.iter().map(|&(_, j)| j).collect()
+            })
+            .collect()
+    }
+
+    /// Work the distance matrix out again from the coordinates, end location, metric, rounding
+    /// and forbidden edges, keeping its precision, and the nearest customers with it. The old
+    /// matrix is freed first so there's only ever one in memory, which leaves the instance without
+    /// distances on an error.
+    fn rebuild_distance_matrix(&mut self) -> Result<(), VrpError> {
+        let precision = self.distance_matrix.precision();
+        let computed = self.distance_matrix.is_computed();
+        self.distance_matrix = DistanceMatrix::new(Vec::new());
+        let mut distance_matrix = Self::distance_matrix(
+            &self.x_coord_of_customer,
+            &self.y_coord_of_customer,
+            self.end_location,
+            self.distance_rounding,
+            self.metric,
+            precision,
+            computed,
+        );
+        for &(a, b) in &self.forbidden_edges {
+            distance_matrix.forbid(a as usize, b as usize);
+        }
+        distance_matrix.check()?;
+        // there are as many neighbors as asked for unless there aren't that many customers, and
+        // the customers haven't changed
+        let k = self.neighbors.first().map_or(0, Vec::len);
+        self.neighbors = Self::nearest_neighbors(&distance_matrix, self.num_customers, k);
+        self.distance_matrix = distance_matrix;
+        Ok(())
+    }
+
+    /// Label distances with `distance_unit` and/or price them at `cost_per_unit` in reports. Neither
+    /// changes what the solver does.
+    pub fn with_cost_units(
+        mut self,
+        distance_unit: Option<String>,
+        cost_per_unit: Option<f64>,
+    ) -> Result<Self, String> {
+        if let Some(rate) = cost_per_unit.filter(|rate| !(rate.is_finite() && *rate >= 0.0)) {
+            return Err(format!(
+                "the cost per unit has to be a non-negative number, not {rate}"
+            ));
+        }
+        self.distance_unit = distance_unit;
+        self.cost_per_unit = cost_per_unit;
+        Ok(self)
+    }
+
+    /// Stop any route from going directly between `a` and `b`, which are distance matrix indices
+    /// (so the end location can be forbidden too, once it's set).
+    pub fn forbid_edge(&mut self, a: u32, b: u32) -> Result<(), VrpError> {
+        let num_nodes = self.num_customers + usize::from(self.end_location.is_some());
+        if let Some(&bad) = [a, b].iter().find(|&&n| n as usize >= num_nodes) {
+            return Err(VrpError::UnknownCustomer(bad as usize));
+        }
+        self.distance_matrix.forbid(a as usize, b as usize);
+        self.forbidden_edges.push((a, b));
+        Ok(())
+    }
+
+    /// A copy of this instance that's safe to share: the coordinates are rotated, maybe mirrored,
+    /// moved and then jittered a little; every demand and the capacity are multiplied by the same
+    /// random whole number, so each demand's share of a vehicle is exactly as before; and the
+    /// customers are shuffled into a new order (the depot stays first). Any end location and
+    /// forbidden edges go along with them.
+    ///
+    /// Distances only change by the jitter, so solution costs are within about
+    /// `2 * ANONYMIZE_JITTER * extent` per edge of the original's, and which routes are feasible
+    /// doesn't change at all. The search won't follow the same trajectory with the same seed
+    /// though, since the customers are visited in a different order and near-ties between moves
+    /// can break the other way; only how well it does should stay about the same.
+    ///
+    /// Instances without coordinates can't be anonymized this way, and nor can ones with a metric
+    /// other than Euclidean, whose distances rotating and moving the points would change.
+    pub fn anonymize(&self, seed: u64) -> Result<VRPInstance, VrpError> {
+        if !self.has_coordinates {
+            return Err(VrpError::NoCoordinates);
+        }
+        if self.metric != Metric::Euclidean {
+            return Err(VrpError::NotEuclidean(self.metric));
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        // new_order[new index] = old index
+        let mut new_order = (1..self.num_customers).collect::<Vec<_>>();
+        new_order.shuffle(&mut rng);
+        new_order.insert(0, 0);
+        let mut new_index = vec![0; self.num_customers];
+        for (new, &old) in new_order.iter().enumerate() {
+            new_index[old] = new;
+        }
+
+        let span = |coords: &[f64]| {
+            let (min, max) = coords
+                .iter()
+                .fold((f64::MAX, f64::MIN), |(lo, hi), &c| (lo.min(c), hi.max(c)));
+            max - min
+        };
+        let extent = span(&self.x_coord_of_customer)
+            .max(span(&self.y_coord_of_customer))
+            .max(1.0);
+        let (sin, cos) = rng.random_range(0.0..TAU).sin_cos();
+        let mirror = if rng.random_bool(0.5) { -1.0 } else { 1.0 };
+        let (dx, dy) = (
+            rng.random_range(-extent..extent),
+            rng.random_range(-extent..extent),
+        );
+        let jitter = ANONYMIZE_JITTER * extent;
+        let mut transform = |x: f64, y: f64| {
+            let x = mirror * x;
+            (
+                cos * x - sin * y + dx + rng.random_range(-jitter..=jitter),
+                sin * x + cos * y + dy + rng.random_range(-jitter..=jitter),
+            )
+        };
+        let (xs, ys) = new_order
+            .iter()
+            .map(|&old| transform(self.x_coord_of_customer[old], self.y_coord_of_customer[old]))
+            .unzip();
+        let end_location = self.end_location.map(|(x, y)| transform(x, y));
+
+        let factor = rng.random_range(2..10);
+        let demands = new_order
+            .iter()
+            .map(|&old| self.demand_of_customer[old] * factor)
+            .collect();
+        let service_times = new_order
+            .iter()
+            .map(|&old| self.service_time_of_customer[old])
+            .collect();
+
+        let mut anonymized = Self::from_parts_unchecked(
+            self.num_vehicles,
+            self.vehicle_capacity * factor,
+            demands,
+            xs,
+            ys,
+        )
+        .with_distance_rounding(self.distance_rounding)
+        .expect("moving points around keeps their distances finite");
+        anonymized.service_time_of_customer = service_times;
+        if let Some((x, y)) = end_location {
+            anonymized = anonymized
+                .with_end_location(x, y)
+                .expect("moving points around keeps their distances finite");
+        }
+        let renumber = |node: u32| {
+            // the end location's index is past the customers, and doesn't move
+            new_index
+                .get(node as usize)
+                .map_or(node, |&new| u32::try_from(new).unwrap())
+        };
+        for &(a, b) in &self.forbidden_edges {
+            anonymized
+                .forbid_edge(renumber(a), renumber(b))
+                .expect("renumbering keeps nodes in range");
+        }
+        Ok(anonymized)
+    }
+
+    /// A copy of this instance with its coordinates moved and scaled so they fit in the unit box
+    /// (the longer side of their bounding box going from 0 to 1), along with how to turn its
+    /// costs back into this instance's units. Every distance and service time is this instance's
+    /// divided by [`ScaleInfo::scale`], so the same routes are feasible and rank the same, but
+    /// anything that compares costs against a fixed amount sees coordinates in millimetres and
+    /// in kilometres alike.
+    ///
+    /// Only coordinates with unrounded Euclidean or Manhattan distances can be scaled like this;
+    /// rounding, and great-circle distances, wouldn't scale along with them.
+    pub fn normalized(&self) -> Result<(VRPInstance, ScaleInfo), VrpError> {
+        if !self.has_coordinates {
+            return Err(VrpError::NoCoordinates);
+        }
+        if self.metric == Metric::Haversine {
+            return Err(VrpError::NotEuclidean(self.metric));
+        }
+        if self.distance_rounding != DistanceRounding::Exact {
+            return Err(VrpError::RoundedDistances);
+        }
+
+        let (xs, ys) = (&self.x_coord_of_customer, &self.y_coord_of_customer);
+        let min_x = xs.iter().copied().fold(f64::INFINITY, f64::min);
+        let min_y = ys.iter().copied().fold(f64::INFINITY, f64::min);
+        let span = |coords: &[f64], min: f64| coords.iter().map(|c| c - min).fold(0.0, f64::max);
+        let extent = span(xs, min_x).max(span(ys, min_y));
+        let info = ScaleInfo {
+            min_x,
+            min_y,
+            // a single point (or all of them in one place) only needs moving
+            scale: if extent > 0.0 { extent } else { 1.0 },
+        };
+        let transform = |x: f64, y: f64| ((x - min_x) / info.scale, (y - min_y) / info.scale);
+        let (xs, ys): (Vec<_>, Vec<_>) = xs.iter().zip(ys).map(|(&x, &y)| transform(x, y)).unzip();
+        let end_location = self.end_location.map(|(x, y)| transform(x, y));
+
+        let distance_matrix = Self::distance_matrix(
+            &xs,
+            &ys,
+            end_location,
+            DistanceRounding::Exact,
+            self.metric,
+            self.distance_matrix.precision(),
+            self.distance_matrix.is_computed(),
+        );
+        let mut normalized = Self::from_coordinates(
+            self.num_vehicles,
+            self.vehicle_capacity,
+            self.demand_of_customer.clone(),
+            xs,
+            ys,
+            distance_matrix,
+        );
+        normalized.metric = self.metric;
+        normalized.service_time_of_customer = self
+            .service_time_of_customer
+            .iter()
+            .map(|&t| info.to_normalized(t))
+            .collect();
+        normalized.end_location = end_location;
+        normalized.end_node = self.end_node;
+        normalized.customer_ids.clone_from(&self.customer_ids);
+        normalized.depot = self.depot;
+        for &(a, b) in &self.forbidden_edges {
+            normalized
+                .forbid_edge(a, b)
+                .expect("the same nodes are still there");
+        }
+        Ok((normalized, info))
+    }
+
+    /// Write this instance out in the same format [`VRPInstance::new`] reads, which reads it back
+    /// with exactly the same distances (and service times, as a fourth column if any aren't 0). That format has no place for an end location, forbidden
+    /// edges, rounding or metric, so those have to be passed along separately (as `--end-location`,
+    /// `--forbid`, `--rounding` and `--metric`), and none at all for customer ids or a distance matrix without
+    /// coordinates.
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        output::write_atomic(path.as_ref(), &self.to_file_string()?)
+    }
+
+    /// What [`VRPInstance::write_to`] writes.
+    pub fn to_file_string(&self) -> io::Result<String> {
+        if !self.has_coordinates {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                VrpError::NoCoordinates.to_string(),
+            ));
+        }
+        let mut contents = format!(
+            "{} {} {}\n",
+            self.num_customers, self.num_vehicles, self.vehicle_capacity
+        );
+        let has_service_times = self.service_time_of_customer.iter().any(|&t| t != 0.0);
+        for i in 0..self.num_customers {
+            // `{}` prints floats in full, so they read back exactly
+            contents += &format!(
+                "{} {} {}",
+                self.demand_of_customer[i],
+                self.x_coord_of_customer[i],
+                self.y_coord_of_customer[i]
+            );
+            if has_service_times {
+                contents += &format!(" {}", self.service_time_of_customer[i]);
+            }
+            contents.push('\n');
+        }
+        Ok(contents)
+    }
+
+    /// Distances by `metric` between every pair of customers, plus a last row and column for the
+    /// end location if there is one.
+    fn distance_matrix(
+        xs: &[f64],
+        ys: &[f64],
+        end_location: Option<(f64, f64)>,
+        rounding: DistanceRounding,
+        metric: Metric,
+        precision: DistancePrecision,
+        computed: bool,
+    ) -> DistanceMatrix {
+        let (mut xs, mut ys) = (xs.to_vec(), ys.to_vec());
+        if let Some((x, y)) = end_location {
+            xs.push(x);
+            ys.push(y);
+        }
+        if computed {
+            let points = xs.into_iter().zip(ys).collect();
+            return DistanceMatrix::computed(points, metric, rounding);
+        }
+
+        DistanceMatrix::from_fn(xs.len(), precision, |i, j| {
+            rounding.apply(metric.distance((xs[i], ys[i]), (xs[j], ys[j])))
+        })
+    }
+
+    /// The angle of each point as seen from the depot (the first point), in radians.
+    fn polar_angles(xs: &[f64], ys: &[f64]) -> Vec<f64> {
+        xs.iter()
+            .zip(ys)
+            .map(|(x, y)| (ys[0] - y).atan2(xs[0] - x))
+            .collect()
+    }
+
     #[allow(dead_code)]
     pub fn to_string(&self) {
         dbg_println!("Number of customers: {}", self.num_customers);
@@ -118,60 +1162,1668 @@ impl VRPInstance {
         count
     }
 
-    fn read_lines_from_file<P: AsRef<Path>>(file_name: P) -> Vec<String> {
-        let Ok(file) = File::open(&file_name) else {
-            eprintln!(
-                "Error: in VRPInstance() {}\nFile not found",
-                file_name.as_ref().display()
-            );
-            process::exit(-1);
+    /// How many of `demands` fit in one vehicle at most, which is how many of the smallest do.
+    /// Unlike [`VRPInstance::max_route_len`], this never counts one too many.
+    fn most_customers_per_route(demands: &[usize], capacity: usize) -> usize {
+        let mut demands = demands.to_owned();
+        demands.sort_unstable();
+        demands
+            .iter()
+            .scan(0, |used, &d| {
+                *used += d;
+                Some(*used)
+            })
+            .take_while(|&used| used <= capacity)
+            .count()
+    }
+
+    /// The file's lines, decompressing it first if it's gzipped (whatever it's called).
+    fn read_lines(reader: impl Read) -> Result<Vec<String>, InstanceError> {
+        let mut reader = BufReader::new(reader);
+        let gzipped = reader
+            .fill_buf()
+            .map_err(|e| InstanceError::Unreadable(e.to_string()))?
+            .starts_with(&GZIP_MAGIC);
+        let reader: Box<dyn BufRead> = if gzipped {
+            Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+        } else {
+            Box::new(reader)
         };
 
-        let reader = BufReader::new(file);
-        let lines: Vec<String> = reader
+        let lines = reader
             .lines()
-            .map(|line| {
-                line.unwrap_or_else(|e| {
-                    eprintln!("Error reading line: {e}");
-                    process::exit(-1);
-                })
-            })
-            .collect();
-
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| InstanceError::Unreadable(e.to_string()))?;
         if lines.is_empty() {
-            eprintln!(
-                "Error: in VRPInstance() {}\nFile is empty",
-                file_name.as_ref().display()
-            );
-            process::exit(-1);
+            return Err(InstanceError::Empty);
         }
+        Ok(lines)
+    }
+
+    /// The (1-based line number, line) of every line that isn't blank or a `#` or `//` comment.
+    fn data_lines(lines: &[String]) -> impl Iterator<Item = (usize, &str)> {
         lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| {
+                !(line.is_empty() || line.starts_with('#') || line.starts_with("//"))
+            })
     }
 
-    fn parse_first_line<P: AsRef<Path>>(line: &str, file_name: P) -> (usize, usize, usize) {
-        let first_line: Vec<&str> = line.split_whitespace().collect();
-        if first_line.len() < 3 {
-            eprintln!(
-                "Error: in VRPInstance() {}\nInvalid first line format",
-                file_name.as_ref().display()
+    /// Our own format: a `<customers> <vehicles> <capacity>` header, then a `<demand> <x> <y>` row
+    /// for the depot and each customer, optionally with its service time after (0 if not). Blank
+    /// lines and comment lines can go anywhere.
+    fn parse_native(lines: &[String]) -> Result<ParsedInstance, InstanceError> {
+        let mut data = Self::data_lines(lines);
+        let (header_line, header) = data.next().ok_or(InstanceError::Empty)?;
+        let (num_customers, num_vehicles, vehicle_capacity) =
+            Self::parse_first_line(header, header_line)?;
+        let rows = data.collect::<Vec<_>>();
+        Self::check_row_count(&rows, num_customers)?;
+
+        // Initialize arrays for customer data
+        let mut demand_of_customer = vec![0; num_customers];
+        let mut x_coord_of_customer = vec![0.0; num_customers];
+        let mut y_coord_of_customer = vec![0.0; num_customers];
+        let mut service_time_of_customer = vec![0.0; num_customers];
+        let mut has_service_times = false;
+
+        // Parse customer data
+        for (i, &(line, row)) in rows.iter().take(num_customers).enumerate() {
+            let bad = |field| InstanceError::BadCustomerRow { line, field };
+            let mut customer_data = row.split_whitespace();
+            let mut next = |field| customer_data.next().ok_or(bad(field));
+
+            demand_of_customer[i] = next("demand")?.parse().map_err(|_| bad("demand"))?;
+            x_coord_of_customer[i] = next("x")?.parse().map_err(|_| bad("x"))?;
+            y_coord_of_customer[i] = next("y")?.parse().map_err(|_| bad("y"))?;
+            if let Some(time) = customer_data.next() {
+                service_time_of_customer[i] = time.parse().map_err(|_| bad("service time"))?;
+                has_service_times = true;
+            }
+        }
+        Ok(ParsedInstance {
+            num_vehicles,
+            vehicle_capacity,
+            demands: demand_of_customer,
+            xs: x_coord_of_customer,
+            ys: y_coord_of_customer,
+            distances: None,
+            customer_ids: None,
+            service_times: has_service_times.then_some(service_time_of_customer),
+        })
+    }
+
+    /// Whether the file starts with a TSPLIB keyword rather than our own numeric header.
+    fn is_tsplib(lines: &[String]) -> bool {
+        let first_key = Self::data_lines(lines)
+            .next()
+            .and_then(|(_, line)| line.split([':', ' ', '\t']).next());
+        matches!(
+            first_key,
+            Some("NAME" | "TYPE" | "COMMENT" | "DIMENSION" | "CAPACITY" | "VEHICLES")
+        )
+    }
+
+    /// A TSPLIB CVRP instance, as in CVRPLIB: `KEY : value` lines, then a `NODE_COORD_SECTION` and
+    /// `DEMAND_SECTION` with a line per (1-based) node, and optionally a `DEPOT_SECTION` naming the
+    /// depot (node 1 otherwise). The depot becomes index 0 and the rest keep their order, so node
+    /// `n` is customer `n - 1` when the depot is node 1. The number of vehicles comes from a
+    /// `VEHICLES` line, or else the `k` in a name like `A-n32-k5`.
+    ///
+    /// Distances are either `EUC_2D`, which are only rounded to whole numbers the way TSPLIB
+    /// specifies with [`DistanceRounding::Nearest`], or
+    /// `EXPLICIT`, read from an `EDGE_WEIGHT_SECTION` in any of the `EDGE_WEIGHT_FORMAT`s
+    /// [`Self::explicit_distances`] knows. Explicit instances don't need (and ignore) coordinates.
+    fn parse_tsplib(lines: &[String], file_name: &str) -> Result<ParsedInstance, String> {
+        #[derive(PartialEq)]
+        enum Section {
+            Header,
+            Coords,
+            Demands,
+            Depots,
+            Weights,
+            /// only for drawing the instance, so skipped
+            Display,
+        }
+
+        let mut name = None;
+        let mut dimension = None;
+        let mut capacity = None;
+        let mut vehicles = None;
+        let mut explicit = false;
+        let mut weight_format = None;
+        let mut weights = Vec::new();
+        let mut coords = Vec::new();
+        let mut demands = Vec::new();
+        let mut depots = Vec::new();
+        let mut section = Section::Header;
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.trim();
+            let bad_line = || format!("line {}: can't parse {line:?}", i + 1);
+            match line.trim_end_matches(':').trim() {
+                "" => continue,
+                "NODE_COORD_SECTION" => section = Section::Coords,
+                "DEMAND_SECTION" => section = Section::Demands,
+                "DEPOT_SECTION" => section = Section::Depots,
+                "EDGE_WEIGHT_SECTION" => section = Section::Weights,
+                "DISPLAY_DATA_SECTION" => section = Section::Display,
+                "EOF" => break,
+                _ if section == Section::Header => {
+                    let (key, value) = line.split_once(':').ok_or_else(bad_line)?;
+                    let value = value.trim();
+                    let number = || value.parse::<usize>().map_err(|_| bad_line());
+                    match key.trim() {
+                        "NAME" => name = Some(value.to_string()),
+                        "TYPE" if value != "CVRP" => {
+                            return Err(format!("only CVRP instances are supported, not {value}"));
+                        }
+                        "EDGE_WEIGHT_TYPE" if value == "EXPLICIT" => explicit = true,
+                        "EDGE_WEIGHT_TYPE" if value != "EUC_2D" => {
+                            return Err(format!(
+                                "only EUC_2D and EXPLICIT distances are supported, not {value}"
+                            ));
+                        }
+                        "EDGE_WEIGHT_FORMAT" => weight_format = Some(value.to_string()),
+                        "DIMENSION" => dimension = Some(number()?),
+                        "CAPACITY" => capacity = Some(number()?),
+                        "VEHICLES" => vehicles = Some(number()?),
+                        // COMMENT, and anything else we don't need
+                        _ => {}
+                    }
+                }
+                _ if section == Section::Display => {}
+                // the weights run on from line to line, however many there are to a line
+                _ if section == Section::Weights => {
+                    for weight in line.split_whitespace() {
+                        weights.push(weight.parse::<f64>().map_err(|_| bad_line())?);
+                    }
+                }
+                _ => {
+                    let fields = line.split_whitespace().collect::<Vec<_>>();
+                    let node = fields[0].parse::<i64>().map_err(|_| bad_line())?;
+                    match (&section, &fields[1..]) {
+                        (Section::Depots, []) if node == -1 => section = Section::Header,
+                        (Section::Depots, []) => depots.push(node),
+                        (Section::Coords, [x, y]) => coords.push((
+                            node,
+                            x.parse::<f64>().map_err(|_| bad_line())?,
+                            y.parse::<f64>().map_err(|_| bad_line())?,
+                        )),
+                        (Section::Demands, [demand]) => {
+                            demands.push((node, demand.parse::<usize>().map_err(|_| bad_line())?));
+                        }
+                        _ => return Err(bad_line()),
+                    }
+                }
+            }
+        }
+
+        let dimension = dimension.ok_or("there's no DIMENSION")?;
+        let capacity = capacity.ok_or("there's no CAPACITY")?;
+        let vehicles = match vehicles {
+            Some(vehicles) => vehicles,
+            None => [name.as_deref(), Some(file_name)]
+                .into_iter()
+                .flatten()
+                .find_map(Self::vehicles_from_name)
+                .ok_or("there's no VEHICLES, and no -k<vehicles> in the NAME or file name")?,
+        };
+        let depot = match depots[..] {
+            [] => 1,
+            [depot] => depot,
+            _ => return Err("only instances with a single depot are supported".to_string()),
+        };
+
+        // order[index] = node, with the depot first
+        let order = std::iter::once(depot)
+            .chain((1..=dimension as i64).filter(|&node| node != depot))
+            .collect::<Vec<_>>();
+        let mut points = vec![None; dimension];
+        let mut node_demands = vec![None; dimension];
+        let index_of = |node: i64| {
+            order.iter().position(|&n| n == node).ok_or(format!(
+                "node {node} is out of range for DIMENSION {dimension}"
+            ))
+        };
+        for &(node, x, y) in &coords {
+            points[index_of(node)?] = Some((x, y));
+        }
+        for &(node, demand) in &demands {
+            node_demands[index_of(node)?] = Some(demand);
+        }
+        let missing = |i: usize, what: &str| format!("node {} has no {what}", order[i]);
+        let distances = if explicit {
+            let format = weight_format.ok_or("there's no EDGE_WEIGHT_FORMAT")?;
+            let by_node = Self::explicit_distances(&format, &weights, dimension)?;
+            let node_index = |node: i64| usize::try_from(node - 1).unwrap();
+            points = vec![Some((0.0, 0.0)); dimension];
+            Some(
+                order
+                    .iter()
+                    .map(|&a| {
+                        order
+                            .iter()
+                            .map(|&b| by_node[node_index(a)][node_index(b)])
+                            .collect()
+                    })
+                    .collect(),
+            )
+        } else {
+            None
+        };
+        if let Some(i) = points.iter().position(Option::is_none) {
+            return Err(missing(i, "coordinates"));
+        }
+        if let Some(i) = node_demands.iter().position(Option::is_none) {
+            return Err(missing(i, "demand"));
+        }
+        let (xs, ys) = points.into_iter().flatten().unzip();
+        Ok(ParsedInstance {
+            num_vehicles: vehicles,
+            vehicle_capacity: capacity,
+            demands: node_demands.into_iter().flatten().collect(),
+            xs,
+            ys,
+            distances,
+            customer_ids: None,
+            service_times: None,
+        })
+    }
+
+    /// The full `dimension`x`dimension` matrix (in file order) from an `EDGE_WEIGHT_SECTION`'s
+    /// `weights`, laid out as `FULL_MATRIX`, or one triangle by rows (`UPPER_ROW`, `LOWER_ROW`,
+    /// or with the diagonal, `UPPER_DIAG_ROW` and `LOWER_DIAG_ROW`).
+    fn explicit_distances(
+        format: &str,
+        weights: &[f64],
+        dimension: usize,
+    ) -> Result<Vec<Vec<f64>>, String> {
+        // the (row, column) each weight is for, in order
+        let cells: Vec<(usize, usize)> = match format {
+            "FULL_MATRIX" => (0..dimension)
+                .flat_map(|i| (0..dimension).map(move |j| (i, j)))
+                .collect(),
+            "UPPER_ROW" => (0..dimension)
+                .flat_map(|i| (i + 1..dimension).map(move |j| (i, j)))
+                .collect(),
+            "UPPER_DIAG_ROW" => (0..dimension)
+                .flat_map(|i| (i..dimension).map(move |j| (i, j)))
+                .collect(),
+            "LOWER_ROW" => (0..dimension)
+                .flat_map(|i| (0..i).map(move |j| (i, j)))
+                .collect(),
+            "LOWER_DIAG_ROW" => (0..dimension)
+                .flat_map(|i| (0..=i).map(move |j| (i, j)))
+                .collect(),
+            _ => return Err(format!("unsupported EDGE_WEIGHT_FORMAT {format}")),
+        };
+        if weights.len() != cells.len() {
+            return Err(format!(
+                "EDGE_WEIGHT_SECTION has {} weights, but a {format} for DIMENSION {dimension} \
+                 needs {}",
+                weights.len(),
+                cells.len()
+            ));
+        }
+
+        let mut matrix = vec![vec![0.0; dimension]; dimension];
+        for (&(i, j), &weight) in cells.iter().zip(weights) {
+            matrix[i][j] = weight;
+            if format != "FULL_MATRIX" {
+                matrix[j][i] = weight;
+            }
+        }
+        Ok(matrix)
+    }
+
+    /// Whether the file has the `VEHICLE` and `CUSTOMER` blocks of a Solomon instance.
+    fn is_json(lines: &[String]) -> bool {
+        Self::data_lines(lines)
+            .next()
+            .is_some_and(|(_, line)| line.starts_with('{'))
+    }
+
+    /// See [`VRPInstance::from_json`].
+    fn parse_json(text: &str) -> Result<ParsedInstance, String> {
+        let json: JsonInstance =
+            serde_json::from_str(text).map_err(|e| format!("invalid JSON instance: {e}"))?;
+
+        let mut customer_ids = vec!["depot".to_string()];
+        let mut seen = HashSet::new();
+        for (i, customer) in json.customers.iter().enumerate() {
+            let id = match &customer.id {
+                serde_json::Value::String(id) => id.clone(),
+                serde_json::Value::Number(id) => id.to_string(),
+                other => {
+                    return Err(format!(
+                        "customer {} has id {other}, expected a string or a number",
+                        i + 1
+                    ));
+                }
+            };
+            if !seen.insert(id.clone()) {
+                return Err(format!("customer id {id:?} is used more than once"));
+            }
+            customer_ids.push(id);
+        }
+
+        let customers = &json.customers;
+        Ok(ParsedInstance {
+            num_vehicles: json.vehicles.count,
+            vehicle_capacity: json.vehicles.capacity,
+            demands: std::iter::once(0)
+                .chain(customers.iter().map(|c| c.demand))
+                .collect(),
+            xs: std::iter::once(json.depot.x)
+                .chain(customers.iter().map(|c| c.x))
+                .collect(),
+            ys: std::iter::once(json.depot.y)
+                .chain(customers.iter().map(|c| c.y))
+                .collect(),
+            distances: None,
+            customer_ids: Some(customer_ids),
+            service_times: Some(
+                std::iter::once(0.0)
+                    .chain(customers.iter().map(|c| c.service_time))
+                    .collect(),
+            ),
+        })
+    }
+
+    fn is_solomon(lines: &[String]) -> bool {
+        let has = |heading| lines.iter().any(|line| line.trim() == heading);
+        has("VEHICLE") && has("CUSTOMER")
+    }
+
+    /// A Solomon instance (e.g. R101): a name, a `VEHICLE` block with a `NUMBER CAPACITY` heading
+    /// and a line with both, then a `CUSTOMER` block with a heading and a row per customer,
+    /// starting with the depot as customer 0. The number, x, y, demand and service time columns
+    /// are read; the time windows between the last two are ignored.
+    fn parse_solomon(lines: &[String]) -> Result<ParsedInstance, String> {
+        // the (line number, fields) of the lines that are all numbers after `heading`
+        let rows_after = |heading: &str| {
+            lines
+                .iter()
+                .enumerate()
+                .skip_while(|(_, line)| line.trim() != heading)
+                .skip(1)
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(i, line)| (i + 1, line.split_whitespace().collect::<Vec<_>>()))
+                .skip_while(|(_, fields)| fields.iter().any(|f| f.parse::<f64>().is_err()))
+                .take_while(|(_, fields)| fields.iter().all(|f| f.parse::<f64>().is_ok()))
+                .collect::<Vec<_>>()
+        };
+        let bad_line = |line: usize| format!("line {line}: can't parse it");
+
+        let (line, vehicle_fields) = rows_after("VEHICLE")
+            .into_iter()
+            .next()
+            .ok_or("there's no vehicle number and capacity under VEHICLE")?;
+        let [vehicles, capacity] = vehicle_fields[..] else {
+            return Err(format!(
+                "line {line}: expected the vehicle number and capacity"
+            ));
+        };
+        let vehicles = vehicles.parse::<usize>().map_err(|_| bad_line(line))?;
+        let capacity = capacity.parse::<usize>().map_err(|_| bad_line(line))?;
+
+        let rows = rows_after("CUSTOMER");
+        let mut demands = Vec::with_capacity(rows.len());
+        let mut xs = Vec::with_capacity(rows.len());
+        let mut ys = Vec::with_capacity(rows.len());
+        let mut service_times = Vec::with_capacity(rows.len());
+        for (expected, (line, fields)) in rows.iter().enumerate() {
+            let [cust_no, x, y, demand, ref rest @ ..] = fields[..] else {
+                return Err(format!(
+                    "line {line}: expected at least the customer number, x, y and demand"
+                ));
+            };
+            // the table's numbering is the only check that no rows went missing
+            if cust_no.parse::<usize>().ok() != Some(expected) {
+                return Err(format!(
+                    "line {line}: expected customer {expected} (the depot is 0 and customers are \
+                     numbered in order), but it's customer {cust_no}"
+                ));
+            }
+            xs.push(x.parse::<f64>().map_err(|_| bad_line(*line))?);
+            ys.push(y.parse::<f64>().map_err(|_| bad_line(*line))?);
+            demands.push(demand.parse::<usize>().map_err(|_| bad_line(*line))?);
+            // after the ready time and due date, if the table has them
+            service_times.push(
+                rest.get(2)
+                    .map_or(Ok(0.0), |t| t.parse::<f64>())
+                    .map_err(|_| bad_line(*line))?,
             );
-            process::exit(-1);
         }
+        if let Some((line, _)) = lines
+            .iter()
+            .enumerate()
+            .skip(rows.last().map_or(0, |(line, _)| *line))
+            .find(|(_, line)| !line.trim().is_empty())
+        {
+            return Err(format!(
+                "line {}: expected another row of the customer table (all numbers), or the end of \
+                 the file",
+                line + 1
+            ));
+        }
+        if rows.len() < 2 {
+            return Err("the customer table needs the depot and at least one customer".to_string());
+        }
+        Ok(ParsedInstance {
+            num_vehicles: vehicles,
+            vehicle_capacity: capacity,
+            demands,
+            xs,
+            ys,
+            distances: None,
+            customer_ids: None,
+            service_times: Some(service_times),
+        })
+    }
 
-        let num_customers = first_line[0].parse::<usize>().unwrap_or_else(|e| {
-            eprintln!("Error parsing number of customers: {e}");
-            process::exit(-1);
+    /// The `k` in a CVRPLIB-style name like `X-n101-k25` (or `X-n101-k25.vrp`, or `.vrp.gz`).
+    fn vehicles_from_name(name: &str) -> Option<usize> {
+        let stem = name.strip_suffix(".gz").unwrap_or(name);
+        let stem = stem.strip_suffix(".vrp").unwrap_or(stem);
+        let stem = Path::new(stem).file_name()?.to_str()?;
+        stem.rsplit('-')
+            .find_map(|part| part.strip_prefix('k'))
+            .and_then(|k| k.parse().ok())
+    }
+
+    /// Make sure there's a row for the depot and each customer after the header, and catch files
+    /// that leave out the depot row (which would otherwise shift every customer along by one).
+    fn check_row_count(rows: &[(usize, &str)], num_customers: usize) -> Result<(), InstanceError> {
+        if rows.len() >= num_customers {
+            return Ok(());
+        }
+
+        let first_demand = rows
+            .first()
+            .and_then(|(_, row)| row.split_whitespace().next())
+            .and_then(|demand| demand.parse::<usize>().ok());
+        Err(InstanceError::TooFewRows {
+            expected: num_customers,
+            found: rows.len(),
+            depot_missing: first_demand.is_some_and(|d| d != 0) && rows.len() + 1 == num_customers,
+        })
+    }
+
+    fn parse_first_line(header: &str, line: usize) -> Result<(usize, usize, usize), InstanceError> {
+        let mut numbers = header.split_whitespace().map(|field| {
+            field
+                .parse::<usize>()
+                .map_err(|_| InstanceError::BadHeader { line })
         });
+        let mut next = || {
+            numbers
+                .next()
+                .unwrap_or(Err(InstanceError::BadHeader { line }))
+        };
+        Ok((next()?, next()?, next()?))
+    }
+}
+
+/// What [`load_dir`] read from a folder.
+#[allow(dead_code)]
+pub struct LoadedDir {
+    /// each instance that could be read, by file name, in file name order
+    pub instances: Vec<(String, VRPInstance)>,
+    /// why each file that couldn't be read was skipped
+    pub warnings: Vec<String>,
+}
+
+/// The instance files (`*.vrp` and `*.txt`) directly inside `dir`, sorted by file name so batches
+/// always run in the same order.
+pub fn instance_files<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>, InstanceError> {
+    let entries = std::fs::read_dir(dir).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => InstanceError::FileNotFound,
+        _ => InstanceError::Unreadable(e.to_string()),
+    })?;
+    let mut files = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext == "vrp" || ext == "txt")
+        })
+        .collect::<Vec<_>>();
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    Ok(files)
+}
+
+/// Read every instance file in `dir` (see [`instance_files`]), skipping the ones that can't be
+/// read instead of failing the whole folder.
+#[allow(dead_code)]
+pub fn load_dir<P: AsRef<Path>>(dir: P, options: ReadOptions) -> Result<LoadedDir, InstanceError> {
+    let mut loaded = LoadedDir {
+        instances: Vec::new(),
+        warnings: Vec::new(),
+    };
+    for path in instance_files(dir)? {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match VRPInstance::new(&path, options) {
+            Ok(instance) => loaded.instances.push((name, instance)),
+            Err(e) => loaded.warnings.push(format!("skipped {name}: {e}")),
+        }
+    }
+    Ok(loaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::common::VRPSolution;
+
+    fn read(contents: &str, strict: bool) -> Result<VRPInstance, InstanceError> {
+        let options = ReadOptions {
+            strict,
+            ..ReadOptions::default()
+        };
+        VRPInstance::from_reader(contents.as_bytes(), "test.vrp", options)
+    }
+
+    #[test]
+    fn a_folder_loads_its_instance_files_in_name_order() {
+        let dir = std::env::temp_dir().join(format!("vrp-load-dir-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("nested.vrp")).unwrap();
+        let file = "3 1 10\n0 0 0\n1 1 1\n2 2 2\n";
+        for name in ["b.vrp", "a.txt", "c.vrp", "notes.md"] {
+            std::fs::write(dir.join(name), file).unwrap();
+        }
+        std::fs::write(dir.join("broken.vrp"), "3 one 10\n").unwrap();
+
+        let loaded = load_dir(&dir, ReadOptions::default()).unwrap();
+        let names = loaded.instances.iter().map(|(name, _)| name.as_str());
+        assert_eq!(names.collect::<Vec<_>>(), ["a.txt", "b.vrp", "c.vrp"]);
+        assert!(loaded.instances.iter().all(|(_, i)| i.num_customers == 3));
+        assert_eq!(loaded.warnings.len(), 1);
+        assert!(loaded.warnings[0].starts_with("skipped broken.vrp: "));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            load_dir(&dir, ReadOptions::default()).err(),
+            Some(InstanceError::FileNotFound)
+        );
+    }
+
+    #[test]
+    fn a_depot_with_demand_is_an_error_only_when_strict() {
+        let file = "3 1 10\n5 0 0\n1 1 1\n2 2 2\n";
+        assert_eq!(
+            read(file, true).err(),
+            Some(InstanceError::DepotHasDemand {
+                demand: 5,
+                native: true
+            })
+        );
+        let instance = read(file, false).unwrap();
+        assert_eq!(instance.demand_of_customer, [0, 1, 2]);
+    }
+
+    #[test]
+    fn a_missing_depot_row_is_pointed_out() {
+        // the header counts the depot, but the first row is a customer with a demand
+        let err = read("3 1 10\n1 1 1\n2 2 2\n", false).err().unwrap();
+        assert_eq!(
+            err,
+            InstanceError::TooFewRows {
+                expected: 3,
+                found: 2,
+                depot_missing: true
+            }
+        );
+        assert!(err.to_string().contains("depot row looks to be missing"));
+    }
 
-        let num_vehicles = first_line[1].parse::<usize>().unwrap_or_else(|e| {
-            eprintln!("Error parsing number of vehicles: {e}");
-            process::exit(-1);
+    #[test]
+    fn too_few_rows_are_an_error_even_with_a_depot() {
+        // blank lines at the end aren't rows, and a depot row without demand isn't missing
+        for (file, found) in [("4 1 10\n0 0 0\n1 1 1\n\n\n", 2), ("4 1 10\n2 0 0\n", 1)] {
+            assert_eq!(
+                read(file, false).err(),
+                Some(InstanceError::TooFewRows {
+                    expected: 4,
+                    found,
+                    depot_missing: false
+                }),
+                "{file:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_depot_in_any_row_solves_like_the_depot_first() {
+        let original = crate::generate::random_instance(40, 50, 2015);
+        let file = original.to_file_string().unwrap();
+        let (header, rows) = file.split_once('\n').unwrap();
+        let mut rows = rows.lines().collect::<Vec<_>>();
+        // the depot moved to row 17, which pushes rows 1 to 17 up by one
+        let depot = rows.remove(0);
+        rows.insert(17, depot);
+        let moved = format!("{header}\n{}\n", rows.join("\n"));
+        let options = ReadOptions {
+            depot: 17,
+            ..ReadOptions::default()
+        };
+        let relabeled = VRPInstance::from_reader(moved.as_bytes(), "moved.vrp", options).unwrap();
+        assert_eq!(relabeled.demand_of_customer, original.demand_of_customer);
+        assert_eq!(relabeled.x_coord_of_customer, original.x_coord_of_customer);
+        assert_eq!(relabeled.y_coord_of_customer, original.y_coord_of_customer);
+        // every customer keeps its row in the file as its id
+        let ids = relabeled.customer_ids.clone().unwrap();
+        assert_eq!(ids[..3], ["17", "0", "1"]);
+        assert_eq!(ids[17..19], ["16", "18"]);
+
+        let params = crate::solver::SolveParams {
+            terminate: crate::solver::TermCond::MaxIters(500),
+            ..crate::default_params(std::time::Duration::ZERO)
+        };
+        let [original, relabeled] = [original, relabeled].map(|instance| {
+            let instance = Arc::new(instance);
+            crate::random::seed(2015);
+            let sol = crate::solver::solve::<crate::solvers::ALNSSolver>(&instance, &params);
+            let read_back = VRPSolution::from_string(&sol.to_string(), &instance).unwrap();
+            assert_eq!(read_back.to_routes_vec(true), sol.to_routes_vec(true));
+            (sol.to_string(), sol.to_json(&instance))
         });
+        // reported by the rows they were in, the depot's at the ends of every route
+        let row = |id: &serde_json::Value| match id.as_u64().unwrap() {
+            0 => 17.to_string(),
+            c if c <= 17 => (c - 1).to_string(),
+            c => c.to_string(),
+        };
+        let (flag, routes) = original.0.split_once(' ').unwrap();
+        let rows = routes
+            .split(' ')
+            .map(|c| row(&serde_json::json!(c.parse::<u64>().unwrap())))
+            .collect::<Vec<_>>();
+        assert_eq!(relabeled.0, format!("{flag} {}", rows.join(" ")));
+        let expected = original.1.as_array().unwrap().iter().map(|route| {
+            route
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(row)
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(
+            relabeled.1,
+            serde_json::to_value(expected.collect::<Vec<_>>()).unwrap()
+        );
+    }
+
+    #[test]
+    fn customers_and_file_rows_map_both_ways() {
+        let native = "4 2 10\n3 0 3\n4 4 0\n0 0 0\n5 0 -4\n";
+        let options = ReadOptions {
+            depot: 2,
+            ..ReadOptions::default()
+        };
+        let instance =
+            Arc::new(VRPInstance::from_reader(native.as_bytes(), "a.vrp", options).unwrap());
+        assert_eq!(instance.depot, 2);
+        let rows = (0..4).map(|c| instance.file_row(c)).collect::<Vec<_>>();
+        assert_eq!(rows, [2, 0, 1, 3]);
+        for c in 0..4 {
+            assert_eq!(instance.customer_of_row(instance.file_row(c)), c);
+        }
+
+        // customer 1 is row 0, so the route out to it and back is 3 each way
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1], vec![2, 3]]).unwrap();
+        assert_eq!(sol.routes[0].cost(), 6.0);
+        assert_eq!(sol.to_string(), "0 2 0 2 2 1 3 2");
+        assert_eq!(
+            sol.to_file_string(),
+            format!("{:.2} 0\n2 0 2\n2 1 3 2\n", sol.cost())
+        );
+        for read in [
+            VRPSolution::from_string(&sol.to_string(), &instance),
+            VRPSolution::from_file_string(&sol.to_file_string(), &instance),
+        ] {
+            assert_eq!(read.unwrap().to_routes_vec(true), sol.to_routes_vec(true));
+        }
+        // a route has to start and end at the depot's row, not at row 0
+        assert!(VRPSolution::from_string("0 0 1 0 0 2 3 0", &instance).is_err());
+    }
+
+    #[test]
+    fn depot_rows_past_the_table_or_in_other_formats_are_refused() {
+        let options = ReadOptions {
+            depot: 3,
+            ..ReadOptions::default()
+        };
+        let native = "3 1 10\n0 0 0\n1 1 1\n2 2 2\n";
+        assert!(VRPInstance::from_reader(native.as_bytes(), "a.vrp", options).is_err());
+        let tsplib = tsplib("A-n3-k1", &[(0, 0), (3, 4), (6, 8)], &[0, 3, 4], "");
+        let options = ReadOptions {
+            depot: 1,
+            ..ReadOptions::default()
+        };
+        assert!(VRPInstance::from_reader(tsplib.as_bytes(), "a.vrp", options).is_err());
+    }
+
+    #[test]
+    fn nearest_rounding_gives_whole_costs() {
+        // legs of sqrt(2), sqrt(8) and sqrt(6.5^2 + 1), so none of them whole
+        let file = "4 2 10\n0 0 0\n1 1 1\n1 2 2\n1 -6.5 1\n";
+        let read_rounded = |rounding| {
+            let options = ReadOptions {
+                rounding,
+                ..ReadOptions::default()
+            };
+            Arc::new(VRPInstance::from_reader(file.as_bytes(), "tiny.vrp", options).unwrap())
+        };
+        let mut costs = Vec::new();
+        for rounding in [
+            DistanceRounding::Exact,
+            DistanceRounding::Nearest,
+            DistanceRounding::Truncate,
+        ] {
+            let instance = read_rounded(rounding);
+            let sol = VRPSolution::from_routes_vec(&instance, &[vec![1, 2], vec![3]]).unwrap();
+            // checking the solution uses the same rounding, so it's valid under every mode
+            sol.validate(&instance).unwrap();
+            costs.push(sol.cost());
+
+            // and whatever the search finds on a bigger one
+            let instance = Arc::new(
+                crate::generate::random_instance(30, 50, 2006)
+                    .with_distance_rounding(rounding)
+                    .unwrap(),
+            );
+            let params = crate::solver::SolveParams {
+                terminate: crate::solver::TermCond::MaxIters(300),
+                ..crate::default_params(std::time::Duration::ZERO)
+            };
+            crate::random::seed(2006);
+            let solved = crate::solver::solve::<crate::solvers::ALNSSolver>(&instance, &params);
+            solved.validate(&instance).unwrap();
+            assert_eq!(
+                solved.cost().fract() == 0.0,
+                rounding != DistanceRounding::Exact,
+                "{rounding:?}: {}",
+                solved.cost()
+            );
+        }
+        // out and back along sqrt(2), sqrt(2) and sqrt(8), then sqrt(43.25) there and back:
+        // rounding each leg gives 1, 1, 3 and 7, 7, and truncating them 1, 1, 2 and 6, 6
+        let exact = 4.0 * 2f64.sqrt() + 2.0 * 43.25f64.sqrt();
+        assert!((costs[0] - exact).abs() < 1e-9);
+        assert_eq!(costs[1..], [19.0, 16.0]);
+    }
+
+    #[test]
+    fn bad_files_are_errors_not_exits() {
+        for (file, expected) in [
+            ("", InstanceError::Empty),
+            ("\n  \n", InstanceError::Empty),
+            (
+                "3 1\n0 0 0\n1 1 1\n2 2 2\n",
+                InstanceError::BadHeader { line: 1 },
+            ),
+            (
+                "3 one 10\n0 0 0\n1 1 1\n2 2 2\n",
+                InstanceError::BadHeader { line: 1 },
+            ),
+            (
+                "3 1 10\n0 0 0\nfive 1 1\n2 2 2\n",
+                InstanceError::BadCustomerRow {
+                    line: 3,
+                    field: "demand",
+                },
+            ),
+            (
+                "3 1 10\n0 0 0\n1 1\n2 2 2\n",
+                InstanceError::BadCustomerRow {
+                    line: 3,
+                    field: "y",
+                },
+            ),
+            // cut off after the first customer
+            (
+                "4 1 10\n0 0 0\n1 1 1\n",
+                InstanceError::TooFewRows {
+                    expected: 4,
+                    found: 2,
+                    depot_missing: false,
+                },
+            ),
+        ] {
+            assert_eq!(read(file, false).err(), Some(expected), "{file:?}");
+        }
+    }
 
-        let vehicle_capacity = first_line[2].parse::<usize>().unwrap_or_else(|e| {
-            eprintln!("Error parsing vehicle capacity: {e}");
-            process::exit(-1);
+    #[test]
+    fn a_missing_file_is_an_error() {
+        assert_eq!(
+            VRPInstance::new("input/no-such-instance.vrp", ReadOptions::default()).err(),
+            Some(InstanceError::FileNotFound)
+        );
+    }
+
+    /// A TSPLIB file with nodes at `coords` (numbered from 1), each with its `demands`, and
+    /// `extra` header lines and sections.
+    fn tsplib(name: &str, coords: &[(i32, i32)], demands: &[usize], extra: &str) -> String {
+        let mut file = format!(
+            "NAME : {name}\nCOMMENT : (made up)\nTYPE : CVRP\nDIMENSION : {}\n\
+             EDGE_WEIGHT_TYPE : EUC_2D\nCAPACITY : 10\n{extra}NODE_COORD_SECTION\n",
+            coords.len()
+        );
+        for (node, (x, y)) in coords.iter().enumerate() {
+            file += &format!(" {} {x} {y}\n", node + 1);
+        }
+        file += "DEMAND_SECTION\n";
+        for (node, demand) in demands.iter().enumerate() {
+            file += &format!("{} {demand}\n", node + 1);
+        }
+        file
+    }
+
+    #[test]
+    fn tsplib_node_n_is_customer_n_minus_1() {
+        let file = tsplib(
+            "A-n4-k2",
+            &[(0, 0), (3, 4), (6, 8), (0, 5)],
+            &[0, 3, 4, 5],
+            "",
+        ) + "DEPOT_SECTION\n 1\n -1\nEOF\n";
+        let instance = read(&file, false).unwrap();
+        assert_eq!(instance.num_vehicles, 2);
+        assert_eq!(instance.vehicle_capacity, 10);
+        assert_eq!(instance.demand_of_customer, [0, 3, 4, 5]);
+        assert_eq!(instance.x_coord_of_customer, [0.0, 3.0, 6.0, 0.0]);
+        assert_eq!(instance.y_coord_of_customer, [0.0, 4.0, 8.0, 5.0]);
+        let dist = |a, b| instance.distance_matrix.dist(CustomerId(a), CustomerId(b));
+        assert_eq!(dist(0, 1), 5.0);
+        assert_eq!(dist(1, 2), 5.0);
+        assert_eq!(dist(0, 3), 5.0);
+    }
+
+    #[test]
+    fn a_tsplib_depot_elsewhere_is_moved_to_the_front() {
+        // node 3 is the depot, so the rest keep their order after it
+        let file = tsplib(
+            "no-fleet-size",
+            &[(3, 4), (6, 8), (0, 0), (0, 5)],
+            &[3, 4, 0, 5],
+            "VEHICLES : 3\n",
+        ) + "DEPOT_SECTION\n3\n-1\n";
+        let instance = read(&file, true).unwrap();
+        assert_eq!(instance.num_vehicles, 3);
+        assert_eq!(instance.demand_of_customer, [0, 3, 4, 5]);
+        assert_eq!(instance.x_coord_of_customer, [0.0, 3.0, 6.0, 0.0]);
+        assert_eq!(instance.y_coord_of_customer, [0.0, 4.0, 8.0, 5.0]);
+    }
+
+    #[test]
+    fn tsplib_explicit_distances_follow_the_nodes_to_their_customers() {
+        // a lower triangle by rows: 2-1 is 7, 3-1 is 8, 3-2 is 9, and node 2 is the depot
+        let file = "NAME : X-n3-k1\nTYPE : CVRP\nDIMENSION : 3\nEDGE_WEIGHT_TYPE : EXPLICIT\n\
+                    EDGE_WEIGHT_FORMAT : LOWER_ROW\nCAPACITY : 10\nEDGE_WEIGHT_SECTION\n7\n8 9\n\
+                    DEMAND_SECTION\n1 2\n2 0\n3 4\nDEPOT_SECTION\n2\n-1\nEOF\n";
+        let instance = read(file, true).unwrap();
+        assert_eq!(instance.demand_of_customer, [0, 2, 4]);
+        let dist = |a, b| instance.distance_matrix.dist(CustomerId(a), CustomerId(b));
+        assert_eq!([dist(0, 1), dist(0, 2), dist(1, 2)], [7.0, 9.0, 8.0]);
+        assert_eq!([dist(1, 0), dist(2, 0), dist(2, 1)], [7.0, 9.0, 8.0]);
+    }
+
+    #[test]
+    fn the_fleet_size_can_come_from_the_file_name() {
+        let file = tsplib("unnamed", &[(0, 0), (1, 1)], &[0, 1], "");
+        let options = ReadOptions::default();
+        let instance = VRPInstance::from_reader(file.as_bytes(), "X-n2-k4.vrp", options).unwrap();
+        assert_eq!(instance.num_vehicles, 4);
+        assert!(VRPInstance::from_reader(file.as_bytes(), "unnamed.vrp", options).is_err());
+    }
+
+    #[test]
+    fn bad_tsplib_files_are_refused() {
+        let coords = [(0, 0), (3, 4), (6, 8)];
+        for (file, why) in [
+            (
+                tsplib("A-n3-k1", &coords, &[0, 3, 4], "").replace("3 6 8", "4 6 8"),
+                "node 4 is out of range",
+            ),
+            (
+                tsplib("A-n3-k1", &coords, &[0, 3], ""),
+                "node 3 has no demand",
+            ),
+            (
+                tsplib("A-n3-k1", &coords, &[0, 3, 4], "").replace("CVRP", "TSP"),
+                "not TSP",
+            ),
+            (
+                tsplib("A-n3-k1", &coords, &[0, 3, 4], "") + "DEPOT_SECTION\n1\n2\n-1\n",
+                "single depot",
+            ),
+            (
+                tsplib("A-n3-k1", &coords, &[0, 3, 4], "").replace("DIMENSION : 3\n", ""),
+                "no DIMENSION",
+            ),
+        ] {
+            let error = read(&file, false).err().unwrap().to_string();
+            assert!(error.contains(why), "{error:?} should say {why:?}");
+        }
+    }
+
+    /// The start of Solomon's C101, down to its first three customers.
+    const SOLOMON: &str = "C101
+
+VEHICLE
+NUMBER     CAPACITY
+  25         200
+
+CUSTOMER
+CUST NO.  XCOORD.   YCOORD.    DEMAND   READY TIME  DUE DATE   SERVICE   TIME
+
+    0      40         50          0          0       1236          0
+    1      45         68         10        912        967         90
+    2      45         70         30        825        870         90
+
+    3      42         66         10         65        146         90
+";
+
+    #[test]
+    fn solomon_files_read_past_their_headings_and_blank_lines() {
+        let instance = read(SOLOMON, true).unwrap();
+        assert_eq!(instance.num_vehicles, 25);
+        assert_eq!(instance.vehicle_capacity, 200);
+        assert_eq!(instance.demand_of_customer, [0, 10, 30, 10]);
+        assert_eq!(instance.x_coord_of_customer, [40.0, 45.0, 45.0, 42.0]);
+        assert_eq!(instance.y_coord_of_customer, [50.0, 68.0, 70.0, 66.0]);
+        // the time windows are skipped, but not the service time after them
+        assert_eq!(instance.service_time_of_customer, [0.0, 90.0, 90.0, 90.0]);
+    }
+
+    #[test]
+    fn solomon_tables_without_time_windows_have_no_service_times() {
+        let file = "R0\nVEHICLE\nNUMBER CAPACITY\n2 50\nCUSTOMER\nCUST NO. X Y DEMAND\n\
+                    0 0 0 0\n1 3 4 5\n";
+        let instance = read(file, true).unwrap();
+        assert_eq!(instance.demand_of_customer, [0, 5]);
+        assert_eq!(instance.service_time_of_customer, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn bad_solomon_tables_are_refused() {
+        for (file, why) in [
+            // a customer missing from the middle of the table
+            (
+                SOLOMON.replace("    2      45", "    4      45"),
+                "expected customer 2",
+            ),
+            (
+                SOLOMON.replace("    0      40", "    1      40"),
+                "expected customer 0",
+            ),
+            (
+                SOLOMON.replace("  25         200", "  25"),
+                "vehicle number and capacity",
+            ),
+            // a word ends the table there, and it isn't the end of the file
+            (
+                SOLOMON.replace("         10        912", "         ten       912"),
+                "line 11: expected another row",
+            ),
+            (
+                SOLOMON.to_string() + "\ntrailing words\n",
+                "another row of the customer table",
+            ),
+        ] {
+            let error = read(&file, true).err().unwrap().to_string();
+            assert!(error.contains(why), "{error:?} should say {why:?}");
+        }
+    }
+
+    #[test]
+    fn a_well_formed_file_reads() {
+        let instance = read("3 1 10\n0 0 0\n1 3 4\n2 6 8\n", true).unwrap();
+        assert_eq!(instance.num_customers, 3);
+        assert_eq!(instance.demand_of_customer, [0, 1, 2]);
+        assert_eq!(instance.distance_matrix.dist(0_usize, 1_usize), 5.0);
+    }
+
+    /// The distances from `cust` to every other customer, nearest first.
+    fn sorted_row(instance: &VRPInstance, cust: usize) -> Vec<f64> {
+        let mut row = (1..instance.num_customers)
+            .filter(|&j| j != cust)
+            .map(|j| instance.distance_matrix.dist(cust, j))
+            .collect::<Vec<_>>();
+        row.sort_by(f64::total_cmp);
+        row
+    }
+
+    fn assert_nearest_match_sorted_rows(instance: &VRPInstance, k: usize) {
+        for cust in 0..instance.num_customers {
+            let nearest = instance.nearest(u32::try_from(cust).unwrap());
+            let distances = nearest
+                .iter()
+                .map(|&j| instance.distance_matrix.dist(cust, j as usize))
+                .collect::<Vec<_>>();
+            let row = sorted_row(instance, cust);
+            assert_eq!(distances, row[..k.min(row.len())], "customer {cust}");
+            assert!(!nearest.contains(&0) && !nearest.contains(&(cust as u32)));
+        }
+    }
+
+    #[test]
+    fn nearest_lists_are_the_closest_customers_in_order() {
+        let instance = crate::generate::random_instance(60, 100, 2019);
+        assert_nearest_match_sorted_rows(&instance, DEFAULT_NUM_NEIGHBORS);
+        let instance = instance.with_num_neighbors(7);
+        assert_nearest_match_sorted_rows(&instance, 7);
+        // rebuilding the matrix keeps the k asked for
+        let instance = instance
+            .with_distance_rounding(DistanceRounding::Truncate)
+            .unwrap();
+        assert_nearest_match_sorted_rows(&instance, 7);
+        // more neighbors than customers just lists them all
+        let instance = instance.with_num_neighbors(500);
+        assert_nearest_match_sorted_rows(&instance, 500);
+        let others = instance.num_customers - 1;
+        assert_eq!(instance.nearest(0).len(), others);
+        assert_eq!(instance.nearest(5).len(), others - 1);
+    }
+
+    /// What scoring a Shaw removal seed's candidates costs on 1000 customers: every customer,
+    /// scored and sorted, as it was before the nearest lists, against just the seed's nearest
+    /// customers. Run with `cargo test --release -- --ignored --nocapture shaw_scoring_cost`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn shaw_scoring_cost() {
+        let instance = crate::generate::random_instance(1000, 100, 2019);
+        let score = |seed: usize, cust: usize| {
+            instance.distance_matrix.dist(seed, cust)
+                + 0.1
+                    * (instance.demand_of_customer[seed] as f64
+                        - instance.demand_of_customer[cust] as f64)
+                        .abs()
+        };
+        let seeds = (0..10_000).map(|i| 1 + i % 999).collect::<Vec<_>>();
+
+        let start = std::time::Instant::now();
+        // the seed scores 0 against itself, so the closest other customer is second
+        let mut checksum = 0.0;
+        for &seed in &seeds {
+            let mut scores = (1..instance.num_customers)
+                .map(|cust| (cust, score(seed, cust)))
+                .collect::<Vec<_>>();
+            scores.sort_by(|a, b| a.1.total_cmp(&b.1));
+            checksum += scores[1].1;
+        }
+        let all = start.elapsed();
+
+        let start = std::time::Instant::now();
+        for &seed in &seeds {
+            let mut scores = instance
+                .nearest(u32::try_from(seed).unwrap())
+                .iter()
+                .map(|&cust| (cust, score(seed, cust as usize)))
+                .collect::<Vec<_>>();
+            scores.sort_by(|a, b| a.1.total_cmp(&b.1));
+            checksum -= scores[0].1;
+        }
+        let nearest = start.elapsed();
+        println!(
+            "{} seeds: {all:.2?} scoring every customer, {nearest:.2?} scoring the nearest {} \
+             ({:.0}x faster), closest scores differ by {checksum:.3}",
+            seeds.len(),
+            DEFAULT_NUM_NEIGHBORS,
+            all.as_secs_f64() / nearest.as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn service_times_are_an_optional_fourth_column() {
+        let without = read("3 1 10\n0 0 0\n1 3 4\n2 6 8\n", true).unwrap();
+        assert_eq!(without.service_time_of_customer, [0.0, 0.0, 0.0]);
+        let written = without.to_file_string().unwrap();
+        assert!(written.lines().all(|l| l.split_whitespace().count() == 3));
+
+        // rows can leave it out, and get 0
+        let with = read("3 1 10\n0 0 0\n1 3 4 2.5\n2 6 8\n", true).unwrap();
+        assert_eq!(with.service_time_of_customer, [0.0, 2.5, 0.0]);
+        assert_eq!(with.distance_matrix.dist(0_usize, 1_usize), 5.0);
+        let written = with.to_file_string().unwrap();
+        let reread = read(&written, true).unwrap();
+        assert_eq!(reread.service_time_of_customer, [0.0, 2.5, 0.0]);
+        assert_eq!(reread.to_file_string().unwrap(), written);
+    }
+
+    #[test]
+    fn bad_service_times_are_refused() {
+        assert_eq!(
+            read("3 1 10\n0 0 0\n1 3 4 soon\n2 6 8\n", false).err(),
+            Some(InstanceError::BadCustomerRow {
+                line: 3,
+                field: "service time",
+            })
+        );
+        for time in ["-1", "inf", "NaN"] {
+            let file = format!("3 1 10\n0 0 0\n1 3 4\n2 6 8 {time}\n");
+            assert!(
+                matches!(read(&file, false).err(), Some(InstanceError::Format(e)) if e.contains("customer 2")),
+                "{time}"
+            );
+        }
+    }
+
+    /// The ALNS loop's iteration rate on 2,000 customers with a distance matrix and with
+    /// distances computed on the fly, which reach the same solution. Run with
+    /// `cargo test --release -- --ignored --nocapture computed_distance_iteration_rate`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn computed_distance_iteration_rate() {
+        let file = crate::generate::random_instance(2_000, 100, 2030)
+            .to_file_string()
+            .unwrap();
+        let params = crate::solver::SolveParams {
+            terminate: crate::solver::TermCond::MaxIters(50_000),
+            ..crate::default_params(std::time::Duration::ZERO)
+        };
+        let mut costs = Vec::new();
+        for matrix_limit in [None, Some(0)] {
+            let options = ReadOptions {
+                matrix_limit,
+                ..ReadOptions::default()
+            };
+            let instance =
+                Arc::new(VRPInstance::from_reader(file.as_bytes(), "test.vrp", options).unwrap());
+            assert_eq!(
+                instance.distance_matrix.is_computed(),
+                matrix_limit.is_some()
+            );
+            crate::random::seed(2030);
+            let initial = (params.constructor)(&instance);
+            let start = std::time::Instant::now();
+            let sol = crate::solver::solve_from::<crate::solvers::ALNSSolver>(
+                &instance, &params, initial,
+            );
+            println!(
+                "{}: {:.0} iterations/s, cost {:.3}",
+                if matrix_limit.is_some() {
+                    "computed"
+                } else {
+                    "matrix"
+                },
+                50_000.0 / start.elapsed().as_secs_f64(),
+                sol.cost()
+            );
+            costs.push(sol.cost());
+        }
+        assert_eq!(costs[0], costs[1]);
+    }
+
+    const COMMENTED: &str = "# a commented fixture\n\
+        // customers vehicles capacity\n\
+        3 1 10   \n\
+        \n\
+        # depot\n\
+        0 0 0\t\n\
+        \x20 1 3 4  \n\
+        // the last customer, after a blank line\n\
+        \n\
+        2 6 8 \r\n";
+
+    #[test]
+    fn comments_blank_lines_and_trailing_whitespace_are_skipped() {
+        let commented = read(COMMENTED, true).unwrap();
+        let plain = read("3 1 10\n0 0 0\n1 3 4\n2 6 8\n", true).unwrap();
+        assert_eq!(
+            commented.to_file_string().unwrap(),
+            plain.to_file_string().unwrap()
+        );
+        assert_eq!(commented.demand_of_customer, [0, 1, 2]);
+    }
+
+    #[test]
+    fn errors_after_comments_give_the_line_in_the_file() {
+        let bad_row = COMMENTED.replace("2 6 8", "2 six 8");
+        assert_eq!(
+            read(&bad_row, false).err(),
+            Some(InstanceError::BadCustomerRow {
+                line: 10,
+                field: "x",
+            })
+        );
+        let bad_header = "# fleet\n\n// below\n3 one 10\n0 0 0\n1 1 1\n2 2 2\n";
+        assert_eq!(
+            read(bad_header, false).err(),
+            Some(InstanceError::BadHeader { line: 4 })
+        );
+        assert_eq!(
+            read("# only\n// comments\n\n", false).err(),
+            Some(InstanceError::Empty)
+        );
+    }
+
+    #[test]
+    fn from_parts_builds_what_the_file_would() {
+        let file = read("4 2 5\n0 0 0\n3 3 4\n2 -1 7.5\n4 6 8\n", true).unwrap();
+        let parts = VRPInstance::from_parts(
+            2,
+            5,
+            vec![0, 3, 2, 4],
+            vec![0.0, 3.0, -1.0, 6.0],
+            vec![0.0, 4.0, 7.5, 8.0],
+        )
+        .unwrap();
+        assert_eq!(parts.num_customers, 4);
+        assert_eq!(parts.max_route_len, file.max_route_len);
+        for from in 0..4_usize {
+            for to in 0..4_usize {
+                assert_eq!(
+                    parts.distance_matrix.dist(from, to),
+                    file.distance_matrix.dist(from, to)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn from_parts_checks_its_parts() {
+        let parts = |demands: Vec<usize>, xs: Vec<f64>, ys: Vec<f64>| {
+            VRPInstance::from_parts(1, 10, demands, xs, ys).err()
+        };
+        assert_eq!(
+            parts(vec![0, 1], vec![0.0, 1.0, 2.0], vec![0.0, 1.0]),
+            Some(InstanceError::MismatchedParts {
+                demands: 2,
+                xs: 3,
+                ys: 2
+            })
+        );
+        assert_eq!(
+            parts(vec![], vec![], vec![]),
+            Some(InstanceError::MismatchedParts {
+                demands: 0,
+                xs: 0,
+                ys: 0
+            })
+        );
+        assert_eq!(
+            parts(vec![2, 1], vec![0.0, 1.0], vec![0.0, 1.0]),
+            Some(InstanceError::DepotHasDemand {
+                demand: 2,
+                native: false
+            })
+        );
+        assert!(matches!(
+            parts(vec![0, 1], vec![0.0, f64::NAN], vec![0.0, 1.0]),
+            Some(InstanceError::Distances(_))
+        ));
+    }
+
+    #[test]
+    fn written_instances_read_back_exactly() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/input/101_8_1.vrp");
+        let mut original = VRPInstance::new(path, ReadOptions::default()).unwrap();
+        original.service_time_of_customer[3] = 0.1 + 0.2;
+        let written = original.to_file_string().unwrap();
+        let copy = read(&written, true).unwrap();
+        assert_eq!(copy.num_vehicles, original.num_vehicles);
+        assert_eq!(copy.vehicle_capacity, original.vehicle_capacity);
+        assert_eq!(copy.demand_of_customer, original.demand_of_customer);
+        assert_eq!(copy.x_coord_of_customer, original.x_coord_of_customer);
+        assert_eq!(copy.y_coord_of_customer, original.y_coord_of_customer);
+        assert_eq!(
+            copy.service_time_of_customer,
+            original.service_time_of_customer
+        );
+        for from in 0..original.num_customers {
+            for to in 0..original.num_customers {
+                assert_eq!(
+                    copy.distance_matrix.dist(from, to).to_bits(),
+                    original.distance_matrix.dist(from, to).to_bits()
+                );
+            }
+        }
+        assert_eq!(copy.to_file_string().unwrap(), written);
+    }
+
+    #[test]
+    fn instances_without_coordinates_are_not_written() {
+        let instance =
+            VRPInstance::from_matrix(1, 10, vec![0, 1], vec![vec![0.0, 2.0], vec![2.0, 0.0]]);
+        let err = instance.to_file_string().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    fn violations(
+        num_vehicles: usize,
+        capacity: usize,
+        demands: &[usize],
+    ) -> Vec<InstanceViolation> {
+        let xs = (0..demands.len()).map(|i| i as f64).collect::<Vec<_>>();
+        VRPInstance::from_parts_unchecked(num_vehicles, capacity, demands.to_vec(), xs.clone(), xs)
+            .validate()
+            .err()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn a_solvable_instance_validates() {
+        assert_eq!(violations(2, 10, &[0, 4, 5, 6]), []);
+    }
+
+    #[test]
+    fn each_violation_is_found_on_its_own() {
+        assert_eq!(
+            violations(2, 10, &[3, 4, 5]),
+            [InstanceViolation::DepotHasDemand(3)]
+        );
+        assert_eq!(
+            violations(3, 10, &[0, 12, 2]),
+            [InstanceViolation::DemandOverCapacity {
+                customer: CustomerId(1),
+                demand: 12,
+                capacity: 10
+            }]
+        );
+        assert_eq!(
+            violations(2, 10, &[0, 1, 1, 9, 9, 1]),
+            [InstanceViolation::FleetTooSmall {
+                total_demand: 21,
+                fleet_capacity: 20
+            }]
+        );
+        assert_eq!(
+            violations(2, 10, &[0, 6, 6, 6, 1, 1]),
+            [InstanceViolation::TooManyLargeDemands {
+                large_demands: 3,
+                num_vehicles: 2,
+                capacity: 10
+            }]
+        );
+        assert_eq!(
+            violations(2, 10, &[0, 4, 4, 4, 4, 4]),
+            [InstanceViolation::TooManyCustomers {
+                customers: 5,
+                per_route: 2,
+                num_vehicles: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn every_violation_is_reported() {
+        let found = violations(1, 10, &[0, 11, 3, 12]);
+        assert_eq!(found.len(), 5, "{found:?}");
+        assert!(found.contains(&InstanceViolation::DemandOverCapacity {
+            customer: CustomerId(1),
+            demand: 11,
+            capacity: 10
+        }));
+        assert!(found.contains(&InstanceViolation::DemandOverCapacity {
+            customer: CustomerId(3),
+            demand: 12,
+            capacity: 10
+        }));
+        assert!(found.contains(&InstanceViolation::FleetTooSmall {
+            total_demand: 26,
+            fleet_capacity: 10
+        }));
+    }
+
+    #[test]
+    fn bad_coordinates_and_lengths_are_violations() {
+        let good = || VRPInstance::from_parts(1, 10, vec![0, 1, 2], vec![0.0; 3], vec![1.0; 3]);
+        let mut instance = good().unwrap();
+        instance.y_coord_of_customer[2] = f64::INFINITY;
+        assert_eq!(
+            instance.validate().err().unwrap(),
+            [InstanceViolation::NonFiniteCoordinate {
+                customer: 2,
+                x: 0.0,
+                y: f64::INFINITY
+            }]
+        );
+
+        let mut instance = good().unwrap();
+        instance.x_coord_of_customer.pop();
+        instance.demand_of_customer.push(0);
+        assert_eq!(
+            instance.validate().err().unwrap(),
+            [InstanceViolation::LengthMismatch {
+                num_customers: 3,
+                demands: 4,
+                xs: 2,
+                ys: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn each_metric_measures_as_worked_out_by_hand() {
+        let close = |a: f64, b: f64| (a - b).abs() < 0.01;
+        assert_eq!(Metric::Euclidean.distance((0.0, 0.0), (3.0, 4.0)), 5.0);
+        assert_eq!(Metric::Euclidean.distance((-1.0, 2.0), (-1.0, -3.0)), 5.0);
+        assert_eq!(Metric::Manhattan.distance((0.0, 0.0), (3.0, 4.0)), 7.0);
+        assert_eq!(Metric::Manhattan.distance((-1.0, 2.0), (2.5, -3.0)), 8.5);
+
+        // London to Paris, and a quarter and a half of the way round
+        let london_to_paris = Metric::Haversine.distance((-0.1278, 51.5074), (2.3522, 48.8566));
+        assert!(close(london_to_paris, 343.56), "{london_to_paris}");
+        let to_the_pole = Metric::Haversine.distance((0.0, 0.0), (0.0, 90.0));
+        assert!(close(to_the_pole, 10007.56), "{to_the_pole}");
+        let round_the_back = Metric::Haversine.distance((0.0, 0.0), (180.0, 0.0));
+        assert!(close(round_the_back, 20015.11), "{round_the_back}");
+    }
+
+    #[test]
+    fn the_metric_fills_the_matrix_and_costs_follow_it() {
+        let instance = VRPInstance::from_parts(
+            1,
+            10,
+            vec![0, 1, 1],
+            vec![0.0, 3.0, 3.0],
+            vec![0.0, 4.0, 0.0],
+        )
+        .unwrap()
+        .with_metric(Metric::Manhattan)
+        .unwrap();
+        assert_eq!(instance.metric, Metric::Manhattan);
+        assert_eq!(instance.distance_matrix.dist(0_usize, 1_usize), 7.0);
+        assert_eq!(instance.distance_matrix.dist(1_usize, 2_usize), 4.0);
+
+        let instance = Arc::new(instance);
+        let sol = VRPSolution::from_routes_vec(&instance, &[vec![1, 2]]).unwrap();
+        assert_eq!(sol.cost(), 7.0 + 4.0 + 3.0);
+        assert!(sol.check().is_ok());
+    }
+
+    #[test]
+    fn anonymizing_keeps_the_same_problem() {
+        let mut instance = crate::generate::random_instance(60, 40, 1999)
+            .with_end_location(10.0, 90.0)
+            .unwrap();
+        instance.forbid_edge(1, 2).unwrap();
+        let anonymized = instance.anonymize(7).unwrap();
+        assert_eq!(
+            anonymized.to_file_string().unwrap(),
+            instance.anonymize(7).unwrap().to_file_string().unwrap()
+        );
+        assert_ne!(
+            anonymized.to_file_string().unwrap(),
+            instance.anonymize(8).unwrap().to_file_string().unwrap()
+        );
+
+        // every demand is scaled along with the capacity
+        let factor = anonymized.vehicle_capacity / instance.vehicle_capacity;
+        assert_eq!(
+            anonymized.vehicle_capacity,
+            instance.vehicle_capacity * factor
+        );
+        let sorted = |demands: &[usize]| {
+            let mut demands = demands.to_vec();
+            demands.sort_unstable();
+            demands
+        };
+        assert_eq!(
+            sorted(&anonymized.demand_of_customer),
+            sorted(&instance.demand_of_customer)
+                .iter()
+                .map(|d| d * factor)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(anonymized.num_vehicles, instance.num_vehicles);
+        assert!(anonymized.end_location.is_some());
+        assert_eq!(anonymized.forbidden_edges.len(), 1);
+        // the depot stays first, so the legs out of it are the same ones, only jittered (by up
+        // to ANONYMIZE_JITTER of the 100-wide square, on each axis at both ends)
+        let depot_legs = |instance: &VRPInstance| {
+            let mut legs = CustomerId::customers(instance.num_customers)
+                .map(|c| instance.distance_matrix.dist(CustomerId::DEPOT, c))
+                .collect::<Vec<_>>();
+            legs.sort_by(f64::total_cmp);
+            legs
+        };
+        let most = 2.0 * 2f64.sqrt() * ANONYMIZE_JITTER * 100.0;
+        for (a, b) in depot_legs(&instance).iter().zip(depot_legs(&anonymized)) {
+            assert!((a - b).abs() <= most, "{a} vs {b}");
+        }
+    }
+
+    #[test]
+    fn anonymized_instances_have_the_same_violations() {
+        let instance = VRPInstance::from_parts_unchecked(
+            1,
+            10,
+            vec![0, 11, 3, 12],
+            vec![0.0, 1.0, 2.0, 3.0],
+            vec![0.0; 4],
+        );
+        let kinds = |instance: &VRPInstance| {
+            instance
+                .validate()
+                .unwrap_err()
+                .iter()
+                .map(std::mem::discriminant)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kinds(&instance.anonymize(1).unwrap()), kinds(&instance));
+    }
+
+    #[test]
+    fn anonymized_instances_solve_about_as_well() {
+        let original = Arc::new(crate::generate::random_instance(60, 40, 1999));
+        let anonymized = Arc::new(original.anonymize(7).unwrap());
+        let params = crate::solver::SolveParams {
+            terminate: crate::solver::TermCond::MaxIters(2000),
+            ..crate::default_params(std::time::Duration::ZERO)
+        };
+        let [original_cost, anonymized_cost] = [&original, &anonymized].map(|instance| {
+            crate::random::seed(1999);
+            let sol = crate::solver::solve::<crate::solvers::ALNSSolver>(instance, &params);
+            sol.validate(instance).unwrap();
+            sol.cost()
         });
-        (num_customers, num_vehicles, vehicle_capacity)
+        let difference = (anonymized_cost - original_cost).abs() / original_cost;
+        assert!(
+            difference < 0.03,
+            "{original_cost:.2} before, {anonymized_cost:.2} anonymized"
+        );
+    }
+
+    #[test]
+    fn metrics_need_coordinates_and_only_euclidean_can_be_anonymized() {
+        let matrix =
+            VRPInstance::from_matrix(1, 10, vec![0, 1], vec![vec![0.0, 2.0], vec![2.0, 0.0]]);
+        assert_eq!(
+            matrix.with_metric(Metric::Haversine).err(),
+            Some(VrpError::NoCoordinates)
+        );
+
+        let instance = VRPInstance::from_parts(1, 10, vec![0, 1], vec![0.0, 1.0], vec![0.0, 1.0])
+            .unwrap()
+            .with_metric(Metric::Haversine)
+            .unwrap();
+        assert_eq!(
+            instance.anonymize(1).err(),
+            Some(VrpError::NotEuclidean(Metric::Haversine))
+        );
+    }
+
+    #[test]
+    fn normalized_instances_fit_the_unit_box_with_every_distance_scaled() {
+        // metres around 10^7, 2000 wide and 500 high
+        let mut original = VRPInstance::from_parts(
+            2,
+            10,
+            vec![0, 1, 2, 3],
+            vec![1.0e7, 1.0e7 + 2000.0, 1.0e7 + 500.0, 1.0e7 + 1000.0],
+            vec![3.0e7, 3.0e7 + 500.0, 3.0e7 + 100.0, 3.0e7],
+        )
+        .unwrap()
+        .with_end_location(1.0e7 + 1000.0, 3.0e7 + 500.0)
+        .unwrap();
+        original.service_time_of_customer = vec![0.0, 100.0, 0.0, 40.0];
+        original.forbid_edge(1, 2).unwrap();
+
+        let (normalized, scale) = original.normalized().unwrap();
+        assert_eq!(
+            scale,
+            ScaleInfo {
+                min_x: 1.0e7,
+                min_y: 3.0e7,
+                scale: 2000.0,
+            }
+        );
+        assert_eq!(normalized.x_coord_of_customer, [0.0, 1.0, 0.25, 0.5]);
+        assert_eq!(normalized.y_coord_of_customer, [0.0, 0.25, 0.05, 0.0]);
+        assert_eq!(normalized.end_location, Some((0.5, 0.25)));
+        assert_eq!(normalized.service_time_of_customer, [0.0, 0.05, 0.0, 0.02]);
+        assert!(normalized.distance_matrix.is_forbidden(1_usize, 2_usize));
+        for a in 0..4_usize {
+            for b in (0..4_usize).filter(|&b| (a, b) != (1, 2) && (a, b) != (2, 1)) {
+                let (before, after) = (
+                    original.distance_matrix.dist(a, b),
+                    normalized.distance_matrix.dist(a, b),
+                );
+                assert!(
+                    (scale.to_original(after) - before).abs() < 1e-6,
+                    "{a} -> {b}"
+                );
+                assert!((scale.to_normalized(before) - after).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn only_unrounded_plane_coordinates_can_be_normalized() {
+        let point = || VRPInstance::from_parts(1, 10, vec![0, 1], vec![5.0, 5.0], vec![7.0, 7.0]);
+        // all in one place only needs moving
+        let (normalized, scale) = point().unwrap().normalized().unwrap();
+        assert_eq!(scale.scale, 1.0);
+        assert_eq!(normalized.x_coord_of_customer, [0.0, 0.0]);
+
+        let matrix =
+            VRPInstance::from_matrix(1, 10, vec![0, 1], vec![vec![0.0, 2.0], vec![2.0, 0.0]]);
+        assert_eq!(matrix.normalized().err(), Some(VrpError::NoCoordinates));
+        let haversine = point().unwrap().with_metric(Metric::Haversine).unwrap();
+        assert_eq!(
+            haversine.normalized().err(),
+            Some(VrpError::NotEuclidean(Metric::Haversine))
+        );
+        let rounded = point()
+            .unwrap()
+            .with_distance_rounding(DistanceRounding::Nearest)
+            .unwrap();
+        assert_eq!(rounded.normalized().err(), Some(VrpError::RoundedDistances));
+        let manhattan = point().unwrap().with_metric(Metric::Manhattan).unwrap();
+        assert!(manhattan.normalized().is_ok());
     }
 }
@@ -0,0 +1,808 @@
+//! End-to-end runs of the binary, for what only shows up once the command line, the search and the
+//! report are put together.
+
+use std::{
+    io::{BufRead, BufReader},
+    ops::Deref,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde_json::Value;
+
+const INSTANCE: &str = "input/101_8_1.vrp";
+
+/// A fresh directory under the system's temporary one, removed again when it's dropped, so a
+/// failing test doesn't leave its files behind.
+struct ScratchDir(PathBuf);
+
+impl Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Make an empty [`ScratchDir`] for the test `name`, emptying what an earlier run left there.
+fn scratch_dir(name: &str) -> ScratchDir {
+    let dir = std::env::temp_dir().join(format!("vrp-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    ScratchDir(dir)
+}
+
+/// Run the solver with `args`, failing the test if it doesn't exit cleanly, and return the JSON
+/// line it printed last.
+fn run(args: &[&str]) -> Value {
+    let out = Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+        .args(args)
+        .output()
+        .expect("the binary runs");
+    assert!(
+        out.status.success(),
+        "{args:?} failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    serde_json::from_str(stdout.lines().last().expect("a JSON line")).unwrap()
+}
+
+#[test]
+fn normalize_reports_reaching_the_bks_gap_in_original_units() {
+    for normalize in [false, true] {
+        let mut args = vec![
+            INSTANCE,
+            "--seed",
+            "2951",
+            "--threads",
+            "1",
+            "--iters",
+            "20000",
+            "--out",
+            "/dev/null",
+        ];
+        args.extend(["--bks", "800", "--bks-gap", "20"]);
+        if normalize {
+            args.push("--normalize");
+        }
+        let output = run(&args);
+        let gap = output["BKSGapPercent"].as_f64().unwrap();
+        assert!(gap <= 20.0, "normalize {normalize}: gap {gap}");
+        assert_eq!(output["ReachedBKSGap"], true, "normalize {normalize}");
+        assert!(output["TimeToGap"].is_number(), "normalize {normalize}");
+    }
+}
+
+#[test]
+fn deterministic_runs_print_byte_identical_output() {
+    let dir = scratch_dir("deterministic");
+    let runs = ["a", "b"].map(|name| {
+        let sol = dir.join(format!("{name}.sol"));
+        let out = Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+            .args([INSTANCE, "--deterministic", "--iters", "5000", "--out"])
+            .arg(&sol)
+            .output()
+            .expect("the binary runs");
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        let stdout = String::from_utf8(out.stdout).unwrap();
+        let line = stdout.lines().last().expect("a JSON line").to_string();
+        (line, std::fs::read(&sol).unwrap())
+    });
+    assert_eq!(runs[0].0, runs[1].0, "the JSON lines differ");
+    assert_eq!(runs[0].1, runs[1].1, "the .sol files differ");
+}
+
+#[test]
+fn no_arguments_runs_a_passing_smoke_test() {
+    let out = Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+        .output()
+        .expect("the binary runs");
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(out.status.success(), "{stdout}");
+    for start in [
+        "smoke test: 60 customers",
+        "smoke test: 3 customers",
+        "chaos test: cost",
+    ] {
+        let summary = stdout
+            .lines()
+            .find(|line| line.starts_with(start))
+            .expect("a summary line");
+        assert!(summary.ends_with(": ok"), "{summary}");
+    }
+}
+
+#[test]
+fn a_tight_memory_budget_cuts_threads_and_an_impossible_one_is_refused() {
+    // big enough to be decomposed, which is what needs memory per thread
+    let dir = scratch_dir("memory-budget");
+    let instance = dir.join("big.vrp");
+    let out = Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+        .arg(&instance)
+        .args(["--generate", "5000,20,1000,uniform", "--seed", "1972"])
+        .output()
+        .expect("the binary runs");
+    assert!(out.status.success());
+
+    let budget_run = |budget: &str| {
+        Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+            .arg(&instance)
+            .args(["--memory-budget", budget, "--threads", "4", "--iters", "10"])
+            .args(["--out", "/dev/null"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("the binary runs")
+    };
+
+    // a single thread needs about 109MB, and each one more another 8MB or so; only wait to hear
+    // about the cut, since solving this many customers in a debug build takes minutes
+    let mut child = budget_run("115");
+    let cut = BufReader::new(child.stderr.take().unwrap())
+        .lines()
+        .map(Result::unwrap)
+        .find(|line| line.starts_with("using"));
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert_eq!(
+        cut.as_deref(),
+        Some("using 1 of 4 threads to stay within the 115MB memory budget")
+    );
+
+    let out = budget_run("50").wait_with_output().unwrap();
+    assert!(!out.status.success());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("over the 50MB memory budget"), "{stderr}");
+}
+
+#[test]
+fn unsolvable_fleets_exit_2_with_a_json_error() {
+    let dir = scratch_dir("unsolvable");
+    // fits by volume (20 of 20), but only two demands of 4 fit in a vehicle of 10
+    let instance = dir.join("by_count.vrp");
+    std::fs::write(
+        &instance,
+        "6 2 10\n0 0 0\n4 1 0\n4 2 0\n4 3 0\n4 4 0\n4 5 0\n",
+    )
+    .unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+        .arg(&instance)
+        .args(["--threads", "1", "--iters", "100"])
+        .output()
+        .expect("the binary runs");
+
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("can't be solved"), "{stderr}");
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let error: Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(error["Error"], "Unsolvable");
+    assert!(
+        error["Instance"]
+            .as_str()
+            .unwrap()
+            .ends_with("by_count.vrp")
+    );
+    let violations = error["Violations"].as_array().unwrap();
+    assert_eq!(violations.len(), 1, "{violations:?}");
+    assert_eq!(
+        violations[0]["TooManyCustomers"]["PerRoute"], 2,
+        "{violations:?}"
+    );
+    assert_eq!(error["Messages"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn batch_solves_a_folder_in_name_order_and_reports_the_ones_that_fail() {
+    let dir = scratch_dir("batch");
+    for name in ["b.vrp", "a.vrp"] {
+        std::fs::copy(INSTANCE, dir.join(name)).unwrap();
+    }
+    std::fs::write(dir.join("broken.vrp"), "3 one 10\n").unwrap();
+    // solutions are written next to where it runs
+    let out = Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+        .current_dir(&*dir)
+        .args([
+            "--batch",
+            ".",
+            "--seed",
+            "2027",
+            "--threads",
+            "1",
+            "--iters",
+            "500",
+        ])
+        .output()
+        .expect("the binary runs");
+
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let lines = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    let instances = lines.iter().map(|line| line["Instance"].as_str().unwrap());
+    assert_eq!(
+        instances.collect::<Vec<_>>(),
+        ["a.vrp", "b.vrp", "broken.vrp"]
+    );
+    // the same seed on the same instance
+    assert_eq!(lines[0]["Result"], lines[1]["Result"]);
+    assert!(lines[0]["Result"].is_number());
+    assert!(lines[2]["Error"].is_string());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("solved 2 of 3 instances"), "{stderr}");
+}
+
+#[test]
+fn warm_starts_never_end_worse_and_bad_ones_fall_back() {
+    let dir = scratch_dir("warm-start");
+    let good = dir.join("good.sol");
+    let good_path = good.to_str().unwrap();
+    let first = run(&[
+        INSTANCE,
+        "--seed",
+        "2032",
+        "--threads",
+        "1",
+        "--iters",
+        "5000",
+        "--out",
+        good_path,
+    ]);
+    let start = first["Result"].as_f64().unwrap();
+
+    for seed in ["1", "2", "3"] {
+        let warm = run(&[
+            INSTANCE,
+            "--seed",
+            seed,
+            "--threads",
+            "1",
+            "--iters",
+            "200",
+            "--warm-start",
+            good_path,
+            "--out",
+            "/dev/null",
+        ]);
+        let result = warm["Result"].as_f64().unwrap();
+        assert!(result <= start, "seed {seed}: {result} > {start}");
+    }
+
+    // a file that doesn't cover every customer is only warned about
+    let contents = std::fs::read_to_string(&good).unwrap();
+    let missing_route = contents.lines().take(2).collect::<Vec<_>>().join("\n");
+    std::fs::write(&good, missing_route).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+        .args([
+            INSTANCE,
+            "--seed",
+            "2032",
+            "--threads",
+            "1",
+            "--iters",
+            "200",
+        ])
+        .args(["--warm-start", good_path, "--out", "/dev/null"])
+        .output()
+        .expect("the binary runs");
+    assert!(out.status.success());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.contains("Warning: not starting from") && stderr.contains("constructing instead"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn check_prints_every_run_and_exits_1_if_any_failed() {
+    let dir = scratch_dir("check-cli");
+    std::fs::write(
+        dir.join("tiny.vrp"),
+        "4 2 10\n0 0 0\n3 0 3\n4 4 0\n5 0 -4\n",
+    )
+    .unwrap();
+    let log = [
+        r#"{"Instance": "tiny.vrp", "Result": 12.0, "Solution": "0 0 1 2 0"}"#,
+        r#"{"Instance": "tiny.vrp", "Result": 20.0, "Solution": "0 0 1 2 0 0 3 0"}"#,
+    ];
+    std::fs::write(dir.join("results.log"), log.join("\n")).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+        .arg(&*dir)
+        .arg("--check")
+        .arg(dir.join("results.log"))
+        .output()
+        .expect("the binary runs");
+
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("2 runs checked, 1 failed"), "{stderr}");
+    assert!(stderr.contains("coverage FAIL"), "{stderr}");
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let reports: Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(reports[0]["Violations"][0]["MissingCustomer"], 3);
+    assert_eq!(reports[1]["Violations"].as_array().unwrap().len(), 0);
+}
+
+#[test]
+fn instances_missing_from_the_bks_file_report_nulls() {
+    let dir = scratch_dir("bks-cli");
+    let csv = dir.join("bks.csv");
+    let solve_with_bks = |bks: &str| {
+        let csv = csv.to_str().unwrap().to_string();
+        std::fs::write(&csv, bks).unwrap();
+        let args = [
+            INSTANCE,
+            "--seed",
+            "2040",
+            "--threads",
+            "1",
+            "--iters",
+            "500",
+        ];
+        run(&[&args[..], &["--out", "/dev/null", "--bks", &csv]].concat())
+    };
+
+    let listed = solve_with_bks("101_8_1,800\n");
+    assert_eq!(listed["BKS"], 800.0);
+    let result = listed["Result"].as_f64().unwrap();
+    let gap = listed["BKSGapPercent"].as_f64().unwrap();
+    assert!((gap - (result - 800.0) / 800.0 * 100.0).abs() < 1e-9);
+
+    let missing = solve_with_bks("121_7_1,1034\n");
+    for field in ["BKS", "BKSGapPercent", "ReachedBKSGap", "TimeToGap"] {
+        assert!(missing[field].is_null(), "{field}: {}", missing[field]);
+    }
+}
+
+#[test]
+fn an_unwritable_out_path_prints_the_solution_instead() {
+    let dir = scratch_dir("unwritable");
+    // a file where a directory should be, which even root can't write under
+    let blocker = dir.join("not_a_dir");
+    std::fs::write(&blocker, "").unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+        .args([
+            INSTANCE,
+            "--seed",
+            "1981",
+            "--threads",
+            "1",
+            "--iters",
+            "500",
+            "--out",
+        ])
+        .arg(blocker.join("out.sol"))
+        .output()
+        .expect("the binary runs");
+
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("Error: couldn't write"), "{stderr}");
+    // the .sol file's routes, every one from the depot and back
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let routes = stdout
+        .lines()
+        .filter(|line| line.starts_with("0 ") && line.ends_with(" 0"))
+        .count();
+    assert!(routes > 0, "{stdout}");
+}
+
+#[test]
+fn repeats_report_each_seed_and_the_spread_of_their_costs() {
+    let output = run(&[
+        INSTANCE,
+        "--repeats",
+        "3",
+        "--seed",
+        "1982",
+        "--threads",
+        "1",
+        "--iters",
+        "300",
+        "--out",
+        "/dev/null",
+    ]);
+    let repeats = &output["Repeats"];
+    let runs = repeats["Runs"].as_array().unwrap();
+    let seeds = runs
+        .iter()
+        .map(|run| run["Seed"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(seeds, [1982, 1983, 1984]);
+
+    let mut costs = runs
+        .iter()
+        .map(|run| run["Result"].as_f64().unwrap())
+        .collect::<Vec<_>>();
+    costs.sort_by(f64::total_cmp);
+    let mean = costs.iter().sum::<f64>() / 3.0;
+    let close = |key: &str, expected: f64| {
+        let got = repeats[key].as_f64().unwrap();
+        assert!((got - expected).abs() < 1e-6, "{key}: {got} vs {expected}");
+    };
+    close("Min", costs[0]);
+    close("Median", costs[1]);
+    close("Mean", mean);
+    close(
+        "StdDev",
+        (costs.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / 3.0).sqrt(),
+    );
+    // and the solution reported is the best of them
+    close("Min", output["Result"].as_f64().unwrap());
+}
+
+#[test]
+fn explain_shows_each_setting_from_the_layer_that_set_it_last() {
+    let dir = scratch_dir("explain");
+    let config = dir.join("alns.json");
+    std::fs::write(&config, r#"{"replace_and_cascade": false}"#).unwrap();
+    let config = config.to_str().unwrap();
+
+    let explain = |args: &[&str]| {
+        let mut all = vec![INSTANCE, "--explain", "--alns-config", config];
+        all.extend(args);
+        let settings = run(&all);
+        move |name: &str| {
+            let setting = settings
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|s| s["Name"] == name)
+                .unwrap_or_else(|| panic!("no {name}"));
+            (
+                setting["Value"].clone(),
+                setting["Source"].as_str().unwrap().to_string(),
+            )
+        }
+    };
+
+    // only the config file, and what's worked out automatically
+    let setting = explain(&[]);
+    assert_eq!(
+        setting("alns.replace_and_cascade"),
+        (Value::Bool(false), format!("config file {config}"))
+    );
+    assert_eq!(
+        setting("alns.endgame_fraction"),
+        (serde_json::json!(0.05), "default".to_string())
+    );
+    assert_eq!(setting("threads").1, "auto (physical cores)");
+
+    // the auto layer over the defaults, and flags over both
+    let setting = explain(&["--deterministic", "--time", "1"]);
+    assert!(setting("terminate").1.starts_with("auto (--deterministic"));
+    assert_eq!(
+        setting("threads"),
+        (serde_json::json!(1), "--deterministic".to_string())
+    );
+    let setting = explain(&[
+        "--deterministic",
+        "--time",
+        "1",
+        "--iters",
+        "700",
+        "--seed",
+        "7",
+    ]);
+    assert_eq!(
+        setting("terminate"),
+        (serde_json::json!({"MaxIters": 700}), "--iters".to_string())
+    );
+    assert_eq!(
+        setting("seed"),
+        (serde_json::json!(7), "--seed".to_string())
+    );
+    let setting = explain(&["--threads", "3"]);
+    assert_eq!(
+        setting("threads"),
+        (serde_json::json!(3), "--threads".to_string())
+    );
+    // a flag that doesn't touch the config file's settings leaves them be
+    assert_eq!(
+        setting("alns.replace_and_cascade").1,
+        format!("config file {config}")
+    );
+}
+
+#[test]
+fn route_summaries_match_the_flat_solution_and_add_up_to_the_cost() {
+    let output = run(&[
+        INSTANCE,
+        "--seed",
+        "2042",
+        "--threads",
+        "1",
+        "--iters",
+        "5000",
+        "--out",
+        "/dev/null",
+    ]);
+    let summaries = output["RouteSummaries"].as_array().unwrap();
+    let mut flat = vec![0];
+    for summary in summaries {
+        let stops = summary["Stops"].as_array().unwrap();
+        assert!(!stops.is_empty());
+        assert_eq!(summary["NumStops"].as_u64().unwrap() as usize, stops.len());
+        flat.push(0);
+        flat.extend(stops.iter().map(|s| s.as_u64().unwrap()));
+        flat.push(0);
+    }
+    let solution: Vec<u64> = output["Solution"]
+        .as_str()
+        .unwrap()
+        .split_whitespace()
+        .map(|s| s.parse().unwrap())
+        .collect();
+    assert_eq!(flat, solution);
+    let distance: f64 = summaries
+        .iter()
+        .map(|s| s["Distance"].as_f64().unwrap())
+        .sum();
+    let result = output["Result"].as_f64().unwrap();
+    assert!((distance - result).abs() < 0.01, "{distance} vs {result}");
+}
+
+#[test]
+fn improvement_logs_have_a_whole_json_line_per_new_best_from_every_thread() {
+    let dir = scratch_dir("improvements");
+    let log = dir.join("improvements.jsonl");
+    let output = run(&[
+        INSTANCE,
+        "--seed",
+        "2043",
+        "--threads",
+        "4",
+        "--iters",
+        "5000",
+        "--out",
+        "/dev/null",
+        "--improvement-log",
+        log.to_str().unwrap(),
+    ]);
+    let lines = std::fs::read_to_string(&log).unwrap();
+    let events: Vec<Value> = lines
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{line:?}: {e}")))
+        .collect();
+    assert!(events.len() > 4, "{lines}");
+    for event in &events {
+        assert!(event["Iteration"].as_u64().unwrap() < 5000);
+        assert!(event["Elapsed"].as_f64().unwrap() >= 0.0);
+    }
+    let cheapest = events
+        .iter()
+        .map(|e| e["Cost"].as_f64().unwrap())
+        .fold(f64::INFINITY, f64::min);
+    let result = output["Result"].as_f64().unwrap();
+    assert!((cheapest - result).abs() < 0.01, "{cheapest} vs {result}");
+}
+
+#[test]
+fn cvrplib_files_list_the_used_routes_and_the_cost() {
+    let dir = scratch_dir("cvrplib");
+    let out = dir.join("cvrplib.sol");
+    let output = run(&[
+        INSTANCE,
+        "--seed",
+        "2047",
+        "--threads",
+        "1",
+        "--iters",
+        "5000",
+        "--format",
+        "cvrplib",
+        "--out",
+        out.to_str().unwrap(),
+    ]);
+    let file = std::fs::read_to_string(&out).unwrap();
+    let mut lines: Vec<&str> = file.lines().collect();
+    let cost: f64 = lines
+        .pop()
+        .unwrap()
+        .strip_prefix("Cost ")
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((cost - output["Result"].as_f64().unwrap()).abs() < 1e-6);
+    let routes: Vec<String> = output["RouteSummaries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .enumerate()
+        .map(|(i, summary)| {
+            let stops = summary["Stops"].as_array().unwrap().iter();
+            let stops: Vec<String> = stops.map(|s| s.to_string()).collect();
+            format!("Route #{}: {}", i + 1, stops.join(" "))
+        })
+        .collect();
+    assert_eq!(lines, routes);
+}
+
+#[test]
+fn compare_exits_1_only_when_the_results_regressed_beyond_the_threshold() {
+    let dir = scratch_dir("compare-cli");
+    let (old, new) = (dir.join("old.log"), dir.join("new.log"));
+    std::fs::write(&old, r#"{"Instance": "a", "Result": 100.0}"#).unwrap();
+    // 4% worse
+    std::fs::write(&new, r#"{"Instance": "a", "Result": 104.0}"#).unwrap();
+    let logs = format!("{},{}", old.display(), new.display());
+    let compare = |threshold: &str| {
+        Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+            .args(["--compare", &logs, "--regression-threshold", threshold])
+            .output()
+            .expect("the binary runs")
+    };
+    let (within, beyond) = (compare("5"), compare("3"));
+
+    assert_eq!(within.status.code(), Some(0));
+    assert_eq!(beyond.status.code(), Some(1));
+    let stderr = String::from_utf8(beyond.stderr).unwrap();
+    assert!(
+        stderr.contains("0 improved, 1 regressed, 0 tied"),
+        "{stderr}"
+    );
+    let stdout = String::from_utf8(beyond.stdout).unwrap();
+    let comparison: Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(comparison["Instances"][0]["Outcome"], "Regressed");
+    assert_eq!(comparison["MeanChangePercent"], 4.0);
+}
+
+#[test]
+fn instances_smaller_than_a_destroy_step_solve_with_any_depot_row() {
+    let dir = scratch_dir("tiny");
+    let tiny = dir.join("tiny.vrp");
+    std::fs::write(&tiny, "4 2 10\n0 0 0\n3 0 3\n4 4 0\n5 0 -4\n").unwrap();
+    let tiny = tiny.to_str().unwrap();
+    let args = [
+        tiny,
+        "--threads",
+        "1",
+        "--iters",
+        "2000",
+        "--out",
+        "/dev/null",
+    ];
+    let outputs = [run(&args), run(&[&args[..], &["--depot", "3"]].concat())];
+    for output in outputs {
+        assert!(output["Result"].as_f64().unwrap() > 0.0, "{output}");
+    }
+}
+
+#[test]
+fn runs_with_the_depot_in_another_row_pass_check_and_warm_start() {
+    let dir = scratch_dir("depot-row");
+    // the same instance, with the depot moved from the first row to the last
+    let original = std::fs::read_to_string(INSTANCE).unwrap();
+    let (header, rows) = original.split_once('\n').unwrap();
+    let mut rows = rows
+        .lines()
+        .filter(|r| !r.trim().is_empty())
+        .collect::<Vec<_>>();
+    let depot = rows.remove(0);
+    rows.push(depot);
+    let last = (rows.len() - 1).to_string();
+    std::fs::write(
+        dir.join("moved.vrp"),
+        format!("{header}\n{}\n", rows.join("\n")),
+    )
+    .unwrap();
+
+    let moved = dir.join("moved.vrp");
+    let sol = dir.join("moved.sol");
+    let (moved, sol) = (moved.to_str().unwrap(), sol.to_str().unwrap());
+    let args = [moved, "--depot", &last, "--seed", "2015", "--threads", "1"];
+    let output = run(&[&args[..], &["--iters", "2000", "--out", sol]].concat());
+    assert_eq!(output["Depot"].as_u64().unwrap().to_string(), last);
+    let solution = output["Solution"].as_str().unwrap();
+    assert!(solution.starts_with(&format!("0 {last} ")), "{solution}");
+    assert!(
+        std::fs::read_to_string(sol)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .all(|line| {
+                line.starts_with(&format!("{last} ")) && line.ends_with(&format!(" {last}"))
+            })
+    );
+
+    std::fs::write(dir.join("results.log"), format!("{output}\n")).unwrap();
+    let check = Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+        .arg(&*dir)
+        .arg("--check")
+        .arg(dir.join("results.log"))
+        .output()
+        .expect("the binary runs");
+    let warm = run(&[
+        &args[..],
+        &["--iters", "10", "--out", "/dev/null", "--warm-start", sol],
+    ]
+    .concat());
+
+    let stderr = String::from_utf8(check.stderr).unwrap();
+    assert!(check.status.success(), "{stderr}");
+    assert!(stderr.contains("1 runs checked, 0 failed"), "{stderr}");
+    let result = output["Result"].as_f64().unwrap();
+    assert!(warm["Result"].as_f64().unwrap() <= result + 1e-9);
+}
+
+#[test]
+fn multi_depot_runs_give_each_route_its_depots_row_and_pass_check() {
+    let dir = scratch_dir("depots");
+    // the customers first, then a second warehouse, then the instance's own depot
+    let original = std::fs::read_to_string(INSTANCE).unwrap();
+    let mut lines = original.lines().filter(|r| !r.trim().is_empty());
+    let header = lines.next().unwrap().split_whitespace().collect::<Vec<_>>();
+    let mut rows = lines.collect::<Vec<_>>();
+    let depot = rows.remove(0);
+    rows.extend(["0 60.0 60.0", depot]);
+    let header = format!("{} {} {}", rows.len(), header[1], header[2]);
+    std::fs::write(
+        dir.join("two.vrp"),
+        format!("{header}\n{}\n", rows.join("\n")),
+    )
+    .unwrap();
+    let (own, extra) = ((rows.len() - 1).to_string(), (rows.len() - 2).to_string());
+
+    let two = dir.join("two.vrp");
+    let sol = dir.join("two.sol");
+    let (two, sol) = (two.to_str().unwrap(), sol.to_str().unwrap());
+    let depots = format!("{own},{extra}");
+    let output = run(&[
+        two,
+        "--depot",
+        &depots,
+        "--seed",
+        "2016",
+        "--threads",
+        "1",
+        "--iters",
+        "2000",
+        "--out",
+        sol,
+    ]);
+    let sol_file = std::fs::read_to_string(sol).unwrap();
+    std::fs::write(dir.join("results.log"), format!("{output}\n")).unwrap();
+    let check = Command::new(env!("CARGO_BIN_EXE_vehicle_routing"))
+        .arg(&*dir)
+        .arg("--check")
+        .arg(dir.join("results.log"))
+        .output()
+        .expect("the binary runs");
+
+    assert_eq!(
+        output["DepotRows"],
+        serde_json::json!([rows.len() - 1, rows.len() - 2])
+    );
+    // every route, in the Solution as in the .sol file, starts and ends at one of the depots
+    let solution = output["Solution"].as_str().unwrap();
+    let routes = sol_file.lines().skip(1).collect::<Vec<_>>();
+    let mut starts = Vec::new();
+    for route in &routes {
+        let stops = route.split_whitespace().collect::<Vec<_>>();
+        assert!(
+            [own.as_str(), extra.as_str()].contains(&stops[0]),
+            "{route}"
+        );
+        assert_eq!(stops[0], *stops.last().unwrap(), "{route}");
+        starts.push(stops[0]);
+    }
+    assert!(starts.contains(&own.as_str()) && starts.contains(&extra.as_str()));
+    assert_eq!(solution, format!("0 {}", routes.join(" ")));
+
+    let stderr = String::from_utf8(check.stderr).unwrap();
+    assert!(check.status.success(), "{stderr}");
+    assert!(stderr.contains("1 runs checked, 0 failed"), "{stderr}");
+}